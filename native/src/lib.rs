@@ -1,8 +1,10 @@
 mod float;
-mod ops;
+pub mod ops;
+mod pool;
 pub(crate) mod python;
+pub mod reference;
 pub mod replay;
-mod ticker_batch;
+pub mod ticker_batch;
 
 pub use self::python::*;
 use pyo3::{prelude::*, wrap_pyfunction};
@@ -20,8 +22,13 @@ fn _lib(py: Python, m: &PyModule) -> PyResult<()> {
         pyo3_built!(py, build, "build", "time", "features", "host", "target"),
     )?;
     m.add_class::<Factor>()?;
+    m.add("ParseError", py.get_type::<python::ParseError>())?;
+    m.add_class::<ArrowStream>()?;
     m.add_function(wrap_pyfunction!(python::replay, m)?)?;
     m.add_function(wrap_pyfunction!(python::replay_file, m)?)?;
+    m.add_function(wrap_pyfunction!(python::operators, m)?)?;
+    m.add_function(wrap_pyfunction!(python::define, m)?)?;
+    m.add_function(wrap_pyfunction!(python::undefine, m)?)?;
 
     Ok(())
 }
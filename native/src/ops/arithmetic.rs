@@ -1,28 +1,95 @@
-use super::{parser::Parameter, BoxOp, Named, Operator};
+use super::{at_child, constant::fmt_constant, parser::Parameter, BoxOp, Getter, Named, Operator};
 use crate::ticker_batch::TickerBatch;
 use anyhow::{anyhow, Error, Result};
 use fehler::{throw, throws};
-use std::{borrow::Cow, cmp::max, iter::FromIterator, mem};
+use itertools::Itertools;
+use rayon::prelude::*;
+use std::{borrow::Cow, cmp::max, iter::FromIterator, mem, str::FromStr};
+
+/// How `/` and `LogAbs` deal with zeros.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZeroPolicy {
+    /// Replace the zero by `f64::EPSILON`. This is the default.
+    Epsilon,
+    /// Strict IEEE 754 arithmetic, e.g. `1 / 0 = inf` and `ln(0) = -inf`.
+    Ieee,
+    /// Produce NaN when a zero is hit.
+    Nan,
+}
+
+impl ZeroPolicy {
+    // Printed in front of the operands, e.g. `(/ ieee :a :b)`, unless it is the default
+    pub(crate) fn prefix(self) -> &'static str {
+        match self {
+            ZeroPolicy::Epsilon => "",
+            ZeroPolicy::Ieee => "ieee ",
+            ZeroPolicy::Nan => "nan ",
+        }
+    }
+}
+
+impl ZeroPolicy {
+    // `l / r` for a ratio, e.g. by `PctChange`, a zero `r` is replaced by `f64::EPSILON` under the default policy
+    pub(crate) fn divide(self, l: f64, r: f64) -> f64 {
+        match self {
+            ZeroPolicy::Epsilon => l / if r == 0. { f64::EPSILON } else { r },
+            ZeroPolicy::Nan if r == 0. => f64::NAN,
+            _ => l / r,
+        }
+    }
+}
 
+impl Default for ZeroPolicy {
+    fn default() -> Self {
+        ZeroPolicy::Epsilon
+    }
+}
+
+impl FromStr for ZeroPolicy {
+    type Err = Error;
+
+    #[throws(Error)]
+    fn from_str(s: &str) -> Self {
+        match s {
+            "epsilon" => ZeroPolicy::Epsilon,
+            "ieee" => ZeroPolicy::Ieee,
+            "nan" => ZeroPolicy::Nan,
+            _ => throw!(anyhow!(
+                "unknown zero policy '{}', expect one of epsilon, ieee or nan",
+                s
+            )),
+        }
+    }
+}
+
+// The operators followed by `(zero)` deal with zeros according to a `ZeroPolicy`, which is passed to `$func`
 macro_rules! impl_arithmetic_bivariate {
-    ($([$name:tt => $op:ident: $($func:tt)+])+) => {
+    ($([$name:tt => $op:ident $(($zero:ident))?: $($func:tt)+])+) => {
         $(
             pub struct $op<T> {
                 l: BoxOp<T>,
                 r: BoxOp<T>,
+                $($zero: ZeroPolicy,)?
                 i: usize,
             }
 
             impl<T> Clone for $op<T> {
                 fn clone(&self) -> Self {
-                    Self::new(self.l.clone(), self.r.clone())
+                    Self::new(self.l.clone(), self.r.clone())$(.with_zero(self.$zero))?
                 }
             }
 
             impl<T> $op<T> {
                 pub fn new(l: BoxOp<T>, r: BoxOp<T>) -> Self {
-                    Self { l, r, i: 0 }
+                    Self { l, r, $($zero: ZeroPolicy::default(),)? i: 0 }
                 }
+
+                $(
+                    pub fn with_zero(mut self, $zero: ZeroPolicy) -> Self {
+                        self.$zero = $zero;
+                        self
+                    }
+                )?
             }
 
             impl<T> Named for $op<T> {
@@ -39,37 +106,47 @@ macro_rules! impl_arithmetic_bivariate {
                 #[throws(Error)]
                 fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
                     let (l, r) = (&mut self.l, &mut self.r);
-                    let (ls, rs) = rayon::join(|| l.update(tb), || r.update(tb));
-                    let (ls, rs) = (&*ls?, &*rs?);
-                    #[cfg(feature = "check")]
-                    assert_eq!(tb.len(), ls.len());
-                    #[cfg(feature = "check")]
-                    assert_eq!(tb.len(), rs.len());
+                    let (ls, rs) = rayon::join(|| l.checked_update(tb), || r.checked_update(tb));
+                    let (ls, rs) = (
+                        &*ls.map_err(at_child(1))?,
+                        &*rs.map_err(at_child(1 + self.l.len()))?,
+                    );
 
                     let mut results = Vec::with_capacity(tb.len());
 
                     for (&lval, &rval) in ls.into_iter().zip(rs) {
                         if self.i < self.l.ready_offset() || self.i < self.r.ready_offset() {
-                            #[cfg(feature = "check")]
-                            assert!(lval.is_nan() || rval.is_nan());
+                            self.check_warmup(tb, &[lval, rval])?;
                             results.push(f64::NAN);
                             self.i += 1;
                             continue;
                         }
 
-                        let val = self.fchecked(($($func)+) (lval, rval))?;
+                        // A NaN or infinity from a nullable input is passed on, see `Operator::is_nullable`
+                        let val = ($($func)+) (lval, rval $(, self.$zero)?);
+                        let checked = $(self.$zero == ZeroPolicy::Epsilon &&)? lval.is_finite() && rval.is_finite();
+                        let val = if checked { self.fchecked(val)? } else { val };
                         results.push(val);
                     }
 
                     results.into()
                 }
 
+                fn is_pointwise(&self) -> bool {
+                    true
+                }
+
+                fn is_nullable(&self) -> bool {
+                    $(self.$zero != ZeroPolicy::Epsilon ||)? self.l.is_nullable() || self.r.is_nullable()
+                }
+
                 fn ready_offset(&self) -> usize {
                     max(self.l.ready_offset(), self.r.ready_offset())
                 }
 
                 fn to_string(&self) -> String {
-                    format!("({} {} {})", Self::NAME, self.l.to_string(), self.r.to_string())
+                    let prefix: &[&str] = &[$(self.$zero.prefix())?];
+                    format!("({} {}{} {})", Self::NAME, prefix.concat(), self.l.to_string(), self.r.to_string())
                 }
 
                 fn depth(&self) -> usize {
@@ -166,27 +243,40 @@ impl_arithmetic_bivariate! (
     [+ => Add: |l: f64, r: f64| l + r]
     [- => Sub: |l: f64, r: f64| l - r]
     [* => Mul: |l: f64, r: f64| l * r]
-    [/ => Div: |l: f64, r: f64| r.signum() * l / if r == 0. { f64::EPSILON } else { r }]
+    [/ => Div (zero): |l: f64, r: f64, zero| match zero {
+        ZeroPolicy::Epsilon => r.signum() * l / if r == 0. { f64::EPSILON } else { r },
+        ZeroPolicy::Nan if r == 0. => f64::NAN,
+        _ => l / r,
+    }]
 );
 
+// As in `impl_arithmetic_bivariate`, the operators followed by `(zero)` take a `ZeroPolicy`
 macro_rules! impl_arithmetic_univariate {
-    ($([$name:tt => $op:ident: $($func:tt)+])+) => {
+    ($([$name:tt => $op:ident $(($zero:ident))?: $($func:tt)+])+) => {
         $(
             pub struct $op<T> {
                 inner: BoxOp<T>,
+                $($zero: ZeroPolicy,)?
                 i: usize,
             }
 
             impl<T> Clone for $op<T> {
                 fn clone(&self) -> Self {
-                    Self::new(self.inner.clone())
+                    Self::new(self.inner.clone())$(.with_zero(self.$zero))?
                 }
             }
 
             impl<T> $op<T> {
                 pub fn new(inner: BoxOp<T>) -> Self {
-                    Self { inner, i: 0 }
+                    Self { inner, $($zero: ZeroPolicy::default(),)? i: 0 }
                 }
+
+                $(
+                    pub fn with_zero(mut self, $zero: ZeroPolicy) -> Self {
+                        self.$zero = $zero;
+                        self
+                    }
+                )?
             }
 
             impl<T> Named for $op<T> {
@@ -201,34 +291,43 @@ macro_rules! impl_arithmetic_univariate {
 
                 #[throws(Error)]
                 fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
-                    let vals = &*self.inner.update(tb)?;
-                    #[cfg(feature = "check")]
-                    assert_eq!(tb.len(), vals.len());
+                    let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
 
                     let mut results = Vec::with_capacity(tb.len());
 
                     for &val in vals {
                         if self.i < self.inner.ready_offset() {
-                            #[cfg(feature = "check")]
-                            assert!(val.is_nan());
+                            self.check_warmup(tb, &[val])?;
                             results.push(f64::NAN);
                             self.i += 1;
                             continue;
                         }
 
-                        let val = self.fchecked(($($func)+) (val))?;
+                        // A NaN or infinity from a nullable input is passed on, see `Operator::is_nullable`
+                        let checked = $(self.$zero == ZeroPolicy::Epsilon &&)? val.is_finite();
+                        let val = ($($func)+) (val $(, self.$zero)?);
+                        let val = if checked { self.fchecked(val)? } else { val };
                         results.push(val);
                     }
 
                     results.into()
                 }
 
+                fn is_pointwise(&self) -> bool {
+                    true
+                }
+
+                fn is_nullable(&self) -> bool {
+                    $(self.$zero != ZeroPolicy::Epsilon ||)? self.inner.is_nullable()
+                }
+
                 fn ready_offset(&self) -> usize {
                     self.inner.ready_offset()
                 }
 
                 fn to_string(&self) -> String {
-                    format!("({} {})", Self::NAME, self.inner.to_string())
+                    let prefix: &[&str] = &[$(self.$zero.prefix())?];
+                    format!("({} {}{})", Self::NAME, prefix.concat(), self.inner.to_string())
                 }
 
                 fn depth(&self) -> usize {
@@ -301,11 +400,22 @@ macro_rules! impl_arithmetic_univariate {
     };
 }
 
+// The inputs of `Sqrt` this close below 0 are taken as 0, e.g. the rounding errors of a variance computed as
+// `E[x^2] - E[x]^2`. The more negative ones produce a NaN, which fails the factor.
+const SQRT_TOLERANCE: f64 = 1e-9;
+
 impl_arithmetic_univariate! (
-    [LogAbs => LogAbs: |s: f64| (s.abs() + f64::EPSILON).ln()]
+    [LogAbs => LogAbs (zero): |s: f64, zero| match zero {
+        ZeroPolicy::Epsilon => (s.abs() + f64::EPSILON).ln(),
+        ZeroPolicy::Nan if s == 0. => f64::NAN,
+        _ => s.abs().ln(),
+    }]
     [Sign => Sign: |s: f64| s.signum()]
     [Abs => Abs: |s: f64| s.abs()]
     [Neg => Neg: |s: f64| -s]
+    [Exp => Exp: |s: f64| s.exp()]
+    [Sqrt => Sqrt: |s: f64| if s < 0. && s >= -SQRT_TOLERANCE { 0. } else { s.sqrt() }]
+    [Cbrt => Cbrt: |s: f64| s.cbrt()]
 );
 
 macro_rules! impl_arithmetic_univariate_1arg {
@@ -341,34 +451,42 @@ macro_rules! impl_arithmetic_univariate_1arg {
 
                 #[throws(Error)]
                 fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
-                    let vals = &*self.inner.update(tb)?;
-                    #[cfg(feature = "check")]
-                    assert_eq!(tb.len(), vals.len());
+                    let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
 
                     let mut results = Vec::with_capacity(tb.len());
 
                     for &val in vals {
                         if self.i < self.inner.ready_offset() {
-                            #[cfg(feature = "check")]
-                            assert!(val.is_nan());
+                            self.check_warmup(tb, &[val])?;
                             results.push(f64::NAN);
                             self.i += 1;
                             continue;
                         }
 
-                        let val = self.fchecked(($($func)+) (self.p, val))?;
+                        let checked = val.is_finite();
+                        let val = ($($func)+) (self.p, val);
+                        let val = if checked { self.fchecked(val)? } else { val };
                         results.push(val);
                     }
 
                     results.into()
                 }
 
+                fn is_pointwise(&self) -> bool {
+                    true
+                }
+
                 fn ready_offset(&self) -> usize {
                     self.inner.ready_offset()
                 }
 
                 fn to_string(&self) -> String {
-                    format!("({} {} {})", Self::NAME, self.p, self.inner.to_string())
+                    format!(
+                        "({} {} {})",
+                        Self::NAME,
+                        fmt_constant(self.p),
+                        self.inner.to_string()
+                    )
                 }
 
                 fn depth(&self) -> usize {
@@ -452,3 +570,529 @@ impl_arithmetic_univariate_1arg! {
     [^ => Pow: |p: f64, s: f64| s.powf(p)]
     [SPow => SignedPow: |p: f64, s: f64| s.signum() * s.abs().powf(p)]
 }
+
+/// The series clamped into `[lo, hi]`, the bounds are swapped if `lo > hi`. `(ClipLower <lo> <expr>)` and
+/// `(ClipUpper <hi> <expr>)` clamp on one side only, i.e. the other bound is infinite. A NaN passes through.
+pub struct Clip<T> {
+    inner: BoxOp<T>,
+    lo: f64,
+    hi: f64,
+    i: usize,
+}
+
+impl<T> Clone for Clip<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.lo, self.hi, self.inner.clone())
+    }
+}
+
+impl<T> Clip<T> {
+    pub fn new(lo: f64, hi: f64, inner: BoxOp<T>) -> Self {
+        let (lo, hi) = if lo > hi { (hi, lo) } else { (lo, hi) };
+        Self {
+            inner,
+            lo,
+            hi,
+            i: 0,
+        }
+    }
+
+    pub const LOWER: &'static str = "ClipLower";
+    pub const UPPER: &'static str = "ClipUpper";
+}
+
+impl<T: TickerBatch> Clip<T> {
+    /// `ClipLower` or `ClipUpper` as given by `name`, from the bound and the series.
+    #[throws(Error)]
+    pub fn one_sided(name: &str, params: Vec<Parameter<T>>) -> Self {
+        if params.len() != 2 {
+            throw!(anyhow!(
+                "{} expect one constant and one series, got {:?}",
+                name,
+                params
+            ))
+        }
+
+        let mut params = params.into_iter();
+        match (params.next().unwrap(), params.next().unwrap().to_operator()) {
+            (Parameter::Constant(lo), Some(s)) if name == Self::LOWER => {
+                Clip::new(lo, f64::INFINITY, s)
+            }
+            (Parameter::Constant(hi), Some(s)) => Clip::new(f64::NEG_INFINITY, hi, s),
+            (a, _) => throw!(anyhow!(
+                "{name} expect a constant and a series, got ({name} {} ...)",
+                a,
+                name = name,
+            )),
+        }
+    }
+}
+
+impl<T> Named for Clip<T> {
+    const NAME: &'static str = "Clip";
+}
+
+impl<T: TickerBatch> Operator<T> for Clip<T> {
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for &val in vals {
+            if self.i < self.inner.ready_offset() {
+                self.check_warmup(tb, &[val])?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            // `f64::max` and `min` would take the bound for a NaN, which is passed on instead (see
+            // `Operator::is_nullable`). An infinity is clamped, unless the clip is one-sided on its side.
+            let val = if val.is_nan() {
+                val
+            } else {
+                val.max(self.lo).min(self.hi)
+            };
+            results.push(val);
+        }
+
+        results.into()
+    }
+
+    fn is_pointwise(&self) -> bool {
+        true
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inner.ready_offset()
+    }
+
+    fn to_string(&self) -> String {
+        match (self.lo == f64::NEG_INFINITY, self.hi == f64::INFINITY) {
+            (false, true) => format!(
+                "({} {} {})",
+                Self::LOWER,
+                fmt_constant(self.lo),
+                self.inner.to_string()
+            ),
+            (true, false) => format!(
+                "({} {} {})",
+                Self::UPPER,
+                fmt_constant(self.hi),
+                self.inner.to_string()
+            ),
+            _ => format!(
+                "({} {} {} {})",
+                Self::NAME,
+                fmt_constant(self.lo),
+                fmt_constant(self.hi),
+                self.inner.to_string()
+            ),
+        }
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inner.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        vec![1]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inner.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            self.inner.get(i)?
+        } else {
+            throw!()
+        }
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            if i == 0 {
+                return mem::replace(&mut self.inner, op) as BoxOp<T>;
+            }
+            self.inner.insert(i, op)?
+        } else {
+            throw!()
+        }
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<Clip<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> Clip<T> {
+        let params: Vec<_> = iter.into_iter().collect();
+        if params.len() != 3 {
+            throw!(anyhow!(
+                "{} expect two constants and one series, got {:?}",
+                Clip::<T>::NAME,
+                params
+            ))
+        }
+
+        let mut params = params.into_iter();
+        match (
+            params.next().unwrap(),
+            params.next().unwrap(),
+            params.next().unwrap().to_operator(),
+        ) {
+            (Parameter::Constant(lo), Parameter::Constant(hi), Some(s)) => Clip::new(lo, hi, s),
+            (a, b, _) => throw!(anyhow!(
+                "{name} expect two constants and a series, got ({name} {} {} ...)",
+                a,
+                b,
+                name = Clip::<T>::NAME,
+            )),
+        }
+    }
+}
+
+/// The weighted sum of several series in a single node, e.g. a depth-weighted order book feature.
+/// `(Dot w1 ... wn (Cols :c1 ... :cn))` is a shorthand of `(WSum w1 :c1 ... wn :cn)`.
+pub struct WSum<T> {
+    weights: Vec<f64>,
+    inputs: Vec<BoxOp<T>>,
+
+    i: usize,
+}
+
+impl<T> Clone for WSum<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.weights.clone(), self.inputs.clone())
+    }
+}
+
+impl<T> WSum<T> {
+    pub fn new(weights: Vec<f64>, inputs: Vec<BoxOp<T>>) -> Self {
+        assert_eq!(weights.len(), inputs.len());
+        Self {
+            weights,
+            inputs,
+            i: 0,
+        }
+    }
+}
+
+impl<T> Named for WSum<T> {
+    const NAME: &'static str = "WSum";
+}
+
+impl<T: TickerBatch> Operator<T> for WSum<T> {
+    fn reset(&mut self) {
+        self.inputs.iter_mut().for_each(|op| op.reset());
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let offsets = self.child_indices();
+        let inputs = self
+            .inputs
+            .par_iter_mut()
+            .zip(offsets)
+            .map(|(op, offset)| op.checked_update(tb).map_err(at_child(offset)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for row in 0..tb.len() {
+            if self.i < self.ready_offset() {
+                let vals: Vec<_> = inputs.iter().map(|vals| vals[row]).collect();
+                self.check_warmup(tb, &vals)?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            let val = self
+                .weights
+                .iter()
+                .zip(&inputs)
+                .map(|(w, vals)| w * vals[row])
+                .sum();
+            let checked = inputs.iter().all(|vals| vals[row].is_finite());
+            results.push(if checked { self.fchecked(val)? } else { val });
+        }
+
+        results.into()
+    }
+
+    fn is_pointwise(&self) -> bool {
+        true
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inputs
+            .iter()
+            .map(|op| op.ready_offset())
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn to_string(&self) -> String {
+        let terms = self
+            .weights
+            .iter()
+            .zip(&self.inputs)
+            .map(|(w, op)| format!("{} {}", fmt_constant(*w), op.to_string()))
+            .join(" ");
+        format!("({} {})", Self::NAME, terms)
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inputs.iter().map(|op| op.depth()).max().unwrap_or(0)
+    }
+
+    fn len(&self) -> usize {
+        1 + self.inputs.iter().map(|op| op.len()).sum::<usize>()
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        self.inputs
+            .iter()
+            .scan(1, |offset, op| {
+                let i = *offset;
+                *offset += op.len();
+                Some(i)
+            })
+            .collect()
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inputs.iter().flat_map(|op| op.columns()).collect()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        let mut i = i - 1;
+
+        let input = self.inputs.iter().find(|input| {
+            let found = i < input.len();
+            if !found {
+                i -= input.len();
+            }
+            found
+        })?;
+        input.get(i)?
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        let mut i = i - 1;
+
+        let input = self.inputs.iter_mut().find(|input| {
+            let found = i < input.len();
+            if !found {
+                i -= input.len();
+            }
+            found
+        })?;
+        if i == 0 {
+            return mem::replace(input, op) as BoxOp<T>;
+        }
+        input.insert(i, op)?
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<WSum<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> WSum<T> {
+        let params: Vec<_> = iter.into_iter().collect();
+        if params.is_empty() || params.len() % 2 != 0 {
+            throw!(anyhow!(
+                "{} expect pairs of a constant and a series, got {:?}",
+                WSum::<T>::NAME,
+                params
+            ))
+        }
+
+        let mut weights = vec![];
+        let mut inputs = vec![];
+        for (w, s) in params.into_iter().tuples() {
+            match (w, s.to_operator()) {
+                (Parameter::Constant(w), Some(s)) => {
+                    weights.push(w);
+                    inputs.push(s);
+                }
+                (w, _) => throw!(anyhow!(
+                    "{} expect pairs of a constant and a series, got weight {}",
+                    WSum::<T>::NAME,
+                    w
+                )),
+            }
+        }
+
+        WSum::new(weights, inputs)
+    }
+}
+
+/// Parses `(Dot w1 ... wn (Cols :c1 ... :cn))` or `(Dot (w1 ... wn) (Cols :c1 ... :cn))` into a `WSum`.
+pub struct Dot;
+
+impl Named for Dot {
+    const NAME: &'static str = "Dot";
+}
+
+impl Dot {
+    #[throws(Error)]
+    pub fn from_params<T: TickerBatch>(mut params: Vec<Parameter<T>>) -> WSum<T> {
+        let columns = match params.pop() {
+            Some(Parameter::Columns(columns)) => columns,
+            _ => throw!(anyhow!(
+                "{} expect constants followed by (Cols ...), got {:?}",
+                Dot::NAME,
+                params
+            )),
+        };
+
+        let weights = match &*params {
+            [Parameter::Vector(weights)] => weights.clone(),
+            _ => params
+                .into_iter()
+                .map(|p| match p {
+                    Parameter::Constant(w) => Ok(w),
+                    p => Err(anyhow!(
+                        "<weights> for {} should be constants, got {}",
+                        Dot::NAME,
+                        p
+                    )),
+                })
+                .collect::<Result<Vec<_>>>()?,
+        };
+        if weights.len() != columns.len() {
+            throw!(anyhow!(
+                "{} got {} weights for {} columns",
+                Dot::NAME,
+                weights.len(),
+                columns.len()
+            ))
+        }
+
+        let inputs = columns.iter().map(|c| Getter::new(c).boxed()).collect();
+        WSum::new(weights, inputs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ops::{from_str, ParseOptions, ZeroPolicy};
+    use arrow::{
+        array::{ArrayRef, Float64Array},
+        record_batch::RecordBatch,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn clip_nan() {
+        let opts = Default::default();
+        let xs = vec![1., f64::NAN, f64::NAN, -2.];
+        let tb =
+            RecordBatch::try_from_iter(vec![("a", Arc::new(Float64Array::from(xs)) as ArrayRef)])
+                .unwrap();
+
+        // The NaN left by FFill past its gap is not taken for a bound
+        for (repr, expected) in [
+            ("(Clip -1 1 (FFill 1 :a))", [1., 1., f64::NAN, -1.]),
+            ("(ClipLower 0 (FFill 1 :a))", [1., 1., f64::NAN, 0.]),
+            ("(ClipUpper 0 (FFill 1 :a))", [0., 0., f64::NAN, -2.]),
+        ] {
+            let mut op = from_str::<RecordBatch>(repr, &opts).unwrap();
+            let got = op.update(&tb).unwrap();
+            assert!(got[2].is_nan(), "{}", repr);
+            assert_eq!(
+                [got[0], got[1], got[3]],
+                [expected[0], expected[1], expected[3]],
+                "{}",
+                repr
+            );
+            assert_eq!(op.to_string(), repr);
+        }
+
+        // The parents working row by row pass the NaN on, the windows cannot take it
+        let mut op = from_str::<RecordBatch>("(* 2 (Clip -1 1 (FFill 1 :a)))", &opts).unwrap();
+        let got = op.update(&tb).unwrap();
+        assert!(got[2].is_nan());
+        assert_eq!([got[0], got[1], got[3]], [2., 2., -2.]);
+        assert!(from_str::<RecordBatch>("(Mean 2 (Clip -1 1 (FFill 1 :a)))", &opts).is_err());
+
+        // An infinity is clamped
+        let mut op = from_str::<RecordBatch>("(Clip -1 1 (/ ieee (FFill :a) 0))", &opts).unwrap();
+        assert_eq!(&*op.update(&tb).unwrap(), &[1., 1., 1., -1.]);
+    }
+
+    #[test]
+    fn nested_zero_policy() {
+        let opts = Default::default();
+        let tb = RecordBatch::try_from_iter(vec![
+            ("a", Arc::new(Float64Array::from(vec![1., 2.])) as ArrayRef),
+            ("b", Arc::new(Float64Array::from(vec![0., 2.])) as ArrayRef),
+        ])
+        .unwrap();
+
+        // The pointwise parents pass the infinities and NaNs on
+        let mut op = from_str::<RecordBatch>("(+ (/ ieee :a :b) 1)", &opts).unwrap();
+        assert_eq!(&*op.update(&tb).unwrap(), &[f64::INFINITY, 2.]);
+        let mut op = from_str::<RecordBatch>("(Neg (* 2 (LogAbs nan :b)))", &opts).unwrap();
+        let got = op.update(&tb).unwrap();
+        assert!(got[0].is_nan());
+        assert_eq!(got[1], -2. * 2f64.ln());
+
+        // A window would keep them in its state for good
+        for repr in [
+            "(Mean 2 (/ ieee :a :b))",
+            "(Mean 2 (+ (LogAbs nan :b) 1))",
+            "(Sum 2 (- :a (Abs (/ nan :a :b))))",
+        ] {
+            let e = from_str::<RecordBatch>(repr, &opts).err().unwrap();
+            assert!(
+                e.to_string().contains("may produce NaNs"),
+                "{}: {}",
+                repr,
+                e
+            );
+        }
+        let opts = ParseOptions {
+            zero: ZeroPolicy::Ieee,
+            ..Default::default()
+        };
+        assert!(from_str::<RecordBatch>("(Mean 2 (/ :a :b))", &opts).is_err());
+        assert!(from_str::<RecordBatch>("(Mean 2 (/ epsilon :a :b))", &opts).is_ok());
+    }
+}
@@ -4,6 +4,16 @@ use anyhow::Error;
 use fehler::{throw, throws};
 use std::borrow::Cow;
 
+/// Print a constant such that parsing it back gives the very same value. The integers are printed as such,
+/// the others with the shortest digits that round-trip, in scientific notation when very large or small.
+pub(super) fn fmt_constant(c: f64) -> String {
+    if c.fract() == 0. && c.abs() < 1e16 && !(c == 0. && c.is_sign_negative()) {
+        format!("{}", c)
+    } else {
+        format!("{:?}", c)
+    }
+}
+
 impl<T: TickerBatch> Operator<T> for f64 {
     fn reset(&mut self) {}
 
@@ -12,12 +22,16 @@ impl<T: TickerBatch> Operator<T> for f64 {
         vec![*self; tb.len()].into()
     }
 
+    fn is_pointwise(&self) -> bool {
+        true
+    }
+
     fn ready_offset(&self) -> usize {
         0
     }
 
     fn to_string(&self) -> String {
-        format!("{}", self)
+        fmt_constant(*self)
     }
 
     fn depth(&self) -> usize {
@@ -50,3 +64,25 @@ impl<T: TickerBatch> Operator<T> for f64 {
         unreachable!("cannot insert root");
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::super::{from_str, Operator};
+    use arrow::record_batch::RecordBatch;
+
+    #[test]
+    fn round_trip() {
+        let opts = Default::default();
+        for c in [0.1 + 0.2, 1e300, -1.5e-7, 1e20, 123456.789, 3., -0.0] {
+            let repr = format!("(+ :a {})", <f64 as Operator<RecordBatch>>::to_string(&c));
+            let op = from_str::<RecordBatch>(&repr, &opts).unwrap();
+            assert_eq!(op.to_string(), repr);
+        }
+        assert_eq!(<f64 as Operator<RecordBatch>>::to_string(&3.), "3");
+        assert_eq!(<f64 as Operator<RecordBatch>>::to_string(&1e-7), "1e-7");
+
+        let repr = "(Conv (0.30000000000000004 1e-9) (^ 2.5e20 (col-or \"a\" 0.1)))";
+        let op = from_str::<RecordBatch>(repr, &opts).unwrap();
+        assert_eq!(op.to_string(), repr);
+    }
+}
@@ -0,0 +1,151 @@
+//! Compare two factors node by node, e.g. to review a mutation or to only replay the parts that changed.
+
+use super::{dot::label, BoxOp, Operator};
+use crate::ticker_batch::TickerBatch;
+
+/// A place where two factors differ. `index` is the position of the node in the first factor and `other`
+/// the one in the second (see `Operator::get`), `before` and `after` are the subtrees there.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Difference {
+    /// One of
+    /// * `operator`: the function changed, the children are compared on their own
+    /// * `params`: the same function with different constant parameters, e.g. the window size
+    /// * `constant`: a constant changed
+    /// * `column`: another column is read
+    /// * `inserted`: the subtree is wrapped into a new function
+    /// * `removed`: the function is removed, one of its children takes its place
+    /// * `replaced`: anything else
+    pub kind: &'static str,
+    pub index: usize,
+    pub other: usize,
+    pub before: String,
+    pub after: String,
+}
+
+fn children<T: TickerBatch>(op: &dyn Operator<T>) -> Vec<(usize, BoxOp<T>)> {
+    op.child_indices()
+        .into_iter()
+        .filter_map(|c| op.get(c).map(|child| (c, child)))
+        .collect()
+}
+
+fn is_constant<T: TickerBatch>(op: &dyn Operator<T>) -> bool {
+    op.len() == 1 && op.to_string().parse::<f64>().is_ok()
+}
+
+fn is_column<T: TickerBatch>(op: &dyn Operator<T>) -> bool {
+    op.len() == 1 && op.columns().len() == 1
+}
+
+fn visit<T: TickerBatch>(
+    a: &dyn Operator<T>,
+    i: usize,
+    b: &dyn Operator<T>,
+    j: usize,
+    out: &mut Vec<Difference>,
+) {
+    let (before, after) = (a.to_string(), b.to_string());
+    if before == after {
+        return;
+    }
+    let mut change = |kind| {
+        out.push(Difference {
+            kind,
+            index: i,
+            other: j,
+            before: before.clone(),
+            after: after.clone(),
+        })
+    };
+
+    let (ac, bc) = (children(a), children(b));
+    if ac.is_empty() && bc.is_empty() {
+        let kind = if is_constant(a) && is_constant(b) {
+            "constant"
+        } else if is_column(a) && is_column(b) {
+            "column"
+        } else {
+            "replaced"
+        };
+        return change(kind);
+    }
+
+    if bc.iter().any(|(_, child)| child.to_string() == before) {
+        return change("inserted");
+    }
+    if ac.iter().any(|(_, child)| child.to_string() == after) {
+        return change("removed");
+    }
+    if ac.len() != bc.len() {
+        return change("replaced");
+    }
+
+    let (al, bl) = (
+        label(a, &ac.iter().map(|(_, c)| c.clone()).collect::<Vec<_>>()),
+        label(b, &bc.iter().map(|(_, c)| c.clone()).collect::<Vec<_>>()),
+    );
+    let head = |label: &str| label.split(' ').next().unwrap_or("").to_string();
+    if head(&al) != head(&bl) {
+        change("operator");
+    } else if al != bl {
+        change("params");
+    }
+    for ((ca, a), (cb, b)) in ac.iter().zip(&bc) {
+        visit(&**a, i + ca, &**b, j + cb, out);
+    }
+}
+
+/// The places where `b` differs from `a`, in the pre-order of `a`. The children of a function are compared
+/// pairwise, so a change deep in the tree is reported there rather than as a different root.
+pub fn diff<T: TickerBatch>(a: &dyn Operator<T>, b: &dyn Operator<T>) -> Vec<Difference> {
+    let mut out = vec![];
+    visit(a, 0, b, 0, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::diff;
+    use crate::ops::from_str;
+    use arrow::record_batch::RecordBatch;
+
+    #[test]
+    fn differences() {
+        let opts = Default::default();
+        let changes = |a: &str, b: &str| {
+            let a = from_str::<RecordBatch>(a, &opts).unwrap();
+            let b = from_str::<RecordBatch>(b, &opts).unwrap();
+            diff(&*a, &*b)
+                .into_iter()
+                .map(|d| (d.kind, d.index, d.other))
+                .collect::<Vec<_>>()
+        };
+
+        assert!(changes("(+ :a 1)", "(+ :a 1)").is_empty());
+        assert_eq!(
+            changes("(+ (Mean 10 :a) (* 2 :b))", "(- (Mean 20 :a) (* 3 :c))"),
+            vec![
+                ("operator", 0, 0),
+                ("params", 1, 1),
+                ("constant", 4, 4),
+                ("column", 5, 5)
+            ]
+        );
+        assert_eq!(
+            changes("(+ :a :b)", "(+ (Abs :a) :b)"),
+            vec![("inserted", 1, 1)]
+        );
+        assert_eq!(
+            changes("(+ (Abs :a) :b)", "(+ :a :b)"),
+            vec![("removed", 1, 1)]
+        );
+        assert_eq!(
+            changes("(+ (Abs :a) :b)", "(+ (Neg :c) :d)"),
+            vec![("operator", 1, 1), ("column", 2, 2), ("column", 3, 3)]
+        );
+        assert_eq!(
+            changes("(+ :a :b)", "(+ (- :c :d) :b)"),
+            vec![("replaced", 1, 1)]
+        );
+    }
+}
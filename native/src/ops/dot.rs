@@ -0,0 +1,100 @@
+//! Render a factor as a Graphviz DOT graph, e.g. for the notebooks and the papers.
+
+use super::{
+    pretty::{parse, tokenize, Node},
+    BoxOp, Operator,
+};
+use crate::ticker_batch::TickerBatch;
+
+// The function name and the parameters which are not children, e.g. `Mean 10` for `(Mean 10 :close)`
+pub(super) fn label<T: TickerBatch>(op: &dyn Operator<T>, children: &[BoxOp<T>]) -> String {
+    let repr = op.to_string();
+    let tokens: Vec<_> = tokenize(&repr).into_iter().map(|(_, t)| t).collect();
+    let items = match parse(&mut tokens.into_iter()) {
+        Some(Node::List(items)) => items,
+        _ => return repr,
+    };
+
+    let mut children = children.iter().map(|child| child.to_string()).peekable();
+    let mut label = vec![];
+    for item in items {
+        let item = item.inline();
+        if children.peek() == Some(&item) {
+            children.next();
+        } else {
+            label.push(item);
+        }
+    }
+    label.join(" ")
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Write the node numbered `i` and its descendants
+fn visit<T: TickerBatch>(op: &dyn Operator<T>, i: usize, out: &mut Vec<String>) {
+    let indices = op.child_indices();
+    let children: Vec<_> = indices.iter().filter_map(|&c| op.get(c)).collect();
+
+    let shape = if children.is_empty() {
+        "ellipse"
+    } else {
+        "box"
+    };
+    out.push(format!(
+        "  n{} [label=\"{}\", shape={}];",
+        i,
+        escape(&label(op, &children)),
+        shape
+    ));
+    for (c, child) in indices.into_iter().zip(&children) {
+        out.push(format!("  n{} -> n{};", i, i + c));
+        visit(&**child, i + c, out);
+    }
+}
+
+/// Render the factor as a DOT graph. The nodes are named `n<i>` after their indices (see `Operator::get`),
+/// the functions are labelled with their names and constant parameters, and the leaves with the columns or constants.
+pub fn to_dot<T: TickerBatch>(op: &dyn Operator<T>) -> String {
+    let mut out = vec!["digraph factor {".to_string()];
+    visit(op, 0, &mut out);
+    out.push("}".to_string());
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::to_dot;
+    use crate::ops::from_str;
+    use arrow::record_batch::RecordBatch;
+
+    #[test]
+    fn render() {
+        let opts = Default::default();
+        let op =
+            from_str::<RecordBatch>("(+ (Mean 10 :close) (WSum 0.5 \"a b\" 2 3))", &opts).unwrap();
+        assert_eq!(
+            to_dot(&*op),
+            [
+                "digraph factor {",
+                "  n0 [label=\"+\", shape=box];",
+                "  n0 -> n1;",
+                "  n1 [label=\"Mean 10\", shape=box];",
+                "  n1 -> n2;",
+                "  n2 [label=\":close\", shape=ellipse];",
+                "  n0 -> n3;",
+                "  n3 [label=\"WSum 0.5 2\", shape=box];",
+                "  n3 -> n4;",
+                "  n4 [label=\"\\\"a b\\\"\", shape=ellipse];",
+                "  n3 -> n5;",
+                "  n5 [label=\"3\", shape=ellipse];",
+                "}",
+            ]
+            .join("\n")
+        );
+
+        let op = from_str::<RecordBatch>("(RowIndex)", &opts).unwrap();
+        assert!(to_dot(&*op).contains("n0 [label=\"RowIndex\", shape=ellipse];"));
+    }
+}
@@ -0,0 +1,377 @@
+//! The genetic programming operators, i.e. crossover and mutations, working on the factor trees directly.
+
+use super::{
+    optimize::head,
+    parser::from_str,
+    pretty::{parse, tokenize, Node},
+    random::{random, Rng},
+    signatures, BoxOp, Kind, Signature,
+};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use itertools::Itertools;
+use std::collections::HashMap;
+
+// Give up after this many draws produce no valid factor
+const MAX_ATTEMPTS: usize = 32;
+
+/// How a factor is mutated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mutation {
+    /// Swap a function for another one taking the same parameters, or a column for another column
+    Point,
+    /// Replace a subtree with a random one, no deeper than the original
+    Subtree,
+    /// Nudge a constant
+    Constant,
+    /// Shrink or grow a window
+    Window,
+}
+
+impl Mutation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Mutation::Point => "point",
+            Mutation::Subtree => "subtree",
+            Mutation::Constant => "constant",
+            Mutation::Window => "window",
+        }
+    }
+}
+
+impl std::str::FromStr for Mutation {
+    type Err = Error;
+
+    #[throws(Error)]
+    fn from_str(s: &str) -> Self {
+        match s {
+            "point" => Mutation::Point,
+            "subtree" => Mutation::Subtree,
+            "constant" => Mutation::Constant,
+            "window" => Mutation::Window,
+            _ => throw!(anyhow!(
+                "Unknown mutation '{}', expect one of {}",
+                s,
+                ["point", "subtree", "constant", "window"].iter().join(", ")
+            )),
+        }
+    }
+}
+
+// Replace the i-th subtree of `op`, the root included
+fn replace<T: TickerBatch>(op: &BoxOp<T>, i: usize, subtree: BoxOp<T>) -> BoxOp<T> {
+    if i == 0 {
+        return subtree;
+    }
+    let mut op = op.clone();
+    op.insert(i, subtree);
+    op
+}
+
+// Keep the candidate only if it differs from the original and is a well-formed factor,
+// e.g. a window operator is still where one is expected
+fn check<T: TickerBatch>(original: &BoxOp<T>, candidate: BoxOp<T>) -> Option<BoxOp<T>> {
+    let repr = candidate.to_string();
+    if repr == original.to_string() || from_str::<T>(&repr, &Default::default()).is_err() {
+        return None;
+    }
+    Some(candidate)
+}
+
+// The function at the root of `node`, and its parameters as written along with their kinds
+pub(super) fn split<'a, T: TickerBatch>(
+    node: &BoxOp<T>,
+    sigs: &'a [Signature],
+) -> Option<(&'a Signature, Vec<(String, Kind)>)> {
+    let tokens = tokenize(&node.to_string())
+        .into_iter()
+        .map(|(_, t)| t)
+        .collect_vec();
+    let items = match parse(&mut tokens.into_iter())? {
+        Node::List(items) => items,
+        Node::Atom(_) => return None,
+    };
+    let name = items.first()?.inline();
+    let sig = sigs.iter().find(|sig| sig.name == name)?;
+
+    let args = &items[1..];
+    let params = if !sig.variadic && args.len() < sig.params.len() {
+        // The optional parameters left out are the last ones, e.g. the sentinel of `(FFill 3 :x)`
+        let mut left_out = sig.params.len() - args.len();
+        let mut params = sig
+            .params
+            .iter()
+            .rev()
+            .filter(|p| {
+                let kept = !p.optional || left_out == 0;
+                left_out -= !kept as usize;
+                kept
+            })
+            .collect_vec();
+        params.reverse();
+        params
+    } else {
+        sig.params.iter().collect_vec()
+    };
+    if params.is_empty() {
+        return Some((sig, vec![]));
+    }
+    let args = args
+        .iter()
+        .enumerate()
+        .map(|(k, arg)| (arg.inline(), params[k % params.len()].kind))
+        .collect();
+    Some((sig, args))
+}
+
+// Whether the two functions can be called with the same parameters, up to the minimum of the integers
+fn same_shape(a: &Signature, b: &Signature) -> bool {
+    a.variadic == b.variadic
+        && a.params.len() == b.params.len()
+        && a.params.iter().zip(&b.params).all(|(p, q)| {
+            p.optional == q.optional
+                && match (p.kind, q.kind) {
+                    (Kind::Integer(_), Kind::Integer(_)) => true,
+                    (p, q) => p == q,
+                }
+        })
+}
+
+fn point<T: TickerBatch>(
+    node: &BoxOp<T>,
+    sigs: &[Signature],
+    columns: &[String],
+    rng: &mut Rng,
+) -> Option<BoxOp<T>> {
+    let repr = match split(node, sigs) {
+        Some((sig, args)) => {
+            let others = sigs
+                .iter()
+                .filter(|other| other.name != sig.name && same_shape(sig, other))
+                .collect_vec();
+            if others.is_empty() {
+                return None;
+            }
+            let other = others[rng.below(others.len())];
+            if args.is_empty() {
+                format!("({})", other.name)
+            } else {
+                format!("({} {})", other.name, args.iter().map(|(a, _)| a).join(" "))
+            }
+        }
+        None => {
+            let current = match &*node.columns() {
+                [col] if node.len() == 1 => col.clone(),
+                _ => return None,
+            };
+            let others = columns.iter().filter(|c| **c != current).collect_vec();
+            if others.is_empty() {
+                return None;
+            }
+            format!("{:?}", others[rng.below(others.len())])
+        }
+    };
+    from_str(&repr, &Default::default()).ok()
+}
+
+// Keep the nudged constants readable
+fn round(v: f64) -> f64 {
+    (v * 1e4).round() / 1e4
+}
+
+// Nudge one of the constants or the windows passed to the function at the root of `node`
+fn tweak<T: TickerBatch>(
+    node: &BoxOp<T>,
+    mutation: Mutation,
+    sigs: &[Signature],
+    rng: &mut Rng,
+) -> Option<BoxOp<T>> {
+    let (sig, mut args) = split(node, sigs)?;
+    let sites = args
+        .iter()
+        .enumerate()
+        .filter(|(_, (arg, kind))| match (mutation, kind) {
+            (Mutation::Window, Kind::Integer(_)) => true,
+            (Mutation::Constant, Kind::Constant | Kind::Fraction | Kind::Series) => {
+                arg.parse::<f64>().is_ok()
+            }
+            _ => false,
+        })
+        .map(|(k, _)| k)
+        .collect_vec();
+    if sites.is_empty() {
+        return None;
+    }
+
+    let k = sites[rng.below(sites.len())];
+    let v: f64 = args[k].0.parse().ok()?;
+    let u = 2. * rng.uniform() - 1.;
+    args[k].0 = match args[k].1 {
+        Kind::Integer(min) => {
+            // Move by up to half of the window, and at least by 1
+            let step = (v * 0.5 * u.abs()).round().max(1.);
+            let w = if u < 0. { v - step } else { v + step };
+            w.max(min as f64).to_string()
+        }
+        Kind::Fraction => round((v + 0.2 * u).max(0.).min(1.)).to_string(),
+        _ => round(v + v.abs().max(1.) * 0.2 * u).to_string(),
+    };
+
+    let repr = format!("({} {})", sig.name, args.iter().map(|(a, _)| a).join(" "));
+    from_str(&repr, &Default::default()).ok()
+}
+
+/// Graft a random subtree of `b` onto a random node of `a` other than its root. The result is reproducible given the seed.
+pub fn crossover<T: TickerBatch>(a: &BoxOp<T>, b: &BoxOp<T>, seed: u64) -> Result<BoxOp<T>> {
+    let mut rng = Rng::new(seed);
+    for _ in 0..MAX_ATTEMPTS {
+        let i = if a.len() > 1 {
+            1 + rng.below(a.len() - 1)
+        } else {
+            0
+        };
+        let j = rng.below(b.len());
+        let subtree = match b.get(j) {
+            Some(subtree) => subtree,
+            None => continue,
+        };
+        if let Some(child) = check(a, replace(a, i, subtree)) {
+            return Ok(child);
+        }
+    }
+    Err(anyhow!(
+        "no valid crossover between {} and {}",
+        a.to_string(),
+        b.to_string()
+    ))
+}
+
+/// Mutate a random node of `op`, see `Mutation`. The new columns are drawn from the ones already used.
+/// The result is reproducible given the seed.
+pub fn mutate<T: TickerBatch>(op: &BoxOp<T>, mutation: Mutation, seed: u64) -> Result<BoxOp<T>> {
+    let mut rng = Rng::new(seed);
+    let sigs = signatures::<T>();
+    let columns = op.columns().into_iter().unique().collect_vec();
+    for _ in 0..MAX_ATTEMPTS {
+        let i = rng.below(op.len());
+        let node = match op.get(i) {
+            Some(node) => node,
+            None => continue,
+        };
+        let mutated = match mutation {
+            Mutation::Point => point(&node, &sigs, &columns, &mut rng),
+            Mutation::Subtree => {
+                let depth = node.depth().max(2);
+                random(&columns, depth, rng.next_u64(), &HashMap::new()).ok()
+            }
+            Mutation::Constant | Mutation::Window => tweak(&node, mutation, &sigs, &mut rng),
+        };
+        if let Some(mutant) = mutated.and_then(|m| check(op, replace(op, i, m))) {
+            return Ok(mutant);
+        }
+    }
+    Err(anyhow!(
+        "no {} mutation applies to {}",
+        mutation.as_str(),
+        op.to_string()
+    ))
+}
+
+/// Remove the i-th subtree of `op` and return it. The hole is filled with `replacement`, or else with the
+/// neutral element for the parent, i.e. 1 under `*`, `/`, `^` and `And`, 0 otherwise.
+pub fn prune<T: TickerBatch>(
+    op: &mut BoxOp<T>,
+    i: usize,
+    replacement: Option<BoxOp<T>>,
+) -> Result<BoxOp<T>> {
+    let removed = op.get(i).ok_or_else(|| anyhow!("idx {} overflows", i))?;
+    let replacement = match replacement {
+        Some(replacement) => replacement,
+        None if i == 0 => return Err(anyhow!("the root can only be pruned with a replacement")),
+        None => {
+            let parent = (0..i)
+                .rev()
+                .filter_map(|j| op.get(j).map(|node| (j, node)))
+                .find(|(j, node)| node.child_indices().iter().any(|c| j + c == i))
+                .map(|(_, node)| node);
+            let neutral = match parent.and_then(|node| head(&*node)).as_deref() {
+                Some("*") | Some("/") | Some("^") | Some("And") => 1.,
+                _ => 0.,
+            };
+            Box::new(neutral) as BoxOp<T>
+        }
+    };
+
+    let pruned = replace(op, i, replacement);
+    if let Err(e) = from_str::<T>(&pruned.to_string(), &Default::default()) {
+        return Err(anyhow!("cannot prune {} at {}: {}", op.to_string(), i, e));
+    }
+    *op = pruned;
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{crossover, mutate, prune, Mutation};
+    use crate::ops::from_str;
+    use arrow::record_batch::RecordBatch;
+
+    #[test]
+    fn evolution() {
+        let opts = Default::default();
+        let a = from_str::<RecordBatch>("(+ (Mean 10 :a) (* 2 :b))", &opts).unwrap();
+        let b = from_str::<RecordBatch>("(Std 20 (- :c :d))", &opts).unwrap();
+
+        for seed in 0..50 {
+            let child = crossover(&a, &b, seed).unwrap();
+            assert!(
+                child.to_string().starts_with("(+ "),
+                "{}",
+                child.to_string()
+            );
+            assert_ne!(child.to_string(), a.to_string());
+            assert_eq!(
+                child.to_string(),
+                crossover(&a, &b, seed).unwrap().to_string()
+            );
+
+            for mutation in [
+                Mutation::Point,
+                Mutation::Subtree,
+                Mutation::Constant,
+                Mutation::Window,
+            ] {
+                let mutant = mutate(&a, mutation, seed).unwrap().to_string();
+                assert_ne!(mutant, a.to_string());
+                assert!(from_str::<RecordBatch>(&mutant, &opts).is_ok());
+                assert_eq!(mutant, mutate(&a, mutation, seed).unwrap().to_string());
+            }
+        }
+
+        let mutant = mutate(&a, Mutation::Window, 0).unwrap().to_string();
+        assert!(mutant.starts_with("(+ (Mean ") && mutant.ends_with(":a) (* 2 :b))"));
+        let mutant = mutate(&a, Mutation::Constant, 0).unwrap().to_string();
+        assert!(mutant.starts_with("(+ (Mean 10 :a) (* ") && !mutant.contains("(* 2 "));
+
+        let x = from_str::<RecordBatch>(":a", &opts).unwrap();
+        assert!(mutate(&x, Mutation::Window, 0).is_err());
+        assert!(mutate(&x, Mutation::Point, 0).is_err());
+        assert!("crossover".parse::<Mutation>().is_err());
+
+        let mut op = a.clone();
+        let removed = prune(&mut op, 1, None).unwrap();
+        assert_eq!(removed.to_string(), "(Mean 10 :a)");
+        assert_eq!(op.to_string(), "(+ 0 (* 2 :b))");
+        prune(&mut op, 4, None).unwrap();
+        assert_eq!(op.to_string(), "(+ 0 (* 2 1))");
+        prune(&mut op, 2, Some(b.clone())).unwrap();
+        assert_eq!(op.to_string(), "(+ 0 (Std 20 (- :c :d)))");
+        assert!(prune(&mut op, 0, None).is_err());
+        assert!(prune(&mut op, 9, None).is_err());
+
+        let mut op = from_str::<RecordBatch>("(SessionWindow :s (Mean 10 :a))", &opts).unwrap();
+        assert!(prune(&mut op, 2, None).is_err());
+        assert_eq!(op.to_string(), "(SessionWindow :s (Mean 10 :a))");
+    }
+}
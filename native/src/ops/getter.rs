@@ -1,58 +1,132 @@
-use super::{BoxOp, Named, Operator};
-use crate::ticker_batch::TickerBatch;
-use anyhow::{anyhow, Error};
+use super::{constant::fmt_constant, parser::Parameter, BoxOp, Named, Operator};
+use crate::ticker_batch::{Lookup, TickerBatch};
+use anyhow::{anyhow, Error, Result};
 use fehler::{throw, throws};
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashMap, iter::FromIterator};
 
-#[derive(Clone)]
 pub struct Getter {
     name: String,
-    idx: Option<usize>,
+    nullable: bool,     // the NaNs are let through, see `Operator::allow_nan`
+    idx: Option<usize>, // resolved against the first batch seen
+}
+
+impl Clone for Getter {
+    fn clone(&self) -> Self {
+        Self {
+            nullable: self.nullable,
+            ..Self::new(&self.name)
+        }
+    }
 }
 
 impl Getter {
     pub fn new(name: &str) -> Self {
         Self {
             name: name.to_string(),
+            nullable: false,
             idx: None,
         }
     }
 }
 
+// A NaN is only taken from a nullable column, an infinity never
+#[throws(Error)]
+fn check_values<T: TickerBatch>(op: &dyn Operator<T>, col: &[f64], nullable: bool) {
+    for &v in col {
+        if !(nullable && v.is_nan()) {
+            op.fchecked(v)?;
+        }
+    }
+}
+
+// The new name of a column, a qualified name like `bid#2` is renamed after its base name
+fn remap(name: &str, mapping: &HashMap<String, String>) -> String {
+    if let Some(new) = mapping.get(name) {
+        return new.clone();
+    }
+    match name.rsplit_once('#') {
+        Some((base, k)) if k.parse::<usize>().is_ok() && mapping.contains_key(base) => {
+            format!("{}#{}", mapping[base], k)
+        }
+        _ => name.to_string(),
+    }
+}
+
+/// Replace each read of `column` by `by`, e.g. to run a factor on the smoothed mid price instead of the raw one.
+/// The defaults of `col-or` are kept as they are.
+pub fn substitute<T: TickerBatch>(op: BoxOp<T>, column: &str, by: &BoxOp<T>) -> Result<BoxOp<T>> {
+    if !op.columns().iter().any(|c| c == column) {
+        return Err(anyhow!(
+            "{} does not read the column {}",
+            op.to_string(),
+            column
+        ));
+    }
+    Ok(replace_column(op, column, by))
+}
+
+fn replace_column<T: TickerBatch>(mut op: BoxOp<T>, column: &str, by: &BoxOp<T>) -> BoxOp<T> {
+    if op.len() == 1 && op.columns() == [column] {
+        return by.clone();
+    }
+    // backwards, so that the indices of the children to visit stay valid as the subtrees change sizes
+    for i in op.child_indices().into_iter().rev() {
+        if let Some(child) = op.get(i) {
+            op.insert(i, replace_column(child, column, by));
+        }
+    }
+    op
+}
+
 impl Named for Getter {
     const NAME: &'static str = "Getter";
 }
 
 impl<T: TickerBatch> Operator<T> for Getter {
-    fn reset(&mut self) {}
+    fn reset(&mut self) {
+        // the next input might come with a different schema
+        self.idx = None;
+    }
 
     #[throws(Error)]
     fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
         if matches!(self.idx, None) {
-            self.idx = Some(
-                tb.index_of(&self.name)
-                    .ok_or_else(|| anyhow!("No such colume {}", self.name))?,
-            );
+            self.idx = Some(tb.index_of(&self.name)?);
         }
         let colid = self.idx.unwrap();
 
         let col = tb
             .values(colid)
-            .ok_or_else(|| anyhow!("No such colume {}", self.name))?;
+            .ok_or_else(|| anyhow!("Column {} has an unsupported type", self.name))?;
 
-        for &v in col {
-            Operator::<T>::fchecked(self, v)?;
-        }
+        check_values::<T>(self, &col, self.nullable)?;
 
-        col.into()
+        col
     }
 
     fn ready_offset(&self) -> usize {
         0
     }
 
+    fn is_nullable(&self) -> bool {
+        self.nullable
+    }
+
+    fn allow_nan(&mut self) {
+        self.nullable = true;
+    }
+
+    // Names that do not fit in a symbol are quoted, e.g. `"bid price.1"`
     fn to_string(&self) -> String {
-        format!(":{}", self.name)
+        let plain = self
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '#');
+        if plain && !self.name.is_empty() {
+            format!(":{}", self.name)
+        } else {
+            format!("{:?}", self.name)
+        }
     }
 
     fn depth(&self) -> usize {
@@ -71,6 +145,129 @@ impl<T: TickerBatch> Operator<T> for Getter {
         vec![self.name.clone()]
     }
 
+    fn remap_columns(&mut self, mapping: &HashMap<String, String>) {
+        self.name = remap(&self.name, mapping);
+        self.idx = None;
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i != 0 {
+            throw!()
+        }
+        self.clone().boxed()
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, _: usize, _: BoxOp<T>) -> BoxOp<T> {
+        unreachable!("cannot insert root");
+    }
+}
+
+/// Reads a column like `Getter`, but yields `default` if the column is absent from the input,
+/// so that one factor can run across datasets with slightly different schemas.
+pub struct GetterOr {
+    name: String,
+    default: f64,
+    nullable: bool,
+    idx: Option<Option<usize>>, // resolved against the first batch seen, None for absent
+}
+
+impl Clone for GetterOr {
+    fn clone(&self) -> Self {
+        Self {
+            nullable: self.nullable,
+            ..Self::new(&self.name, self.default)
+        }
+    }
+}
+
+impl GetterOr {
+    pub fn new(name: &str, default: f64) -> Self {
+        Self {
+            name: name.to_string(),
+            default,
+            nullable: false,
+            idx: None,
+        }
+    }
+}
+
+impl Named for GetterOr {
+    const NAME: &'static str = "col-or";
+}
+
+impl<T: TickerBatch> Operator<T> for GetterOr {
+    fn reset(&mut self) {
+        self.idx = None;
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        if matches!(self.idx, None) {
+            self.idx = Some(match tb.lookup(&self.name) {
+                Lookup::Missing => None,
+                _ => Some(tb.index_of(&self.name)?),
+            });
+        }
+
+        let colid = match self.idx.unwrap() {
+            Some(colid) => colid,
+            None => return vec![self.default; tb.len()].into(),
+        };
+
+        let col = tb
+            .values(colid)
+            .ok_or_else(|| anyhow!("Column {} has an unsupported type", self.name))?;
+
+        check_values::<T>(self, &col, self.nullable)?;
+
+        col
+    }
+
+    fn ready_offset(&self) -> usize {
+        0
+    }
+
+    fn is_nullable(&self) -> bool {
+        self.nullable
+    }
+
+    fn allow_nan(&mut self) {
+        self.nullable = true;
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "({} {:?} {})",
+            Self::NAME,
+            self.name,
+            fmt_constant(self.default)
+        )
+    }
+
+    fn depth(&self) -> usize {
+        1
+    }
+
+    fn len(&self) -> usize {
+        1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        vec![]
+    }
+
+    // The column is optional, hence not required from the input
+    fn columns(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn remap_columns(&mut self, mapping: &HashMap<String, String>) {
+        self.name = remap(&self.name, mapping);
+        self.idx = None;
+    }
+
     #[throws(as Option)]
     fn get(&self, i: usize) -> BoxOp<T> {
         if i != 0 {
@@ -84,3 +281,88 @@ impl<T: TickerBatch> Operator<T> for Getter {
         unreachable!("cannot insert root");
     }
 }
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<GetterOr> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> GetterOr {
+        let mut params: Vec<_> = iter.into_iter().collect();
+        if params.len() != 2 {
+            throw!(anyhow!(
+                "{} expect a column name and a constant, got {:?}",
+                GetterOr::NAME,
+                params
+            ))
+        }
+
+        let k1 = params.remove(0);
+        let k2 = params.remove(0);
+        match (k1, k2) {
+            (Parameter::String(name), Parameter::Constant(default)) => {
+                GetterOr::new(&name, default)
+            }
+            (a, b) => throw!(anyhow!(
+                "{name} expect a column name and a constant, got ({name} {} {})",
+                a,
+                b,
+                name = GetterOr::NAME,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::substitute;
+    use crate::ops::from_str;
+    use arrow::record_batch::RecordBatch;
+    use std::collections::HashMap;
+
+    #[test]
+    fn remap() {
+        let mut op = from_str::<RecordBatch>(
+            "(+ (Mean 10 :bid1) (* :bid1#2 (col-or \"ask1\" 0)))",
+            &Default::default(),
+        )
+        .unwrap();
+        let mapping: HashMap<_, _> = [("bid1", "bid_price"), ("ask1", "ask_price")]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        op.remap_columns(&mapping);
+        assert_eq!(
+            op.to_string(),
+            "(+ (Mean 10 :bid_price) (* :bid_price#2 (col-or \"ask_price\" 0)))"
+        );
+    }
+
+    #[test]
+    fn substitution() {
+        let opts = Default::default();
+        let op = from_str::<RecordBatch>("(/ (- :mid (Mean 10 :mid)) (col-or \"mid\" 1))", &opts)
+            .unwrap();
+        let by = from_str::<RecordBatch>("(Mean 3 (/ (+ :bid :ask) 2))", &opts).unwrap();
+        assert_eq!(
+            substitute(op.clone(), "mid", &by).unwrap().to_string(),
+            "(/ (- (Mean 3 (/ (+ :bid :ask) 2)) (Mean 10 (Mean 3 (/ (+ :bid :ask) 2)))) (col-or \"mid\" 1))"
+        );
+        assert_eq!(substitute(by.clone(), "bid", &by).unwrap().len(), 11);
+        assert!(substitute(op, "close", &by).is_err());
+    }
+
+    #[test]
+    fn quoted() {
+        let opts = Default::default();
+        for repr in [
+            "(+ \"bid price.1\" (Mean 10 \"收盘价\"))",
+            "(col-or \"bid price\" 0)",
+            "\"bid price\"",
+        ] {
+            let op = from_str::<RecordBatch>(repr, &opts).unwrap();
+            assert_eq!(op.to_string(), repr);
+        }
+
+        let op = from_str::<RecordBatch>("(Mean 10 \"bid_price\")", &opts).unwrap();
+        assert_eq!(op.to_string(), "(Mean 10 :bid_price)");
+        assert_eq!(op.columns(), vec!["bid_price"]);
+    }
+}
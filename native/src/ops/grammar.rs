@@ -0,0 +1,524 @@
+//! The signatures of the built-in functions, so that the factors can be built programmatically,
+//! e.g. by a genetic programming framework.
+
+use super::*;
+use crate::ticker_batch::TickerBatch;
+
+/// What a parameter takes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    /// Any constant
+    Constant,
+    /// An integer constant no less than the bound, e.g. a window size
+    Integer(usize),
+    /// A constant in `[0, 1]`, e.g. a quantile
+    Fraction,
+    /// An expression
+    Series,
+    /// An expression rooted at a window function, see `Operator::evict`
+    Window,
+    /// A list of constants, e.g. `(0.5 0.3 0.2)`
+    Vector,
+    /// A list of columns, e.g. `(Cols :bid1 :bid2)`
+    Columns,
+    /// A column name in a string, e.g. `"imbalance"`
+    Name,
+    /// A duration in a string, e.g. `"500ms"`
+    Duration,
+}
+
+impl Kind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Constant => "constant",
+            Kind::Integer(_) => "integer",
+            Kind::Fraction => "fraction",
+            Kind::Series => "series",
+            Kind::Window => "window",
+            Kind::Vector => "vector",
+            Kind::Columns => "columns",
+            Kind::Name => "name",
+            Kind::Duration => "duration",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Param {
+    pub name: &'static str,
+    pub kind: Kind,
+    pub optional: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Signature {
+    pub name: &'static str,
+    pub category: &'static str,
+    pub params: Vec<Param>,
+    /// The parameters repeat, e.g. the weights and the series of `WSum`
+    pub variadic: bool,
+    /// The function can only be the root of a factor, see `Operator::is_root_only`
+    pub root_only: bool,
+}
+
+impl Signature {
+    fn new(name: &'static str, category: &'static str, params: &[(&'static str, Kind)]) -> Self {
+        Signature {
+            name,
+            category,
+            params: params
+                .iter()
+                .map(|&(name, kind)| Param {
+                    name,
+                    kind,
+                    optional: false,
+                })
+                .collect(),
+            variadic: false,
+            root_only: false,
+        }
+    }
+
+    // A window function, with the optional `<min_periods>` right after the window size
+    fn window(
+        name: &'static str,
+        win_size: usize,
+        min_periods: usize,
+        params: &[(&'static str, Kind)],
+    ) -> Self {
+        let mut sig = Signature::new(name, "window", params);
+        sig.params.insert(
+            0,
+            Param {
+                name: "win_size",
+                kind: Kind::Integer(win_size),
+                optional: false,
+            },
+        );
+        sig.params.insert(
+            1,
+            Param {
+                name: "min_periods",
+                kind: Kind::Integer(min_periods),
+                optional: true,
+            },
+        );
+        sig
+    }
+
+    // The parameters which can be left out for their defaults, e.g. the periods of `UltOsc`
+    fn optional(mut self, names: &[&str]) -> Self {
+        for param in &mut self.params {
+            param.optional |= names.contains(&param.name);
+        }
+        self
+    }
+
+    fn variadic(mut self) -> Self {
+        self.variadic = true;
+        self
+    }
+
+    fn root_only(mut self) -> Self {
+        self.root_only = true;
+        self
+    }
+
+    /// The number of parameters, without the optional ones. Variadic functions take a multiple of it.
+    pub fn arity(&self) -> usize {
+        self.params.iter().filter(|p| !p.optional).count()
+    }
+}
+
+/// The signatures of all the built-in functions, under their canonical names.
+pub fn signatures<T: TickerBatch>() -> Vec<Signature> {
+    use Kind::*;
+
+    let x = ("x", Series);
+    let (l, r) = (("l", Series), ("r", Series));
+    let (d, t) = (("duration", Duration), ("time", Series));
+
+    vec![
+        // arithmetics
+        Signature::new(Add::<T>::NAME, "arithmetic", &[l, r]),
+        Signature::new(Sub::<T>::NAME, "arithmetic", &[l, r]),
+        Signature::new(Mul::<T>::NAME, "arithmetic", &[l, r]),
+        Signature::new(Div::<T>::NAME, "arithmetic", &[l, r]),
+        Signature::new(Pow::<T>::NAME, "arithmetic", &[("p", Constant), x]),
+        Signature::new(Neg::<T>::NAME, "arithmetic", &[x]),
+        Signature::new(SignedPow::<T>::NAME, "arithmetic", &[("p", Constant), x]),
+        Signature::new(
+            Clip::<T>::NAME,
+            "arithmetic",
+            &[("lo", Constant), ("hi", Constant), x],
+        ),
+        Signature::new(Clip::<T>::LOWER, "arithmetic", &[("lo", Constant), x]),
+        Signature::new(Clip::<T>::UPPER, "arithmetic", &[("hi", Constant), x]),
+        Signature::new(LogAbs::<T>::NAME, "arithmetic", &[x]),
+        Signature::new(Sign::<T>::NAME, "arithmetic", &[x]),
+        Signature::new(Abs::<T>::NAME, "arithmetic", &[x]),
+        Signature::new(Exp::<T>::NAME, "arithmetic", &[x]),
+        Signature::new(Sqrt::<T>::NAME, "arithmetic", &[x]),
+        Signature::new(Cbrt::<T>::NAME, "arithmetic", &[x]),
+        Signature::new(WSum::<T>::NAME, "arithmetic", &[("w", Constant), x]).variadic(),
+        Signature::new(Dot::NAME, "arithmetic", &[("w", Vector), ("cols", Columns)]),
+        // getters
+        Signature::new(
+            GetterOr::NAME,
+            "column",
+            &[("name", Name), ("default", Constant)],
+        ),
+        // ticks
+        Signature::new(RowIndex::NAME, "tick", &[]),
+        Signature::new(TimeDelta::<T>::NAME, "tick", &[x]),
+        // noises
+        Signature::new(
+            Noise::NAME,
+            "noise",
+            &[("seed", Integer(0)), ("scale", Constant)],
+        ),
+        Signature::new(
+            Jitter::<T>::NAME,
+            "noise",
+            &[("seed", Integer(0)), ("scale", Constant), x],
+        ),
+        // sessions
+        Signature::new(
+            SessionReset::<T>::NAME,
+            "session",
+            &[("session", Series), x],
+        )
+        .root_only(),
+        Signature::new(
+            SessionWindow::<T>::NAME,
+            "session",
+            &[("session", Series), ("x", Window)],
+        )
+        .root_only(),
+        Signature::new(
+            ResetDaily::<T>::NAME,
+            "session",
+            &[("offset", Constant), ("time", Series), x],
+        )
+        .optional(&["offset"])
+        .root_only(),
+        Signature::new(ResetOn::<T>::NAME, "session", &[("cond", Series), x]).root_only(),
+        // logics
+        Signature::new(
+            If::<T>::NAME,
+            "logic",
+            &[("cond", Series), ("then", Series), ("else", Series)],
+        ),
+        Signature::new(And::<T>::NAME, "logic", &[l, r]),
+        Signature::new(Or::<T>::NAME, "logic", &[l, r]),
+        Signature::new(Lt::<T>::NAME, "logic", &[l, r]),
+        Signature::new(Lte::<T>::NAME, "logic", &[l, r]),
+        Signature::new(Gt::<T>::NAME, "logic", &[l, r]),
+        Signature::new(Gte::<T>::NAME, "logic", &[l, r]),
+        Signature::new(Eq::<T>::NAME, "logic", &[l, r]),
+        Signature::new(Not::<T>::NAME, "logic", &[x]),
+        // windows
+        Signature::window(Sum::<T>::NAME, 1, 1, &[x]),
+        Signature::window(Mean::<T>::NAME, 1, 1, &[x]),
+        Signature::window(Product::<T>::NAME, 1, 1, &[x]),
+        Signature::window(CountIf::<T>::NAME, 1, 1, &[("cond", Series)]),
+        Signature::window(Any::<T>::NAME, 1, 1, &[("cond", Series)]),
+        Signature::window(All::<T>::NAME, 1, 1, &[("cond", Series)]),
+        Signature::window(
+            Correlation::<T>::NAME,
+            1,
+            1,
+            &[("x", Series), ("y", Series)],
+        ),
+        Signature::window(AutoCorr::<T>::NAME, 1, 1, &[("lag", Integer(1)), x]),
+        Signature::window(Beta::<T>::NAME, 2, 2, &[("y", Series), ("x", Series)]),
+        Signature::window(Alpha::<T>::NAME, 2, 2, &[("y", Series), ("x", Series)]),
+        Signature::window(Resid::<T>::NAME, 2, 2, &[("y", Series), ("x", Series)]),
+        Signature::window(Min::<T>::NAME, 1, 1, &[x]),
+        Signature::window(Max::<T>::NAME, 1, 1, &[x]),
+        Signature::window(ArgMin::<T>::NAME, 1, 1, &[x]),
+        Signature::window(ArgMax::<T>::NAME, 1, 1, &[x]),
+        Signature::window(Drawdown::<T>::NAME, 1, 1, &[x]),
+        Signature::window(Slope::<T>::NAME, 2, 2, &[x]),
+        Signature::window(TrendR2::<T>::NAME, 2, 2, &[x]),
+        Signature::window(TheilSen::<T>::NAME, 2, 2, &[x]),
+        Signature::new(Hurst::<T>::NAME, "window", &[("win_size", Integer(16)), x]),
+        Signature::window(Stdev::<T>::NAME, 2, 2, &[x]),
+        Signature::window(Skew::<T>::NAME, 3, 3, &[x]),
+        Signature::window(Kurt::<T>::NAME, 4, 4, &[x]),
+        Signature::new(Delay::<T>::NAME, "window", &[("win_size", Integer(0)), x]),
+        Signature::window(First::<T>::NAME, 1, 1, &[x]),
+        Signature::new(
+            FFill::<T>::NAME,
+            "window",
+            &[("max_gap", Integer(1)), ("sentinel", Constant), x],
+        )
+        .optional(&["max_gap", "sentinel"]),
+        Signature::new(SumT::<T>::NAME, "window", &[d, t, x]),
+        Signature::new(MeanT::<T>::NAME, "window", &[d, t, x]),
+        Signature::new(StdT::<T>::NAME, "window", &[d, t, x]),
+        Signature::new(MinT::<T>::NAME, "window", &[d, t, x]),
+        Signature::new(MaxT::<T>::NAME, "window", &[d, t, x]),
+        Signature::new(CountT::<T>::NAME, "window", &[d, t, x]),
+        Signature::window(Rank::<T>::NAME, 1, 1, &[x]),
+        Signature::window(Percentile::<T>::NAME, 2, 2, &[x]),
+        Signature::window(Median::<T>::NAME, 1, 1, &[x]),
+        Signature::window(Quantile::<T>::NAME, 1, 1, &[("q", Fraction), x]),
+        Signature::window(
+            Winsorize::<T>::NAME,
+            1,
+            1,
+            &[("lower", Fraction), ("upper", Fraction), x],
+        ),
+        Signature::new(
+            QuantileApprox::<T>::NAME,
+            "window",
+            &[("q", Fraction), ("k", Integer(2)), x],
+        ),
+        Signature::new(RankApprox::<T>::NAME, "window", &[("k", Integer(2)), x]),
+        Signature::new(
+            LogReturn::<T>::NAME,
+            "window",
+            &[("win_size", Integer(0)), x],
+        ),
+        Signature::new(ROC::<T>::NAME, "window", &[("win_size", Integer(1)), x]),
+        Signature::new(
+            PctChange::<T>::NAME,
+            "window",
+            &[("win_size", Integer(1)), x],
+        ),
+        Signature::new(
+            Momentum::<T>::NAME,
+            "window",
+            &[("win_size", Integer(1)), x],
+        ),
+        Signature::new(RSI::<T>::NAME, "window", &[("win_size", Integer(1)), x]),
+        Signature::new(
+            OBV::<T>::NAME,
+            "window",
+            &[("price", Series), ("volume", Series)],
+        ),
+        Signature::new(CumSum::<T>::NAME, "window", &[x]),
+        Signature::new(CumMean::<T>::NAME, "window", &[x]),
+        Signature::new(CumMax::<T>::NAME, "window", &[x]),
+        Signature::new(CumMin::<T>::NAME, "window", &[x]),
+        Signature::window(
+            VWAP::<T>::NAME,
+            1,
+            1,
+            &[("price", Series), ("volume", Series)],
+        ),
+        Signature::window(
+            TWAP::<T>::NAME,
+            1,
+            1,
+            &[("time", Series), ("price", Series)],
+        ),
+        Signature::new(Conv::<T>::NAME, "window", &[("weights", Vector), x]),
+        Signature::new(EMA::<T>::NAME, "window", &[("span", Integer(1)), x]),
+        Signature::new(EWMStd::<T>::NAME, "window", &[("span", Integer(1)), x]),
+        Signature::new(
+            EWMCov::<T>::NAME,
+            "window",
+            &[("span", Integer(1)), ("x", Series), ("y", Series)],
+        ),
+        Signature::new(
+            EWMCorr::<T>::NAME,
+            "window",
+            &[("span", Integer(1)), ("x", Series), ("y", Series)],
+        ),
+        // overlap studies
+        Signature::new(SMA::<T>::NAME, "overlap_study", &[("n", Integer(1)), x]),
+        Signature::new(WMA::<T>::NAME, "overlap_study", &[("n", Integer(1)), x]),
+        Signature::new(DEMA::<T>::NAME, "overlap_study", &[("n", Integer(1)), x]),
+        Signature::new(TEMA::<T>::NAME, "overlap_study", &[("n", Integer(1)), x]),
+        Signature::new(
+            Tenkan::<T>::NAME,
+            "overlap_study",
+            &[("win_size", Integer(1)), ("high", Series), ("low", Series)],
+        ),
+        Signature::new(
+            Kijun::<T>::NAME,
+            "overlap_study",
+            &[("win_size", Integer(1)), ("high", Series), ("low", Series)],
+        ),
+        Signature::new(
+            SenkouA::<T>::NAME,
+            "overlap_study",
+            &[
+                ("fast", Integer(1)),
+                ("slow", Integer(1)),
+                ("shift", Integer(1)),
+                ("high", Series),
+                ("low", Series),
+            ],
+        ),
+        Signature::new(
+            SenkouB::<T>::NAME,
+            "overlap_study",
+            &[
+                ("win_size", Integer(1)),
+                ("shift", Integer(1)),
+                ("high", Series),
+                ("low", Series),
+            ],
+        ),
+        // momentum indicators
+        Signature::new(
+            MACD::<T>::NAME,
+            "momentum_indicator",
+            &[
+                ("fast", Integer(1)),
+                ("slow", Integer(1)),
+                ("signal", Integer(1)),
+                x,
+            ],
+        ),
+        Signature::new(
+            MACDSignal::<T>::NAME,
+            "momentum_indicator",
+            &[
+                ("fast", Integer(1)),
+                ("slow", Integer(1)),
+                ("signal", Integer(1)),
+                x,
+            ],
+        ),
+        Signature::new(
+            MACDHist::<T>::NAME,
+            "momentum_indicator",
+            &[
+                ("fast", Integer(1)),
+                ("slow", Integer(1)),
+                ("signal", Integer(1)),
+                x,
+            ],
+        ),
+        Signature::new(
+            StochK::<T>::NAME,
+            "momentum_indicator",
+            &[
+                ("win_size", Integer(1)),
+                ("smooth_k", Integer(1)),
+                ("high", Series),
+                ("low", Series),
+                ("close", Series),
+            ],
+        ),
+        Signature::new(
+            StochD::<T>::NAME,
+            "momentum_indicator",
+            &[
+                ("win_size", Integer(1)),
+                ("smooth_k", Integer(1)),
+                ("smooth_d", Integer(1)),
+                ("high", Series),
+                ("low", Series),
+                ("close", Series),
+            ],
+        ),
+        Signature::new(
+            CCI::<T>::NAME,
+            "momentum_indicator",
+            &[
+                ("win_size", Integer(1)),
+                ("high", Series),
+                ("low", Series),
+                ("close", Series),
+            ],
+        ),
+        Signature::new(
+            UltOsc::<T>::NAME,
+            "momentum_indicator",
+            &[
+                ("fast", Integer(1)),
+                ("medium", Integer(1)),
+                ("slow", Integer(1)),
+                ("high", Series),
+                ("low", Series),
+                ("close", Series),
+            ],
+        )
+        .optional(&["fast", "medium", "slow"]),
+        Signature::new(
+            AroonUp::<T>::NAME,
+            "momentum_indicator",
+            &[("win_size", Integer(1)), ("high", Series), ("low", Series)],
+        ),
+        Signature::new(
+            AroonDown::<T>::NAME,
+            "momentum_indicator",
+            &[("win_size", Integer(1)), ("high", Series), ("low", Series)],
+        ),
+        Signature::new(
+            AroonOsc::<T>::NAME,
+            "momentum_indicator",
+            &[("win_size", Integer(1)), ("high", Series), ("low", Series)],
+        ),
+        Signature::new(
+            MFI::<T>::NAME,
+            "momentum_indicator",
+            &[
+                ("win_size", Integer(1)),
+                ("high", Series),
+                ("low", Series),
+                ("close", Series),
+                ("volume", Series),
+            ],
+        ),
+        // volatility indicators
+        Signature::new(
+            TrueRange::<T>::NAME,
+            "volatility_indicator",
+            &[("high", Series), ("low", Series), ("close", Series)],
+        ),
+        Signature::new(
+            ATR::<T>::NAME,
+            "volatility_indicator",
+            &[
+                ("win_size", Integer(1)),
+                ("high", Series),
+                ("low", Series),
+                ("close", Series),
+            ],
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::{signatures, Kind};
+    use crate::ops::from_str;
+    use arrow::record_batch::RecordBatch;
+    use itertools::Itertools;
+
+    // Every signature, filled with the simplest parameters, parses
+    #[test]
+    fn signatures_parse() {
+        let opts = Default::default();
+        for sig in signatures::<RecordBatch>() {
+            let params = sig
+                .params
+                .iter()
+                .filter(|p| !p.optional)
+                .map(|p| match p.kind {
+                    Kind::Constant => "0.5".to_string(),
+                    Kind::Integer(min) => format!("{}", min.max(3)),
+                    Kind::Fraction => "0.5".to_string(),
+                    Kind::Series => ":a".to_string(),
+                    Kind::Window => "(Mean 3 :a)".to_string(),
+                    Kind::Vector => "(0.5 0.5)".to_string(),
+                    Kind::Columns => "(Cols :a :b)".to_string(),
+                    Kind::Name => "\"a\"".to_string(),
+                    Kind::Duration => "\"5s\"".to_string(),
+                })
+                .collect_vec();
+            let repr = if params.is_empty() {
+                format!("({})", sig.name)
+            } else {
+                format!("({} {})", sig.name, params.join(" "))
+            };
+            assert!(from_str::<RecordBatch>(&repr, &opts).is_ok(), "{}", repr);
+        }
+    }
+}
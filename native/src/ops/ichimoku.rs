@@ -0,0 +1,267 @@
+//! The lines of the Ichimoku cloud, the midpoints of the high and low over windows of different sizes.
+
+use std::{borrow::Cow, collections::VecDeque, iter::FromIterator, mem};
+
+use anyhow::{Error, Result};
+use fehler::throws;
+use itertools::Itertools;
+
+use crate::ticker_batch::TickerBatch;
+
+use super::{
+    inputs::{split_bars, Inputs},
+    parser::Parameter,
+    BoxOp, MonotonicQueue, Named, Operator,
+};
+
+/// The midpoint of the highest high and the lowest low of the last `win_size` bars, NaN until there are as many.
+struct Midpoint {
+    win_size: usize,
+    highest: MonotonicQueue,
+    lowest: MonotonicQueue,
+}
+
+impl Midpoint {
+    fn new(win_size: usize) -> Self {
+        Self {
+            win_size,
+            highest: MonotonicQueue::max(win_size),
+            lowest: MonotonicQueue::min(win_size),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.highest.clear();
+        self.lowest.clear();
+    }
+
+    fn push(&mut self, high: f64, low: f64) -> f64 {
+        self.highest.push(high);
+        self.lowest.push(low);
+        if self.highest.seq() < self.win_size {
+            return f64::NAN;
+        }
+        (self.highest.front().unwrap().1 + self.lowest.front().unwrap().1) / 2.
+    }
+}
+
+// The Ichimoku lines over the midpoints of `$n` windows. When `$shifted`, the last period is not a window
+// but the number of bars the line is displaced forward, i.e. the line at a bar is the value `shift` bars before.
+macro_rules! impl_ichimoku {
+    ($($(#[$doc:meta])* $op:ident [$n:literal] ($($param:ident),+) $shifted:literal |$mids:ident| $line:expr;)+) => {
+        $(
+            $(#[$doc])*
+            pub struct $op<T> {
+                periods: [usize; $n],
+                inputs: Inputs<T, 2>,
+
+                mids: Vec<Midpoint>,
+                line: VecDeque<f64>, // the values not shifted out yet
+                i: usize,
+            }
+
+            impl<T> Clone for $op<T> {
+                fn clone(&self) -> Self {
+                    let [high, low] = self.inputs.clone().0;
+                    Self::new(self.periods, high, low)
+                }
+            }
+
+            impl<T> $op<T> {
+                pub fn new(periods: [usize; $n], high: BoxOp<T>, low: BoxOp<T>) -> Self {
+                    let mut op = Self {
+                        periods,
+                        inputs: Inputs([high, low]),
+
+                        mids: vec![],
+                        line: VecDeque::new(),
+                        i: 0,
+                    };
+                    op.mids = op.windows().iter().map(|&n| Midpoint::new(n)).collect();
+                    op
+                }
+
+                fn windows(&self) -> &[usize] {
+                    &self.periods[..$n - $shifted as usize]
+                }
+
+                fn shift(&self) -> usize {
+                    if $shifted {
+                        self.periods[$n - 1]
+                    } else {
+                        0
+                    }
+                }
+
+                fn clear(&mut self) {
+                    self.mids.iter_mut().for_each(Midpoint::clear);
+                    self.line.clear();
+                }
+            }
+
+            impl<T> Named for $op<T> {
+                const NAME: &'static str = stringify!($op);
+            }
+
+            impl<T: TickerBatch> Operator<T> for $op<T> {
+                fn reset(&mut self) {
+                    self.inputs.reset();
+                    self.clear();
+                    self.i = 0;
+                }
+
+                #[throws(Error)]
+                fn evict(&mut self) {
+                    self.clear();
+                }
+
+                #[throws(Error)]
+                fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+                    let inputs = self.inputs.update(tb)?;
+                    let (highs, lows) = (&*inputs[0], &*inputs[1]);
+
+                    let mut results = Vec::with_capacity(tb.len());
+
+                    for (&high, &low) in highs.iter().zip(lows) {
+                        if self.i < self.inputs.ready_offset() {
+                            self.check_warmup(tb, &[high, low])?;
+                            results.push(f64::NAN);
+                            self.i += 1;
+                            continue;
+                        }
+
+                        let val = if high.is_nan() || low.is_nan() {
+                            f64::NAN
+                        } else {
+                            let $mids: Vec<f64> = self.mids.iter_mut().map(|mid| mid.push(high, low)).collect();
+                            $line
+                        };
+                        self.line.push_back(val);
+                        results.push(if self.line.len() > self.shift() {
+                            self.line.pop_front().unwrap()
+                        } else {
+                            f64::NAN
+                        });
+                    }
+
+                    results.into()
+                }
+
+                fn ready_offset(&self) -> usize {
+                    self.inputs.ready_offset() + self.windows().iter().max().unwrap() - 1 + self.shift()
+                }
+
+                fn lookback(&self) -> Option<usize> {
+                    Some(self.inputs.lookback()? + self.windows().iter().max().unwrap() - 1 + self.shift())
+                }
+
+                fn state_bytes(&self) -> usize {
+                    let windows: usize = self.windows().iter().sum();
+                    2 * windows * mem::size_of::<(usize, f64)>()
+                        + self.shift() * mem::size_of::<f64>()
+                        + self.inputs.state_bytes()
+                }
+
+                fn to_string(&self) -> String {
+                    format!(
+                        "({} {} {})",
+                        Self::NAME,
+                        self.periods.iter().join(" "),
+                        self.inputs.to_string()
+                    )
+                }
+
+                fn depth(&self) -> usize {
+                    1 + self.inputs.depth()
+                }
+
+                fn len(&self) -> usize {
+                    self.inputs.len() + 1
+                }
+
+                fn child_indices(&self) -> Vec<usize> {
+                    self.inputs.child_indices()
+                }
+
+                fn columns(&self) -> Vec<String> {
+                    self.inputs.columns()
+                }
+
+                #[throws(as Option)]
+                fn get(&self, i: usize) -> BoxOp<T> {
+                    if i == 0 {
+                        return self.clone().boxed();
+                    }
+                    self.inputs.get(i)?
+                }
+
+                #[throws(as Option)]
+                fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+                    if i == 0 {
+                        unreachable!("cannot insert root");
+                    }
+                    self.inputs.insert(i, op)?
+                }
+            }
+
+            impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<$op<T>> {
+                #[throws(Error)]
+                fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> $op<T> {
+                    let name = $op::<T>::NAME;
+                    let (periods, inputs) = split_bars(name, [$(stringify!($param)),+], ["high", "low"], iter)?;
+                    let [high, low] = inputs.0;
+                    $op::new(periods, high, low)
+                }
+            }
+        )+
+    };
+}
+
+impl_ichimoku! {
+    /// The conversion line (tenkan-sen) of Ichimoku, the midpoint of the highest high and the lowest low
+    /// of the last `win_size` bars, usually 9. The bars with a NaN are skipped, and produce a NaN.
+    Tenkan [1] (win_size) false |mids| mids[0];
+
+    /// The base line (kijun-sen) of Ichimoku, the same midpoint as `Tenkan` over a longer window, usually 26.
+    Kijun [1] (win_size) false |mids| mids[0];
+
+    /// The leading span A (senkou span A) of Ichimoku, the mean of the `fast` and `slow` midpoints, i.e. of `Tenkan`
+    /// and `Kijun`, displaced `shift` bars forward, usually 9, 26 and 26. The bars with a NaN are skipped,
+    /// and produce a NaN `shift` bars later.
+    SenkouA [3] (fast, slow, shift) true |mids| (mids[0] + mids[1]) / 2.;
+
+    /// The leading span B (senkou span B) of Ichimoku, the midpoint over the last `win_size` bars displaced
+    /// `shift` bars forward, usually 52 and 26. The bars with a NaN are skipped, and produce a NaN `shift` bars later.
+    SenkouB [2] (win_size, shift) true |mids| mids[0];
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ops::from_str;
+    use arrow::record_batch::RecordBatch;
+
+    #[test]
+    fn displacement() {
+        let opts = Default::default();
+        for (repr, ready_offset, lookback) in [
+            ("(Tenkan 9 :high :low)", 8, 9),
+            ("(Kijun 26 (Delay 1 :high) :low)", 26, 27),
+            ("(SenkouA 9 26 26 :high :low)", 25 + 26, 26 + 26),
+            ("(SenkouB 52 26 :high :low)", 51 + 26, 52 + 26),
+        ] {
+            let op = from_str::<RecordBatch>(repr, &opts).unwrap();
+            assert_eq!(op.to_string(), repr);
+            assert_eq!(op.ready_offset(), ready_offset, "{}", repr);
+            assert_eq!(op.lookback(), Some(lookback), "{}", repr);
+        }
+
+        for repr in [
+            "(Tenkan :high :low)",
+            "(Tenkan 9 :high)",
+            "(SenkouA 9 26 :high :low)",
+            "(SenkouB 52 0 :high :low)",
+        ] {
+            assert!(from_str::<RecordBatch>(repr, &opts).is_err(), "{}", repr);
+        }
+    }
+}
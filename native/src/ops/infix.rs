@@ -0,0 +1,412 @@
+//! The infix syntax, e.g. `(bid + ask) / 2` or `Mean(10, :close) > :close`. It is lowered into the
+//! S-expression syntax, so both syntaxes build exactly the same operators.
+
+use super::{parser, BoxOp, ParseError, ParseOptions};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error};
+use fehler::{throw, throws};
+use itertools::Itertools;
+use std::{iter::Peekable, str::CharIndices};
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(String),
+    Ident(String),
+    Column(String),
+    Str(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "'{}'", n),
+            Token::Ident(name) => write!(f, "'{}'", name),
+            Token::Column(name) => write!(f, "':{}'", name),
+            Token::Str(s) => write!(f, "{:?}", s),
+            Token::Op(op) => write!(f, "'{}'", op),
+            Token::LParen => write!(f, "'('"),
+            Token::RParen => write!(f, "')'"),
+            Token::LBracket => write!(f, "'['"),
+            Token::RBracket => write!(f, "']'"),
+            Token::Comma => write!(f, "','"),
+        }
+    }
+}
+
+// Longer operators go first, so that `<=` is not read as `<`
+const OPS: &[&str] = &[
+    "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "^", "<", ">", "!",
+];
+
+fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// Column names may also be qualified, e.g. `:bid#2`
+fn is_column(c: char) -> bool {
+    is_ident(c) || c == '#'
+}
+
+fn take_while(chars: &mut Peekable<CharIndices>, f: impl Fn(char) -> bool) -> String {
+    let mut s = String::new();
+    while let Some(&(_, c)) = chars.peek() {
+        if !f(c) {
+            break;
+        }
+        s.push(c);
+        chars.next();
+    }
+    s
+}
+
+#[throws(Error)]
+fn tokenize(expr: &str) -> Vec<(usize, Token)> {
+    let mut tokens = vec![];
+    let mut chars = expr.char_indices().peekable();
+
+    while let Some(&(pos, c)) = chars.peek() {
+        let token = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '(' | ')' | '[' | ']' | ',' => {
+                chars.next();
+                match c {
+                    '(' => Token::LParen,
+                    ')' => Token::RParen,
+                    '[' => Token::LBracket,
+                    ']' => Token::RBracket,
+                    _ => Token::Comma,
+                }
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let mut num = take_while(&mut chars, |c| c.is_ascii_digit() || c == '.');
+                // the exponent, e.g. 1e-3
+                if let Some(&(_, e @ ('e' | 'E'))) = chars.peek() {
+                    num.push(e);
+                    chars.next();
+                    if let Some(&(_, sign @ ('+' | '-'))) = chars.peek() {
+                        num.push(sign);
+                        chars.next();
+                    }
+                    num.push_str(&take_while(&mut chars, |c| c.is_ascii_digit()));
+                }
+                if num.parse::<f64>().is_err() {
+                    throw!(anyhow!("malformed number '{}' at position {}", num, pos))
+                }
+                Token::Number(num)
+            }
+            ':' => {
+                chars.next();
+                let name = take_while(&mut chars, is_column);
+                if name.is_empty() {
+                    throw!(anyhow!("missing column name at position {}", pos))
+                }
+                Token::Column(name)
+            }
+            '"' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c)) => s.push(c),
+                            None => throw!(anyhow!("unterminated string at position {}", pos)),
+                        },
+                        Some((_, c)) => s.push(c),
+                        None => throw!(anyhow!("unterminated string at position {}", pos)),
+                    }
+                }
+                Token::Str(s)
+            }
+            c if is_ident(c) => Token::Ident(take_while(&mut chars, is_ident)),
+            _ => {
+                let rest = &expr[pos..];
+                match OPS.iter().find(|op| rest.starts_with(**op)) {
+                    Some(op) => {
+                        for _ in 0..op.len() {
+                            chars.next();
+                        }
+                        Token::Op(op)
+                    }
+                    None => throw!(anyhow!("unexpected character '{}' at position {}", c, pos)),
+                }
+            }
+        };
+        tokens.push((pos, token));
+    }
+
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    i: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.i).map(|(_, t)| t)
+    }
+
+    fn pos(&self) -> usize {
+        self.tokens.get(self.i).map(|(p, _)| *p).unwrap_or(self.end)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.i).map(|(_, t)| t.clone());
+        self.i += 1;
+        token
+    }
+
+    #[throws(Error)]
+    fn expect(&mut self, expected: Token) {
+        let pos = self.pos();
+        match self.next() {
+            Some(t) if t == expected => {}
+            Some(t) => throw!(anyhow!(
+                "expected {}, got {} at position {}",
+                expected,
+                t,
+                pos
+            )),
+            None => throw!(anyhow!("expected {}, got the end of the input", expected)),
+        }
+    }
+
+    // The binding power of the binary operators, the higher the tighter
+    fn binary(&self) -> Option<(&'static str, u8)> {
+        match self.peek()? {
+            Token::Op(op) => {
+                let bp = match *op {
+                    "||" => 1,
+                    "&&" => 2,
+                    "<" | "<=" | ">" | ">=" | "==" | "!=" => 3,
+                    "+" | "-" => 4,
+                    "*" | "/" => 5,
+                    "^" => 7,
+                    _ => return None,
+                };
+                Some((op, bp))
+            }
+            _ => None,
+        }
+    }
+
+    #[throws(Error)]
+    fn expr(&mut self, min_bp: u8) -> String {
+        let mut lhs = self.unary()?;
+
+        while let Some((op, bp)) = self.binary() {
+            if bp < min_bp {
+                break;
+            }
+            self.next();
+            // `^` is right associative, the others are left associative
+            let rhs = if op == "^" {
+                self.expr(bp)?
+            } else {
+                self.expr(bp + 1)?
+            };
+            lhs = match op {
+                "&&" => format!("(And {} {})", lhs, rhs),
+                "||" => format!("(Or {} {})", lhs, rhs),
+                "!=" => format!("(! (== {} {}))", lhs, rhs),
+                // `(^ <const> <expr>)` takes the exponent first
+                "^" => format!("(^ {} {})", rhs, lhs),
+                op => format!("({} {} {})", op, lhs, rhs),
+            };
+        }
+
+        lhs
+    }
+
+    #[throws(Error)]
+    fn unary(&mut self) -> String {
+        match self.peek() {
+            Some(Token::Op("-")) => {
+                self.next();
+                match self.peek() {
+                    // a negative constant, so that it still fits in a constant parameter
+                    Some(Token::Number(_))
+                        if !matches!(self.tokens.get(self.i + 1), Some((_, Token::Op("^")))) =>
+                    {
+                        match self.next() {
+                            Some(Token::Number(n)) => format!("-{}", n),
+                            _ => unreachable!(),
+                        }
+                    }
+                    _ => format!("(Neg {})", self.expr(6)?),
+                }
+            }
+            Some(Token::Op("!")) => {
+                self.next();
+                format!("(! {})", self.expr(6)?)
+            }
+            _ => self.primary()?,
+        }
+    }
+
+    #[throws(Error)]
+    fn primary(&mut self) -> String {
+        let pos = self.pos();
+        match self.next() {
+            Some(Token::Number(n)) => n,
+            Some(Token::Column(name)) => format!(":{}", name),
+            Some(Token::Str(s)) => format!("{:?}", s),
+            Some(Token::LParen) => {
+                let e = self.expr(0)?;
+                self.expect(Token::RParen)?;
+                e
+            }
+            Some(Token::LBracket) => {
+                let items = self.list(Token::RBracket)?;
+                format!("({})", items.join(" "))
+            }
+            Some(Token::Ident(name)) => {
+                if self.peek() == Some(&Token::LParen) {
+                    self.next();
+                    let args = self.list(Token::RParen)?;
+                    if args.is_empty() {
+                        format!("({})", name)
+                    } else {
+                        format!("({} {})", name, args.join(" "))
+                    }
+                } else {
+                    // a bare name is a column
+                    format!(":{}", name)
+                }
+            }
+            Some(t) => throw!(anyhow!("unexpected {} at position {}", t, pos)),
+            None => throw!(anyhow!("unexpected end of the input")),
+        }
+    }
+
+    // Comma separated expressions up to the closing token
+    #[throws(Error)]
+    fn list(&mut self, close: Token) -> Vec<String> {
+        let mut items = vec![];
+        if self.peek() == Some(&close) {
+            self.next();
+            return items;
+        }
+        loop {
+            items.push(self.expr(0)?);
+            let pos = self.pos();
+            match self.next() {
+                Some(Token::Comma) => continue,
+                Some(t) if t == close => break,
+                Some(t) => throw!(anyhow!(
+                    "expected ',' or {}, got {} at position {}",
+                    close,
+                    t,
+                    pos
+                )),
+                None => throw!(anyhow!("expected {}, got the end of the input", close)),
+            }
+        }
+        items
+    }
+}
+
+/// Lower an infix expression into the S-expression syntax.
+#[throws(Error)]
+pub fn to_sexpr(expr: &str) -> String {
+    let mut parser = Parser {
+        tokens: tokenize(expr)?,
+        i: 0,
+        end: expr.len(),
+    };
+    let sexpr = parser.expr(0)?;
+    if let Some(t) = parser.peek() {
+        throw!(anyhow!("unexpected {} at position {}", t, parser.pos()))
+    }
+    sexpr
+}
+
+#[throws(Error)]
+pub fn from_infix<T: TickerBatch>(expr: &str, opts: &ParseOptions) -> BoxOp<T> {
+    // The positions in the lowered S-expression do not point into `expr`, only the fragment is kept
+    parser::from_str(&to_sexpr(expr)?, opts).map_err(|e| match e.downcast::<ParseError>() {
+        Ok(e) => ParseError {
+            span: None,
+            message: match e.fragment {
+                Some(fragment) => format!("{} in {}", e.message, fragment),
+                None => e.message,
+            },
+            fragment: None,
+        }
+        .into(),
+        Err(e) => e,
+    })?
+}
+
+/// Which syntax an expression is written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    /// `(/ (+ :bid :ask) 2)`, the default
+    Sexpr,
+    /// `(bid + ask) / 2`
+    Infix,
+}
+
+impl Default for Syntax {
+    fn default() -> Self {
+        Syntax::Sexpr
+    }
+}
+
+impl std::str::FromStr for Syntax {
+    type Err = Error;
+
+    #[throws(Error)]
+    fn from_str(s: &str) -> Self {
+        match s {
+            "sexpr" => Syntax::Sexpr,
+            "infix" => Syntax::Infix,
+            _ => throw!(anyhow!(
+                "Unknown syntax '{}', expect one of {}",
+                s,
+                ["sexpr", "infix"].iter().join(", ")
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::to_sexpr;
+
+    #[test]
+    fn lowering() {
+        for (infix, sexpr) in [
+            ("(bid + ask) / 2", "(/ (+ :bid :ask) 2)"),
+            ("Mean(10, :close)", "(Mean 10 :close)"),
+            ("a - b - c", "(- (- :a :b) :c)"),
+            ("a + b * c ^ 2", "(+ :a (* :b (^ 2 :c)))"),
+            ("-a ^ 2", "(Neg (^ 2 :a))"),
+            ("Delay(-1.5e-3, x)", "(Delay -1.5e-3 :x)"),
+            (
+                "a > 1 && !(b != c) || d",
+                "(Or (And (> :a 1) (! (! (== :b :c)))) :d)",
+            ),
+            ("Conv([0.5, 0.5], :bid#2)", "(Conv (0.5 0.5) :bid#2)"),
+            ("RowIndex()", "(RowIndex)"),
+        ] {
+            assert_eq!(to_sexpr(infix).unwrap(), sexpr, "{}", infix);
+        }
+
+        for infix in ["(a + b", "a +", "Mean(10 :a)", "a $ b", "a b"] {
+            assert!(to_sexpr(infix).is_err(), "{}", infix);
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! The series of the operators over several inputs, e.g. the high, low and close of `ATR`.
+//! They are the children of the operator, in order, right after the root.
+
+use super::{
+    at_child,
+    parser::{to_usize, Parameter},
+    BoxOp,
+};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use itertools::Itertools;
+use rayon::prelude::*;
+use std::{borrow::Cow, convert::TryInto, mem};
+
+pub(super) struct Inputs<T, const N: usize>(pub(super) [BoxOp<T>; N]);
+
+impl<T, const N: usize> Clone for Inputs<T, N> {
+    fn clone(&self) -> Self {
+        Inputs(self.0.clone())
+    }
+}
+
+impl<T: TickerBatch, const N: usize> Inputs<T, N> {
+    /// Take the `names`d series out of `params`, in order.
+    #[throws(Error)]
+    pub(super) fn from_params<I>(op: &str, names: [&str; N], params: &mut I) -> Self
+    where
+        I: Iterator<Item = Parameter<T>>,
+    {
+        let mut inputs = Vec::with_capacity(N);
+        for name in names {
+            let input = params
+                .next()
+                .ok_or_else(|| anyhow!("Too few parameters for {}", op))?
+                .to_operator()
+                .ok_or_else(|| anyhow!("<{}> for {} should be an operator", name, op))?;
+            inputs.push(input);
+        }
+        match inputs.try_into() {
+            Ok(inputs) => Inputs(inputs),
+            Err(_) => unreachable!(),
+        }
+    }
+
+    pub(super) fn reset(&mut self) {
+        self.0.iter_mut().for_each(|op| op.reset());
+    }
+
+    #[throws(Error)]
+    pub(super) fn update<'a>(&mut self, tb: &'a T) -> Vec<Cow<'a, [f64]>> {
+        let offsets = self.child_indices();
+        self.0
+            .par_iter_mut()
+            .zip(offsets)
+            .map(|(op, offset)| op.checked_update(tb).map_err(at_child(offset)))
+            .collect::<Result<Vec<_>>>()?
+    }
+
+    pub(super) fn ready_offset(&self) -> usize {
+        self.0.iter().map(|op| op.ready_offset()).max().unwrap_or(0)
+    }
+
+    pub(super) fn lookback(&self) -> Option<usize> {
+        self.0
+            .iter()
+            .try_fold(1, |lookback, op| Some(lookback.max(op.lookback()?)))
+    }
+
+    pub(super) fn state_bytes(&self) -> usize {
+        self.0.iter().map(|op| op.state_bytes()).sum()
+    }
+
+    pub(super) fn to_string(&self) -> String {
+        self.0.iter().map(|op| op.to_string()).join(" ")
+    }
+
+    pub(super) fn depth(&self) -> usize {
+        self.0.iter().map(|op| op.depth()).max().unwrap_or(0)
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.0.iter().map(|op| op.len()).sum()
+    }
+
+    pub(super) fn child_indices(&self) -> Vec<usize> {
+        self.0
+            .iter()
+            .scan(1, |offset, op| {
+                let i = *offset;
+                *offset += op.len();
+                Some(i)
+            })
+            .collect()
+    }
+
+    pub(super) fn columns(&self) -> Vec<String> {
+        self.0.iter().flat_map(|op| op.columns()).collect()
+    }
+
+    /// The node at `i` (as in `Operator::get`, `i > 0`) of the operator.
+    #[throws(as Option)]
+    pub(super) fn get(&self, i: usize) -> BoxOp<T> {
+        let mut i = i - 1;
+
+        let input = self.0.iter().find(|input| {
+            let found = i < input.len();
+            if !found {
+                i -= input.len();
+            }
+            found
+        })?;
+        input.get(i)?
+    }
+
+    /// Insert the subtree at `i` (as in `Operator::insert`, `i > 0`) of the operator.
+    #[throws(as Option)]
+    pub(super) fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        let mut i = i - 1;
+
+        let input = self.0.iter_mut().find(|input| {
+            let found = i < input.len();
+            if !found {
+                i -= input.len();
+            }
+            found
+        })?;
+        if i == 0 {
+            return mem::replace(input, op) as BoxOp<T>;
+        }
+        input.insert(i, op)?
+    }
+}
+
+/// Take the periods, e.g. the window sizes, then the `series` out of `params`.
+#[throws(Error)]
+pub(super) fn split_bars<T: TickerBatch, const N: usize, const M: usize>(
+    name: &str,
+    names: [&str; N],
+    series: [&str; M],
+    params: impl IntoIterator<Item = Parameter<T>>,
+) -> ([usize; N], Inputs<T, M>) {
+    let mut iter = params.into_iter();
+
+    let mut periods = [0; N];
+    for (period, param) in periods.iter_mut().zip(names) {
+        let Some(Parameter::Constant(c)) = iter.next() else {
+            throw!(anyhow!("<{}> for {} should be an constant", param, name));
+        };
+        *period = to_usize(name, param, c, 1)?;
+    }
+    let inputs = Inputs::from_params(name, series, &mut iter)?;
+
+    if iter.count() != 0 {
+        throw!(anyhow!("Too many parameters for {}", name))
+    }
+
+    (periods, inputs)
+}
@@ -0,0 +1,149 @@
+//! Spot the constructs which are valid but useless, e.g. `(- x x)`. Random and evolved factors are full of them,
+//! and they only cost replay time.
+
+use super::{
+    dot::label,
+    optimize::{head, is_constant, rewrite},
+    signatures, BoxOp, Kind, Operator, Signature,
+};
+use crate::ticker_batch::TickerBatch;
+
+/// A useless construct found at the node `index` (see `Operator::get`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    pub index: usize,
+    pub node: String,
+    pub message: String,
+}
+
+// The window operators looking `win_size` rows back, i.e. comparing 2 rows with a window of 1
+const LAGGED: &[&str] = &["ROC", "PctChange", "Momentum", "RSI"];
+
+// The window operators for which a window of 1 row makes no sense
+fn is_windowed(sig: &Signature) -> bool {
+    sig.category == "window"
+        && !LAGGED.contains(&sig.name)
+        && matches!(sig.params.first(), Some(p) if p.name == "win_size" && matches!(p.kind, Kind::Integer(min) if min >= 1))
+}
+
+fn check<T: TickerBatch>(node: &BoxOp<T>, sigs: &[Signature]) -> Option<String> {
+    let func = head(&**node)?;
+    let children: Vec<_> = node
+        .child_indices()
+        .into_iter()
+        .filter_map(|i| node.get(i))
+        .collect();
+    let reprs: Vec<_> = children.iter().map(|child| child.to_string()).collect();
+
+    let same = match &*reprs {
+        [x, y] => x == y,
+        [_, x, y] => x == y && func == "If",
+        _ => false,
+    };
+    if same {
+        let message = match func.as_str() {
+            "-" => "is always 0",
+            "/" => "is always 1",
+            "<" | ">" => "is always false",
+            "<=" | ">=" | "==" => "is always true, unless NaN",
+            "And" | "Or" => "is the same as its operand",
+            "Corr" => "is always 1, unless NaN",
+            "Beta" => "is always 1, unless flat",
+            "Alpha" => "is always 0, unless flat",
+            "Resid" => "is always 0",
+            "If" => "has the same value in both branches",
+            _ => "",
+        };
+        if !message.is_empty() {
+            return Some(format!(
+                "{} takes the same operand twice, it {}",
+                func, message
+            ));
+        }
+    }
+
+    if let [child] = &*children {
+        let inner = head(&**child);
+        match (func.as_str(), inner.as_deref()) {
+            ("Abs", Some("Abs")) | ("Sign", Some("Sign")) | ("Abs", Some("Sign")) => {
+                return Some(format!("the outer {} is a no-op", func))
+            }
+            ("Neg", Some("Neg")) | ("!", Some("!")) => {
+                return Some(format!("the two {}s cancel out", func))
+            }
+            _ => {}
+        }
+    }
+
+    let windowed = sigs.iter().any(|sig| sig.name == func && is_windowed(sig));
+    if windowed && label(&**node, &children).split(' ').nth(1) == Some("1") {
+        return Some(format!(
+            "{} has a window of 1 row, it only sees the current value",
+            func
+        ));
+    }
+
+    if is_constant(&**node) {
+        return Some("is a constant, see optimize".to_string());
+    }
+    if rewrite(node).is_some() {
+        return Some("can be simplified, see simplify".to_string());
+    }
+    None
+}
+
+/// Flag the useless constructs, e.g. `(- x x)`, `(Corr 10 x x)`, `(Abs (Abs x))`, `(Mean 1 x)` or the constant
+/// subtrees, in the pre-order. The children of a flagged node are still checked.
+pub fn lint<T: TickerBatch>(op: &dyn Operator<T>) -> Vec<Warning> {
+    let sigs = signatures::<T>();
+    (0..op.len())
+        .filter_map(|i| op.get(i).map(|node| (i, node)))
+        .filter_map(|(index, node)| {
+            check(&node, &sigs).map(|message| Warning {
+                index,
+                node: node.to_string(),
+                message,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::lint;
+    use crate::ops::from_str;
+    use arrow::record_batch::RecordBatch;
+
+    #[test]
+    fn warnings() {
+        let opts = Default::default();
+        let flagged = |sexpr: &str| {
+            let op = from_str::<RecordBatch>(sexpr, &opts).unwrap();
+            lint(&*op).into_iter().map(|w| w.index).collect::<Vec<_>>()
+        };
+
+        assert!(flagged("(+ (Mean 10 :a) (Corr 10 :a :b))").is_empty());
+        for sexpr in [
+            "(- :a :a)",
+            "(/ (Mean 5 :a) (Mean 5 :a))",
+            "(> :a :a)",
+            "(Corr 10 :a :a)",
+            "(Beta 10 :a :a)",
+            "(If (> :a 0) :b :b)",
+            "(Abs (Abs :a))",
+            "(Neg (Neg :a))",
+            "(Mean 1 :a)",
+            "(Max 1 :a)",
+            "(+ 1 2)",
+            "(* :a 1)",
+        ] {
+            assert_eq!(flagged(sexpr), vec![0], "{}", sexpr);
+        }
+        assert!(flagged("(Delay 1 :a)").is_empty());
+        assert!(flagged("(ROC 1 :a)").is_empty() && flagged("(RSI 1 :a)").is_empty());
+        assert_eq!(
+            flagged("(+ (- :a :a) (Sum 1 (Abs (Abs :b))))"),
+            vec![1, 4, 5]
+        );
+    }
+}
@@ -1,32 +1,87 @@
-use super::{parser::Parameter, BoxOp, Named, Operator};
+use super::{at_child, parser::Parameter, BoxOp, Named, Operator};
 use crate::ticker_batch::TickerBatch;
 use anyhow::{anyhow, Error, Result};
 use fehler::{throw, throws};
-use std::{borrow::Cow, cmp::max, iter::FromIterator, mem};
+use std::{borrow::Cow, cmp::max, iter::FromIterator, mem, str::FromStr};
+
+/// How the logic operators deal with NaN inputs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NanPolicy {
+    /// Treat NaN as false. This is the default.
+    False,
+    /// Produce NaN if any of the inputs is NaN.
+    Propagate,
+}
+
+impl NanPolicy {
+    // Printed in front of the operands, e.g. `(And propagate :a :b)`, unless it is the default
+    pub(crate) fn prefix(self) -> &'static str {
+        match self {
+            NanPolicy::False => "",
+            NanPolicy::Propagate => "propagate ",
+        }
+    }
+}
+
+impl Default for NanPolicy {
+    fn default() -> Self {
+        NanPolicy::False
+    }
+}
+
+impl FromStr for NanPolicy {
+    type Err = Error;
+
+    #[throws(Error)]
+    fn from_str(s: &str) -> Self {
+        match s {
+            "false" => NanPolicy::False,
+            "propagate" => NanPolicy::Propagate,
+            _ => throw!(anyhow!(
+                "unknown nan policy '{}', expect one of false or propagate",
+                s
+            )),
+        }
+    }
+}
 
 // #[derive(Clone)]
 pub struct If<T> {
     cond: BoxOp<T>,
     btrue: BoxOp<T>,
     bfalse: BoxOp<T>,
+    nan: NanPolicy,
     i: usize,
 }
 
 impl<T> Clone for If<T> {
     fn clone(&self) -> Self {
-        Self::new(self.cond.clone(), self.btrue.clone(), self.bfalse.clone())
+        Self {
+            cond: self.cond.clone(),
+            btrue: self.btrue.clone(),
+            bfalse: self.bfalse.clone(),
+            nan: self.nan,
+            i: 0,
+        }
     }
 }
 
-impl<T> If<T> {
-    pub fn new(cond: BoxOp<T>, btrue: BoxOp<T>, bfalse: BoxOp<T>) -> Self {
+impl<T: TickerBatch> If<T> {
+    pub fn new(mut cond: BoxOp<T>, btrue: BoxOp<T>, bfalse: BoxOp<T>) -> Self {
+        cond.allow_nan();
         Self {
             cond,
             btrue,
             bfalse,
+            nan: NanPolicy::default(),
             i: 0,
         }
     }
+
+    pub fn with_nan(mut self, nan: NanPolicy) -> Self {
+        self.nan = nan;
+        self
+    }
 }
 
 impl<T> Named for If<T> {
@@ -48,45 +103,70 @@ impl<T: TickerBatch> Operator<T> for If<T> {
         let bfalse = &mut self.bfalse;
 
         let (conds, (btrues, bfalses)) = rayon::join(
-            || cond.update(tb),
-            || rayon::join(|| btrue.update(tb), || bfalse.update(tb)),
+            || cond.checked_update(tb),
+            || rayon::join(|| btrue.checked_update(tb), || bfalse.checked_update(tb)),
         );
 
-        let (conds, btrues, bfalses) = (&*conds?, &*btrues?, &*bfalses?);
-        #[cfg(feature = "check")]
-        assert_eq!(tb.len(), conds.len());
-        #[cfg(feature = "check")]
-        assert_eq!(tb.len(), btrues.len());
-        #[cfg(feature = "check")]
-        assert_eq!(tb.len(), bfalses.len());
+        let ncond = self.cond.len();
+        let (conds, btrues, bfalses) = (
+            &*conds.map_err(at_child(1))?,
+            &*btrues.map_err(at_child(1 + ncond))?,
+            &*bfalses.map_err(at_child(1 + ncond + self.btrue.len()))?,
+        );
 
         let mut results = Vec::with_capacity(tb.len());
 
         for ((&cond, &tval), &fval) in conds.into_iter().zip(btrues).zip(bfalses) {
             if self.i < self.ready_offset() {
-                #[cfg(feature = "check")]
-                assert!(cond.is_nan() || tval.is_nan() || fval.is_nan());
+                self.check_warmup(tb, &[cond, tval, fval])?;
                 results.push(f64::NAN);
                 self.i += 1;
                 continue;
             }
 
-            let val = if cond > 0. { tval } else { fval };
+            let val = if self.nan == NanPolicy::Propagate && cond.is_nan() {
+                f64::NAN
+            } else if cond > 0. {
+                tval
+            } else {
+                fval
+            };
             results.push(val);
         }
 
         results.into()
     }
 
+    fn is_pointwise(&self) -> bool {
+        true
+    }
+
     fn ready_offset(&self) -> usize {
         let l = max(self.cond.ready_offset(), self.btrue.ready_offset());
         max(l, self.bfalse.ready_offset())
     }
 
+    fn is_nullable(&self) -> bool {
+        (self.nan == NanPolicy::Propagate && self.cond.is_nullable())
+            || self.btrue.is_nullable()
+            || self.bfalse.is_nullable()
+    }
+
+    fn allow_nan(&mut self) {
+        self.cond.allow_nan();
+        self.btrue.allow_nan();
+        self.bfalse.allow_nan();
+    }
+
+    fn is_predicate(&self) -> bool {
+        self.btrue.is_predicate() && self.bfalse.is_predicate()
+    }
+
     fn to_string(&self) -> String {
         format!(
-            "({} {} {} {})",
+            "({} {}{} {} {})",
             Self::NAME,
+            self.nan.prefix(),
             self.cond.to_string(),
             self.btrue.to_string(),
             self.bfalse.to_string()
@@ -155,10 +235,14 @@ impl<T: TickerBatch> Operator<T> for If<T> {
         let nbfalse = self.bfalse.len();
 
         if i < ncond {
-            if i == 0 {
-                return mem::replace(&mut self.cond, op) as BoxOp<T>;
-            }
-            self.cond.insert(i, op)?
+            let old = if i == 0 {
+                mem::replace(&mut self.cond, op)
+            } else {
+                self.cond.insert(i, op)?
+            };
+            // the NaNs are taken by the condition, whatever is put in there
+            self.cond.allow_nan();
+            old
         } else if i >= ncond && i < ncond + nbtrue {
             if i - ncond == 0 {
                 return mem::replace(&mut self.btrue, op) as BoxOp<T>;
@@ -182,17 +266,17 @@ impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<If<T>> {
 
         let cond = iter
             .next()
-            .unwrap()
+            .ok_or_else(|| anyhow!("Too few parameters for If"))?
             .to_operator()
             .ok_or_else(|| anyhow!("<cond> for If should be an operator"))?;
         let btrue = iter
             .next()
-            .unwrap()
+            .ok_or_else(|| anyhow!("Too few parameters for If"))?
             .to_operator()
             .ok_or_else(|| anyhow!("<btrue> for If should be an operator"))?;
         let bfalse = iter
             .next()
-            .unwrap()
+            .ok_or_else(|| anyhow!("Too few parameters for If"))?
             .to_operator()
             .ok_or_else(|| anyhow!("<bfalse> for If should be an operator"))?;
 
@@ -210,18 +294,26 @@ macro_rules! impl_logic_bivariate {
             pub struct $op<T> {
                 l: BoxOp<T>,
                 r: BoxOp<T>,
+                nan: NanPolicy,
                 i: usize,
             }
 
             impl<T> Clone for $op<T> {
                 fn clone(&self) -> Self {
-                    Self::new(self.l.clone(), self.r.clone())
+                    Self { l: self.l.clone(), r: self.r.clone(), nan: self.nan, i: 0 }
                 }
             }
 
-            impl<T> $op<T> {
-                pub fn new(l: BoxOp<T>, r: BoxOp<T>) -> Self {
-                    Self { l, r, i: 0 }
+            impl<T: TickerBatch> $op<T> {
+                pub fn new(mut l: BoxOp<T>, mut r: BoxOp<T>) -> Self {
+                    l.allow_nan();
+                    r.allow_nan();
+                    Self { l, r, nan: NanPolicy::default(), i: 0 }
+                }
+
+                pub fn with_nan(mut self, nan: NanPolicy) -> Self {
+                    self.nan = nan;
+                    self
                 }
             }
 
@@ -240,37 +332,56 @@ macro_rules! impl_logic_bivariate {
                 #[throws(Error)]
                 fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
                     let (l, r) = (&mut self.l, &mut self.r);
-                    let (ls, rs) = rayon::join(|| l.update(tb), || r.update(tb));
-                    let (ls, rs) = (&*ls?, &*rs?);
-                    #[cfg(feature = "check")]
-                    assert_eq!(tb.len(), ls.len());
-                    #[cfg(feature = "check")]
-                    assert_eq!(tb.len(), rs.len());
+                    let (ls, rs) = rayon::join(|| l.checked_update(tb), || r.checked_update(tb));
+                    let (ls, rs) = (
+                        &*ls.map_err(at_child(1))?,
+                        &*rs.map_err(at_child(1 + self.l.len()))?,
+                    );
 
                     let mut results = Vec::with_capacity(tb.len());
 
                     for (&lval, &rval) in ls.into_iter().zip(rs) {
                         if self.i < self.l.ready_offset() || self.i < self.r.ready_offset() {
-                            #[cfg(feature = "check")]
-                            assert!(lval.is_nan() || rval.is_nan());
+                            self.check_warmup(tb, &[lval, rval])?;
                             results.push(f64::NAN);
                             self.i += 1;
                             continue;
                         }
 
-                        let val = ($($func)+) (lval, rval) as u64 as f64;
+                        let val = if self.nan == NanPolicy::Propagate && (lval.is_nan() || rval.is_nan()) {
+                            f64::NAN
+                        } else {
+                            ($($func)+) (lval, rval) as u64 as f64
+                        };
                         results.push(val);
                     }
 
                     results.into()
                 }
 
+                fn is_pointwise(&self) -> bool {
+                    true
+                }
+
                 fn ready_offset(&self) -> usize {
                     max(self.l.ready_offset(), self.r.ready_offset())
                 }
 
+                fn is_nullable(&self) -> bool {
+                    self.nan == NanPolicy::Propagate && (self.l.is_nullable() || self.r.is_nullable())
+                }
+
+                fn allow_nan(&mut self) {
+                    self.l.allow_nan();
+                    self.r.allow_nan();
+                }
+
+                fn is_predicate(&self) -> bool {
+                    true
+                }
+
                 fn to_string(&self) -> String {
-                    format!("({} {} {})", Self::NAME, self.l.to_string(), self.r.to_string())
+                    format!("({} {}{} {})", Self::NAME, self.nan.prefix(), self.l.to_string(), self.r.to_string())
                 }
 
                 fn depth(&self) -> usize {
@@ -322,19 +433,24 @@ macro_rules! impl_logic_bivariate {
                     let nl = self.l.len();
                     let nr = self.r.len();
 
-                    if i < nl {
+                    let old = if i < nl {
                         if i == 0 {
-                            return mem::replace(&mut self.l, op) as BoxOp<T>;
+                            mem::replace(&mut self.l, op)
+                        } else {
+                            self.l.insert(i, op)?
                         }
-                        self.l.insert(i, op)?
                     } else if i >= nl && i < nl + nr {
                         if i - nl == 0 {
-                            return mem::replace(&mut self.r, op) as BoxOp<T>;
+                            mem::replace(&mut self.r, op)
+                        } else {
+                            self.r.insert(i - nl, op)?
                         }
-                        self.r.insert(i - nl, op)?
                     } else {
                         throw!()
-                    }
+                    };
+                    self.l.allow_nan();
+                    self.r.allow_nan();
+                    old
                 }
             }
 
@@ -377,18 +493,33 @@ impl_logic_bivariate! (
 
 pub struct Not<T> {
     inner: BoxOp<T>,
+    nan: NanPolicy,
     i: usize,
 }
 
 impl<T> Clone for Not<T> {
     fn clone(&self) -> Self {
-        Self::new(self.inner.clone())
+        Self {
+            inner: self.inner.clone(),
+            nan: self.nan,
+            i: 0,
+        }
     }
 }
 
-impl<T> Not<T> {
-    pub fn new(s: BoxOp<T>) -> Self {
-        Self { inner: s, i: 0 }
+impl<T: TickerBatch> Not<T> {
+    pub fn new(mut s: BoxOp<T>) -> Self {
+        s.allow_nan();
+        Self {
+            inner: s,
+            nan: NanPolicy::default(),
+            i: 0,
+        }
+    }
+
+    pub fn with_nan(mut self, nan: NanPolicy) -> Self {
+        self.nan = nan;
+        self
     }
 }
 
@@ -404,34 +535,58 @@ impl<T: TickerBatch> Operator<T> for Not<T> {
 
     #[throws(Error)]
     fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
-        let vals = &*self.inner.update(tb)?;
-        #[cfg(feature = "check")]
-        assert_eq!(tb.len(), vals.len());
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
 
         let mut results = Vec::with_capacity(tb.len());
 
         for &val in vals {
             if self.i < self.inner.ready_offset() {
-                #[cfg(feature = "check")]
-                assert!(val.is_nan());
+                self.check_warmup(tb, &[val])?;
                 results.push(f64::NAN);
                 self.i += 1;
                 continue;
             }
 
-            let val = if val > 0. { 0. } else { 1. };
+            let val = if self.nan == NanPolicy::Propagate && val.is_nan() {
+                f64::NAN
+            } else if val > 0. {
+                0.
+            } else {
+                1.
+            };
             results.push(val);
         }
 
         results.into()
     }
 
+    fn is_pointwise(&self) -> bool {
+        true
+    }
+
     fn ready_offset(&self) -> usize {
-        0
+        self.inner.ready_offset()
+    }
+
+    fn is_nullable(&self) -> bool {
+        self.nan == NanPolicy::Propagate && self.inner.is_nullable()
+    }
+
+    fn allow_nan(&mut self) {
+        self.inner.allow_nan();
+    }
+
+    fn is_predicate(&self) -> bool {
+        true
     }
 
     fn to_string(&self) -> String {
-        format!("({} {})", Self::NAME, self.inner.to_string())
+        format!(
+            "({} {}{})",
+            Self::NAME,
+            self.nan.prefix(),
+            self.inner.to_string()
+        )
     }
 
     fn depth(&self) -> usize {
@@ -475,14 +630,16 @@ impl<T: TickerBatch> Operator<T> for Not<T> {
 
         let ns = self.inner.len();
 
-        if i < ns {
-            if i == 0 {
-                return mem::replace(&mut self.inner, op) as BoxOp<T>;
-            }
-            self.inner.insert(i, op)?
-        } else {
+        if i >= ns {
             throw!()
         }
+        let old = if i == 0 {
+            mem::replace(&mut self.inner, op)
+        } else {
+            self.inner.insert(i, op)?
+        };
+        self.inner.allow_nan();
+        old
     }
 }
 
@@ -500,3 +657,47 @@ impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<Not<T>> {
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::ops::from_str;
+    use arrow::{
+        array::{ArrayRef, Float64Array},
+        record_batch::RecordBatch,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn nan_inputs() {
+        let opts = Default::default();
+        let tb = RecordBatch::try_from_iter(vec![
+            (
+                "a",
+                Arc::new(Float64Array::from(vec![f64::NAN, 1., -1.])) as ArrayRef,
+            ),
+            (
+                "b",
+                Arc::new(Float64Array::from(vec![1., 1., 1.])) as ArrayRef,
+            ),
+        ])
+        .unwrap();
+
+        let mut op = from_str::<RecordBatch>("(If (+ :a 1) :b (Neg :b))", &opts).unwrap();
+        assert_eq!(&*op.update(&tb).unwrap(), &[-1., 1., -1.]);
+        // only the condition takes the NaNs
+        let mut op = from_str::<RecordBatch>("(If :b :a :b)", &opts).unwrap();
+        assert!(op.update(&tb).is_err());
+        let mut op = from_str::<RecordBatch>("(! (Mean 2 :a))", &opts).unwrap();
+        assert!(op.update(&tb).is_err());
+
+        // A NaN let through can only reach a window if the logic function propagates it
+        assert!(from_str::<RecordBatch>("(Mean 2 (> :a 0))", &opts).is_ok());
+        assert!(from_str::<RecordBatch>("(Mean 2 (> propagate :a 0))", &opts).is_err());
+
+        // The inputs put in later take the NaNs as well
+        let mut op = from_str::<RecordBatch>("(! (+ :b 1))", &opts).unwrap();
+        op.insert(3, from_str("(Abs :a)", &opts).unwrap());
+        assert_eq!(op.to_string(), "(! (+ :b (Abs :a)))");
+        assert_eq!(&*op.update(&tb).unwrap(), &[1., 0., 0.]);
+    }
+}
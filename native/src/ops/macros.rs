@@ -0,0 +1,174 @@
+//! User defined macros, e.g. `(def zscore (x w) (/ (- x (Mean w x)) (Std w x)))`. Once defined, `(zscore :close 10)`
+//! can be used in any expression, and is expanded into the plain operators before the factor is built.
+
+use super::parser::{expand_lets, is_reserved};
+use anyhow::{anyhow, Error};
+use fehler::{throw, throws};
+use lexpr::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::RwLock,
+};
+
+const DEF: &str = "def";
+
+// Guards against macros expanding into themselves
+const MAX_EXPANSIONS: usize = 64;
+
+struct Macro {
+    params: Vec<String>,
+    body: Value,
+}
+
+static MACROS: RwLock<BTreeMap<String, Macro>> = RwLock::new(BTreeMap::new());
+
+/// Register a macro written as `(def <name> (<param> ...) <body>)`, replacing the one with the same name.
+/// Returns the name of the macro.
+#[throws(Error)]
+pub fn define_macro(def: &str) -> String {
+    let sexpr = lexpr::from_str(def)?;
+    let form = match &sexpr {
+        Value::Cons(cons) if cons.car().as_symbol() == Some(DEF) => cons.to_vec().0,
+        _ => throw!(anyhow!(
+            "a macro should be written as ({} <name> (<param> ...) <body>), got {}",
+            DEF,
+            def
+        )),
+    };
+
+    let (name, params, body) = match &*form {
+        [_, Value::Symbol(name), Value::Cons(params), body] => (name, params.to_vec().0, body),
+        [_, Value::Symbol(name), Value::Null, body] => (name, vec![], body),
+        _ => throw!(anyhow!(
+            "a macro should be written as ({} <name> (<param> ...) <body>), got {}",
+            DEF,
+            def
+        )),
+    };
+    if name.starts_with(':') || is_reserved(name) {
+        throw!(anyhow!("'{}' cannot be used as the name of a macro", name))
+    }
+
+    let mut names = vec![];
+    for param in params {
+        match param {
+            Value::Symbol(param)
+                if !param.starts_with(':') && !names.contains(&param.to_string()) =>
+            {
+                names.push(param.to_string())
+            }
+            _ => throw!(anyhow!("malformed parameter {} of macro {}", param, name)),
+        }
+    }
+
+    MACROS.write().unwrap().insert(
+        name.to_string(),
+        Macro {
+            params: names,
+            body: body.clone(),
+        },
+    );
+
+    name.to_string()
+}
+
+/// Remove a macro, returns whether it was defined.
+pub fn undefine_macro(name: &str) -> bool {
+    MACROS.write().unwrap().remove(name).is_some()
+}
+
+/// Expand the calls to the macros, the arguments are substituted for the parameters in the body.
+#[throws(Error)]
+pub(super) fn expand_macros(sexpr: &Value) -> Value {
+    let macros = MACROS.read().unwrap();
+    if macros.is_empty() {
+        return sexpr.clone();
+    }
+    expand(sexpr, &macros, 0)?
+}
+
+#[throws(Error)]
+fn expand(sexpr: &Value, macros: &BTreeMap<String, Macro>, expansions: usize) -> Value {
+    let cons = match sexpr {
+        Value::Cons(cons) => cons,
+        _ => return sexpr.clone(),
+    };
+
+    let (items, _) = cons.to_vec();
+    let mut expanded = vec![items[0].clone()];
+    for item in &items[1..] {
+        expanded.push(expand(item, macros, expansions)?);
+    }
+
+    let m = match items[0].as_symbol().and_then(|name| macros.get(name)) {
+        Some(m) => m,
+        None => return Value::list(expanded),
+    };
+    let name = items[0].as_symbol().unwrap();
+    if expansions >= MAX_EXPANSIONS {
+        throw!(anyhow!(
+            "macro {} is expanded more than {} times, is it recursive?",
+            name,
+            MAX_EXPANSIONS
+        ))
+    }
+    if m.params.len() != expanded.len() - 1 {
+        throw!(anyhow!(
+            "macro {} expect {} parameters, got {}",
+            name,
+            m.params.len(),
+            expanded.len() - 1
+        ))
+    }
+
+    let env: HashMap<_, _> = m
+        .params
+        .iter()
+        .cloned()
+        .zip(expanded.into_iter().skip(1))
+        .collect();
+    let body = expand_lets(&m.body, &env)?;
+    expand(&body, macros, expansions + 1)?
+}
+
+#[cfg(test)]
+mod test {
+    use super::{define_macro, undefine_macro};
+    use arrow::record_batch::RecordBatch;
+
+    #[test]
+    fn expansion() {
+        let opts = Default::default();
+        assert_eq!(
+            define_macro("(def zscore_test (x w) (/ (- x (TSMean w x)) (TSStd w x)))").unwrap(),
+            "zscore_test"
+        );
+        define_macro("(def mid_test () (/ (+ :bid :ask) 2))").unwrap();
+
+        let op = crate::ops::from_str::<RecordBatch>("(zscore_test (mid_test) 10)", &opts).unwrap();
+        assert_eq!(
+            op.to_string(),
+            "(/ (- (/ (+ :bid :ask) 2) (Mean 10 (/ (+ :bid :ask) 2))) (Std 10 (/ (+ :bid :ask) 2)))"
+        );
+        assert!(crate::ops::from_str::<RecordBatch>("(zscore_test :a)", &opts).is_err());
+
+        define_macro("(def loop_test (x) (loop_test x))").unwrap();
+        assert!(crate::ops::from_str::<RecordBatch>("(loop_test :a)", &opts).is_err());
+
+        for def in [
+            "(def Mean (x) x)",
+            "(def ts_mean (x) x)",
+            "(def f (:x) :x)",
+            "(def f (x x) x)",
+            "(define f (x) x)",
+            "(def f x)",
+        ] {
+            assert!(define_macro(def).is_err(), "{}", def);
+        }
+
+        for name in ["zscore_test", "mid_test", "loop_test"] {
+            assert!(undefine_macro(name));
+        }
+        assert!(crate::ops::from_str::<RecordBatch>("(mid_test)", &opts).is_err());
+    }
+}
@@ -1,26 +1,101 @@
 mod arithmetic;
 mod constant;
+mod diff;
+mod dot;
+mod evolve;
 mod getter;
+mod grammar;
+mod ichimoku;
+mod infix;
+mod inputs;
+mod lint;
 mod logic;
+mod macros;
+mod momentum_indicators;
+mod noise;
+mod optimize;
 mod overlap_studies;
 mod parser;
+mod path;
+mod pretty;
+mod random;
+mod registry;
+mod rescale;
+mod session;
+mod template;
+mod tick;
+mod volatility_indicators;
 mod window;
 
 pub use arithmetic::*;
+pub use diff::{diff, Difference};
+pub use dot::to_dot;
+pub use evolve::{crossover, mutate, prune, Mutation};
 pub use getter::*;
+pub use grammar::*;
+pub use ichimoku::*;
+pub use infix::{from_infix, Syntax};
+pub use lint::{lint, Warning};
 pub use logic::*;
+pub use macros::{define_macro, undefine_macro};
+pub use momentum_indicators::*;
+pub use noise::*;
+pub use optimize::*;
 pub use overlap_studies::*;
-pub use parser::from_str;
+pub use parser::{
+    from_str, to_duration, to_fraction, to_usize, Parameter, ParseError, ParseOptions,
+};
+pub use path::{path_of, resolve_path};
+pub use pretty::{pretty, round_constants};
+pub use random::random;
+pub use registry::{register, unregister, Constructor};
+pub use rescale::scale_windows;
+pub use session::*;
+pub use template::Template;
+pub use tick::*;
+pub use volatility_indicators::*;
 pub use window::*;
 
-use crate::ticker_batch::TickerBatch;
+use crate::ticker_batch::{is_supported, lookup, Lookup, TickerBatch};
 use anyhow::{anyhow, Error, Result};
+use arrow::datatypes::Schema;
 use dyn_clone::DynClone;
 use fehler::{throw, throws};
-use std::borrow::Cow;
+use itertools::Itertools;
+use std::{borrow::Cow, collections::HashMap};
+use thiserror::Error;
 
 pub type BoxOp<T> = Box<dyn Operator<T>>;
 
+/// A node did not produce exactly one value per row of the input batch.
+#[derive(Debug, Error)]
+#[error("{node} produced {got} values for a batch of {expected} rows")]
+pub struct LengthMismatch {
+    pub node: String,
+    pub expected: usize,
+    pub got: usize,
+    /// The index of the node (as used by `Operator::get`) in the factor which ran `checked_update`, see `path_of`
+    /// for its path. None if the node could not be located, i.e. a node on the way did not tell which of its
+    /// inputs failed (see `at_child`).
+    pub index: Option<usize>,
+    // Whether `index` is still relative to a child, waiting for its parent to add the offset of the child
+    pending: bool,
+}
+
+/// Locate a `LengthMismatch` raised by the child at `offset` (as in `Operator::child_indices`) of a node, e.g.
+/// `self.inner.checked_update(tb).map_err(at_child(1))?`. The other errors are left as they are.
+pub fn at_child(offset: usize) -> impl Fn(Error) -> Error {
+    move |mut e| {
+        if let Some(mismatch) = e.downcast_mut::<LengthMismatch>() {
+            if mismatch.pending {
+                mismatch.index = mismatch.index.map(|index| index + offset);
+                mismatch.pending = false;
+            }
+        }
+        e
+    }
+}
+
 pub trait Named {
     const NAME: &'static str;
 }
@@ -42,6 +117,78 @@ where
     fn get(&self, i: usize) -> Option<BoxOp<T>>;
     fn insert(&mut self, i: usize, subtree: BoxOp<T>) -> Option<BoxOp<T>>; // insert the subtree, return the subtree swaped out
 
+    /// Whether the outputs are all 0 or 1 (or NaN), e.g. the node is a comparison.
+    fn is_predicate(&self) -> bool {
+        false
+    }
+
+    /// Whether each output only depends on the inputs in the same row, i.e. the node keeps no state
+    /// across rows and has no randomness. Such nodes over constants are constants (see `fold_constants`).
+    fn is_pointwise(&self) -> bool {
+        false
+    }
+
+    /// Whether the node can only be the root of a factor, e.g. it restarts its input at a new session, which then
+    /// produces NaNs in the middle of the replay while it warms up again. Any other node would fail on these NaNs.
+    fn is_root_only(&self) -> bool {
+        false
+    }
+
+    /// Whether the node may produce NaNs or infinities after its warm-up, e.g. `(/ ieee :a :b)`. The pointwise nodes
+    /// pass these on from their inputs, any other node cannot take them, as they would stay in its state for good.
+    fn is_nullable(&self) -> bool {
+        self.is_pointwise()
+            && self
+                .child_indices()
+                .into_iter()
+                .filter_map(|i| self.get(i))
+                .any(|child| child.is_nullable())
+    }
+
+    /// The input which may produce NaNs or infinities after its warm-up (see `is_nullable`) that this node cannot take.
+    fn nullable_input(&self) -> Option<BoxOp<T>> {
+        if self.is_pointwise() {
+            return None;
+        }
+        self.child_indices()
+            .into_iter()
+            .filter_map(|i| self.get(i))
+            .find(|child| child.is_nullable())
+    }
+
+    /// Let the NaNs in the data reach this node, for a parent which takes them, e.g. a logic function. The pointwise
+    /// nodes pass the NaNs on and ask the same from their inputs, the other nodes keep failing on them.
+    fn allow_nan(&mut self) {
+        if !self.is_pointwise() {
+            return;
+        }
+        for i in self.child_indices() {
+            if let Some(mut child) = self.get(i) {
+                child.allow_nan();
+                self.insert(i, child);
+            }
+        }
+    }
+
+    /// The number of rows, the current one included, the output at a row depends on, i.e. the window sizes
+    /// added up along the longest path. At least `ready_offset() + 1`, more when the windows have a smaller
+    /// `min_periods`. None if the output depends on the whole history.
+    fn lookback(&self) -> Option<usize> {
+        self.child_indices()
+            .into_iter()
+            .filter_map(|i| self.get(i))
+            .try_fold(1, |lookback, child| Some(lookback.max(child.lookback()?)))
+    }
+
+    /// An estimate of the bytes held across rows by this node and its descendants, mostly the windows.
+    fn state_bytes(&self) -> usize {
+        self.child_indices()
+            .into_iter()
+            .filter_map(|i| self.get(i))
+            .map(|child| child.state_bytes())
+            .sum()
+    }
+
     fn boxed(self) -> BoxOp<T>
     where
         Self: Sized,
@@ -49,6 +196,139 @@ where
         Box::new(self)
     }
 
+    /// Rename the columns read by this node and its descendants according to `mapping`, so that one factor
+    /// can be replayed on datasets with different naming schemes. The columns not in `mapping` are kept.
+    fn remap_columns(&mut self, mapping: &HashMap<String, String>) {
+        for i in self.child_indices() {
+            if let Some(mut child) = self.get(i) {
+                child.remap_columns(mapping);
+                self.insert(i, child);
+            }
+        }
+    }
+
+    /// Substitute `values` for the placeholders of a template (see `Template`) and build the factor.
+    /// A factor without placeholders only binds an empty set of values, to a copy of itself.
+    fn bind(&self, values: &HashMap<String, f64>) -> Result<BoxOp<T>> {
+        match values.keys().next() {
+            Some(name) => Err(anyhow!("{} has no placeholder ?{}", self.to_string(), name)),
+            None => self
+                .get(0)
+                .ok_or_else(|| anyhow!("{} cannot be copied", self.to_string())),
+        }
+    }
+
+    /// Drop the observations held in the window of this node, e.g. at the start of a new session.
+    /// The children are left untouched. The node produces NaNs again until its window is refilled,
+    /// so only a node which can only be the root (see `is_root_only`) evicts its input.
+    fn evict(&mut self) -> Result<()> {
+        Err(anyhow!("{} is not a window operator", self.to_string()))
+    }
+
+    /// `update`, then make sure there is one output per row of `tb`. A mismatch coming from below is located on
+    /// the way up, see `LengthMismatch::index`.
+    #[throws(Error)]
+    fn checked_update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let vals = self.update(tb).map_err(|mut e| {
+            if let Some(mismatch) = e.downcast_mut::<LengthMismatch>() {
+                if mismatch.pending {
+                    // The child which failed did not go through `at_child`
+                    mismatch.index = None;
+                }
+                mismatch.pending = true;
+            }
+            e
+        })?;
+        if vals.len() != tb.len() {
+            throw!(LengthMismatch {
+                node: self.to_string(),
+                expected: tb.len(),
+                got: vals.len(),
+                index: Some(0),
+                pending: true,
+            })
+        }
+        vals
+    }
+
+    /// Check the columns used against the schema without running the factor, so that a factor reading
+    /// missing, ambiguous or non-float columns fails fast, with all the problems listed.
+    fn validate(&self, schema: &Schema) -> Result<()> {
+        let mut missing = vec![];
+        let mut ambiguous = vec![];
+        let mut unsupported = vec![];
+        for col in self.columns().into_iter().unique() {
+            let names = schema.fields().iter().map(|f| f.name().as_str());
+            match lookup(names, &col) {
+                Lookup::Found(i) => {
+                    let dt = schema.field(i).data_type();
+                    if !is_supported(dt) {
+                        unsupported.push(format!("{} ({})", col, dt));
+                    }
+                }
+                Lookup::Missing => missing.push(col),
+                Lookup::Ambiguous(n) => ambiguous.push(format!("{} (x{})", col, n)),
+            }
+        }
+
+        let mut problems = vec![];
+        if !missing.is_empty() {
+            problems.push(format!("missing columns {}", missing.join(", ")));
+        }
+        if !ambiguous.is_empty() {
+            problems.push(format!(
+                "ambiguous columns {}, qualify them as :<name>#<k> to pick the k-th one",
+                ambiguous.join(", ")
+            ));
+        }
+        if !unsupported.is_empty() {
+            problems.push(format!("unsupported columns {}", unsupported.join(", ")));
+        }
+        // The factors built by replacing the subtrees are not checked by the parser
+        let nested: Vec<_> = (1..self.len())
+            .filter_map(|i| self.get(i))
+            .filter(|node| node.is_root_only())
+            .map(|node| node.to_string())
+            .collect();
+        if !nested.is_empty() {
+            problems.push(format!(
+                "{} can only be the root of a factor",
+                nested.join(", ")
+            ));
+        }
+        let nullable: Vec<_> = (0..self.len())
+            .filter_map(|i| self.get(i))
+            .filter_map(|node| {
+                let input = node.nullable_input()?;
+                Some(format!("{} in {}", input.to_string(), node.to_string()))
+            })
+            .collect();
+        if !nullable.is_empty() {
+            problems.push(format!(
+                "{} may produce NaNs after the warm-up",
+                nullable.join(", ")
+            ));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("{}: {}", self.to_string(), problems.join("; ")))
+        }
+    }
+
+    /// If `tb` is replayed in strict mode, make sure at least one of the inputs is NaN during the warm-up period.
+    #[throws(Error)]
+    fn check_warmup(&self, tb: &T, vals: &[f64]) {
+        if tb.is_strict() && !vals.iter().any(|v| v.is_nan()) {
+            throw!(anyhow!(
+                "{} received {:?} during its warm-up period",
+                self.to_string(),
+                vals
+            ))
+        }
+    }
+
     #[throws(Error)]
     fn fchecked(&self, f: f64) -> f64 {
         let c = f.classify();
@@ -0,0 +1,1423 @@
+//! The momentum indicators of TA-Lib.
+
+use std::{borrow::Cow, collections::VecDeque, iter::FromIterator, mem};
+
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+
+use crate::ticker_batch::TickerBatch;
+
+use super::{
+    at_child,
+    inputs::{split_bars, Inputs},
+    overlap_studies::Smoothing,
+    parser::{to_usize, Parameter},
+    volatility_indicators::true_range,
+    BoxOp, MonotonicQueue, Named, Operator,
+};
+
+// The MACD line, the signal line (its EMA) and their difference, which only differ in the output
+macro_rules! impl_macd {
+    ($($(#[$doc:meta])* $op:ident |$macd:ident, $signal:ident| $select:expr;)+) => {
+        $(
+            $(#[$doc])*
+            pub struct $op<T> {
+                inner: BoxOp<T>,
+                fast: usize,
+                slow: usize,
+                signal: usize,
+
+                i: usize,
+                skipped: usize, // the values not seen by the fast EMA, so that it starts along with the slow one
+                fast_ema: Smoothing,
+                slow_ema: Smoothing,
+                signal_ema: Smoothing,
+            }
+
+            impl<T> Clone for $op<T> {
+                fn clone(&self) -> Self {
+                    Self::new(self.inner.clone(), self.fast, self.slow, self.signal)
+                }
+            }
+
+            impl<T> $op<T> {
+                /// The fast and the slow periods are swapped if `fast > slow`, as TA-Lib does.
+                pub fn new(inner: BoxOp<T>, fast: usize, slow: usize, signal: usize) -> Self {
+                    let (fast, slow) = if fast > slow { (slow, fast) } else { (fast, slow) };
+                    Self {
+                        inner,
+                        fast,
+                        slow,
+                        signal,
+
+                        i: 0,
+                        skipped: 0,
+                        fast_ema: Smoothing::new(fast),
+                        slow_ema: Smoothing::new(slow),
+                        signal_ema: Smoothing::new(signal),
+                    }
+                }
+
+                fn clear(&mut self) {
+                    self.skipped = 0;
+                    self.fast_ema.clear();
+                    self.slow_ema.clear();
+                    self.signal_ema.clear();
+                }
+            }
+
+            impl<T> Named for $op<T> {
+                const NAME: &'static str = stringify!($op);
+            }
+
+            impl<T: TickerBatch> Operator<T> for $op<T> {
+                fn reset(&mut self) {
+                    self.inner.reset();
+                    self.clear();
+                    self.i = 0;
+                }
+
+                #[throws(Error)]
+                fn evict(&mut self) {
+                    self.clear();
+                }
+
+                #[throws(Error)]
+                fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+                    let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+
+                    let mut results = Vec::with_capacity(tb.len());
+
+                    for &val in vals {
+                        if self.i < self.inner.ready_offset() {
+                            self.check_warmup(tb, &[val])?;
+                            results.push(f64::NAN);
+                            self.i += 1;
+                            continue;
+                        }
+
+                        let slow = self.slow_ema.push(val);
+                        let fast = if self.skipped < self.slow - self.fast {
+                            if !val.is_nan() {
+                                self.skipped += 1;
+                            }
+                            f64::NAN
+                        } else {
+                            self.fast_ema.push(val)
+                        };
+                        let $macd = fast - slow;
+                        let $signal = self.signal_ema.push($macd);
+
+                        // All the three lines start along with the signal line
+                        results.push(if $signal.is_nan() { f64::NAN } else { $select });
+                    }
+
+                    results.into()
+                }
+
+                fn ready_offset(&self) -> usize {
+                    self.inner.ready_offset() + self.slow - 1 + self.signal - 1
+                }
+
+                // Every value ever seen keeps a (vanishing) weight
+                fn lookback(&self) -> Option<usize> {
+                    None
+                }
+
+                fn state_bytes(&self) -> usize {
+                    3 * mem::size_of::<Smoothing>() + self.inner.state_bytes()
+                }
+
+                fn to_string(&self) -> String {
+                    format!(
+                        "({} {} {} {} {})",
+                        Self::NAME,
+                        self.fast,
+                        self.slow,
+                        self.signal,
+                        self.inner.to_string()
+                    )
+                }
+
+                fn depth(&self) -> usize {
+                    1 + self.inner.depth()
+                }
+
+                fn len(&self) -> usize {
+                    self.inner.len() + 1
+                }
+
+                fn child_indices(&self) -> Vec<usize> {
+                    vec![1]
+                }
+
+                fn columns(&self) -> Vec<String> {
+                    self.inner.columns()
+                }
+
+                #[throws(as Option)]
+                fn get(&self, i: usize) -> BoxOp<T> {
+                    if i == 0 {
+                        return self.clone().boxed();
+                    }
+                    let i = i - 1;
+
+                    let ns = self.inner.len();
+
+                    if i < ns {
+                        self.inner.get(i)?
+                    } else {
+                        throw!()
+                    }
+                }
+
+                #[throws(as Option)]
+                fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+                    if i == 0 {
+                        unreachable!("cannot insert root");
+                    }
+                    let i = i - 1;
+
+                    let ns = self.inner.len();
+                    if i < ns {
+                        if i == 0 {
+                            return mem::replace(&mut self.inner, op) as BoxOp<T>;
+                        }
+                        self.inner.insert(i, op)?
+                    } else {
+                        throw!()
+                    }
+                }
+            }
+
+            impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<$op<T>> {
+                #[throws(Error)]
+                fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> $op<T> {
+                    let name = $op::<T>::NAME;
+                    let mut iter = iter.into_iter();
+
+                    let mut periods = [0; 3];
+                    for (period, param) in periods.iter_mut().zip(["fast", "slow", "signal"]) {
+                        let Some(Parameter::Constant(c)) = iter.next() else {
+                            throw!(anyhow!("<{}> for {} should be an constant", param, name));
+                        };
+                        *period = to_usize(name, param, c, 1)?;
+                    }
+
+                    let inner = iter
+                        .next()
+                        .ok_or_else(|| anyhow!("Too few parameters for {}", name))?
+                        .to_operator()
+                        .ok_or_else(|| anyhow!("<inner> for {} should be an operator", name))?;
+
+                    if iter.count() != 0 {
+                        throw!(anyhow!("Too many parameters for {}", name))
+                    }
+
+                    let [fast, slow, signal] = periods;
+                    $op::new(inner, fast, slow, signal)
+                }
+            }
+        )+
+    };
+}
+
+impl_macd! {
+    /// The MACD line of TA-Lib, the fast EMA minus the slow EMA. The fast EMA is seeded along with the slow one,
+    /// i.e. by the mean of the last `fast` values of the first `slow` ones.
+    MACD |macd, _signal| macd;
+
+    /// The signal line of the MACD, the EMA of the MACD line.
+    MACDSignal |_macd, signal| signal;
+
+    /// The MACD histogram, the MACD line minus the signal line.
+    MACDHist |macd, signal| macd - signal;
+}
+
+/// The mean of the last `win_size` values. The NaNs are skipped, and produce a NaN.
+#[derive(Clone)]
+struct RollingMean {
+    win_size: usize,
+
+    window: VecDeque<f64>,
+    sum: f64,
+}
+
+impl RollingMean {
+    fn new(win_size: usize) -> Self {
+        Self {
+            win_size,
+
+            window: VecDeque::with_capacity(win_size),
+            sum: 0.,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.window.clear();
+        self.sum = 0.;
+    }
+
+    fn push(&mut self, val: f64) -> f64 {
+        if val.is_nan() {
+            return f64::NAN;
+        }
+
+        if self.window.len() == self.win_size {
+            self.sum -= self.window.pop_front().unwrap();
+        }
+        self.window.push_back(val);
+        self.sum += val;
+
+        if self.window.len() == self.win_size {
+            self.sum / self.win_size as f64
+        } else {
+            f64::NAN
+        }
+    }
+}
+
+/// The %K of the stochastic oscillator of TA-Lib, `100 * (close - lowest low) / (highest high - lowest low)`
+/// over the last `win_size` bars (0 if the range is empty), smoothed by its mean over the last `smooth_k` values.
+/// The bars with a NaN are skipped, and produce a NaN.
+pub struct StochK<T> {
+    win_size: usize,
+    smooth_k: usize,
+    inputs: Inputs<T, 3>,
+
+    highest: MonotonicQueue,
+    lowest: MonotonicQueue,
+    k: RollingMean,
+    i: usize,
+}
+
+impl<T> Clone for StochK<T> {
+    fn clone(&self) -> Self {
+        let [high, low, close] = self.inputs.clone().0;
+        Self::new(self.win_size, self.smooth_k, high, low, close)
+    }
+}
+
+impl<T> StochK<T> {
+    pub fn new(
+        win_size: usize,
+        smooth_k: usize,
+        high: BoxOp<T>,
+        low: BoxOp<T>,
+        close: BoxOp<T>,
+    ) -> Self {
+        Self {
+            win_size,
+            smooth_k,
+            inputs: Inputs([high, low, close]),
+
+            highest: MonotonicQueue::max(win_size),
+            lowest: MonotonicQueue::min(win_size),
+            k: RollingMean::new(smooth_k),
+            i: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.highest.clear();
+        self.lowest.clear();
+        self.k.clear();
+    }
+}
+
+impl<T> Named for StochK<T> {
+    const NAME: &'static str = "StochK";
+}
+
+impl<T: TickerBatch> Operator<T> for StochK<T> {
+    fn reset(&mut self) {
+        self.inputs.reset();
+        self.clear();
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.clear();
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let inputs = self.inputs.update(tb)?;
+        let (highs, lows, closes) = (&*inputs[0], &*inputs[1], &*inputs[2]);
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for ((&high, &low), &close) in highs.iter().zip(lows).zip(closes) {
+            if self.i < self.inputs.ready_offset() {
+                self.check_warmup(tb, &[high, low, close])?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            if high.is_nan() || low.is_nan() || close.is_nan() {
+                results.push(f64::NAN);
+                continue;
+            }
+            self.highest.push(high);
+            self.lowest.push(low);
+
+            let fast_k = if self.highest.seq() < self.win_size {
+                f64::NAN
+            } else {
+                let highest = self.highest.front().unwrap().1;
+                let lowest = self.lowest.front().unwrap().1;
+                if highest > lowest {
+                    100. * (close - lowest) / (highest - lowest)
+                } else {
+                    0.
+                }
+            };
+            results.push(self.k.push(fast_k));
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inputs.ready_offset() + self.win_size - 1 + self.smooth_k - 1
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.inputs.lookback()? + self.win_size - 1 + self.smooth_k - 1)
+    }
+
+    fn state_bytes(&self) -> usize {
+        2 * self.win_size * mem::size_of::<(usize, f64)>()
+            + self.smooth_k * mem::size_of::<f64>()
+            + self.inputs.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "({} {} {} {})",
+            Self::NAME,
+            self.win_size,
+            self.smooth_k,
+            self.inputs.to_string()
+        )
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inputs.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inputs.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        self.inputs.child_indices()
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inputs.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        self.inputs.get(i)?
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        self.inputs.insert(i, op)?
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<StochK<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> StochK<T> {
+        let name = StochK::<T>::NAME;
+        let ([win_size, smooth_k], inputs) = split_bars(name, ["win_size", "smooth_k"], HLC, iter)?;
+        let [high, low, close] = inputs.0;
+        StochK::new(win_size, smooth_k, high, low, close)
+    }
+}
+
+const HLC: [&str; 3] = ["high", "low", "close"];
+
+/// The %D of the stochastic oscillator of TA-Lib, the mean of `StochK` over the last `smooth_d` values.
+pub struct StochD<T> {
+    k: StochK<T>,
+    smooth_d: usize,
+
+    d: RollingMean,
+}
+
+impl<T> Clone for StochD<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.k.clone(), self.smooth_d)
+    }
+}
+
+impl<T> StochD<T> {
+    pub fn new(k: StochK<T>, smooth_d: usize) -> Self {
+        Self {
+            k,
+            smooth_d,
+
+            d: RollingMean::new(smooth_d),
+        }
+    }
+}
+
+impl<T> Named for StochD<T> {
+    const NAME: &'static str = "StochD";
+}
+
+impl<T: TickerBatch> Operator<T> for StochD<T> {
+    fn reset(&mut self) {
+        self.k.reset();
+        self.d.clear();
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.k.evict()?;
+        self.d.clear();
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let ks = self.k.update(tb)?;
+        let d = &mut self.d;
+        ks.iter().map(|&k| d.push(k)).collect::<Vec<_>>().into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.k.ready_offset() + self.smooth_d - 1
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.k.lookback()? + self.smooth_d - 1)
+    }
+
+    fn state_bytes(&self) -> usize {
+        self.smooth_d * mem::size_of::<f64>() + self.k.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "({} {} {} {} {})",
+            Self::NAME,
+            self.k.win_size,
+            self.k.smooth_k,
+            self.smooth_d,
+            self.k.inputs.to_string()
+        )
+    }
+
+    fn depth(&self) -> usize {
+        self.k.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.k.len()
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        self.k.child_indices()
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.k.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        self.k.get(i)?
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        self.k.insert(i, op)?
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<StochD<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> StochD<T> {
+        let name = StochD::<T>::NAME;
+        let ([win_size, smooth_k, smooth_d], inputs) =
+            split_bars(name, ["win_size", "smooth_k", "smooth_d"], HLC, iter)?;
+        let [high, low, close] = inputs.0;
+        StochD::new(StochK::new(win_size, smooth_k, high, low, close), smooth_d)
+    }
+}
+
+/// The mean absolute deviation of the last `win_size` values around their mean, from the values sorted and split
+/// at the previous mean. The mean only moves a little from one value to the next, and so does the split.
+#[derive(Clone)]
+struct MeanDeviation {
+    win_size: usize,
+
+    window: VecDeque<f64>,
+    sorted: Vec<f64>,
+    sum: f64,
+    pivot: f64,
+    below: usize, // the number of the values less than the pivot, i.e. the leading ones in `sorted`
+    sum_below: f64,
+    pushed: usize,
+}
+
+impl MeanDeviation {
+    fn new(win_size: usize) -> Self {
+        Self {
+            win_size,
+
+            window: VecDeque::with_capacity(win_size),
+            sorted: Vec::with_capacity(win_size),
+            sum: 0.,
+            pivot: 0.,
+            below: 0,
+            sum_below: 0.,
+            pushed: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.window.clear();
+        self.sorted.clear();
+        self.sum = 0.;
+        self.pivot = 0.;
+        self.below = 0;
+        self.sum_below = 0.;
+        self.pushed = 0;
+    }
+
+    /// The mean and the mean absolute deviation once the window is full.
+    fn push(&mut self, val: f64) -> Option<(f64, f64)> {
+        if self.window.len() == self.win_size {
+            let old = self.window.pop_front().unwrap();
+            let pos = self.sorted.partition_point(|&v| v < old);
+            self.sorted.remove(pos);
+            self.sum -= old;
+            if old < self.pivot {
+                self.below -= 1;
+                self.sum_below -= old;
+            }
+        }
+        self.window.push_back(val);
+        let pos = self.sorted.partition_point(|&v| v < val);
+        self.sorted.insert(pos, val);
+        self.sum += val;
+        if val < self.pivot {
+            self.below += 1;
+            self.sum_below += val;
+        }
+
+        if self.window.len() < self.win_size {
+            return None;
+        }
+
+        // Sum the window again once in a while, so that the rounding errors do not pile up
+        self.pushed += 1;
+        if self.pushed % self.win_size == 0 {
+            self.sum = self.window.iter().sum();
+            self.sum_below = self.sorted[..self.below].iter().sum();
+        }
+
+        // Move the split to the current mean
+        let n = self.win_size as f64;
+        let mean = self.sum / n;
+        while self.below > 0 && self.sorted[self.below - 1] >= mean {
+            self.below -= 1;
+            self.sum_below -= self.sorted[self.below];
+        }
+        while self.below < self.sorted.len() && self.sorted[self.below] < mean {
+            self.sum_below += self.sorted[self.below];
+            self.below += 1;
+        }
+        self.pivot = mean;
+
+        let above = self.win_size - self.below;
+        let sum_above = self.sum - self.sum_below;
+        // The mean of a flat window is off by the rounding errors, so is the deviation from it
+        let mad = if self.sorted[0] == self.sorted[self.win_size - 1] {
+            0.
+        } else {
+            (sum_above - self.sum_below + mean * (self.below as f64 - above as f64)) / n
+        };
+        Some((mean, mad))
+    }
+}
+
+/// The commodity channel index of TA-Lib, `(tp - mean) / (0.015 * mad)` where `tp = (high + low + close) / 3`
+/// is the typical price, `mean` and `mad` the mean and the mean absolute deviation of it over the last
+/// `win_size` bars. It is 0 if the deviation is. The bars with a NaN are skipped, and produce a NaN.
+pub struct CCI<T> {
+    win_size: usize,
+    inputs: Inputs<T, 3>,
+
+    deviation: MeanDeviation,
+    i: usize,
+}
+
+impl<T> Clone for CCI<T> {
+    fn clone(&self) -> Self {
+        let [high, low, close] = self.inputs.clone().0;
+        Self::new(self.win_size, high, low, close)
+    }
+}
+
+impl<T> CCI<T> {
+    pub fn new(win_size: usize, high: BoxOp<T>, low: BoxOp<T>, close: BoxOp<T>) -> Self {
+        Self {
+            win_size,
+            inputs: Inputs([high, low, close]),
+
+            deviation: MeanDeviation::new(win_size),
+            i: 0,
+        }
+    }
+}
+
+impl<T> Named for CCI<T> {
+    const NAME: &'static str = "CCI";
+}
+
+impl<T: TickerBatch> Operator<T> for CCI<T> {
+    fn reset(&mut self) {
+        self.inputs.reset();
+        self.deviation.clear();
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.deviation.clear();
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let inputs = self.inputs.update(tb)?;
+        let (highs, lows, closes) = (&*inputs[0], &*inputs[1], &*inputs[2]);
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for ((&high, &low), &close) in highs.iter().zip(lows).zip(closes) {
+            if self.i < self.inputs.ready_offset() {
+                self.check_warmup(tb, &[high, low, close])?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            let tp = (high + low + close) / 3.;
+            if tp.is_nan() {
+                results.push(f64::NAN);
+                continue;
+            }
+            let val = match self.deviation.push(tp) {
+                Some((_, mad)) if mad == 0. => 0.,
+                Some((mean, mad)) => (tp - mean) / (0.015 * mad),
+                None => f64::NAN,
+            };
+            results.push(val);
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inputs.ready_offset() + self.win_size - 1
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.inputs.lookback()? + self.win_size - 1)
+    }
+
+    fn state_bytes(&self) -> usize {
+        2 * self.win_size * mem::size_of::<f64>() + self.inputs.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "({} {} {})",
+            Self::NAME,
+            self.win_size,
+            self.inputs.to_string()
+        )
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inputs.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inputs.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        self.inputs.child_indices()
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inputs.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        self.inputs.get(i)?
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        self.inputs.insert(i, op)?
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<CCI<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> CCI<T> {
+        let name = CCI::<T>::NAME;
+        let ([win_size], inputs) = split_bars(name, ["win_size"], HLC, iter)?;
+        let [high, low, close] = inputs.0;
+        CCI::new(win_size, high, low, close)
+    }
+}
+
+// The Aroon up, down and oscillator, which only differ in the output
+macro_rules! impl_aroon {
+    ($($(#[$doc:meta])* $op:ident |$up:ident, $down:ident| $select:expr;)+) => {
+        $(
+            $(#[$doc])*
+            pub struct $op<T> {
+                win_size: usize,
+                inputs: Inputs<T, 2>,
+
+                highest: MonotonicQueue,
+                lowest: MonotonicQueue,
+                i: usize,
+            }
+
+            impl<T> Clone for $op<T> {
+                fn clone(&self) -> Self {
+                    let [high, low] = self.inputs.clone().0;
+                    Self::new(self.win_size, high, low)
+                }
+            }
+
+            impl<T> $op<T> {
+                pub fn new(win_size: usize, high: BoxOp<T>, low: BoxOp<T>) -> Self {
+                    // The window holds the current bar and the `win_size` bars before
+                    Self {
+                        win_size,
+                        inputs: Inputs([high, low]),
+
+                        highest: MonotonicQueue::max(win_size + 1).prefer_latest(),
+                        lowest: MonotonicQueue::min(win_size + 1).prefer_latest(),
+                        i: 0,
+                    }
+                }
+
+                fn clear(&mut self) {
+                    self.highest.clear();
+                    self.lowest.clear();
+                }
+            }
+
+            impl<T> Named for $op<T> {
+                const NAME: &'static str = stringify!($op);
+            }
+
+            impl<T: TickerBatch> Operator<T> for $op<T> {
+                fn reset(&mut self) {
+                    self.inputs.reset();
+                    self.clear();
+                    self.i = 0;
+                }
+
+                #[throws(Error)]
+                fn evict(&mut self) {
+                    self.clear();
+                }
+
+                #[throws(Error)]
+                fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+                    let inputs = self.inputs.update(tb)?;
+                    let (highs, lows) = (&*inputs[0], &*inputs[1]);
+
+                    let mut results = Vec::with_capacity(tb.len());
+
+                    for (&high, &low) in highs.iter().zip(lows) {
+                        if self.i < self.inputs.ready_offset() {
+                            self.check_warmup(tb, &[high, low])?;
+                            results.push(f64::NAN);
+                            self.i += 1;
+                            continue;
+                        }
+
+                        if high.is_nan() || low.is_nan() {
+                            results.push(f64::NAN);
+                            continue;
+                        }
+                        self.highest.push(high);
+                        self.lowest.push(low);
+                        if self.highest.seq() <= self.win_size {
+                            results.push(f64::NAN);
+                            continue;
+                        }
+
+                        // 100 if the extremum is the current bar, down to 0 if it is the oldest one
+                        let n = self.win_size as f64;
+                        let since = |queue: &MonotonicQueue| (queue.seq() - queue.front().unwrap().0) as f64;
+                        let $up = 100. * (n - since(&self.highest)) / n;
+                        let $down = 100. * (n - since(&self.lowest)) / n;
+                        results.push($select);
+                    }
+
+                    results.into()
+                }
+
+                fn ready_offset(&self) -> usize {
+                    self.inputs.ready_offset() + self.win_size
+                }
+
+                fn lookback(&self) -> Option<usize> {
+                    Some(self.inputs.lookback()? + self.win_size)
+                }
+
+                fn state_bytes(&self) -> usize {
+                    2 * (self.win_size + 1) * mem::size_of::<(usize, f64)>() + self.inputs.state_bytes()
+                }
+
+                fn to_string(&self) -> String {
+                    format!("({} {} {})", Self::NAME, self.win_size, self.inputs.to_string())
+                }
+
+                fn depth(&self) -> usize {
+                    1 + self.inputs.depth()
+                }
+
+                fn len(&self) -> usize {
+                    self.inputs.len() + 1
+                }
+
+                fn child_indices(&self) -> Vec<usize> {
+                    self.inputs.child_indices()
+                }
+
+                fn columns(&self) -> Vec<String> {
+                    self.inputs.columns()
+                }
+
+                #[throws(as Option)]
+                fn get(&self, i: usize) -> BoxOp<T> {
+                    if i == 0 {
+                        return self.clone().boxed();
+                    }
+                    self.inputs.get(i)?
+                }
+
+                #[throws(as Option)]
+                fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+                    if i == 0 {
+                        unreachable!("cannot insert root");
+                    }
+                    self.inputs.insert(i, op)?
+                }
+            }
+
+            impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<$op<T>> {
+                #[throws(Error)]
+                fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> $op<T> {
+                    let name = $op::<T>::NAME;
+                    let ([win_size], inputs) = split_bars(name, ["win_size"], ["high", "low"], iter)?;
+                    let [high, low] = inputs.0;
+                    $op::new(win_size, high, low)
+                }
+            }
+        )+
+    };
+}
+
+impl_aroon! {
+    /// The Aroon up of TA-Lib, `100 * (win_size - bars since the highest high) / win_size` over the current bar and
+    /// the `win_size` bars before. The latest of the equal highs counts. The bars with a NaN are skipped, and produce a NaN.
+    AroonUp |up, _down| up;
+
+    /// The Aroon down of TA-Lib, `100 * (win_size - bars since the lowest low) / win_size`, see `AroonUp`.
+    AroonDown |_up, down| down;
+
+    /// The Aroon oscillator of TA-Lib, the Aroon up minus the Aroon down.
+    AroonOsc |up, down| up - down;
+}
+
+/// The money flow index of TA-Lib, `100 * positive flow / (positive flow + negative flow)` over the last `win_size` bars,
+/// where the money flow `tp * volume` of a bar is positive if the typical price `tp = (high + low + close) / 3` went up
+/// from the previous bar, negative if it went down. It is 0 without any flow. The bars with a NaN are skipped, and produce a NaN.
+pub struct MFI<T> {
+    win_size: usize,
+    inputs: Inputs<T, 4>,
+
+    prev_tp: f64,
+    flows: VecDeque<(f64, f64)>, // the positive and the negative flows
+    positive: f64,
+    negative: f64,
+    ups: usize, // the number of the positive flows, so that their sum is exactly 0 without any
+    downs: usize,
+    i: usize,
+}
+
+impl<T> Clone for MFI<T> {
+    fn clone(&self) -> Self {
+        let [high, low, close, volume] = self.inputs.clone().0;
+        Self::new(self.win_size, high, low, close, volume)
+    }
+}
+
+impl<T> MFI<T> {
+    pub fn new(
+        win_size: usize,
+        high: BoxOp<T>,
+        low: BoxOp<T>,
+        close: BoxOp<T>,
+        volume: BoxOp<T>,
+    ) -> Self {
+        Self {
+            win_size,
+            inputs: Inputs([high, low, close, volume]),
+
+            prev_tp: f64::NAN,
+            flows: VecDeque::with_capacity(win_size),
+            positive: 0.,
+            negative: 0.,
+            ups: 0,
+            downs: 0,
+            i: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.prev_tp = f64::NAN;
+        self.flows.clear();
+        self.positive = 0.;
+        self.negative = 0.;
+        self.ups = 0;
+        self.downs = 0;
+    }
+}
+
+impl<T> Named for MFI<T> {
+    const NAME: &'static str = "MFI";
+}
+
+impl<T: TickerBatch> Operator<T> for MFI<T> {
+    fn reset(&mut self) {
+        self.inputs.reset();
+        self.clear();
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.clear();
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let inputs = self.inputs.update(tb)?;
+        let (highs, lows, closes, volumes) = (&*inputs[0], &*inputs[1], &*inputs[2], &*inputs[3]);
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for row in 0..tb.len() {
+            let (high, low, close, volume) = (highs[row], lows[row], closes[row], volumes[row]);
+            if self.i < self.inputs.ready_offset() {
+                self.check_warmup(tb, &[high, low, close, volume])?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            let tp = (high + low + close) / 3.;
+            if tp.is_nan() || volume.is_nan() {
+                results.push(f64::NAN);
+                continue;
+            }
+            let prev_tp = mem::replace(&mut self.prev_tp, tp);
+            if prev_tp.is_nan() {
+                results.push(f64::NAN);
+                continue;
+            }
+
+            let flow = tp * volume;
+            let flow = if tp > prev_tp {
+                (flow, 0.)
+            } else if tp < prev_tp {
+                (0., flow)
+            } else {
+                (0., 0.)
+            };
+            if self.flows.len() == self.win_size {
+                let (positive, negative) = self.flows.pop_front().unwrap();
+                self.ups -= (positive != 0.) as usize;
+                self.downs -= (negative != 0.) as usize;
+                self.positive -= positive;
+                self.negative -= negative;
+            }
+            self.flows.push_back(flow);
+            self.ups += (flow.0 != 0.) as usize;
+            self.downs += (flow.1 != 0.) as usize;
+            self.positive += flow.0;
+            self.negative += flow.1;
+            if self.ups == 0 {
+                self.positive = 0.;
+            }
+            if self.downs == 0 {
+                self.negative = 0.;
+            }
+
+            let val = if self.flows.len() < self.win_size {
+                f64::NAN
+            } else if self.positive + self.negative == 0. {
+                0.
+            } else {
+                100. * self.positive / (self.positive + self.negative)
+            };
+            results.push(val);
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inputs.ready_offset() + self.win_size
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.inputs.lookback()? + self.win_size)
+    }
+
+    fn state_bytes(&self) -> usize {
+        self.win_size * mem::size_of::<(f64, f64)>() + self.inputs.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "({} {} {})",
+            Self::NAME,
+            self.win_size,
+            self.inputs.to_string()
+        )
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inputs.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inputs.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        self.inputs.child_indices()
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inputs.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        self.inputs.get(i)?
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        self.inputs.insert(i, op)?
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<MFI<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> MFI<T> {
+        let name = MFI::<T>::NAME;
+        let series = ["high", "low", "close", "volume"];
+        let ([win_size], inputs) = split_bars(name, ["win_size"], series, iter)?;
+        let [high, low, close, volume] = inputs.0;
+        MFI::new(win_size, high, low, close, volume)
+    }
+}
+
+/// The ultimate oscillator of TA-Lib, `100 * (4 * avg_fast + 2 * avg_medium + avg_slow) / 7` where `avg` is
+/// the buying pressure `close - min(low, previous close)` over the true range summed over the last `fast`,
+/// `medium` and `slow` bars, by default 7, 14 and 28. The periods are sorted, so the shortest one weighs 4.
+/// An average is 0 without any range. The bars with a NaN are skipped, and produce a NaN.
+pub struct UltOsc<T> {
+    periods: [usize; 3],
+    inputs: Inputs<T, 3>,
+
+    prev_close: f64,
+    bars: VecDeque<(f64, f64)>, // the buying pressures and the true ranges of the longest window
+    sums: [(f64, f64); 3],      // over the windows from the shortest one
+    ranges: [usize; 3], // the number of nonzero true ranges, so that the sums are exactly 0 without any
+    i: usize,
+}
+
+impl<T> Clone for UltOsc<T> {
+    fn clone(&self) -> Self {
+        let [high, low, close] = self.inputs.clone().0;
+        Self::new(self.periods, high, low, close)
+    }
+}
+
+impl<T> UltOsc<T> {
+    pub const DEFAULT_PERIODS: [usize; 3] = [7, 14, 28];
+
+    pub fn new(periods: [usize; 3], high: BoxOp<T>, low: BoxOp<T>, close: BoxOp<T>) -> Self {
+        Self {
+            periods,
+            inputs: Inputs([high, low, close]),
+
+            prev_close: f64::NAN,
+            bars: VecDeque::new(),
+            sums: [(0., 0.); 3],
+            ranges: [0; 3],
+            i: 0,
+        }
+    }
+
+    fn windows(&self) -> [usize; 3] {
+        let mut windows = self.periods;
+        windows.sort_unstable();
+        windows
+    }
+
+    fn clear(&mut self) {
+        self.prev_close = f64::NAN;
+        self.bars.clear();
+        self.sums = [(0., 0.); 3];
+        self.ranges = [0; 3];
+    }
+}
+
+impl<T> Named for UltOsc<T> {
+    const NAME: &'static str = "UltOsc";
+}
+
+impl<T: TickerBatch> Operator<T> for UltOsc<T> {
+    fn reset(&mut self) {
+        self.inputs.reset();
+        self.clear();
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.clear();
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let inputs = self.inputs.update(tb)?;
+        let (highs, lows, closes) = (&*inputs[0], &*inputs[1], &*inputs[2]);
+        let windows = self.windows();
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for ((&high, &low), &close) in highs.iter().zip(lows).zip(closes) {
+            if self.i < self.inputs.ready_offset() {
+                self.check_warmup(tb, &[high, low, close])?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            if high.is_nan() || low.is_nan() || close.is_nan() {
+                results.push(f64::NAN);
+                continue;
+            }
+            let prev_close = mem::replace(&mut self.prev_close, close);
+            if prev_close.is_nan() {
+                results.push(f64::NAN);
+                continue;
+            }
+
+            let bar = (
+                close - low.min(prev_close),
+                true_range(high, low, prev_close),
+            );
+            self.bars.push_back(bar);
+            for k in 0..3 {
+                self.sums[k].0 += bar.0;
+                self.sums[k].1 += bar.1;
+                self.ranges[k] += (bar.1 != 0.) as usize;
+                // The bar leaving the window
+                if self.bars.len() > windows[k] {
+                    let (pressure, range) = self.bars[self.bars.len() - 1 - windows[k]];
+                    self.sums[k].0 -= pressure;
+                    self.sums[k].1 -= range;
+                    self.ranges[k] -= (range != 0.) as usize;
+                }
+                if self.ranges[k] == 0 {
+                    self.sums[k] = (0., 0.);
+                }
+            }
+            if self.bars.len() > windows[2] {
+                self.bars.pop_front();
+            }
+
+            if self.bars.len() < windows[2] {
+                results.push(f64::NAN);
+                continue;
+            }
+            let avg =
+                |(pressure, range): (f64, f64)| if range == 0. { 0. } else { pressure / range };
+            let val = 4. * avg(self.sums[0]) + 2. * avg(self.sums[1]) + avg(self.sums[2]);
+            results.push(100. * val / 7.);
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inputs.ready_offset() + self.windows()[2]
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.inputs.lookback()? + self.windows()[2])
+    }
+
+    fn state_bytes(&self) -> usize {
+        self.windows()[2] * mem::size_of::<(f64, f64)>() + self.inputs.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        let [fast, medium, slow] = self.periods;
+        format!(
+            "({} {} {} {} {})",
+            Self::NAME,
+            fast,
+            medium,
+            slow,
+            self.inputs.to_string()
+        )
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inputs.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inputs.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        self.inputs.child_indices()
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inputs.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        self.inputs.get(i)?
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        self.inputs.insert(i, op)?
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<UltOsc<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> UltOsc<T> {
+        let name = UltOsc::<T>::NAME;
+        let params: Vec<_> = iter.into_iter().collect();
+        // The periods can be left out for the defaults
+        let (periods, inputs) = match params.first() {
+            Some(Parameter::Constant(_)) => {
+                split_bars(name, ["fast", "medium", "slow"], HLC, params)?
+            }
+            _ => {
+                let ([], inputs) = split_bars(name, [], HLC, params)?;
+                (UltOsc::<T>::DEFAULT_PERIODS, inputs)
+            }
+        };
+        let [high, low, close] = inputs.0;
+        UltOsc::new(periods, high, low, close)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ops::{from_str, Operator};
+    use arrow::{
+        array::{ArrayRef, Float64Array},
+        record_batch::RecordBatch,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn flat_windows() {
+        let opts = Default::default();
+        let xs: Vec<f64> = (0..50)
+            .map(|i| if i < 20 { 0.1 * i as f64 } else { 3.3 })
+            .collect();
+        let tb =
+            RecordBatch::try_from_iter(vec![("x", Arc::new(Float64Array::from(xs)) as ArrayRef)])
+                .unwrap();
+
+        // The typical price settles at 3.3 after a while, its deviation is exactly 0 then.
+        // Of the equal highs and lows, Aroon counts the latest ones
+        for (repr, expected) in [
+            ("(CCI 10 :x :x :x)", 0.),
+            ("(StochK 10 1 :x :x :x)", 0.),
+            ("(AroonUp 10 :x :x)", 100.),
+            ("(AroonOsc 10 :x :x)", 0.),
+            ("(MFI 10 :x :x :x :x)", 0.),
+            ("(UltOsc 10 2 5 :x :x :x)", 0.),
+        ] {
+            let mut op = from_str::<RecordBatch>(repr, &opts).unwrap();
+            let got = op.update(&tb).unwrap();
+            assert!(got[..9].iter().all(|v| v.is_nan()), "{}", repr);
+            assert!(
+                got[30..].iter().all(|&v| v == expected),
+                "{}: {:?}",
+                repr,
+                got
+            );
+        }
+    }
+}
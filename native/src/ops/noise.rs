@@ -0,0 +1,276 @@
+use super::{
+    at_child,
+    constant::fmt_constant,
+    parser::{to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use std::{borrow::Cow, iter::FromIterator, mem};
+
+/// A uniform draw in [-1, 1) for the n-th row. The draws are a pure function of the seed and the row
+/// (SplitMix64), so they are bitwise reproducible on every platform, regardless of how the data is batched.
+fn uniform(seed: u64, n: usize) -> f64 {
+    let mut z = seed.wrapping_add((n as u64).wrapping_add(1).wrapping_mul(0x9E3779B97F4A7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^= z >> 31;
+
+    // the top 53 bits make a float in [0, 1)
+    (z >> 11) as f64 / (1u64 << 53) as f64 * 2. - 1.
+}
+
+/// Uniform noise in [-scale, scale), reproducible given the seed.
+pub struct Noise {
+    seed: u64,
+    scale: f64,
+
+    n: usize,
+}
+
+impl Clone for Noise {
+    fn clone(&self) -> Self {
+        Self::new(self.seed, self.scale)
+    }
+}
+
+impl Noise {
+    pub fn new(seed: u64, scale: f64) -> Self {
+        Self { seed, scale, n: 0 }
+    }
+}
+
+impl Named for Noise {
+    const NAME: &'static str = "Noise";
+}
+
+impl<T: TickerBatch> Operator<T> for Noise {
+    fn reset(&mut self) {
+        self.n = 0;
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let results: Vec<_> = (self.n..self.n + tb.len())
+            .map(|n| self.scale * uniform(self.seed, n))
+            .collect();
+        self.n += tb.len();
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        0
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "({} {} {})",
+            Self::NAME,
+            self.seed,
+            fmt_constant(self.scale)
+        )
+    }
+
+    fn depth(&self) -> usize {
+        1
+    }
+
+    fn len(&self) -> usize {
+        1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        vec![]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        vec![]
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i != 0 {
+            throw!()
+        }
+        self.clone().boxed()
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, _: usize, _: BoxOp<T>) -> BoxOp<T> {
+        unreachable!("cannot insert root");
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<Noise> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> Noise {
+        let params: Vec<_> = iter.into_iter().collect();
+        match &*params {
+            [Parameter::Constant(seed), Parameter::Constant(scale)] => {
+                Noise::new(to_usize(Noise::NAME, "seed", *seed, 0)? as u64, *scale)
+            }
+            _ => throw!(anyhow!(
+                "{} expect a seed and a scale, got {:?}",
+                Noise::NAME,
+                params
+            )),
+        }
+    }
+}
+
+/// Perturbs a series multiplicatively by `1 + noise`, the noise being uniform in [-scale, scale)
+/// and reproducible given the seed.
+pub struct Jitter<T> {
+    seed: u64,
+    scale: f64,
+    inner: BoxOp<T>,
+
+    n: usize,
+}
+
+impl<T> Clone for Jitter<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.seed, self.scale, self.inner.clone())
+    }
+}
+
+impl<T> Jitter<T> {
+    pub fn new(seed: u64, scale: f64, inner: BoxOp<T>) -> Self {
+        Self {
+            seed,
+            scale,
+            inner,
+            n: 0,
+        }
+    }
+}
+
+impl<T> Named for Jitter<T> {
+    const NAME: &'static str = "Jitter";
+}
+
+impl<T: TickerBatch> Operator<T> for Jitter<T> {
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.n = 0;
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for &val in vals {
+            if self.n < self.inner.ready_offset() {
+                self.check_warmup(tb, &[val])?;
+                results.push(f64::NAN);
+                self.n += 1;
+                continue;
+            }
+
+            results.push(val * (1. + self.scale * uniform(self.seed, self.n)));
+            self.n += 1;
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inner.ready_offset()
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "({} {} {} {})",
+            Self::NAME,
+            self.seed,
+            fmt_constant(self.scale),
+            self.inner.to_string()
+        )
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inner.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        vec![1]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inner.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            self.inner.get(i)?
+        } else {
+            throw!()
+        }
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            if i == 0 {
+                return mem::replace(&mut self.inner, op) as BoxOp<T>;
+            }
+            self.inner.insert(i, op)?
+        } else {
+            throw!()
+        }
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<Jitter<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> Jitter<T> {
+        let mut params: Vec<_> = iter.into_iter().collect();
+        if params.len() != 3 {
+            throw!(anyhow!(
+                "{} expect a seed, a scale and a series, got {:?}",
+                Jitter::<T>::NAME,
+                params
+            ))
+        }
+
+        match (params.remove(0), params.remove(0), params.remove(0)) {
+            (Parameter::Constant(seed), Parameter::Constant(scale), Parameter::Operator(inner)) => {
+                Jitter::new(
+                    to_usize(Jitter::<T>::NAME, "seed", seed, 0)? as u64,
+                    scale,
+                    inner,
+                )
+            }
+            (a, b, c) => throw!(anyhow!(
+                "{name} expect a seed, a scale and a series, got ({name} {} {} {})",
+                a,
+                b,
+                c,
+                name = Jitter::<T>::NAME
+            )),
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! Rewrite the factor trees into equivalent ones, e.g. cheaper to replay or in a canonical form.
+
+use super::{BoxOp, Delay, Momentum, Named, Operator};
+use crate::ticker_batch::TickerBatch;
+use anyhow::Error;
+use fehler::throws;
+
+// Whether the subtree is made of pointwise nodes over constants only
+pub(super) fn is_constant<T: TickerBatch>(op: &dyn Operator<T>) -> bool {
+    op.is_pointwise()
+        && op
+            .child_indices()
+            .into_iter()
+            .all(|i| op.get(i).map_or(false, |child| is_constant(&*child)))
+}
+
+/// Evaluate the constant subtrees, e.g. `(* 2 (/ 1 4))` becomes `0.5`. The subtrees evaluating to NaN or inf are kept.
+#[throws(Error)]
+pub fn fold_constants<T: TickerBatch>(mut op: BoxOp<T>) -> BoxOp<T> {
+    if op.len() > 1 && is_constant(&*op) {
+        let mut fresh = op.clone();
+        fresh.reset();
+        let val = fresh.checked_update(&T::blank())?[0];
+        if val.is_finite() {
+            return val.boxed();
+        }
+        return op;
+    }
+
+    // From the last child on, so that folding a child does not move the ones before it
+    for i in op.child_indices().into_iter().rev() {
+        if let Some(child) = op.get(i) {
+            op.insert(i, fold_constants(child)?);
+        }
+    }
+    op
+}
+
+// The function name of a node, None for the leaves
+pub(super) fn head<T: TickerBatch>(op: &dyn Operator<T>) -> Option<String> {
+    let repr = op.to_string();
+    let repr = repr.strip_prefix('(')?;
+    repr.split(|c: char| c.is_whitespace() || c == ')')
+        .next()
+        .map(|head| head.to_string())
+}
+
+fn is_zero<T: TickerBatch>(op: &dyn Operator<T>) -> bool {
+    op.len() == 1 && op.to_string().parse::<f64>() == Ok(0.)
+}
+
+fn is_one<T: TickerBatch>(op: &dyn Operator<T>) -> bool {
+    op.len() == 1 && op.to_string().parse::<f64>() == Ok(1.)
+}
+
+// Apply the first matching rule at the root, the children are already simplified
+pub(super) fn rewrite<T: TickerBatch>(op: &BoxOp<T>) -> Option<BoxOp<T>> {
+    let func = head(&**op)?;
+    let children: Vec<_> = op
+        .child_indices()
+        .into_iter()
+        .map(|i| op.get(i))
+        .collect::<Option<_>>()?;
+
+    match (func.as_str(), &*children) {
+        ("+", [x, zero]) | ("+", [zero, x]) | ("-", [x, zero]) if is_zero(&**zero) => {
+            Some(x.clone())
+        }
+        ("*", [x, one]) | ("*", [one, x]) | ("/", [x, one]) if is_one(&**one) => Some(x.clone()),
+        ("Neg", [neg]) if head(&**neg).as_deref() == Some("Neg") => neg.get(1),
+        // Only if `x` is never NaN, otherwise the warm-up period or the NaNs let through would be lost. The warm-up
+        // of e.g. `FFill` is only known once replayed, its lookback tells it apart.
+        ("-", [x, y])
+            if x.ready_offset() == 0
+                && x.lookback() == Some(1)
+                && !x.is_nullable()
+                && x.to_string() == y.to_string() =>
+        {
+            Some(0f64.boxed())
+        }
+        // The difference with the lagged self is computed once, e.g. `(- x (Delay 3 x))` becomes `(Momentum 3 x)`
+        ("-", [x, lagged]) if head(&**lagged).as_deref() == Some(Delay::<T>::NAME) => {
+            let lag: usize = lagged.to_string().split(' ').nth(1)?.parse().ok()?;
+            if lag == 0 || lagged.get(1)?.to_string() != x.to_string() {
+                return None;
+            }
+            Some(Momentum::new(lag, x.clone()).boxed())
+        }
+        _ => None,
+    }
+}
+
+/// Remove the dead weight with algebraic rules: `(+ x 0)` and `(- x 0)` become `x`, `(* x 1)` and `(/ x 1)` become `x`,
+/// `(Neg (Neg x))` becomes `x`, `(- x x)` becomes `0` and `(- x (Delay n x))` becomes `(Momentum n x)`. The outputs are the same.
+pub fn simplify<T: TickerBatch>(mut op: BoxOp<T>) -> BoxOp<T> {
+    for i in op.child_indices().into_iter().rev() {
+        if let Some(child) = op.get(i) {
+            op.insert(i, simplify(child));
+        }
+    }
+
+    while let Some(simpler) = rewrite(&op) {
+        op = simpler;
+    }
+    op
+}
+
+// The functions whose operands can be swapped
+const COMMUTATIVE: &[&str] = &["+", "*", "==", "And", "Or"];
+
+/// Rewrite the factor into a canonical form, so that equivalent factors written differently, e.g. `(+ :a :b)`
+/// and `(+ :b :a)`, become the same: the constant subtrees are folded, `-0` becomes `0` and the operands
+/// of the commutative functions are sorted.
+pub fn canonicalize<T: TickerBatch>(op: BoxOp<T>) -> BoxOp<T> {
+    let op = fold_constants(op.clone()).unwrap_or(op);
+    sort_operands(op)
+}
+
+fn sort_operands<T: TickerBatch>(mut op: BoxOp<T>) -> BoxOp<T> {
+    if op.len() == 1 && op.to_string() == "-0.0" {
+        return 0f64.boxed();
+    }
+
+    for i in op.child_indices().into_iter().rev() {
+        if let Some(child) = op.get(i) {
+            op.insert(i, sort_operands(child));
+        }
+    }
+
+    let commutative = head(&*op).map_or(false, |func| COMMUTATIVE.contains(&func.as_str()));
+    if let (true, [i, j]) = (commutative, &*op.child_indices()) {
+        let (l, r) = (op.get(*i).unwrap(), op.get(*j).unwrap());
+        if l.to_string() > r.to_string() {
+            let rlen = r.len();
+            op.insert(*i, r);
+            op.insert(i + rlen, l);
+        }
+    }
+    op
+}
+
+#[cfg(test)]
+mod test {
+    use super::{canonicalize, fold_constants, simplify};
+    use crate::ops::{from_str, Operator, ParseOptions, ZeroPolicy};
+    use arrow::record_batch::RecordBatch;
+
+    #[test]
+    fn folding() {
+        let opts = Default::default();
+        for (repr, folded) in [
+            ("(* 2 (/ 1 4))", "0.5"),
+            ("(+ :a (* 2 (/ 1 4)))", "(+ :a 0.5)"),
+            (
+                "(+ (- 3 1) (Mean 3 (* :a (^ 2 3))))",
+                "(+ 2 (Mean 3 (* :a 9)))",
+            ),
+            ("(If (> 2 1) (Abs -1) :a)", "(If 1 1 :a)"),
+            ("(WSum 0.5 (+ 1 1) 2 :a)", "(WSum 0.5 2 2 :a)"),
+            ("(+ (RowIndex) 1)", "(+ (RowIndex) 1)"),
+            ("(Mean 3 (+ 1 1))", "(Mean 3 2)"),
+            ("(Abs (- 2 3))", "1"),
+            ("(* :a (Exp 0))", "(* :a 1)"),
+            ("(+ (Sqrt -1e-12) (Cbrt -8))", "-2"),
+            (
+                "(Clip -1 1 (+ :a (ClipUpper 0.5 2)))",
+                "(Clip -1 1 (+ :a 0.5))",
+            ),
+        ] {
+            let op = from_str::<RecordBatch>(repr, &opts).unwrap();
+            assert_eq!(fold_constants(op).unwrap().to_string(), folded, "{}", repr);
+        }
+
+        let opts = ParseOptions {
+            zero: ZeroPolicy::Nan,
+            ..Default::default()
+        };
+        let op = from_str::<RecordBatch>("(+ :a (/ 1 0))", &opts).unwrap();
+        assert_eq!(
+            fold_constants(op).unwrap().to_string(),
+            "(+ :a (/ nan 1 0))"
+        );
+    }
+
+    #[test]
+    fn simplification() {
+        let opts = Default::default();
+        for (repr, simplified) in [
+            ("(+ :a 0)", ":a"),
+            ("(* 1 (- :a 0))", ":a"),
+            ("(Neg (Neg (Neg (Neg (Mean 10 :a)))))", "(Mean 10 :a)"),
+            ("(Neg (Neg (Neg :a)))", "(Neg :a)"),
+            ("(+ (- :a :a) (/ :b 1))", ":b"),
+            ("(Mean 10 (* (+ :a 0) 1))", "(Mean 10 :a)"),
+            (
+                "(- (Mean 10 :a) (Mean 10 :a))",
+                "(- (Mean 10 :a) (Mean 10 :a))",
+            ),
+            ("(- (FFill :a) (FFill :a))", "(- (FFill :a) (FFill :a))"),
+            ("(! (- :a :a))", "(! (- :a :a))"),
+            ("(- 0 :a)", "(- 0 :a)"),
+            ("(/ 1 :a)", "(/ 1 :a)"),
+            (
+                "(- (Mean 3 :a) (Delay 2 (Mean 3 :a)))",
+                "(Momentum 2 (Mean 3 :a))",
+            ),
+            ("(- :a (Delay 2 :b))", "(- :a (Delay 2 :b))"),
+            ("(- :a (Delay 0 :a))", "(- :a (Delay 0 :a))"),
+        ] {
+            let op = from_str::<RecordBatch>(repr, &opts).unwrap();
+            assert_eq!(simplify(op).to_string(), simplified, "{}", repr);
+        }
+    }
+
+    #[test]
+    fn canonical() {
+        let opts = Default::default();
+        let canonical =
+            |repr| canonicalize(from_str::<RecordBatch>(repr, &opts).unwrap()).to_string();
+        assert_eq!(canonical("(+ :b :a)"), "(+ :a :b)");
+        assert_eq!(
+            canonical("(* (Mean 10 (+ :b (* 2 3))) (And :y :x))"),
+            canonical("(* (And :x :y) (Mean 10 (+ 6 :b)))")
+        );
+        assert_eq!(canonical("(- :b (* -0 1))"), "(- :b 0)");
+        assert_ne!(canonical("(- :b :a)"), canonical("(- :a :b)"));
+    }
+}
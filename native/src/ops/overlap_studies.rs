@@ -5,7 +5,11 @@ use fehler::{throw, throws};
 
 use crate::ticker_batch::TickerBatch;
 
-use super::{parser::Parameter, BoxOp, Named, Operator};
+use super::{
+    at_child,
+    parser::{to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
 
 pub struct SMA<T> {
     inner: BoxOp<T>,
@@ -47,18 +51,21 @@ impl<T: TickerBatch> Operator<T> for SMA<T> {
         self.i = 0;
     }
 
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.window.clear();
+        self.sum = 0.;
+    }
+
     #[throws(Error)]
     fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
-        let vals = &*self.inner.update(tb)?;
-        #[cfg(feature = "check")]
-        assert_eq!(tb.len(), vals.len());
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
 
         let mut results = Vec::with_capacity(tb.len());
 
         for &val in vals {
             if self.i < self.inner.ready_offset() {
-                #[cfg(feature = "check")]
-                assert!(val.is_nan());
+                self.check_warmup(tb, &[val])?;
                 results.push(f64::NAN);
                 self.i += 1;
                 continue;
@@ -68,6 +75,12 @@ impl<T: TickerBatch> Operator<T> for SMA<T> {
             self.sum += val;
             let val = if self.window.len() == self.win_size {
                 let val = self.sum / self.win_size as f64;
+
+                #[cfg(feature = "validate")]
+                crate::reference::validate(Self::NAME, val, || {
+                    crate::reference::mean(self.window.make_contiguous())
+                })?;
+
                 self.sum -= self.window.pop_front().unwrap();
                 val
             } else {
@@ -83,6 +96,14 @@ impl<T: TickerBatch> Operator<T> for SMA<T> {
         self.inner.ready_offset() + self.win_size - 1
     }
 
+    fn lookback(&self) -> Option<usize> {
+        Some(self.inner.lookback()? + self.win_size - 1)
+    }
+
+    fn state_bytes(&self) -> usize {
+        self.win_size * mem::size_of::<f64>() + self.inner.state_bytes()
+    }
+
     fn to_string(&self) -> String {
         format!(
             "({} {} {})",
@@ -113,6 +134,7 @@ impl<T: TickerBatch> Operator<T> for SMA<T> {
         if i == 0 {
             return self.clone().boxed();
         }
+        let i = i - 1;
 
         let ns = self.inner.len();
 
@@ -147,13 +169,13 @@ impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<SMA<T>> {
     fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> SMA<T> {
         let mut iter = iter.into_iter();
 
-        let Parameter::Constant(n) = iter.next().unwrap() else {
+        let Some(Parameter::Constant(n)) = iter.next() else {
             throw!(anyhow!("<n> for SMA should be an constant"));
         };
 
         let inner = iter
             .next()
-            .unwrap()
+            .ok_or_else(|| anyhow!("Too few parameters for SMA"))?
             .to_operator()
             .ok_or_else(|| anyhow!("<inner> for SMA should be an operator"))?;
 
@@ -161,6 +183,416 @@ impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<SMA<T>> {
             throw!(anyhow!("Too many parameters for SMA"))
         }
 
-        SMA::new(inner, n as usize)
+        SMA::new(inner, to_usize(SMA::<T>::NAME, "n", n, 1)?)
     }
 }
+
+/// The linearly weighted moving average of TA-Lib, the newest value weighs `n` and the oldest one 1.
+pub struct WMA<T> {
+    inner: BoxOp<T>,
+    win_size: usize,
+
+    i: usize,
+    window: VecDeque<f64>,
+    sum: f64,
+    wsum: f64, // the values weighted by 1 (the oldest) up to the number of values
+}
+
+impl<T> Clone for WMA<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.inner.clone(), self.win_size)
+    }
+}
+
+impl<T> WMA<T> {
+    pub fn new(inner: BoxOp<T>, win_size: usize) -> Self {
+        Self {
+            inner,
+            win_size,
+
+            window: VecDeque::with_capacity(win_size),
+            sum: 0.,
+            wsum: 0.,
+            i: 0,
+        }
+    }
+}
+
+impl<T> Named for WMA<T> {
+    const NAME: &'static str = "WMA";
+}
+
+impl<T: TickerBatch> Operator<T> for WMA<T> {
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.window.clear();
+        self.sum = 0.;
+        self.wsum = 0.;
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.window.clear();
+        self.sum = 0.;
+        self.wsum = 0.;
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for &val in vals {
+            if self.i < self.inner.ready_offset() {
+                self.check_warmup(tb, &[val])?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            // The weights of the values in the window go down by one, the new value weighs the window size
+            if self.window.len() == self.win_size {
+                self.wsum -= self.sum;
+                self.sum -= self.window.pop_front().unwrap();
+            }
+            self.window.push_back(val);
+            self.sum += val;
+            self.wsum += self.window.len() as f64 * val;
+
+            let val = if self.window.len() == self.win_size {
+                let n = self.win_size as f64;
+                let val = self.wsum / (n * (n + 1.) / 2.);
+
+                #[cfg(feature = "validate")]
+                crate::reference::validate(Self::NAME, val, || {
+                    crate::reference::wma(self.window.make_contiguous())
+                })?;
+
+                val
+            } else {
+                f64::NAN
+            };
+            results.push(val);
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inner.ready_offset() + self.win_size - 1
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.inner.lookback()? + self.win_size - 1)
+    }
+
+    fn state_bytes(&self) -> usize {
+        self.win_size * mem::size_of::<f64>() + self.inner.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "({} {} {})",
+            Self::NAME,
+            self.win_size.to_string(),
+            self.inner.to_string()
+        )
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inner.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        vec![1]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inner.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            self.inner.get(i)?
+        } else {
+            throw!()
+        }
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+        if i < ns {
+            if i == 0 {
+                return mem::replace(&mut self.inner, op) as BoxOp<T>;
+            }
+            self.inner.insert(i, op)?
+        } else {
+            throw!()
+        }
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<WMA<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> WMA<T> {
+        let mut iter = iter.into_iter();
+
+        let Some(Parameter::Constant(n)) = iter.next() else {
+            throw!(anyhow!("<n> for WMA should be an constant"));
+        };
+
+        let inner = iter
+            .next()
+            .ok_or_else(|| anyhow!("Too few parameters for WMA"))?
+            .to_operator()
+            .ok_or_else(|| anyhow!("<inner> for WMA should be an operator"))?;
+
+        if iter.count() != 0 {
+            throw!(anyhow!("Too many parameters for WMA"))
+        }
+
+        WMA::new(inner, to_usize(WMA::<T>::NAME, "n", n, 1)?)
+    }
+}
+
+/// The exponential moving average of TA-Lib, `alpha = 2 / (n + 1)`, seeded by the mean of the first `n` values.
+/// The NaNs are skipped, and produce a NaN.
+#[derive(Clone)]
+pub(super) struct Smoothing {
+    win_size: usize,
+    alpha: f64,
+
+    count: usize,
+    ema: f64,
+}
+
+impl Smoothing {
+    pub(super) fn new(win_size: usize) -> Self {
+        Self {
+            win_size,
+            alpha: 2. / (win_size as f64 + 1.),
+
+            count: 0,
+            ema: 0.,
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.count = 0;
+        self.ema = 0.;
+    }
+
+    pub(super) fn push(&mut self, val: f64) -> f64 {
+        if val.is_nan() {
+            return f64::NAN;
+        }
+
+        if self.count < self.win_size {
+            self.count += 1;
+            self.ema += (val - self.ema) / self.count as f64;
+            if self.count < self.win_size {
+                return f64::NAN;
+            }
+        } else {
+            self.ema += self.alpha * (val - self.ema);
+        }
+        self.ema
+    }
+}
+
+// The operators combining a chain of `Smoothing`s, each one smoothing the output of the previous one
+macro_rules! impl_ema_chain {
+    ($($(#[$doc:meta])* $op:ident [$depth:literal] |$emas:ident| $combine:expr;)+) => {
+        $(
+            $(#[$doc])*
+            pub struct $op<T> {
+                inner: BoxOp<T>,
+                win_size: usize,
+
+                i: usize,
+                chain: [Smoothing; $depth],
+            }
+
+            impl<T> Clone for $op<T> {
+                fn clone(&self) -> Self {
+                    Self::new(self.inner.clone(), self.win_size)
+                }
+            }
+
+            impl<T> $op<T> {
+                pub fn new(inner: BoxOp<T>, win_size: usize) -> Self {
+                    Self {
+                        inner,
+                        win_size,
+
+                        i: 0,
+                        chain: [(); $depth].map(|_| Smoothing::new(win_size)),
+                    }
+                }
+            }
+
+            impl<T> Named for $op<T> {
+                const NAME: &'static str = stringify!($op);
+            }
+
+            impl<T: TickerBatch> Operator<T> for $op<T> {
+                fn reset(&mut self) {
+                    self.inner.reset();
+                    self.chain.iter_mut().for_each(Smoothing::clear);
+                    self.i = 0;
+                }
+
+                #[throws(Error)]
+                fn evict(&mut self) {
+                    self.chain.iter_mut().for_each(Smoothing::clear);
+                }
+
+                #[throws(Error)]
+                fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+                    let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+
+                    let mut results = Vec::with_capacity(tb.len());
+
+                    for &val in vals {
+                        if self.i < self.inner.ready_offset() {
+                            self.check_warmup(tb, &[val])?;
+                            results.push(f64::NAN);
+                            self.i += 1;
+                            continue;
+                        }
+
+                        let mut $emas = [f64::NAN; $depth];
+                        let mut val = val;
+                        for (ema, smoothing) in $emas.iter_mut().zip(&mut self.chain) {
+                            val = smoothing.push(val);
+                            *ema = val;
+                        }
+                        results.push(if val.is_nan() { f64::NAN } else { $combine });
+                    }
+
+                    results.into()
+                }
+
+                // Each smoothing starts at the `n`-th output of the previous one
+                fn ready_offset(&self) -> usize {
+                    self.inner.ready_offset() + $depth * (self.win_size - 1)
+                }
+
+                // Every value ever seen keeps a (vanishing) weight
+                fn lookback(&self) -> Option<usize> {
+                    None
+                }
+
+                fn state_bytes(&self) -> usize {
+                    mem::size_of::<[Smoothing; $depth]>() + self.inner.state_bytes()
+                }
+
+                fn to_string(&self) -> String {
+                    format!("({} {} {})", Self::NAME, self.win_size, self.inner.to_string())
+                }
+
+                fn depth(&self) -> usize {
+                    1 + self.inner.depth()
+                }
+
+                fn len(&self) -> usize {
+                    self.inner.len() + 1
+                }
+
+                fn child_indices(&self) -> Vec<usize> {
+                    vec![1]
+                }
+
+                fn columns(&self) -> Vec<String> {
+                    self.inner.columns()
+                }
+
+                #[throws(as Option)]
+                fn get(&self, i: usize) -> BoxOp<T> {
+                    if i == 0 {
+                        return self.clone().boxed();
+                    }
+                    let i = i - 1;
+
+                    let ns = self.inner.len();
+
+                    if i < ns {
+                        self.inner.get(i)?
+                    } else {
+                        throw!()
+                    }
+                }
+
+                #[throws(as Option)]
+                fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+                    if i == 0 {
+                        unreachable!("cannot insert root");
+                    }
+                    let i = i - 1;
+
+                    let ns = self.inner.len();
+                    if i < ns {
+                        if i == 0 {
+                            return mem::replace(&mut self.inner, op) as BoxOp<T>;
+                        }
+                        self.inner.insert(i, op)?
+                    } else {
+                        throw!()
+                    }
+                }
+            }
+
+            impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<$op<T>> {
+                #[throws(Error)]
+                fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> $op<T> {
+                    let name = $op::<T>::NAME;
+                    let mut iter = iter.into_iter();
+
+                    let Some(Parameter::Constant(n)) = iter.next() else {
+                        throw!(anyhow!("<n> for {} should be an constant", name));
+                    };
+
+                    let inner = iter
+                        .next()
+                        .ok_or_else(|| anyhow!("Too few parameters for {}", name))?
+                        .to_operator()
+                        .ok_or_else(|| anyhow!("<inner> for {} should be an operator", name))?;
+
+                    if iter.count() != 0 {
+                        throw!(anyhow!("Too many parameters for {}", name))
+                    }
+
+                    $op::new(inner, to_usize(name, "n", n, 1)?)
+                }
+            }
+        )+
+    };
+}
+
+impl_ema_chain! {
+    /// The double exponential moving average of TA-Lib, `2 * EMA - EMA(EMA)`.
+    DEMA [2] |emas| 2. * emas[0] - emas[1];
+
+    /// The triple exponential moving average of TA-Lib, `3 * EMA - 3 * EMA(EMA) + EMA(EMA(EMA))`.
+    TEMA [3] |emas| 3. * emas[0] - 3. * emas[1] + emas[2];
+}
@@ -1,13 +1,18 @@
-use super::*;
+use super::{macros::expand_macros, registry, template::placeholders, *};
 use crate::ticker_batch::TickerBatch;
 use anyhow::{anyhow, Error};
+use arrow::record_batch::RecordBatch;
 use fehler::{throw, throws};
+use itertools::Itertools;
 use lexpr::{self, Cons, Value};
-use std::iter::FromIterator;
+use std::{collections::HashMap, iter::FromIterator, time::Duration};
 
 pub enum Parameter<T: TickerBatch> {
     Constant(f64),
     Symbol(String),
+    String(String),
+    Columns(Vec<String>),
+    Vector(Vec<f64>),
     Operator(BoxOp<T>),
 }
 
@@ -16,6 +21,14 @@ impl<T: TickerBatch> std::fmt::Display for Parameter<T> {
         match self {
             Parameter::Constant(v) => write!(f, "{}", v),
             Parameter::Symbol(v) => write!(f, "{}", v),
+            Parameter::String(v) => write!(f, "{:?}", v),
+            Parameter::Columns(v) => write!(
+                f,
+                "({} {})",
+                COLS,
+                v.iter().map(|c| format!(":{}", c)).join(" ")
+            ),
+            Parameter::Vector(v) => write!(f, "({})", v.iter().join(" ")),
             Parameter::Operator(v) => write!(f, "{}", v.to_string()),
         }
     }
@@ -32,53 +45,511 @@ impl<T: TickerBatch> Parameter<T> {
         match self {
             Parameter::Operator(op) => Some(op),
             Parameter::Symbol(_) => None,
+            Parameter::String(_) => None,
+            Parameter::Columns(_) => None,
+            Parameter::Vector(_) => None,
             Parameter::Constant(c) => Some(c.boxed()),
         }
     }
 }
 
+// The vector getter, only valid as a parameter, e.g. `(Dot 0.5 0.5 (Cols :bid1 :bid2))`
+const COLS: &str = "Cols";
+
+/// Knobs that change how an expression is turned into operators.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ParseOptions {
+    pub zero: ZeroPolicy,
+    pub nan: NanPolicy,
+}
+
+// The larger integers are not exactly represented by the constants
+const MAX_INTEGER: f64 = 9007199254740992.; // 2^53
+
+/// Validate an integer-valued constant, e.g. a window size, and convert it into usize.
+#[throws(Error)]
+pub fn to_usize(op: &str, param: &str, c: f64, min: usize) -> usize {
+    if c.fract() != 0. || c < min as f64 {
+        throw!(anyhow!(
+            "<{}> for {} should be an integer no less than {}, got {}",
+            param,
+            op,
+            min,
+            c
+        ))
+    }
+    if c > MAX_INTEGER {
+        throw!(anyhow!(
+            "<{}> for {} should be no larger than {}, got {}",
+            param,
+            op,
+            MAX_INTEGER,
+            c
+        ))
+    }
+    c as usize
+}
+
+/// Validate a constant in `[0, 1]`, e.g. a quantile.
+#[throws(Error)]
+pub fn to_fraction(op: &str, param: &str, c: f64) -> f64 {
+    if !(0. ..=1.).contains(&c) {
+        throw!(anyhow!(
+            "<{}> for {} should be within [0, 1], got {}",
+            param,
+            op,
+            c
+        ))
+    }
+    c
+}
+
+/// Parse a positive duration written as a number followed by a unit among `ns`, `us`, `ms`, `s`, `m`, `h` and `d`,
+/// e.g. `"500ms"` or `"1.5h"`.
 #[throws(Error)]
-pub fn from_str<T: TickerBatch>(sexpr: &str) -> BoxOp<T> {
-    let sexpr = lexpr::from_str(sexpr)?;
-    let sexpr = match sexpr {
-        Value::Bool(b) => throw!(anyhow!("unexpected bool {}", b)),
-        Value::Bytes(b) => throw!(anyhow!("unexpected bytes {:?}", b)),
-        Value::Char(c) => throw!(anyhow!("unexpected char {}", c)),
-        Value::Cons(cons) => cons,
-        Value::Keyword(k) => throw!(anyhow!("unexpected keyword {}", k)),
-        Value::String(s) => throw!(anyhow!("unexpected string {}", s)),
+pub fn to_duration(op: &str, param: &str, s: &str) -> Duration {
+    let split = s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let nanos = match unit {
+        "ns" => 1.,
+        "us" => 1e3,
+        "ms" => 1e6,
+        "s" => 1e9,
+        "m" => 60e9,
+        "h" => 3600e9,
+        "d" => 86400e9,
+        _ => f64::NAN,
+    };
+    match value.parse::<f64>().map(|v| (v * nanos).round()) {
+        Ok(nanos) if nanos >= 1. && nanos < u64::MAX as f64 => Duration::from_nanos(nanos as u64),
+        _ => throw!(anyhow!(
+            "<{}> for {} should be a positive duration like \"500ms\", \"5s\" or \"1h\", got {:?}",
+            param,
+            op,
+            s
+        )),
+    }
+}
+
+// Validate the parameters against the kinds in the signature of the function (see `Kind`), so that all the
+// functions reject e.g. a window of 2.7 rows with the same error. The number of parameters is left to the functions.
+#[throws(Error)]
+fn check_kinds<T: TickerBatch>(func: &str, params: &[Parameter<T>]) {
+    let sig = match signatures::<T>().into_iter().find(|sig| sig.name == func) {
+        Some(sig) => sig,
+        None => return,
+    };
+    let kinds: Vec<_> = if sig.variadic {
+        sig.params.iter().cycle().take(params.len()).collect()
+    } else if params.len() == sig.params.len() {
+        sig.params.iter().collect()
+    } else if params.len() == sig.arity() {
+        sig.params.iter().filter(|p| !p.optional).collect()
+    } else {
+        return;
+    };
+
+    for (kind, param) in kinds.into_iter().zip(params) {
+        match (kind.kind, param) {
+            (Kind::Integer(min), Parameter::Constant(c)) => {
+                to_usize(func, kind.name, *c, min)?;
+            }
+            (Kind::Fraction, Parameter::Constant(c)) => {
+                to_fraction(func, kind.name, *c)?;
+            }
+            (Kind::Duration, Parameter::String(s)) => {
+                to_duration(func, kind.name, s)?;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[throws(Error)]
+pub fn from_str<T: TickerBatch>(sexpr: &str, opts: &ParseOptions) -> BoxOp<T> {
+    let parsed = lexpr::from_str(sexpr).map_err(|e| {
+        let span = e.location().map(|loc| {
+            let pos = sexpr
+                .split('\n')
+                .take(loc.line().saturating_sub(1))
+                .map(|line| line.chars().count() + 1)
+                .sum::<usize>()
+                + loc.column();
+            (pos, pos + 1)
+        });
+        ParseError::new(e.to_string(), sexpr, span)
+    })?;
+    let expanded = expand_lets(&parsed, &HashMap::new())?;
+    let expanded = expand_macros(&expanded)?;
+    let placeholders = placeholders(&expanded);
+    if !placeholders.is_empty() {
+        return Template::new(sexpr, placeholders, opts).boxed();
+    }
+    // Anything but a list or a column is wrong as a whole
+    let whole = {
+        let start = sexpr.chars().take_while(|c| c.is_whitespace()).count();
+        Some((start, sexpr.trim_end().chars().count().max(start)))
+    };
+    let unexpected = |message: String| ParseError::new(message, sexpr, whole);
+    let cons = match &expanded {
+        Value::Bool(b) => throw!(unexpected(format!("unexpected bool {}", b))),
+        Value::Bytes(b) => throw!(unexpected(format!("unexpected bytes {:?}", b))),
+        Value::Char(c) => throw!(unexpected(format!("unexpected char {}", c))),
+        Value::Cons(cons) => cons.clone(),
+        Value::Keyword(k) => throw!(unexpected(format!("unexpected keyword {}", k))),
+        Value::String(s) => return Getter::new(s).boxed(),
         Value::Symbol(s) => {
             if s.starts_with(":") {
                 return Getter::new(&s[1..]).boxed();
             } else {
-                throw!(anyhow!("unexpected symbol {}", s))
+                throw!(unexpected(format!("unexpected symbol {}", s)))
             }
         }
-        Value::Vector(v) => throw!(anyhow!("unexpected vector {:?}", v)),
-        _ => throw!(anyhow!("unexpected value")),
+        Value::Vector(v) => throw!(unexpected(format!("unexpected vector {:?}", v))),
+        Value::Null => throw!(unexpected("empty expression".to_string())),
+        _ => throw!(unexpected("unexpected value".to_string())),
     };
 
-    visit(sexpr)?
+    visit(cons, opts).map_err(|e| match e.downcast::<Located>() {
+        Ok(located) => {
+            let tokens = pretty::tokenize(sexpr);
+            // The path only makes sense if no let or macro changed the shape of the expression,
+            // otherwise take the first call to the failing function
+            let span = if expanded == parsed {
+                follow(&tokens, &located.path)
+            } else {
+                call(&tokens, &located.func)
+            };
+            ParseError::new(located.error.to_string(), sexpr, span).into()
+        }
+        Err(e) => e,
+    })?
+}
+
+/// The expression failed to parse. `span` is the range of chars in the expression that caused the error, if known.
+#[derive(Debug)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Option<(usize, usize)>,
+    pub fragment: Option<String>,
+}
+
+impl ParseError {
+    fn new(message: String, sexpr: &str, span: Option<(usize, usize)>) -> Self {
+        let fragment =
+            span.map(|(start, end)| sexpr.chars().skip(start).take(end - start).collect());
+        ParseError {
+            message,
+            span,
+            fragment,
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.span, &self.fragment) {
+            (Some((start, _)), Some(fragment)) => {
+                write!(f, "{} at position {}: {}", self.message, start, fragment)
+            }
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// An error raised while building a node, `path` leads to the node from the root (see `follow`)
+#[derive(Debug)]
+struct Located {
+    path: Vec<usize>,
+    func: String,
+    error: Error,
+}
+
+impl std::fmt::Display for Located {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for Located {}
+
+// Follow the path, i.e. the positions of the items in each list, to a list in the tokens and return its span
+fn follow(tokens: &[(usize, String)], path: &[usize]) -> Option<(usize, usize)> {
+    let mut i = 0;
+    for &k in path {
+        i += 1; // skip the open parenthesis
+        for _ in 0..k {
+            i = skip(tokens, i)?;
+        }
+    }
+    span(tokens, i)
+}
+
+// The span of the first list starting with `func`
+fn call(tokens: &[(usize, String)], func: &str) -> Option<(usize, usize)> {
+    let i = tokens
+        .windows(2)
+        .position(|w| w[0].1 == "(" && w[1].1 == func)?;
+    span(tokens, i)
+}
+
+// The index of the token after the item starting at `i`
+fn skip(tokens: &[(usize, String)], i: usize) -> Option<usize> {
+    if tokens.get(i)?.1 != "(" {
+        return Some(i + 1);
+    }
+    let mut depth = 0;
+    for (j, (_, t)) in tokens.iter().enumerate().skip(i) {
+        match t.as_str() {
+            "(" => depth += 1,
+            ")" if depth == 1 => return Some(j + 1),
+            ")" => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn span(tokens: &[(usize, String)], i: usize) -> Option<(usize, usize)> {
+    let start = tokens.get(i)?.0;
+    let end = tokens.get(skip(tokens, i)? - 1)?;
+    Some((start, end.0 + end.1.chars().count()))
+}
+
+const LET: &str = "let";
+
+// The associative functions taking any number of operands
+const VARIADIC: [&str; 4] = ["+", "*", "And", "Or"];
+
+/// Expand `(let ((<name> <expr>) ...) <body>)` by substituting the bound names in the body.
+/// The bindings are done in order, so a binding can refer to the ones before it.
+#[throws(Error)]
+pub(super) fn expand_lets(sexpr: &Value, env: &HashMap<String, Value>) -> Value {
+    match sexpr {
+        Value::Symbol(sym) => env.get(&**sym).cloned().unwrap_or_else(|| sexpr.clone()),
+        Value::Cons(cons) if cons.car().as_symbol() == Some(LET) => {
+            let (form, _) = cons.to_vec();
+            let (bindings, body) = match &*form {
+                [_, Value::Cons(bindings), body] => (bindings.to_vec().0, body),
+                [_, Value::Null, body] => (vec![], body),
+                _ => throw!(anyhow!(
+                    "{} expect a list of bindings and a body, got {}",
+                    LET,
+                    sexpr
+                )),
+            };
+
+            let mut env = env.clone();
+            for binding in bindings {
+                let binding = match &binding {
+                    Value::Cons(binding) => binding.to_vec().0,
+                    _ => throw!(anyhow!("malformed binding {} in {}", binding, LET)),
+                };
+                match &*binding {
+                    [Value::Symbol(name), expr] if !name.starts_with(":") => {
+                        let expr = expand_lets(expr, &env)?;
+                        env.insert(name.to_string(), expr);
+                    }
+                    _ => throw!(anyhow!(
+                        "a binding in {} should be a name and an expression, got {:?}",
+                        LET,
+                        binding
+                    )),
+                }
+            }
+
+            expand_lets(body, &env)?
+        }
+        Value::Cons(cons) => {
+            let (items, _) = cons.to_vec();
+            // the function name is never substituted
+            let mut expanded = vec![items[0].clone()];
+            for item in &items[1..] {
+                expanded.push(expand_lets(item, env)?);
+            }
+            Value::list(expanded)
+        }
+        _ => sexpr.clone(),
+    }
+}
+
+// Other spellings of the operators, so that expressions ported from other alpha frameworks work as is.
+// The aliases are normalized (see `normalize`), and map to the canonical names.
+const ALIASES: &[(&str, &str)] = &[
+    ("add", "+"),
+    ("sub", "-"),
+    ("mul", "*"),
+    ("div", "/"),
+    ("lt", "<"),
+    ("le", "<="),
+    ("gt", ">"),
+    ("ge", ">="),
+    ("eq", "=="),
+    ("not", "!"),
+    ("signedpow", "SPow"),
+    ("tssum", "Sum"),
+    ("tsmean", "Mean"),
+    ("tsstd", "Std"),
+    ("tsstddev", "Std"),
+    ("stddev", "Std"),
+    ("stdev", "Std"),
+    ("tsskew", "Skew"),
+    ("tskurt", "Kurt"),
+    ("tskurtosis", "Kurt"),
+    ("kurtosis", "Kurt"),
+    ("tscorr", "Corr"),
+    ("correlation", "Corr"),
+    ("tsbeta", "Beta"),
+    ("tsalpha", "Alpha"),
+    ("tsresid", "Resid"),
+    ("tsautocorr", "AutoCorr"),
+    ("tsproduct", "Product"),
+    ("tsprod", "Product"),
+    ("tscountif", "CountIf"),
+    ("tsany", "Any"),
+    ("tsall", "All"),
+    ("tsmin", "Min"),
+    ("tsmax", "Max"),
+    ("tsargmin", "ArgMin"),
+    ("tsargmax", "ArgMax"),
+    ("tsrank", "Rank"),
+    ("tsquantile", "Quantile"),
+    ("tsmedian", "Median"),
+    ("tsdelay", "Delay"),
+    ("tsfirst", "First"),
+    ("diff", "Momentum"),
+    ("tsdiff", "Momentum"),
+    ("delta", "Momentum"),
+    ("tspctchange", "PctChange"),
+    ("tsdrawdown", "Drawdown"),
+    ("tshurst", "Hurst"),
+    ("tsslope", "Slope"),
+    ("tstrendr2", "TrendR2"),
+    ("tstheilsen", "TheilSen"),
+    ("tspercentile", "Percentile"),
+    ("tswinsorize", "Winsorize"),
+    ("tssumt", "SumT"),
+    ("tsmeant", "MeanT"),
+    ("tsstdt", "StdT"),
+    ("tsmint", "MinT"),
+    ("tsmaxt", "MaxT"),
+    ("tscountt", "CountT"),
+    ("tsema", "EMA"),
+    ("tsewmstd", "EWMStd"),
+    ("tsewmcov", "EWMCov"),
+    ("tsewmcorr", "EWMCorr"),
+    ("tsvwap", "VWAP"),
+    ("tstwap", "TWAP"),
+    ("decaylinear", "WMA"),
+    ("tsdecaylinear", "WMA"),
+    ("ref", "Delay"),
+];
+
+/// The canonical names of the functions.
+fn names<T: TickerBatch>() -> Vec<&'static str> {
+    signatures::<T>().into_iter().map(|sig| sig.name).collect()
+}
+
+fn normalize(func: &str) -> String {
+    func.chars()
+        .filter(|&c| c != '_' && c != '-')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Resolve an alias or a differently cased name (e.g. `ts_mean` or `mean`) into the canonical name (`Mean`).
+/// Unknown names are returned as is.
+pub(super) fn resolve<T: TickerBatch>(func: &str) -> &str {
+    let names = names::<T>();
+    if names.contains(&func) {
+        return func;
+    }
+
+    let key = normalize(func);
+    ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == key)
+        .map(|(_, name)| *name)
+        .or_else(|| names.into_iter().find(|name| normalize(name) == key))
+        .unwrap_or(func)
+}
+
+/// Whether the name is taken by the language, i.e. it is a built-in function (or an alias of one) or a keyword.
+pub(super) fn is_builtin(name: &str) -> bool {
+    let names = names::<RecordBatch>();
+    [LET, COLS].contains(&name) || names.contains(&resolve::<RecordBatch>(name))
+}
+
+/// Whether the name is taken by a built-in or a registered function.
+pub(super) fn is_reserved(name: &str) -> bool {
+    is_builtin(name) || registry::is_registered(name)
 }
 
 #[throws(Error)]
-fn visit<T: TickerBatch>(sexpr: Cons) -> BoxOp<T> {
+fn visit<T: TickerBatch>(sexpr: Cons, opts: &ParseOptions) -> BoxOp<T> {
     let sexpr = sexpr.to_vec().0;
-    let (func, params) = match &*sexpr {
+    let func = sexpr
+        .first()
+        .and_then(Value::as_symbol)
+        .unwrap_or_default()
+        .to_string();
+    build(&sexpr, opts).map_err(|e| match e.downcast::<Located>() {
+        Ok(located) => located,
+        Err(error) => Located {
+            path: vec![],
+            func,
+            error,
+        },
+    })?
+}
+
+#[throws(Error)]
+fn build<T: TickerBatch>(sexpr: &[Value], opts: &ParseOptions) -> BoxOp<T> {
+    let (func, params) = match sexpr {
         [func, params @ ..] => (func, params),
-        _ => unimplemented!(),
+        [] => throw!(anyhow!("empty expression")),
     };
 
     let func = match func {
-        Value::Symbol(func) => &**func,
+        Value::Symbol(func) => resolve::<T>(func),
         _ => throw!(anyhow!("function name should be symbol")),
     };
+    // A string is a quoted column name, e.g. "bid price.1", unless the function takes the name itself or a duration
+    let durations: Vec<_> = signatures::<T>()
+        .into_iter()
+        .find(|sig| sig.name == func)
+        .map(|sig| {
+            sig.params
+                .iter()
+                .map(|p| p.kind == Kind::Duration)
+                .collect()
+        })
+        .unwrap_or_default();
+    let quoted = |i: usize| func != GetterOr::NAME && !durations.get(i).copied().unwrap_or(false);
 
     let params = params
         .into_iter()
-        .map(|p| match p {
+        .enumerate()
+        .map(|(i, p)| match p {
             Value::Number(c) => Ok(Parameter::Constant(c.as_f64().unwrap())),
-            Value::Cons(expr) => Ok(Parameter::Operator(visit(expr.clone())?)),
+            Value::Cons(expr) if expr.car().as_symbol() == Some(COLS) => {
+                Ok(Parameter::Columns(columns(expr)?))
+            }
+            Value::Cons(expr) if matches!(expr.car(), Value::Number(_)) => {
+                Ok(Parameter::Vector(vector(expr)?))
+            }
+            Value::Cons(expr) => visit(expr.clone(), opts)
+                .map(Parameter::Operator)
+                .map_err(|e| match e.downcast::<Located>() {
+                    Ok(mut located) => {
+                        located.path.insert(0, i + 1);
+                        located.into()
+                    }
+                    Err(e) => e,
+                }),
+            Value::Null => Ok(Parameter::Vector(vec![])), // the empty list
             Value::Symbol(sym) => {
                 if sym.starts_with(":") {
                     Ok(Parameter::Operator(Box::new(Getter::new(&sym[1..]))))
@@ -86,65 +557,647 @@ fn visit<T: TickerBatch>(sexpr: Cons) -> BoxOp<T> {
                     Ok(Parameter::Symbol(sym.to_string()))
                 }
             }
-            _ => unimplemented!(),
+            Value::String(s) if quoted(i) => Ok(Parameter::Operator(Box::new(Getter::new(s)))),
+            Value::String(s) => Ok(Parameter::String(s.to_string())),
+            // e.g. a bool, located at the parameter itself rather than the call
+            _ => Err(Located {
+                path: vec![i + 1],
+                func: func.to_string(),
+                error: anyhow!("unexpected parameter {} for {}", p, func),
+            }
+            .into()),
         })
         .collect::<Result<Vec<_>>>()?;
+    let (params, opts) = policy::<T>(func, params, opts)?;
+
+    if VARIADIC.contains(&func) && params.len() > 2 {
+        return balance(func, params, &opts)?;
+    }
+    construct(func, params, &opts)?
+}
+
+// A policy in front of the operands, e.g. `(/ ieee :a :b)`, overrides the one of the options for this node
+#[throws(Error)]
+fn policy<T: TickerBatch>(
+    func: &str,
+    mut params: Vec<Parameter<T>>,
+    opts: &ParseOptions,
+) -> (Vec<Parameter<T>>, ParseOptions) {
+    let zeros = [
+        Div::<T>::NAME,
+        LogAbs::<T>::NAME,
+        ROC::<T>::NAME,
+        PctChange::<T>::NAME,
+    ];
+    let nans = [
+        If::<T>::NAME,
+        And::<T>::NAME,
+        Or::<T>::NAME,
+        Lt::<T>::NAME,
+        Lte::<T>::NAME,
+        Gt::<T>::NAME,
+        Gte::<T>::NAME,
+        Eq::<T>::NAME,
+        Not::<T>::NAME,
+    ];
+    let mut opts = *opts;
+    if let Some(Parameter::Symbol(sym)) = params.first() {
+        if zeros.contains(&func) {
+            opts.zero = sym.parse()?;
+            params.remove(0);
+        } else if nans.contains(&func) {
+            opts.nan = sym.parse()?;
+            params.remove(0);
+        } else if sym.parse::<ZeroPolicy>().is_ok() || sym.parse::<NanPolicy>().is_ok() {
+            throw!(anyhow!(
+                "{} takes no policy, got {}, only {} take one",
+                func,
+                sym,
+                zeros.iter().chain(&nans).join(", ")
+            ))
+        }
+    }
+    (params, opts)
+}
+
+// Desugar e.g. `(+ a b c d)` into `(+ (+ a b) (+ c d))`, balanced to keep the depth low
+#[throws(Error)]
+fn balance<T: TickerBatch>(
+    func: &str,
+    mut params: Vec<Parameter<T>>,
+    opts: &ParseOptions,
+) -> BoxOp<T> {
+    if params.len() <= 2 {
+        return construct(func, params, opts)?;
+    }
+
+    let right = params.split_off(params.len() / 2);
+    let half = |mut params: Vec<Parameter<T>>| -> Result<Parameter<T>> {
+        if params.len() == 1 {
+            Ok(params.pop().unwrap())
+        } else {
+            Ok(Parameter::Operator(balance(func, params, opts)?))
+        }
+    };
+    let params = vec![half(params)?, half(right)?];
+    construct(func, params, opts)?
+}
 
-    match func {
+#[throws(Error)]
+fn construct<T: TickerBatch>(
+    func: &str,
+    params: Vec<Parameter<T>>,
+    opts: &ParseOptions,
+) -> BoxOp<T> {
+    check_kinds(func, &params)?;
+    for param in &params {
+        if let Parameter::Operator(op) = param {
+            if op.is_root_only() {
+                throw!(anyhow!(
+                    "{} can only be the root of a factor, not an input of {}",
+                    op.to_string(),
+                    func
+                ))
+            }
+        }
+    }
+    let op = match func {
         // arithmetics
         Add::<T>::NAME => Result::<Add<T>>::from_iter(params)?.boxed(),
         Sub::<T>::NAME => Result::<Sub<T>>::from_iter(params)?.boxed(),
         Mul::<T>::NAME => Result::<Mul<T>>::from_iter(params)?.boxed(),
-        Div::<T>::NAME => Result::<Div<T>>::from_iter(params)?.boxed(),
+        Div::<T>::NAME => Result::<Div<T>>::from_iter(params)?
+            .with_zero(opts.zero)
+            .boxed(),
         Pow::<T>::NAME => Result::<Pow<T>>::from_iter(params)?.boxed(),
         Neg::<T>::NAME => Result::<Neg<T>>::from_iter(params)?.boxed(),
         SignedPow::<T>::NAME => Result::<SignedPow<T>>::from_iter(params)?.boxed(),
-        LogAbs::<T>::NAME => Result::<LogAbs<T>>::from_iter(params)?.boxed(),
+        Clip::<T>::NAME => Result::<Clip<T>>::from_iter(params)?.boxed(),
+        Clip::<T>::LOWER | Clip::<T>::UPPER => Clip::one_sided(func, params)?.boxed(),
+        LogAbs::<T>::NAME => Result::<LogAbs<T>>::from_iter(params)?
+            .with_zero(opts.zero)
+            .boxed(),
         Sign::<T>::NAME => Result::<Sign<T>>::from_iter(params)?.boxed(),
         Abs::<T>::NAME => Result::<Abs<T>>::from_iter(params)?.boxed(),
+        Exp::<T>::NAME => Result::<Exp<T>>::from_iter(params)?.boxed(),
+        Sqrt::<T>::NAME => Result::<Sqrt<T>>::from_iter(params)?.boxed(),
+        Cbrt::<T>::NAME => Result::<Cbrt<T>>::from_iter(params)?.boxed(),
+        WSum::<T>::NAME => Result::<WSum<T>>::from_iter(params)?.boxed(),
+        Dot::NAME => Dot::from_params(params)?.boxed(),
+
+        // getters
+        GetterOr::NAME => Result::<GetterOr>::from_iter(params)?.boxed(),
+
+        // ticks
+        RowIndex::NAME => Result::<RowIndex>::from_iter(params)?.boxed(),
+        TimeDelta::<T>::NAME => Result::<TimeDelta<T>>::from_iter(params)?.boxed(),
+
+        // noises
+        Noise::NAME => Result::<Noise>::from_iter(params)?.boxed(),
+        Jitter::<T>::NAME => Result::<Jitter<T>>::from_iter(params)?.boxed(),
+
+        // sessions
+        SessionReset::<T>::NAME => Result::<SessionReset<T>>::from_iter(params)?.boxed(),
+        SessionWindow::<T>::NAME => Result::<SessionWindow<T>>::from_iter(params)?.boxed(),
+        ResetDaily::<T>::NAME => Result::<ResetDaily<T>>::from_iter(params)?.boxed(),
+        ResetOn::<T>::NAME => Result::<ResetOn<T>>::from_iter(params)?.boxed(),
 
         // logics
-        If::<T>::NAME => Result::<If<T>>::from_iter(params)?.boxed(),
-        And::<T>::NAME => Result::<And<T>>::from_iter(params)?.boxed(),
-        Or::<T>::NAME => Result::<Or<T>>::from_iter(params)?.boxed(),
-        Lt::<T>::NAME => Result::<Lt<T>>::from_iter(params)?.boxed(),
-        Lte::<T>::NAME => Result::<Lte<T>>::from_iter(params)?.boxed(),
-        Gt::<T>::NAME => Result::<Gt<T>>::from_iter(params)?.boxed(),
-        Gte::<T>::NAME => Result::<Gte<T>>::from_iter(params)?.boxed(),
-        Eq::<T>::NAME => Result::<Eq<T>>::from_iter(params)?.boxed(),
-        Not::<T>::NAME => Result::<Not<T>>::from_iter(params)?.boxed(),
+        If::<T>::NAME => Result::<If<T>>::from_iter(params)?
+            .with_nan(opts.nan)
+            .boxed(),
+        And::<T>::NAME => Result::<And<T>>::from_iter(params)?
+            .with_nan(opts.nan)
+            .boxed(),
+        Or::<T>::NAME => Result::<Or<T>>::from_iter(params)?
+            .with_nan(opts.nan)
+            .boxed(),
+        Lt::<T>::NAME => Result::<Lt<T>>::from_iter(params)?
+            .with_nan(opts.nan)
+            .boxed(),
+        Lte::<T>::NAME => Result::<Lte<T>>::from_iter(params)?
+            .with_nan(opts.nan)
+            .boxed(),
+        Gt::<T>::NAME => Result::<Gt<T>>::from_iter(params)?
+            .with_nan(opts.nan)
+            .boxed(),
+        Gte::<T>::NAME => Result::<Gte<T>>::from_iter(params)?
+            .with_nan(opts.nan)
+            .boxed(),
+        Eq::<T>::NAME => Result::<Eq<T>>::from_iter(params)?
+            .with_nan(opts.nan)
+            .boxed(),
+        Not::<T>::NAME => Result::<Not<T>>::from_iter(params)?
+            .with_nan(opts.nan)
+            .boxed(),
 
         // windows
         Sum::<T>::NAME => Result::<Sum<T>>::from_iter(params)?.boxed(),
         Mean::<T>::NAME => Result::<Mean<T>>::from_iter(params)?.boxed(),
+        Product::<T>::NAME => Result::<Product<T>>::from_iter(params)?.boxed(),
+        CountIf::<T>::NAME => Result::<CountIf<T>>::from_iter(params)?.boxed(),
+        Any::<T>::NAME => Result::<Any<T>>::from_iter(params)?.boxed(),
+        All::<T>::NAME => Result::<All<T>>::from_iter(params)?.boxed(),
         Correlation::<T>::NAME => Result::<Correlation<T>>::from_iter(params)?.boxed(),
+        AutoCorr::<T>::NAME => Result::<AutoCorr<T>>::from_iter(params)?.boxed(),
+        Beta::<T>::NAME => Result::<Beta<T>>::from_iter(params)?.boxed(),
+        Alpha::<T>::NAME => Result::<Alpha<T>>::from_iter(params)?.boxed(),
+        Resid::<T>::NAME => Result::<Resid<T>>::from_iter(params)?.boxed(),
         Min::<T>::NAME => Result::<Min<T>>::from_iter(params)?.boxed(),
         Max::<T>::NAME => Result::<Max<T>>::from_iter(params)?.boxed(),
         ArgMin::<T>::NAME => Result::<ArgMin<T>>::from_iter(params)?.boxed(),
         ArgMax::<T>::NAME => Result::<ArgMax<T>>::from_iter(params)?.boxed(),
+        Drawdown::<T>::NAME => Result::<Drawdown<T>>::from_iter(params)?.boxed(),
+        Hurst::<T>::NAME => Result::<Hurst<T>>::from_iter(params)?.boxed(),
+        Slope::<T>::NAME => Result::<Slope<T>>::from_iter(params)?.boxed(),
+        TrendR2::<T>::NAME => Result::<TrendR2<T>>::from_iter(params)?.boxed(),
+        TheilSen::<T>::NAME => Result::<TheilSen<T>>::from_iter(params)?.boxed(),
         Stdev::<T>::NAME => Result::<Stdev<T>>::from_iter(params)?.boxed(),
         Skew::<T>::NAME => Result::<Skew<T>>::from_iter(params)?.boxed(),
+        Kurt::<T>::NAME => Result::<Kurt<T>>::from_iter(params)?.boxed(),
         Delay::<T>::NAME => Result::<Delay<T>>::from_iter(params)?.boxed(),
+        First::<T>::NAME => Result::<First<T>>::from_iter(params)?.boxed(),
+        FFill::<T>::NAME => Result::<FFill<T>>::from_iter(params)?.boxed(),
+        SumT::<T>::NAME => Result::<SumT<T>>::from_iter(params)?.boxed(),
+        MeanT::<T>::NAME => Result::<MeanT<T>>::from_iter(params)?.boxed(),
+        StdT::<T>::NAME => Result::<StdT<T>>::from_iter(params)?.boxed(),
+        MinT::<T>::NAME => Result::<MinT<T>>::from_iter(params)?.boxed(),
+        MaxT::<T>::NAME => Result::<MaxT<T>>::from_iter(params)?.boxed(),
+        CountT::<T>::NAME => Result::<CountT<T>>::from_iter(params)?.boxed(),
         Rank::<T>::NAME => Result::<Rank<T>>::from_iter(params)?.boxed(),
+        Percentile::<T>::NAME => Result::<Percentile<T>>::from_iter(params)?.boxed(),
+        Median::<T>::NAME => Result::<Median<T>>::from_iter(params)?.boxed(),
         Quantile::<T>::NAME => Result::<Quantile<T>>::from_iter(params)?.boxed(),
+        Winsorize::<T>::NAME => Result::<Winsorize<T>>::from_iter(params)?.boxed(),
+        QuantileApprox::<T>::NAME => Result::<QuantileApprox<T>>::from_iter(params)?.boxed(),
+        RankApprox::<T>::NAME => Result::<RankApprox<T>>::from_iter(params)?.boxed(),
         LogReturn::<T>::NAME => Result::<LogReturn<T>>::from_iter(params)?.boxed(),
+        ROC::<T>::NAME => Result::<ROC<T>>::from_iter(params)?
+            .with_zero(opts.zero)
+            .boxed(),
+        PctChange::<T>::NAME => Result::<PctChange<T>>::from_iter(params)?
+            .with_zero(opts.zero)
+            .boxed(),
+        Momentum::<T>::NAME => Result::<Momentum<T>>::from_iter(params)?.boxed(),
+        OBV::<T>::NAME => Result::<OBV<T>>::from_iter(params)?.boxed(),
+        CumSum::<T>::NAME => Result::<CumSum<T>>::from_iter(params)?.boxed(),
+        CumMean::<T>::NAME => Result::<CumMean<T>>::from_iter(params)?.boxed(),
+        CumMax::<T>::NAME => Result::<CumMax<T>>::from_iter(params)?.boxed(),
+        CumMin::<T>::NAME => Result::<CumMin<T>>::from_iter(params)?.boxed(),
+        VWAP::<T>::NAME => Result::<VWAP<T>>::from_iter(params)?.boxed(),
+        TWAP::<T>::NAME => Result::<TWAP<T>>::from_iter(params)?.boxed(),
+        RSI::<T>::NAME => Result::<RSI<T>>::from_iter(params)?.boxed(),
+        Conv::<T>::NAME => Result::<Conv<T>>::from_iter(params)?.boxed(),
+        EMA::<T>::NAME => Result::<EMA<T>>::from_iter(params)?.boxed(),
+        EWMStd::<T>::NAME => Result::<EWMStd<T>>::from_iter(params)?.boxed(),
+        EWMCov::<T>::NAME => Result::<EWMCov<T>>::from_iter(params)?.boxed(),
+        EWMCorr::<T>::NAME => Result::<EWMCorr<T>>::from_iter(params)?.boxed(),
 
         // overla_studies
         SMA::<T>::NAME => Result::<SMA<T>>::from_iter(params)?.boxed(),
-        _ => throw!(anyhow!("Unknown function '{}'", func)),
+        WMA::<T>::NAME => Result::<WMA<T>>::from_iter(params)?.boxed(),
+        DEMA::<T>::NAME => Result::<DEMA<T>>::from_iter(params)?.boxed(),
+        TEMA::<T>::NAME => Result::<TEMA<T>>::from_iter(params)?.boxed(),
+        Tenkan::<T>::NAME => Result::<Tenkan<T>>::from_iter(params)?.boxed(),
+        Kijun::<T>::NAME => Result::<Kijun<T>>::from_iter(params)?.boxed(),
+        SenkouA::<T>::NAME => Result::<SenkouA<T>>::from_iter(params)?.boxed(),
+        SenkouB::<T>::NAME => Result::<SenkouB<T>>::from_iter(params)?.boxed(),
+        MACD::<T>::NAME => Result::<MACD<T>>::from_iter(params)?.boxed(),
+        MACDSignal::<T>::NAME => Result::<MACDSignal<T>>::from_iter(params)?.boxed(),
+        MACDHist::<T>::NAME => Result::<MACDHist<T>>::from_iter(params)?.boxed(),
+        StochK::<T>::NAME => Result::<StochK<T>>::from_iter(params)?.boxed(),
+        StochD::<T>::NAME => Result::<StochD<T>>::from_iter(params)?.boxed(),
+        CCI::<T>::NAME => Result::<CCI<T>>::from_iter(params)?.boxed(),
+        AroonUp::<T>::NAME => Result::<AroonUp<T>>::from_iter(params)?.boxed(),
+        AroonDown::<T>::NAME => Result::<AroonDown<T>>::from_iter(params)?.boxed(),
+        AroonOsc::<T>::NAME => Result::<AroonOsc<T>>::from_iter(params)?.boxed(),
+        MFI::<T>::NAME => Result::<MFI<T>>::from_iter(params)?.boxed(),
+        UltOsc::<T>::NAME => Result::<UltOsc<T>>::from_iter(params)?.boxed(),
+        TrueRange::<T>::NAME => Result::<TrueRange<T>>::from_iter(params)?.boxed(),
+        ATR::<T>::NAME => Result::<ATR<T>>::from_iter(params)?.boxed(),
+        _ => match registry::lookup::<T>(func) {
+            Some(ctor) => ctor(params, opts)?,
+            None => throw!(anyhow!("Unknown function '{}'", func)),
+        },
+    };
+
+    // The NaNs would stay in the state of a window, only the pointwise nodes pass them on
+    if let Some(input) = op.nullable_input() {
+        throw!(anyhow!(
+            "{} may produce NaNs after its warm-up, which {} cannot take",
+            input.to_string(),
+            func
+        ))
     }
+    op
+}
+
+// The column names listed in `(Cols :c1 "c2" ...)`, either as symbols or strings
+#[throws(Error)]
+fn columns(expr: &Cons) -> Vec<String> {
+    expr.to_vec().0[1..]
+        .iter()
+        .map(|v| match v {
+            Value::Symbol(sym) if sym.starts_with(":") => Ok(sym[1..].to_string()),
+            Value::String(s) => Ok(s.to_string()),
+            v => Err(anyhow!("{} expect columns, got {}", COLS, v)),
+        })
+        .collect::<Result<Vec<_>>>()?
+}
+
+// The constants listed in a vector literal, e.g. `(0.5 0.3 0.2)`
+#[throws(Error)]
+fn vector(expr: &Cons) -> Vec<f64> {
+    expr.to_vec()
+        .0
+        .iter()
+        .map(|v| match v {
+            Value::Number(c) => Ok(c.as_f64().unwrap()),
+            v => Err(anyhow!("vectors should only contain constants, got {}", v)),
+        })
+        .collect::<Result<Vec<_>>>()?
 }
 
 #[cfg(test)]
 mod test {
+    use crate::ops::{NanPolicy, ParseOptions, ZeroPolicy};
     use arrow::record_batch::RecordBatch;
 
     #[test]
     fn t1() {
         let repr = "(+ :bid_price :ask_price)";
-        let op = super::from_str::<RecordBatch>(repr).unwrap();
+        let op = super::from_str::<RecordBatch>(repr, &Default::default()).unwrap();
 
         let s = op.to_string();
         assert_eq!(s, repr);
     }
+
+    #[test]
+    fn getter_or() {
+        let repr = "(+ :bid_price (col-or \"imbalance\" 0))";
+        let op = super::from_str::<RecordBatch>(repr, &Default::default()).unwrap();
+
+        assert_eq!(op.to_string(), repr);
+        assert_eq!(op.columns(), vec!["bid_price".to_string()]);
+        assert!(
+            super::from_str::<RecordBatch>("(col-or :imbalance 0)", &Default::default()).is_err()
+        );
+    }
+
+    #[test]
+    fn dot() {
+        let opts = Default::default();
+        let op =
+            super::from_str::<RecordBatch>("(Dot 0.5 0.3 (Cols :bid1 \"bid2\"))", &opts).unwrap();
+        assert_eq!(op.to_string(), "(WSum 0.5 :bid1 0.3 :bid2)");
+        assert_eq!(op.child_indices(), vec![1, 2]);
+
+        let repr = "(WSum 0.5 (+ :a :b) -1 :c)";
+        let op = super::from_str::<RecordBatch>(repr, &opts).unwrap();
+        assert_eq!(op.to_string(), repr);
+        assert_eq!(op.get(4).unwrap().to_string(), ":c");
+
+        for repr in [
+            "(Dot 0.5 (Cols :a :b))",
+            "(Dot 0.5 0.5 :a)",
+            "(WSum 0.5 :a 0.5)",
+            "(Cols :a :b)",
+            "(Dot (0.5) (Cols :a :b))",
+        ] {
+            assert!(
+                super::from_str::<RecordBatch>(repr, &opts).is_err(),
+                "{}",
+                repr
+            );
+        }
+    }
+
+    #[test]
+    fn variadic() {
+        let opts = Default::default();
+        for (repr, expected) in [
+            ("(+ :a :b :c :d)", "(+ (+ :a :b) (+ :c :d))"),
+            ("(+ :a :b :c)", "(+ :a (+ :b :c))"),
+            ("(* 2 :a :b :c :d)", "(* (* 2 :a) (* :b (* :c :d)))"),
+            (
+                "(And (> :a 0) (> :b 0) (> :c 0))",
+                "(And (> :a 0) (And (> :b 0) (> :c 0)))",
+            ),
+            ("(add :a :b :c)", "(+ :a (+ :b :c))"),
+        ] {
+            let op = super::from_str::<RecordBatch>(repr, &opts).unwrap();
+            assert_eq!(op.to_string(), expected);
+        }
+
+        let op = super::from_str::<RecordBatch>("(+ :a :b :c :d :e :f :g :h)", &opts).unwrap();
+        assert_eq!(op.depth(), 4);
+
+        for repr in ["(- :a :b :c)", "(+ :a)", "(+ :a :b (Cols :c))"] {
+            assert!(
+                super::from_str::<RecordBatch>(repr, &opts).is_err(),
+                "{}",
+                repr
+            );
+        }
+    }
+
+    #[test]
+    fn vectors() {
+        let opts = Default::default();
+        let repr = "(Conv (0.5 0.3 0.2) :mid)";
+        let op = super::from_str::<RecordBatch>(repr, &opts).unwrap();
+        assert_eq!(op.to_string(), repr);
+        assert_eq!(op.ready_offset(), 2);
+
+        let op =
+            super::from_str::<RecordBatch>("(Dot (0.5 0.5) (Cols :bid1 :bid2))", &opts).unwrap();
+        assert_eq!(op.to_string(), "(WSum 0.5 :bid1 0.5 :bid2)");
+
+        for repr in ["(Conv () :mid)", "(Conv (0.5 :a) :mid)", "(Conv 0.5 :mid)"] {
+            assert!(
+                super::from_str::<RecordBatch>(repr, &opts).is_err(),
+                "{}",
+                repr
+            );
+        }
+    }
+
+    #[test]
+    fn lets() {
+        let opts = Default::default();
+        let op = super::from_str::<RecordBatch>(
+            "(let ((mid (/ (+ :bid :ask) 2)) (spread (- :ask :bid))) (/ spread (let ((mid 1)) mid)))",
+            &opts,
+        )
+        .unwrap();
+        assert_eq!(op.to_string(), "(/ (- :ask :bid) 1)");
+
+        let op = super::from_str::<RecordBatch>(
+            "(let ((mid (/ (+ :bid :ask) 2)) (ret (LogReturn 1 mid))) (* ret (Mean 10 mid)))",
+            &opts,
+        )
+        .unwrap();
+        assert_eq!(
+            op.to_string(),
+            "(* (LogReturn 1 (/ (+ :bid :ask) 2)) (Mean 10 (/ (+ :bid :ask) 2)))"
+        );
+
+        for repr in [
+            "(let ((:mid 1)) :mid)",
+            "(let (mid 1) mid)",
+            "(let ((mid 1)))",
+            "(let ((mid 1)) undefined)",
+        ] {
+            assert!(
+                super::from_str::<RecordBatch>(repr, &opts).is_err(),
+                "{}",
+                repr
+            );
+        }
+    }
+
+    #[test]
+    fn aliases() {
+        let opts = Default::default();
+        for (repr, canonical) in [
+            ("(TSMean 10 :a)", "(Mean 10 :a)"),
+            ("(ts_mean 10 :a)", "(Mean 10 :a)"),
+            ("(mean 10 :a)", "(Mean 10 :a)"),
+            ("(TSStd 10 :a)", "(Std 10 :a)"),
+            ("(Correlation 10 :a :b)", "(Corr 10 :a :b)"),
+            ("(ts_corr 10 :a :b)", "(Corr 10 :a :b)"),
+            ("(TSBeta 10 :a :b)", "(Beta 10 :a :b)"),
+            ("(TSResid 10 :a :b)", "(Resid 10 :a :b)"),
+            ("(TSAutoCorr 10 1 :a)", "(AutoCorr 10 1 :a)"),
+            ("(ts_product 10 :a)", "(Product 10 :a)"),
+            ("(TSCountIf 10 :a)", "(CountIf 10 :a)"),
+            ("(TSAny 10 :a)", "(Any 10 :a)"),
+            ("(ts_all 10 :a)", "(All 10 :a)"),
+            ("(TSFirst 10 :a)", "(First 10 :a)"),
+            ("(Diff 10 :a)", "(Momentum 10 :a)"),
+            ("(delta 10 :a)", "(Momentum 10 :a)"),
+            ("(ts_pct_change 10 :a)", "(PctChange 10 :a)"),
+            ("(TSDrawdown 10 :a)", "(Drawdown 10 :a)"),
+            ("(TSHurst 100 :a)", "(Hurst 100 :a)"),
+            ("(TSSlope 10 :a)", "(Slope 10 :a)"),
+            ("(ts_trend_r2 10 :a)", "(TrendR2 10 :a)"),
+            ("(TSTheilSen 10 :a)", "(TheilSen 10 :a)"),
+            ("(TSPercentile 10 :a)", "(Percentile 10 :a)"),
+            ("(TSWinsorize 10 0.1 0.9 :a)", "(Winsorize 10 0.1 0.9 :a)"),
+            ("(TSMeanT \"5s\" :t :a)", "(MeanT \"5s\" :t :a)"),
+            ("(decay_linear 10 :a)", "(WMA 10 :a)"),
+            ("(TSKurtosis 10 :a)", "(Kurt 10 :a)"),
+            ("(TSVWAP 10 :p :v)", "(VWAP 10 :p :v)"),
+            ("(ts_twap 10 :t :p)", "(TWAP 10 :t :p)"),
+            ("(add :a (LOGABS :b))", "(+ :a (LogAbs :b))"),
+            ("(Col_Or \"a\" 0)", "(col-or \"a\" 0)"),
+        ] {
+            let op = super::from_str::<RecordBatch>(repr, &opts).unwrap();
+            assert_eq!(op.to_string(), canonical);
+        }
+
+        assert!(super::from_str::<RecordBatch>("(TSMeans 10 :a)", &opts).is_err());
+    }
+
+    #[test]
+    fn error_positions() {
+        let opts = Default::default();
+        for (repr, message, span, fragment) in [
+            (
+                "(+ :a (Mean 10 (TSMeen 5 :b)))",
+                "Unknown function 'TSMeen'",
+                (15, 28),
+                "(TSMeen 5 :b)",
+            ),
+            (
+                "(+ (Cols :a) :b)",
+                "<param1> for Add",
+                (0, 16),
+                "(+ (Cols :a) :b)",
+            ),
+            (
+                "(let ((x (Std 1 :a))) (+ x 1))",
+                "<win_size> for Std",
+                (9, 19),
+                "(Std 1 :a)",
+            ),
+            (
+                "(+ :a (Mean 10 #t :a))",
+                "unexpected parameter #t for Mean",
+                (15, 17),
+                "#t",
+            ),
+            ("  () ", "empty expression", (2, 4), "()"),
+        ] {
+            let e = super::from_str::<RecordBatch>(repr, &opts).err().unwrap();
+            let e = e.downcast::<super::ParseError>().unwrap();
+            assert!(e.message.contains(message), "{}", e.message);
+            assert_eq!(e.span, Some(span), "{}", repr);
+            assert_eq!(e.fragment.as_deref(), Some(fragment));
+        }
+    }
+
+    #[test]
+    fn parameter_ranges() {
+        let opts = Default::default();
+        for repr in [
+            "(Mean 0 :a)",
+            "(Sum 2.5 :a)",
+            "(Std 1 :a)",
+            "(Skew 2 :a)",
+            "(Kurt 3 :a)",
+            "(Delay -1 :a)",
+            "(Quantile 10 1.5 :a)",
+            "(Winsorize 10 0.1 1.5 :a)",
+            "(Mean 10 11 :a)",
+            "(Std 10 1 :a)",
+            "(If :a :b)",
+        ] {
+            assert!(
+                super::from_str::<RecordBatch>(repr, &opts).is_err(),
+                "{}",
+                repr
+            );
+        }
+
+        for repr in ["(Delay 0 :a)", "(Std 2 :a)", "(Quantile 10 1 :a)"] {
+            assert!(
+                super::from_str::<RecordBatch>(repr, &opts).is_ok(),
+                "{}",
+                repr
+            );
+        }
+    }
+
+    #[test]
+    fn typed_parameters() {
+        let opts = Default::default();
+        for (repr, error) in [
+            (
+                "(Mean 2.7 :a)",
+                "<win_size> for Mean should be an integer no less than 1, got 2.7",
+            ),
+            ("(WSum 0.5 :a 1.5 :b)", ""),
+            (
+                "(QuantileApprox 1.5 100 :a)",
+                "<q> for QuantileApprox should be within [0, 1], got 1.5",
+            ),
+            (
+                "(RankApprox 1 :a)",
+                "<k> for RankApprox should be an integer no less than 2, got 1",
+            ),
+            (
+                "(Mean 1e300 :a)",
+                "<win_size> for Mean should be no larger than",
+            ),
+        ] {
+            match super::from_str::<RecordBatch>(repr, &opts) {
+                Ok(_) => assert!(error.is_empty(), "{}", repr),
+                Err(e) => assert!(
+                    !error.is_empty() && e.to_string().contains(error),
+                    "{}: {}",
+                    repr,
+                    e
+                ),
+            }
+        }
+
+        for (s, nanos) in [
+            ("500ms", 500_000_000),
+            ("1.5h", 5_400_000_000_000),
+            ("3ns", 3),
+        ] {
+            assert_eq!(super::to_duration("f", "p", s).unwrap().as_nanos(), nanos);
+        }
+        for s in ["", "5", "s", "-1s", "0s", "0.1ns", "5x", "1e30d"] {
+            assert!(super::to_duration("f", "p", s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn policies() {
+        let opts = ParseOptions {
+            zero: ZeroPolicy::Ieee,
+            nan: NanPolicy::Propagate,
+        };
+        for (repr, printed) in [
+            ("(/ :a :b)", "(/ ieee :a :b)"),
+            ("(LogAbs :a)", "(LogAbs ieee :a)"),
+            ("(PctChange 1 :a)", "(PctChange ieee 1 :a)"),
+            (
+                "(If (< :a :b) :a :b)",
+                "(If propagate (< propagate :a :b) :a :b)",
+            ),
+            ("(Not :a)", "(! propagate :a)"),
+            ("(+ :a :b)", "(+ :a :b)"),
+        ] {
+            let op = super::from_str::<RecordBatch>(repr, &opts).unwrap();
+            assert_eq!(op.to_string(), printed);
+            let op = super::from_str::<RecordBatch>(printed, &Default::default()).unwrap();
+            assert_eq!(op.to_string(), printed);
+        }
+
+        // the one in the expression wins over the options
+        let op = super::from_str::<RecordBatch>("(/ nan :a (/ epsilon :b :c))", &opts).unwrap();
+        assert_eq!(op.to_string(), "(/ nan :a (/ :b :c))");
+        let op = super::from_str::<RecordBatch>("(And false :a :b :c)", &opts).unwrap();
+        assert_eq!(op.to_string(), "(And :a (And :b :c))");
+
+        for repr in ["(/ zero :a :b)", "(And ieee :a :b)", "(+ ieee :a :b)"] {
+            assert!(
+                super::from_str::<RecordBatch>(repr, &opts).is_err(),
+                "{}",
+                repr
+            );
+        }
+        for (repr, fragment) in [
+            ("(+ ieee :a :b)", "(+ ieee :a :b)"),
+            ("(Mean 3 (Abs nan :a))", "(Abs nan :a)"),
+        ] {
+            let e = super::from_str::<RecordBatch>(repr, &opts).err().unwrap();
+            let e = e.downcast::<super::ParseError>().unwrap();
+            assert!(e.message.contains("takes no policy"), "{}", e.message);
+            assert_eq!(e.fragment.as_deref(), Some(fragment));
+        }
+    }
 }
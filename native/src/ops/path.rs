@@ -0,0 +1,92 @@
+//! Address the nodes by their paths from the root, e.g. `0/1` is the second child of the first child of the root.
+//! Unlike the pre-order indices, a path does not move when a subtree elsewhere in the factor changes size.
+
+use super::Operator;
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Result};
+
+/// The pre-order index (as used by `Operator::get`) of the node at `path`, a `/` separated list of child positions.
+/// The empty path is the root.
+pub fn resolve_path<T: TickerBatch>(op: &dyn Operator<T>, path: &str) -> Result<usize> {
+    let mut index = 0;
+    let mut node = match op.get(0) {
+        Some(node) => node,
+        None => return Err(anyhow!("{} cannot be copied", op.to_string())),
+    };
+    if path.is_empty() {
+        return Ok(index);
+    }
+
+    for (depth, step) in path.split('/').enumerate() {
+        let k: usize = step
+            .parse()
+            .map_err(|_| anyhow!("malformed path {}, expect child positions like 0/1/2", path))?;
+        let offset = match node.child_indices().get(k) {
+            Some(&offset) => offset,
+            None => {
+                return Err(anyhow!(
+                    "{} at depth {} of path {} has no child #{}",
+                    node.to_string(),
+                    depth,
+                    path,
+                    k
+                ))
+            }
+        };
+        index += offset;
+        node = node.get(offset).unwrap();
+    }
+    Ok(index)
+}
+
+/// The path of the node at the pre-order index `i`, the inverse of `resolve_path`.
+pub fn path_of<T: TickerBatch>(op: &dyn Operator<T>, mut i: usize) -> Option<String> {
+    if i >= op.len() {
+        return None;
+    }
+
+    let mut steps = vec![];
+    let mut node = op.get(0)?;
+    while i > 0 {
+        let (k, offset) = node
+            .child_indices()
+            .into_iter()
+            .enumerate()
+            .take_while(|&(_, offset)| offset <= i)
+            .last()?;
+        steps.push(k.to_string());
+        i -= offset;
+        node = node.get(offset)?;
+    }
+    Some(steps.join("/"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{path_of, resolve_path};
+    use crate::ops::from_str;
+    use arrow::record_batch::RecordBatch;
+
+    #[test]
+    fn paths() {
+        let op =
+            from_str::<RecordBatch>("(+ (/ :close (Mean 10 :open)) :high)", &Default::default())
+                .unwrap();
+        for (path, i, node) in [
+            ("", 0, "(+ (/ :close (Mean 10 :open)) :high)"),
+            ("0", 1, "(/ :close (Mean 10 :open))"),
+            ("0/1", 3, "(Mean 10 :open)"),
+            ("0/1/0", 4, ":open"),
+            ("1", 5, ":high"),
+        ] {
+            assert_eq!(resolve_path(&*op, path).unwrap(), i);
+            assert_eq!(op.get(i).unwrap().to_string(), node);
+            assert_eq!(path_of(&*op, i).unwrap(), path);
+        }
+        assert_eq!(path_of(&*op, 6), None);
+
+        for path in ["2", "1/0", "0/a", "/0", "0/"] {
+            assert!(resolve_path(&*op, path).is_err(), "{}", path);
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Render a factor over multiple lines, so that deep factors can be reviewed.
+
+use super::constant::fmt_constant;
+
+pub(super) enum Node {
+    Atom(String),
+    List(Vec<Node>),
+}
+
+/// Split an S-expression into parentheses and atoms, along with their positions (in chars).
+pub(super) fn tokenize(sexpr: &str) -> Vec<(usize, String)> {
+    let mut tokens = vec![];
+    let mut chars = sexpr.chars().enumerate().peekable();
+    while let Some((pos, c)) = chars.next() {
+        match c {
+            '(' | ')' => tokens.push((pos, c.to_string())),
+            c if c.is_whitespace() => {}
+            '"' => {
+                let mut s = String::from('"');
+                while let Some((_, c)) = chars.next() {
+                    s.push(c);
+                    match c {
+                        '\\' => s.extend(chars.next().map(|(_, c)| c)),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+                tokens.push((pos, s));
+            }
+            c => {
+                let mut s = String::from(c);
+                while let Some(&(_, c)) = chars.peek() {
+                    if c == '(' || c == ')' || c.is_whitespace() {
+                        break;
+                    }
+                    s.push(c);
+                    chars.next();
+                }
+                tokens.push((pos, s));
+            }
+        }
+    }
+    tokens
+}
+
+pub(super) fn parse(tokens: &mut std::vec::IntoIter<String>) -> Option<Node> {
+    let token = tokens.next()?;
+    if token != "(" {
+        return Some(Node::Atom(token));
+    }
+
+    let mut items = vec![];
+    loop {
+        match tokens.as_slice().first().map(|t| t.as_str()) {
+            Some(")") | None => {
+                tokens.next();
+                return Some(Node::List(items));
+            }
+            _ => items.push(parse(tokens)?),
+        }
+    }
+}
+
+impl Node {
+    fn is_atom(&self) -> bool {
+        matches!(self, Node::Atom(_))
+    }
+
+    fn round(self, digits: usize) -> Node {
+        match self {
+            Node::Atom(a) => match a.parse::<f64>() {
+                Ok(c) if c.is_finite() && (a.contains('.') || a.contains('e')) => {
+                    let rounded = format!("{:.*e}", digits.max(1) - 1, c);
+                    Node::Atom(fmt_constant(rounded.parse().unwrap_or(c)))
+                }
+                _ => Node::Atom(a),
+            },
+            Node::List(items) => {
+                Node::List(items.into_iter().map(|item| item.round(digits)).collect())
+            }
+        }
+    }
+
+    pub(super) fn inline(&self) -> String {
+        match self {
+            Node::Atom(a) => a.clone(),
+            Node::List(items) => format!(
+                "({})",
+                items.iter().map(Node::inline).collect::<Vec<_>>().join(" ")
+            ),
+        }
+    }
+
+    // A list of atoms goes on one line. Otherwise the function name and the leading constants stay on
+    // the first line, and each of the other parameters starts a new line.
+    fn render(&self, indent: usize, depth: usize, out: &mut String) {
+        let items = match self {
+            Node::List(items) if !items.iter().all(Node::is_atom) => items,
+            _ => {
+                out.push_str(&self.inline());
+                return;
+            }
+        };
+
+        let head = items.iter().take_while(|item| item.is_atom()).count();
+        out.push('(');
+        out.push_str(
+            &items[..head]
+                .iter()
+                .map(Node::inline)
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+        for item in &items[head..] {
+            out.push('\n');
+            out.push_str(&" ".repeat(indent * (depth + 1)));
+            item.render(indent, depth + 1, out);
+        }
+        out.push(')');
+    }
+}
+
+/// Render the S-expression of a factor with one parameter per line, indented by `indent` spaces per level.
+pub fn pretty(sexpr: &str, indent: usize) -> String {
+    let mut out = String::new();
+    let tokens: Vec<_> = tokenize(sexpr).into_iter().map(|(_, t)| t).collect();
+    match parse(&mut tokens.into_iter()) {
+        Some(node) => node.render(indent, 0, &mut out),
+        None => out.push_str(sexpr),
+    }
+    out
+}
+
+/// Round the non-integer constants of an S-expression to `digits` significant digits (at least 1), for display.
+/// The integers, e.g. the window sizes, are left as they are.
+pub fn round_constants(sexpr: &str, digits: usize) -> String {
+    let tokens: Vec<_> = tokenize(sexpr).into_iter().map(|(_, t)| t).collect();
+    match parse(&mut tokens.into_iter()) {
+        Some(node) => node.round(digits).inline(),
+        None => sexpr.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{pretty, round_constants};
+
+    #[test]
+    fn render() {
+        assert_eq!(pretty(":close", 2), ":close");
+        assert_eq!(pretty("(Mean 10 :close)", 2), "(Mean 10 :close)");
+        assert_eq!(
+            pretty(
+                "(+ (Mean 10 (/ :close :open)) (col-or \"a b\" 0) (Conv (0.5 0.5) :high))",
+                2
+            ),
+            "(+\n  (Mean 10\n    (/ :close :open))\n  (col-or \"a b\" 0)\n  (Conv\n    (0.5 0.5)\n    :high))"
+        );
+    }
+
+    #[test]
+    fn rounding() {
+        assert_eq!(
+            round_constants("(+ (Mean 10 :close) 0.30000000000000004)", 3),
+            "(+ (Mean 10 :close) 0.3)"
+        );
+        assert_eq!(
+            round_constants("(Conv (0.123456 1.5e-7) (col-or \"0.5\" 2.718))", 2),
+            "(Conv (0.12 1.5e-7) (col-or \"0.5\" 2.7))"
+        );
+        assert_eq!(round_constants(":close", 2), ":close");
+    }
+}
@@ -0,0 +1,277 @@
+//! Sample random factors from the grammar (see `signatures`), the building block of alpha mining.
+
+use super::{
+    parser::{from_str, resolve},
+    signatures, BoxOp, Kind, Signature,
+};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error};
+use fehler::{throw, throws};
+use itertools::Itertools;
+use std::collections::HashMap;
+
+// The chance to stop at a column before the maximum depth is reached
+const LEAF: f64 = 0.2;
+// The largest window size sampled
+const MAX_WINDOW: usize = 60;
+
+/// SplitMix64, so that the factors are reproducible given the seed.
+pub(super) struct Rng(u64);
+
+impl Rng {
+    pub(super) fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub(super) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// A uniform draw in [0, 1)
+    pub(super) fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A uniform draw in [0, n)
+    pub(super) fn below(&mut self, n: usize) -> usize {
+        (self.uniform() * n as f64) as usize
+    }
+
+    /// A uniform draw in [lo, hi), rounded to 2 decimals
+    fn constant(&mut self, lo: f64, hi: f64) -> f64 {
+        ((lo + (hi - lo) * self.uniform()) * 100.).round() / 100.
+    }
+
+    /// Pick an index with the probabilities proportional to `weights`
+    pub(super) fn choose(&mut self, weights: &[f64]) -> Option<usize> {
+        let total: f64 = weights.iter().sum();
+        if total <= 0. {
+            return None;
+        }
+        let mut x = self.uniform() * total;
+        for (i, &w) in weights.iter().enumerate() {
+            if x < w {
+                return Some(i);
+            }
+            x -= w;
+        }
+        weights.iter().rposition(|&w| w > 0.)
+    }
+}
+
+// The smallest depth of a tree rooted at the function
+fn min_depth(sig: &Signature) -> usize {
+    1 + sig
+        .params
+        .iter()
+        .filter(|p| !p.optional)
+        .map(|p| match p.kind {
+            Kind::Series => 1,
+            Kind::Window => 2,
+            _ => 0,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+struct Generator<'a> {
+    columns: &'a [String],
+    signatures: Vec<(Signature, f64)>,
+    rng: Rng,
+    // The length of the last vector sampled, the columns of Dot match its weights
+    weights: usize,
+}
+
+impl<'a> Generator<'a> {
+    fn column(&mut self) -> String {
+        let col = &self.columns[self.rng.below(self.columns.len())];
+        format!("{:?}", col)
+    }
+
+    // A series no deeper than `depth`, or a window operator if `windows_only`. Only the `root` can be a function
+    // restarting its input (see `Signature::root_only`)
+    fn series(&mut self, depth: usize, windows_only: bool, root: bool) -> Option<String> {
+        if !windows_only && (depth == 1 || self.rng.uniform() < LEAF) {
+            return Some(self.column());
+        }
+
+        let weights = self
+            .signatures
+            .iter()
+            .map(|(sig, w)| {
+                let fits = min_depth(sig) <= depth
+                    && (!windows_only || sig.category == "window")
+                    && (root || !sig.root_only);
+                if fits {
+                    *w
+                } else {
+                    0.
+                }
+            })
+            .collect_vec();
+        let sig = match self.rng.choose(&weights) {
+            Some(i) => self.signatures[i].0.clone(),
+            None if windows_only => return None,
+            None => return Some(self.column()),
+        };
+
+        let repeat = if sig.variadic {
+            1 + self.rng.below(3)
+        } else {
+            1
+        };
+        let mut params = vec![];
+        for _ in 0..repeat {
+            for param in sig.params.iter().filter(|p| !p.optional) {
+                params.push(self.param(param.kind, depth - 1)?);
+            }
+        }
+
+        Some(if params.is_empty() {
+            format!("({})", sig.name)
+        } else {
+            format!("({} {})", sig.name, params.join(" "))
+        })
+    }
+
+    fn param(&mut self, kind: Kind, depth: usize) -> Option<String> {
+        Some(match kind {
+            Kind::Constant => format!("{}", self.rng.constant(0.1, 3.)),
+            Kind::Integer(min) => {
+                let lo = min.max(1);
+                format!("{}", lo + self.rng.below(MAX_WINDOW.max(lo) - lo + 1))
+            }
+            Kind::Fraction => format!("{}", self.rng.constant(0., 1.)),
+            Kind::Series => self.series(depth, false, false)?,
+            Kind::Window => self.series(depth, true, false)?,
+            Kind::Vector => {
+                let n = 1 + self.rng.below(self.columns.len().min(4));
+                self.weights = n;
+                let weights = (0..n).map(|_| self.rng.constant(-1., 1.)).join(" ");
+                format!("({})", weights)
+            }
+            Kind::Columns => {
+                let n = self.weights;
+                let cols = (0..n).map(|_| self.column()).join(" ");
+                format!("(Cols {})", cols)
+            }
+            Kind::Name => self.column(),
+            Kind::Duration => format!("\"{}s\"", 1 + self.rng.below(MAX_WINDOW)),
+        })
+    }
+}
+
+/// Sample a random factor over `columns`, no deeper than `max_depth`. The functions are drawn with the
+/// probabilities proportional to `weights`, keyed by the function names or the categories (see `signatures`),
+/// 1 by default. The factors are reproducible given the seed.
+#[throws(Error)]
+pub fn random<T: TickerBatch>(
+    columns: &[String],
+    max_depth: usize,
+    seed: u64,
+    weights: &HashMap<String, f64>,
+) -> BoxOp<T> {
+    if columns.is_empty() {
+        throw!(anyhow!("no columns to sample the factors from"))
+    }
+    if max_depth == 0 {
+        throw!(anyhow!("<max_depth> should be at least 1"))
+    }
+
+    let signatures = signatures::<T>();
+    for (key, &w) in weights {
+        let known = signatures
+            .iter()
+            .any(|sig| sig.name == resolve::<T>(key) || sig.category == key);
+        if !known {
+            throw!(anyhow!(
+                "Unknown function or category '{}' in the weights",
+                key
+            ))
+        }
+        if !(w >= 0.) {
+            throw!(anyhow!(
+                "the weight of {} should be non-negative, got {}",
+                key,
+                w
+            ))
+        }
+    }
+
+    let signatures = signatures
+        .into_iter()
+        .map(|sig| {
+            let w = weights
+                .iter()
+                .find(|(key, _)| resolve::<T>(key) == sig.name)
+                .or_else(|| weights.iter().find(|(key, _)| **key == sig.category))
+                .map_or(1., |(_, &w)| w);
+            (sig, w)
+        })
+        .collect();
+    let mut gen = Generator {
+        columns,
+        signatures,
+        rng: Rng::new(seed),
+        weights: 1,
+    };
+
+    // The root is a function unless there is no room for one
+    let mut sexpr = None;
+    while sexpr.is_none() {
+        sexpr = if max_depth == 1 {
+            Some(gen.column())
+        } else {
+            gen.series(max_depth, false, true)
+        };
+    }
+    from_str(&sexpr.unwrap(), &Default::default())?
+}
+
+#[cfg(test)]
+mod test {
+    use super::random;
+    use arrow::record_batch::RecordBatch;
+    use std::collections::HashMap;
+
+    #[test]
+    fn sampling() {
+        let columns = vec!["bid".to_string(), "ask price".to_string()];
+        let none = HashMap::new();
+        for seed in 0..500 {
+            let op = random::<RecordBatch>(&columns, 4, seed, &none).unwrap();
+            assert!(op.depth() <= 4, "{}", op.to_string());
+            assert!(op.columns().iter().all(|c| columns.contains(c)));
+            assert_eq!(
+                op.to_string(),
+                random::<RecordBatch>(&columns, 4, seed, &none)
+                    .unwrap()
+                    .to_string()
+            );
+        }
+
+        let weights: HashMap<_, _> = [("window", 0.), ("ts_mean", 1.)]
+            .iter()
+            .map(|(k, v)| (k.to_string(), *v))
+            .collect();
+        for seed in 0..100 {
+            let repr = random::<RecordBatch>(&columns, 3, seed, &weights)
+                .unwrap()
+                .to_string();
+            assert!(!repr.contains("(Std") && !repr.contains("(Sum"), "{}", repr);
+        }
+
+        let op = random::<RecordBatch>(&columns, 1, 0, &none).unwrap();
+        assert_eq!(op.depth(), 1);
+
+        for (key, w) in [("Meen", 1.), ("Mean", -1.)] {
+            let weights = [(key.to_string(), w)].iter().cloned().collect();
+            assert!(random::<RecordBatch>(&columns, 3, 0, &weights).is_err());
+        }
+        assert!(random::<RecordBatch>(&[], 3, 0, &none).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+//! Operators defined outside of this crate. A downstream crate registers the constructors of its operators
+//! at startup, and `from_str` builds them like the built-in ones.
+
+use super::{
+    parser::{is_builtin, Parameter},
+    BoxOp, ParseOptions,
+};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use std::{
+    any::{Any, TypeId},
+    collections::BTreeMap,
+    sync::RwLock,
+};
+
+/// Build an operator from the parameters of `(<name> <param> ...)`.
+pub type Constructor<T> = fn(Vec<Parameter<T>>, &ParseOptions) -> Result<BoxOp<T>>;
+
+// The constructors, keyed by the name and the ticker batch type they are built for
+static REGISTRY: RwLock<BTreeMap<(String, TypeId), Box<dyn Any + Send + Sync>>> =
+    RwLock::new(BTreeMap::new());
+
+/// Register the constructor of an operator under `name`, replacing the one registered before.
+/// The names of the built-in functions cannot be taken.
+#[throws(Error)]
+pub fn register<T: TickerBatch>(name: &str, ctor: Constructor<T>) {
+    if name.is_empty()
+        || name.starts_with(':')
+        || name
+            .chars()
+            .any(|c| c.is_whitespace() || "()\"".contains(c))
+    {
+        throw!(anyhow!("'{}' is not a valid function name", name))
+    }
+    if is_builtin(name) {
+        throw!(anyhow!("'{}' is taken by a built-in function", name))
+    }
+
+    REGISTRY
+        .write()
+        .unwrap()
+        .insert((name.to_string(), TypeId::of::<T>()), Box::new(ctor));
+}
+
+/// Remove a registered operator, returns whether it was registered.
+pub fn unregister<T: TickerBatch>(name: &str) -> bool {
+    REGISTRY
+        .write()
+        .unwrap()
+        .remove(&(name.to_string(), TypeId::of::<T>()))
+        .is_some()
+}
+
+pub(super) fn lookup<T: TickerBatch>(name: &str) -> Option<Constructor<T>> {
+    REGISTRY
+        .read()
+        .unwrap()
+        .get(&(name.to_string(), TypeId::of::<T>()))
+        .and_then(|ctor| ctor.downcast_ref::<Constructor<T>>())
+        .copied()
+}
+
+/// Whether an operator is registered under `name`, for any ticker batch type.
+pub(super) fn is_registered(name: &str) -> bool {
+    REGISTRY
+        .read()
+        .unwrap()
+        .keys()
+        .any(|(registered, _)| registered == name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{register, unregister};
+    use crate::ops::{from_str, Mul, Operator, Parameter};
+    use anyhow::Result;
+    use arrow::record_batch::RecordBatch;
+    use std::iter::{once, FromIterator};
+
+    #[test]
+    fn registry() {
+        let opts = Default::default();
+        register::<RecordBatch>("Twice", |params, _| {
+            let op =
+                Result::<Mul<RecordBatch>>::from_iter(once(Parameter::Constant(2.)).chain(params))?;
+            Ok(op.boxed())
+        })
+        .unwrap();
+
+        let op = from_str::<RecordBatch>("(+ (Twice :a) 1)", &opts).unwrap();
+        assert_eq!(op.to_string(), "(+ (* 2 :a) 1)");
+        assert!(from_str::<RecordBatch>("(Twice)", &opts).is_err());
+
+        for name in ["Mean", "ts_mean", "let", ":a", "a b", ""] {
+            assert!(
+                register::<RecordBatch>(name, |_, _| unreachable!()).is_err(),
+                "{}",
+                name
+            );
+        }
+
+        assert!(unregister::<RecordBatch>("Twice"));
+        assert!(!unregister::<RecordBatch>("Twice"));
+        assert!(from_str::<RecordBatch>("(Twice :a)", &opts).is_err());
+    }
+}
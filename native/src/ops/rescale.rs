@@ -0,0 +1,114 @@
+//! Rescale the windows of a factor, e.g. to move a factor from 1-second bars to 100ms ticks.
+
+use super::{
+    constant::fmt_constant, evolve::split, parser::from_str, signatures, BoxOp, Kind, Signature,
+};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Result};
+use itertools::Itertools;
+
+// The integer parameters counted in rows, as opposed to e.g. the seeds or the sketch sizes
+const WINDOWS: &[&str] = &[
+    "win_size",
+    "min_periods",
+    "n",
+    "span",
+    "fast",
+    "medium",
+    "slow",
+    "signal",
+    "smooth_k",
+    "smooth_d",
+    "shift",
+    "lag",
+    "max_gap",
+];
+
+/// Multiply the window sizes of all the window functions by `k`, rounded and kept above the minimum of each function.
+/// The other parameters, e.g. the kernel of `Conv`, are left as they are.
+pub fn scale_windows<T: TickerBatch>(op: &BoxOp<T>, k: f64) -> Result<BoxOp<T>> {
+    if !(k.is_finite() && k > 0.) {
+        return Err(anyhow!("the scale should be a positive number, got {}", k));
+    }
+    scale(op.clone(), k, &signatures::<T>())
+}
+
+fn scale<T: TickerBatch>(mut op: BoxOp<T>, k: f64, sigs: &[Signature]) -> Result<BoxOp<T>> {
+    for i in op.child_indices().into_iter().rev() {
+        if let Some(child) = op.get(i) {
+            op.insert(i, scale(child, k, sigs)?);
+        }
+    }
+
+    let (sig, mut args) = match split(&op, sigs) {
+        Some(split) => split,
+        None => return Ok(op),
+    };
+    let all = args.len() >= sig.params.len();
+    let names = sig.params.iter().filter(|p| all || !p.optional);
+    let mut changed = false;
+    for ((arg, kind), param) in args.iter_mut().zip(names) {
+        match (*kind, arg.parse::<f64>()) {
+            (Kind::Integer(min), Ok(v)) if WINDOWS.contains(&param.name) => {
+                let w = (v * k).round().max(min as f64);
+                changed |= w != v;
+                *arg = format!("{}", w);
+            }
+            _ => {}
+        }
+    }
+    // A halflife is counted in rows too, e.g. `(EMA halflife 5 :x)`
+    for j in 1..args.len() {
+        if args[j - 1].0 == "halflife" {
+            if let Ok(v) = args[j].0.parse::<f64>() {
+                args[j].0 = fmt_constant(v * k);
+                changed = true;
+            }
+        }
+    }
+    if !changed {
+        return Ok(op);
+    }
+
+    // Only the parameters are parsed again, the (already scaled) children are put back as they are
+    let repr = format!("({} {})", sig.name, args.iter().map(|(a, _)| a).join(" "));
+    let mut scaled = from_str::<T>(&repr, &Default::default())?;
+    for i in scaled.child_indices().into_iter().rev() {
+        if let Some(child) = op.get(i) {
+            scaled.insert(i, child);
+        }
+    }
+    Ok(scaled)
+}
+
+#[cfg(test)]
+mod test {
+    use super::scale_windows;
+    use crate::ops::from_str;
+    use arrow::record_batch::RecordBatch;
+
+    #[test]
+    fn rescale() {
+        let opts = Default::default();
+        let op = from_str::<RecordBatch>(
+            "(/ (- :a (Mean 10 5 :a)) (+ (Std 20 (Delay 1 :a)) (Conv (0.5 0.5) (QuantileApprox 0.5 100 :b))))",
+            &opts,
+        )
+        .unwrap();
+        assert_eq!(
+            scale_windows(&op, 10.).unwrap().to_string(),
+            "(/ (- :a (Mean 100 50 :a)) (+ (Std 200 (Delay 10 :a)) (Conv (0.5 0.5) (QuantileApprox 0.5 100 :b))))"
+        );
+        assert_eq!(
+            scale_windows(&op, 0.1).unwrap().to_string(),
+            "(/ (- :a (Mean 1 :a)) (+ (Std 2 (Delay 0 :a)) (Conv (0.5 0.5) (QuantileApprox 0.5 100 :b))))"
+        );
+        assert_eq!(scale_windows(&op, 1.).unwrap().to_string(), op.to_string());
+        let op = from_str::<RecordBatch>("(- (EMA 10 :a) (EMA halflife 2.5 :a))", &opts).unwrap();
+        assert_eq!(
+            scale_windows(&op, 2.).unwrap().to_string(),
+            "(- (EMA 20 :a) (EMA halflife 5 :a))"
+        );
+        assert!(scale_windows(&op, 0.).is_err());
+    }
+}
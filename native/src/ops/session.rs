@@ -0,0 +1,358 @@
+use super::{at_child, constant::fmt_constant, parser::Parameter, BoxOp, Named, Operator};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use std::{borrow::Cow, cmp::max, iter::FromIterator, mem};
+
+// The operators replaying the inner factor session by session. `$key` gives the session of a row from the value of
+// the session series there and the session of the previous row, a new session starts whenever it changes.
+// The inner factor produces NaNs at the start of every session, so these can only be the root of a factor.
+// `$param` is an optional constant coming first, given to `$key` as an `Option<f64>`.
+macro_rules! impl_session {
+    ($($(#[$doc:meta])* $op:ident $([$param:ident])? ($last:ident, $s:ident => $key:expr) |$inner:ident| { $($restart:tt)+ })+) => {
+        $(
+            $(#[$doc])*
+            pub struct $op<T> {
+                session: BoxOp<T>,
+                inner: BoxOp<T>,
+                $($param: Option<f64>,)?
+
+                last: Option<f64>,
+            }
+
+            impl<T> Clone for $op<T> {
+                fn clone(&self) -> Self {
+                    Self {
+                        session: self.session.clone(),
+                        inner: self.inner.clone(),
+                        $($param: self.$param,)?
+                        last: None,
+                    }
+                }
+            }
+
+            impl<T> $op<T> {
+                pub fn new(session: BoxOp<T>, inner: BoxOp<T>) -> Self {
+                    Self {
+                        session,
+                        inner,
+                        $($param: None,)?
+                        last: None,
+                    }
+                }
+
+                // The optional constant, if the operator takes one
+                fn param(&self) -> Option<f64> {
+                    None $(.or(self.$param))?
+                }
+            }
+
+            impl<T: TickerBatch> $op<T> {
+                // called at the start of every session but the first one
+                #[throws(Error)]
+                fn restart(&mut self) {
+                    let $inner = &mut self.inner;
+                    $($restart)+
+                }
+            }
+
+            impl<T> Named for $op<T> {
+                const NAME: &'static str = stringify!($op);
+            }
+
+            impl<T: TickerBatch> Operator<T> for $op<T> {
+                fn reset(&mut self) {
+                    self.session.reset();
+                    self.inner.reset();
+                    self.last = None;
+                }
+
+                #[throws(Error)]
+                fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+                    let sessions = &*self.session.checked_update(tb).map_err(at_child(1))?;
+
+                    // The rows opening a new session
+                    let mut starts = vec![];
+                    for (i, &$s) in sessions.iter().enumerate() {
+                        let $last = self.last;
+                        $(let $param = self.$param;)?
+                        let s = $key;
+                        if let Some(last) = self.last.replace(s) {
+                            if last != s && !(last.is_nan() && s.is_nan()) {
+                                starts.push(i);
+                            }
+                        }
+                    }
+
+                    let inner = at_child(1 + self.session.len());
+                    if starts.is_empty() {
+                        return self.inner.checked_update(tb).map_err(&inner)?;
+                    }
+
+                    let mut results = Vec::with_capacity(tb.len());
+                    let mut offset = 0;
+                    for end in starts.into_iter().chain(Some(tb.len())) {
+                        if end > offset {
+                            let session = tb.slice(offset, end - offset);
+                            results.extend_from_slice(
+                                &self.inner.checked_update(&session).map_err(&inner)?,
+                            );
+                        }
+                        if end < tb.len() {
+                            self.restart()?;
+                        }
+                        offset = end;
+                    }
+
+                    results.into()
+                }
+
+                // Only the first warm-up, the inner factor warms up again at every session
+                fn ready_offset(&self) -> usize {
+                    max(self.session.ready_offset(), self.inner.ready_offset())
+                }
+
+                fn is_root_only(&self) -> bool {
+                    true
+                }
+
+                fn to_string(&self) -> String {
+                    match self.param() {
+                        Some(p) => format!(
+                            "({} {} {} {})",
+                            Self::NAME,
+                            fmt_constant(p),
+                            self.session.to_string(),
+                            self.inner.to_string()
+                        ),
+                        None => format!(
+                            "({} {} {})",
+                            Self::NAME,
+                            self.session.to_string(),
+                            self.inner.to_string()
+                        ),
+                    }
+                }
+
+                fn depth(&self) -> usize {
+                    1 + max(self.session.depth(), self.inner.depth())
+                }
+
+                fn len(&self) -> usize {
+                    self.session.len() + self.inner.len() + 1
+                }
+
+                fn child_indices(&self) -> Vec<usize> {
+                    vec![1, self.session.len() + 1]
+                }
+
+                fn columns(&self) -> Vec<String> {
+                    self.session
+                        .columns()
+                        .into_iter()
+                        .chain(self.inner.columns())
+                        .collect()
+                }
+
+                #[throws(as Option)]
+                fn get(&self, i: usize) -> BoxOp<T> {
+                    if i == 0 {
+                        return self.clone().boxed();
+                    }
+                    let i = i - 1;
+
+                    let ns = self.session.len();
+                    let ni = self.inner.len();
+
+                    if i < ns {
+                        self.session.get(i)?
+                    } else if i >= ns && i < ns + ni {
+                        self.inner.get(i - ns)?
+                    } else {
+                        throw!()
+                    }
+                }
+
+                #[throws(as Option)]
+                fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+                    if i == 0 {
+                        unreachable!("cannot insert root");
+                    }
+                    let i = i - 1;
+
+                    let ns = self.session.len();
+                    let ni = self.inner.len();
+
+                    if i < ns {
+                        if i == 0 {
+                            return mem::replace(&mut self.session, op) as BoxOp<T>;
+                        }
+                        self.session.insert(i, op)?
+                    } else if i >= ns && i < ns + ni {
+                        if i - ns == 0 {
+                            return mem::replace(&mut self.inner, op) as BoxOp<T>;
+                        }
+                        self.inner.insert(i - ns, op)?
+                    } else {
+                        throw!()
+                    }
+                }
+            }
+
+            impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<$op<T>> {
+                #[throws(Error)]
+                fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> $op<T> {
+                    let mut params: Vec<_> = iter.into_iter().collect();
+                    let param_name: Option<&str> = None $(.or(Some(stringify!($param))))?;
+                    let param = match (param_name, params.len()) {
+                        (Some(param_name), 3) => match params.remove(0) {
+                            Parameter::Constant(c) => Some(c),
+                            p => throw!(anyhow!(
+                                "<{}> for {} should be a constant, got {}",
+                                param_name,
+                                $op::<T>::NAME,
+                                p
+                            )),
+                        },
+                        _ => None,
+                    };
+                    if params.len() != 2 {
+                        throw!(anyhow!(
+                            "{} expect a session series and a series, got {:?}",
+                            $op::<T>::NAME,
+                            params
+                        ))
+                    }
+
+                    match (params.remove(0), params.remove(0)) {
+                        (Parameter::Operator(session), Parameter::Operator(inner)) => {
+                            // fails early if the inner factor cannot be restarted
+                            let mut op = $op::new(session, inner);
+                            $(op.$param = param;)?
+                            let _ = param; // unused by the operators without a constant
+                            op.restart()?;
+                            op
+                        }
+                        (session, inner) => throw!(anyhow!(
+                            "<session> and <param> for {} should be operators, got {} and {}",
+                            $op::<T>::NAME,
+                            session,
+                            inner
+                        )),
+                    }
+                }
+            }
+        )+
+    };
+}
+
+impl_session! {
+    /// Replays the inner factor session by session: its state is reset whenever the session series,
+    /// usually a date or session id column, changes value. This keeps e.g. the overnight gap out of the windows.
+    SessionReset (_last, s => s) |inner| { inner.reset() }
+
+    /// Like `SessionReset`, but only the window of the inner window operator is emptied at a new session,
+    /// the state of its inputs is kept. So a 30-tick mean never mixes yesterday's close with today's open.
+    SessionWindow (_last, s => s) |inner| { inner.evict()? }
+
+    /// Like `SessionReset`, with a session per day of the time series, in seconds since the epoch, e.g. a timestamp
+    /// column. The days are in UTC, unless they are shifted by `offset` hours, e.g. `(ResetDaily 8 :time ...)` for
+    /// the days in Shanghai. Daylight saving time is not taken into account.
+    ResetDaily [offset] (_last, t => ((t + offset.unwrap_or(0.) * 3600.) / 86400.).floor()) |inner| { inner.reset() }
+
+    /// Like `SessionReset`, but a new session starts at every row where the condition series is true,
+    /// e.g. `(ResetOn (> (TimeDelta :time) 300) (Mean 30 :close))` starts afresh after a gap of 5 minutes.
+    ResetOn (last, cond => last.unwrap_or(0.) + (cond > 0.) as u8 as f64) |inner| { inner.reset() }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ops::{from_str, Operator};
+    use arrow::{
+        array::{ArrayRef, Float64Array},
+        record_batch::RecordBatch,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn nesting() {
+        let opts = Default::default();
+        let column = |vals: Vec<f64>| Arc::new(Float64Array::from(vals)) as ArrayRef;
+        let tb = RecordBatch::try_from_iter(vec![
+            ("d", column(vec![1., 1., 1., 2., 2., 2.])),
+            ("y", column(vec![1., 2., 3., 4., 5., 6.])),
+        ])
+        .unwrap();
+
+        // The inner factor warms up again at the new session
+        let mut op = from_str::<RecordBatch>("(SessionReset :d (Mean 2 :y))", &opts).unwrap();
+        let got = op.update(&tb).unwrap();
+        assert!(got[0].is_nan() && got[3].is_nan());
+        assert_eq!(&got[1..3], &[1.5, 2.5]);
+        assert_eq!(&got[4..], &[4.5, 5.5]);
+
+        // So a parent would fail on these NaNs, or keep them forever in its window
+        for repr in [
+            "(+ :y (SessionReset :d (Mean 2 :y)))",
+            "(Mean 10 (SessionReset :d (Mean 2 :y)))",
+        ] {
+            let e = from_str::<RecordBatch>(repr, &opts).err().unwrap();
+            assert!(e.to_string().contains("can only be the root"), "{}", e);
+        }
+
+        // The same for the window emptied at the new session
+        let mut op = from_str::<RecordBatch>("(SessionWindow :d (Mean 2 :y))", &opts).unwrap();
+        let got = op.update(&tb).unwrap();
+        assert!(got[0].is_nan() && got[3].is_nan());
+        assert_eq!(&got[4..], &[4.5, 5.5]);
+        for repr in [
+            "(+ :y (SessionWindow :d (Mean 2 :y)))",
+            "(Mean 10 (SessionWindow :d (Mean 2 :y)))",
+        ] {
+            let e = from_str::<RecordBatch>(repr, &opts).err().unwrap();
+            assert!(e.to_string().contains("can only be the root"), "{}", e);
+        }
+
+        // Nor can it be nested by replacing a subtree
+        let mut op = from_str::<RecordBatch>("(+ :y (Mean 2 :y))", &opts).unwrap();
+        let session = from_str::<RecordBatch>("(SessionReset :d (Mean 2 :y))", &opts).unwrap();
+        op.insert(2, session).unwrap();
+        assert!(op.validate(&tb.schema()).is_err());
+    }
+
+    #[test]
+    fn daily() {
+        let opts = Default::default();
+        let hours = |hs: Vec<f64>| hs.into_iter().map(|h| h * 3600.).collect::<Vec<_>>();
+        let column = |vals: Vec<f64>| Arc::new(Float64Array::from(vals)) as ArrayRef;
+        let tb = RecordBatch::try_from_iter(vec![
+            ("t", column(hours(vec![0., 20., 23., 25., 30.]))),
+            ("y", column(vec![1., 2., 3., 4., 5.])),
+        ])
+        .unwrap();
+
+        // The UTC days start at the row of 25h
+        let mut op = from_str::<RecordBatch>("(ResetDaily :t (Mean 2 :y))", &opts).unwrap();
+        let got = op.update(&tb).unwrap();
+        assert!(got[0].is_nan() && got[3].is_nan());
+        assert_eq!(&got[1..3], &[1.5, 2.5]);
+
+        // 2 hours ahead of UTC, the second day starts at 22h UTC
+        let mut op = from_str::<RecordBatch>("(ResetDaily 2 :t (Mean 2 :y))", &opts).unwrap();
+        let got = op.update(&tb).unwrap();
+        assert!(got[0].is_nan() && got[2].is_nan());
+        assert_eq!(&got[3..], &[3.5, 4.5]);
+        assert_eq!(op.to_string(), "(ResetDaily 2 :t (Mean 2 :y))");
+
+        for repr in [
+            "(+ :y (ResetDaily :t (Mean 2 :y)))",
+            "(Mean 10 (ResetDaily 2 :t (Mean 2 :y)))",
+            "(+ :y (ResetOn (> :y 3) (Mean 2 :y)))",
+            "(Mean 10 (ResetOn (> :y 3) (Mean 2 :y)))",
+        ] {
+            let e = from_str::<RecordBatch>(repr, &opts).err().unwrap();
+            assert!(e.to_string().contains("can only be the root"), "{}", e);
+        }
+        assert!(from_str::<RecordBatch>("(ResetDaily :t :t (Mean 2 :y))", &opts).is_err());
+    }
+}
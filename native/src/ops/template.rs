@@ -0,0 +1,203 @@
+//! Factors with placeholders, e.g. `(Mean ?w :close)`, whose constants are bound later. A hyper-parameter sweep
+//! parses the template once and binds it to each combination of the values.
+
+use super::{
+    parser::{from_str, ParseOptions},
+    pretty::tokenize,
+    BoxOp, Operator,
+};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Result};
+use fehler::{throw, throws};
+use itertools::Itertools;
+use lexpr::Value;
+use std::{borrow::Cow, collections::HashMap};
+
+/// Whether the symbol is a placeholder like `?w`.
+pub(super) fn is_placeholder(sym: &str) -> bool {
+    let mut chars = sym.chars();
+    chars.next() == Some('?')
+        && chars
+            .next()
+            .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The placeholders in the expression, in the order of appearance.
+pub(super) fn placeholders(sexpr: &Value) -> Vec<String> {
+    match sexpr {
+        Value::Symbol(sym) if is_placeholder(sym) => vec![sym[1..].to_string()],
+        Value::Cons(cons) => cons
+            .to_vec()
+            .0
+            .iter()
+            .flat_map(placeholders)
+            .unique()
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// An expression with placeholders. It cannot be replayed until all the placeholders are bound (see `Operator::bind`),
+/// binding some of them gives another template.
+#[derive(Clone)]
+pub struct Template {
+    sexpr: String,
+    placeholders: Vec<String>,
+    opts: ParseOptions,
+}
+
+impl Template {
+    pub(super) fn new(sexpr: &str, placeholders: Vec<String>, opts: &ParseOptions) -> Self {
+        Self {
+            sexpr: sexpr.to_string(),
+            placeholders,
+            opts: *opts,
+        }
+    }
+
+    pub fn placeholders(&self) -> &[String] {
+        &self.placeholders
+    }
+}
+
+impl<T: TickerBatch> Operator<T> for Template {
+    fn reset(&mut self) {}
+
+    fn update<'a>(&mut self, _: &'a T) -> Result<Cow<'a, [f64]>> {
+        Err(anyhow!(
+            "{} has unbound placeholders {}",
+            self.sexpr,
+            self.placeholders
+                .iter()
+                .map(|p| format!("?{}", p))
+                .join(", ")
+        ))
+    }
+
+    fn ready_offset(&self) -> usize {
+        0
+    }
+
+    fn to_string(&self) -> String {
+        self.sexpr.clone()
+    }
+
+    fn depth(&self) -> usize {
+        1
+    }
+
+    fn len(&self) -> usize {
+        1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        vec![]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        vec![]
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i > 0 {
+            throw!()
+        }
+
+        self.clone().boxed()
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, _: usize, _: BoxOp<T>) -> BoxOp<T> {
+        unreachable!("cannot insert root");
+    }
+
+    fn bind(&self, values: &HashMap<String, f64>) -> Result<BoxOp<T>> {
+        if let Some(name) = values.keys().find(|name| !self.placeholders.contains(name)) {
+            throw!(anyhow!("{} has no placeholder ?{}", self.sexpr, name))
+        }
+
+        // Substitute the tokens, so that the errors point into the bound expression
+        let mut bound = String::new();
+        let mut chars = self.sexpr.chars().enumerate().peekable();
+        for (pos, token) in tokenize(&self.sexpr) {
+            while let Some(&(i, c)) = chars.peek() {
+                if i == pos {
+                    break;
+                }
+                bound.push(c);
+                chars.next();
+            }
+            let value = token
+                .strip_prefix('?')
+                .filter(|_| is_placeholder(&token))
+                .and_then(|name| values.get(name));
+            match value {
+                Some(value) => bound.push_str(&format!("{}", value)),
+                None => bound.push_str(&token),
+            }
+            for _ in 0..token.chars().count() {
+                chars.next();
+            }
+        }
+        bound.extend(chars.map(|(_, c)| c));
+
+        Ok(from_str(&bound, &self.opts)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ops::from_str;
+    use arrow::record_batch::RecordBatch;
+    use std::collections::HashMap;
+
+    #[test]
+    fn binding() {
+        let opts = Default::default();
+        let template = from_str::<RecordBatch>(
+            "(let ((x (Delay ?lag :close))) (+ (Mean ?w x) (* ?k ?w)))",
+            &opts,
+        )
+        .unwrap();
+        assert_eq!(template.columns().len(), 0);
+
+        let values: HashMap<_, _> = [("w", 20.), ("lag", 1.), ("k", 0.5)]
+            .iter()
+            .map(|(k, v)| (k.to_string(), *v))
+            .collect();
+        assert_eq!(
+            template.bind(&values).unwrap().to_string(),
+            "(+ (Mean 20 (Delay 1 :close)) (* 0.5 20))"
+        );
+
+        let mut partial = values.clone();
+        partial.remove("k");
+        let partial_bound = template.bind(&partial).unwrap();
+        assert_eq!(
+            partial_bound.to_string(),
+            "(let ((x (Delay 1 :close))) (+ (Mean 20 x) (* ?k 20)))"
+        );
+        assert_eq!(
+            partial_bound
+                .bind(&[("k".to_string(), 2.)].iter().cloned().collect())
+                .unwrap()
+                .to_string(),
+            "(+ (Mean 20 (Delay 1 :close)) (* 2 20))"
+        );
+        partial.insert("n".into(), 1.);
+        assert!(template.bind(&partial).is_err());
+
+        let mut bad = values.clone();
+        bad.insert("w".into(), 2.5);
+        assert!(template.bind(&bad).is_err());
+
+        let op = from_str::<RecordBatch>("(Mean 10 :close)", &opts).unwrap();
+        assert_eq!(
+            op.bind(&HashMap::new()).unwrap().to_string(),
+            "(Mean 10 :close)"
+        );
+        assert!(op.bind(&values).is_err());
+    }
+}
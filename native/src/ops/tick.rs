@@ -0,0 +1,248 @@
+use super::{at_child, parser::Parameter, BoxOp, Named, Operator};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use std::{borrow::Cow, iter::FromIterator, mem};
+
+/// The number of the current row since the start of the replay, starting from 0.
+#[derive(Default)]
+pub struct RowIndex {
+    n: usize,
+}
+
+impl Clone for RowIndex {
+    fn clone(&self) -> Self {
+        Self::new()
+    }
+}
+
+impl RowIndex {
+    pub fn new() -> Self {
+        Self { n: 0 }
+    }
+}
+
+impl Named for RowIndex {
+    const NAME: &'static str = "RowIndex";
+}
+
+impl<T: TickerBatch> Operator<T> for RowIndex {
+    fn reset(&mut self) {
+        self.n = 0;
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let results: Vec<_> = (self.n..self.n + tb.len()).map(|i| i as f64).collect();
+        self.n += tb.len();
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        0
+    }
+
+    fn to_string(&self) -> String {
+        format!("({})", Self::NAME)
+    }
+
+    fn depth(&self) -> usize {
+        1
+    }
+
+    fn len(&self) -> usize {
+        1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        vec![]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        vec![]
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i != 0 {
+            throw!()
+        }
+        self.clone().boxed()
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, _: usize, _: BoxOp<T>) -> BoxOp<T> {
+        unreachable!("cannot insert root");
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<RowIndex> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> RowIndex {
+        let params: Vec<_> = iter.into_iter().collect();
+        if !params.is_empty() {
+            throw!(anyhow!(
+                "{} expect no parameters, got {:?}",
+                RowIndex::NAME,
+                params
+            ))
+        }
+
+        RowIndex::new()
+    }
+}
+
+/// The difference between the current and the previous tick of a series, usually a time column,
+/// which is read in seconds since the epoch.
+pub struct TimeDelta<T> {
+    inner: BoxOp<T>,
+
+    last: f64,
+    i: usize,
+}
+
+impl<T> Clone for TimeDelta<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.inner.clone())
+    }
+}
+
+impl<T> TimeDelta<T> {
+    pub fn new(inner: BoxOp<T>) -> Self {
+        Self {
+            inner,
+            last: f64::NAN,
+            i: 0,
+        }
+    }
+}
+
+impl<T> Named for TimeDelta<T> {
+    const NAME: &'static str = "TimeDelta";
+}
+
+impl<T: TickerBatch> Operator<T> for TimeDelta<T> {
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.last = f64::NAN;
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for &val in vals {
+            if self.i < self.inner.ready_offset() {
+                self.check_warmup(tb, &[val])?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            let last = mem::replace(&mut self.last, val);
+            let val = if self.i < self.ready_offset() {
+                self.i += 1;
+                f64::NAN
+            } else {
+                self.fchecked(val - last)?
+            };
+            results.push(val);
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inner.ready_offset() + 1
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.inner.lookback()? + 1)
+    }
+
+    fn state_bytes(&self) -> usize {
+        mem::size_of::<f64>() + self.inner.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        format!("({} {})", Self::NAME, self.inner.to_string())
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inner.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        vec![1]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inner.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            self.inner.get(i)?
+        } else {
+            throw!()
+        }
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            if i == 0 {
+                return mem::replace(&mut self.inner, op) as BoxOp<T>;
+            }
+            self.inner.insert(i, op)?
+        } else {
+            throw!()
+        }
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<TimeDelta<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> TimeDelta<T> {
+        let mut params: Vec<_> = iter.into_iter().collect();
+        if params.len() != 1 {
+            throw!(anyhow!(
+                "{} expect one series, got {:?}",
+                TimeDelta::<T>::NAME,
+                params
+            ))
+        }
+
+        match params.remove(0) {
+            Parameter::Operator(s) => TimeDelta::new(s),
+            p => throw!(anyhow!(
+                "<param> for {} should be an operator, got {}",
+                TimeDelta::<T>::NAME,
+                p
+            )),
+        }
+    }
+}
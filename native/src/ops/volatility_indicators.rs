@@ -0,0 +1,371 @@
+//! The volatility indicators of TA-Lib, over the high, low and close series.
+
+use std::{borrow::Cow, iter::FromIterator, mem};
+
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+
+use crate::ticker_batch::TickerBatch;
+
+use super::{
+    inputs::Inputs,
+    parser::{to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
+
+const HLC: [&str; 3] = ["high", "low", "close"];
+
+// The larger of the high and the previous close, minus the smaller of the low and the previous close
+pub(super) fn true_range(high: f64, low: f64, prev_close: f64) -> f64 {
+    high.max(prev_close) - low.min(prev_close)
+}
+
+/// The true range of TA-Lib, the range of the bar stretched to the previous close.
+/// The bars with a NaN are skipped, and produce a NaN.
+pub struct TrueRange<T> {
+    inputs: Inputs<T, 3>,
+
+    prev_close: f64,
+    i: usize,
+}
+
+impl<T> Clone for TrueRange<T> {
+    fn clone(&self) -> Self {
+        let [high, low, close] = self.inputs.clone().0;
+        Self::new(high, low, close)
+    }
+}
+
+impl<T> TrueRange<T> {
+    pub fn new(high: BoxOp<T>, low: BoxOp<T>, close: BoxOp<T>) -> Self {
+        Self {
+            inputs: Inputs([high, low, close]),
+
+            prev_close: f64::NAN,
+            i: 0,
+        }
+    }
+}
+
+impl<T> Named for TrueRange<T> {
+    const NAME: &'static str = "TrueRange";
+}
+
+impl<T: TickerBatch> Operator<T> for TrueRange<T> {
+    fn reset(&mut self) {
+        self.inputs.reset();
+        self.prev_close = f64::NAN;
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.prev_close = f64::NAN;
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let inputs = self.inputs.update(tb)?;
+        let (highs, lows, closes) = (&*inputs[0], &*inputs[1], &*inputs[2]);
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for ((&high, &low), &close) in highs.iter().zip(lows).zip(closes) {
+            if self.i < self.inputs.ready_offset() {
+                self.check_warmup(tb, &[high, low, close])?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            if high.is_nan() || low.is_nan() || close.is_nan() {
+                results.push(f64::NAN);
+                continue;
+            }
+            let prev_close = mem::replace(&mut self.prev_close, close);
+            results.push(if prev_close.is_nan() {
+                f64::NAN
+            } else {
+                true_range(high, low, prev_close)
+            });
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inputs.ready_offset() + 1
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.inputs.lookback()? + 1)
+    }
+
+    fn state_bytes(&self) -> usize {
+        mem::size_of::<f64>() + self.inputs.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        format!("({} {})", Self::NAME, self.inputs.to_string())
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inputs.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inputs.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        self.inputs.child_indices()
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inputs.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        self.inputs.get(i)?
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        self.inputs.insert(i, op)?
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<TrueRange<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> TrueRange<T> {
+        let name = TrueRange::<T>::NAME;
+        let mut iter = iter.into_iter();
+
+        let inputs = Inputs::from_params(name, HLC, &mut iter)?;
+
+        if iter.count() != 0 {
+            throw!(anyhow!("Too many parameters for {}", name))
+        }
+
+        let [high, low, close] = inputs.0;
+        TrueRange::new(high, low, close)
+    }
+}
+
+/// The average true range of TA-Lib. It starts as the mean of the first `win_size` true ranges,
+/// then follows Wilder's smoothing `atr = (atr * (win_size - 1) + tr) / win_size`.
+/// The bars with a NaN are skipped, and produce a NaN.
+pub struct ATR<T> {
+    win_size: usize,
+    inputs: Inputs<T, 3>,
+
+    prev_close: f64,
+    count: usize, // the number of true ranges seen, up to the window size
+    atr: f64,
+    i: usize,
+}
+
+impl<T> Clone for ATR<T> {
+    fn clone(&self) -> Self {
+        let [high, low, close] = self.inputs.clone().0;
+        Self::new(self.win_size, high, low, close)
+    }
+}
+
+impl<T> ATR<T> {
+    pub fn new(win_size: usize, high: BoxOp<T>, low: BoxOp<T>, close: BoxOp<T>) -> Self {
+        Self {
+            win_size,
+            inputs: Inputs([high, low, close]),
+
+            prev_close: f64::NAN,
+            count: 0,
+            atr: 0.,
+            i: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.prev_close = f64::NAN;
+        self.count = 0;
+        self.atr = 0.;
+    }
+}
+
+impl<T> Named for ATR<T> {
+    const NAME: &'static str = "ATR";
+}
+
+impl<T: TickerBatch> Operator<T> for ATR<T> {
+    fn reset(&mut self) {
+        self.inputs.reset();
+        self.clear();
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.clear();
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let inputs = self.inputs.update(tb)?;
+        let (highs, lows, closes) = (&*inputs[0], &*inputs[1], &*inputs[2]);
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for ((&high, &low), &close) in highs.iter().zip(lows).zip(closes) {
+            if self.i < self.inputs.ready_offset() {
+                self.check_warmup(tb, &[high, low, close])?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            if high.is_nan() || low.is_nan() || close.is_nan() {
+                results.push(f64::NAN);
+                continue;
+            }
+            let prev_close = mem::replace(&mut self.prev_close, close);
+            if prev_close.is_nan() {
+                results.push(f64::NAN);
+                continue;
+            }
+
+            let n = self.win_size as f64;
+            let tr = true_range(high, low, prev_close);
+            if self.count < self.win_size {
+                self.count += 1;
+                self.atr += tr / n;
+                if self.count < self.win_size {
+                    results.push(f64::NAN);
+                    continue;
+                }
+            } else {
+                self.atr = (self.atr * (n - 1.) + tr) / n;
+            }
+            results.push(self.atr);
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inputs.ready_offset() + self.win_size
+    }
+
+    // Every true range ever seen keeps a (vanishing) weight
+    fn lookback(&self) -> Option<usize> {
+        None
+    }
+
+    fn state_bytes(&self) -> usize {
+        2 * mem::size_of::<f64>() + self.inputs.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "({} {} {})",
+            Self::NAME,
+            self.win_size,
+            self.inputs.to_string()
+        )
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inputs.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inputs.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        self.inputs.child_indices()
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inputs.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        self.inputs.get(i)?
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        self.inputs.insert(i, op)?
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<ATR<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> ATR<T> {
+        let name = ATR::<T>::NAME;
+        let mut iter = iter.into_iter();
+
+        let Some(Parameter::Constant(n)) = iter.next() else {
+            throw!(anyhow!("<win_size> for {} should be an constant", name));
+        };
+        let inputs = Inputs::from_params(name, HLC, &mut iter)?;
+
+        if iter.count() != 0 {
+            throw!(anyhow!("Too many parameters for {}", name))
+        }
+
+        let [high, low, close] = inputs.0;
+        ATR::new(to_usize(name, "win_size", n, 1)?, high, low, close)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ops::{from_str, resolve_path, Operator};
+    use arrow::record_batch::RecordBatch;
+
+    #[test]
+    fn children() {
+        let opts = Default::default();
+        let repr = "(ATR 14 (Max 5 :high) :low (Mean 3 :close))";
+        let mut op = from_str::<RecordBatch>(repr, &opts).unwrap();
+        assert_eq!(op.to_string(), repr);
+        assert_eq!(op.len(), 6);
+        assert_eq!(op.child_indices(), vec![1, 3, 4]);
+        assert_eq!(op.columns(), vec!["high", "low", "close"]);
+        assert_eq!(op.ready_offset(), 4 + 14);
+
+        assert_eq!(resolve_path(&*op, "2/0").unwrap(), 5);
+        assert_eq!(op.get(5).unwrap().to_string(), ":close");
+        let low = op.insert(3, from_str("(Min 5 :low)", &opts).unwrap());
+        assert_eq!(low.unwrap().to_string(), ":low");
+        assert_eq!(
+            op.to_string(),
+            "(ATR 14 (Max 5 :high) (Min 5 :low) (Mean 3 :close))"
+        );
+        assert!(op.get(6).is_some() && op.get(7).is_none());
+
+        for repr in [
+            "(TrueRange :high :low)",
+            "(TrueRange :high :low :close :open)",
+            "(ATR :high :low :close)",
+            "(ATR 0 :high :low :close)",
+        ] {
+            assert!(from_str::<RecordBatch>(repr, &opts).is_err(), "{}", repr);
+        }
+    }
+}
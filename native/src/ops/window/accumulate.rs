@@ -0,0 +1,279 @@
+use super::super::{inputs::Inputs, parser::Parameter, BoxOp, Named, Operator};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use std::{borrow::Cow, iter::FromIterator, mem};
+
+/// The state of an operator over the whole history of its `N` inputs, e.g. the running total of `OBV`,
+/// as opposed to the fixed windows of the other window operators.
+pub trait Accumulator<const N: usize>: Clone + Default + Send + 'static {
+    const NAME: &'static str;
+    /// The names of the inputs, in order.
+    const INPUTS: [&'static str; N];
+
+    /// Take the inputs at the next row, give the output there.
+    fn accumulate(&mut self, vals: [f64; N]) -> f64;
+}
+
+/// The operator keeping an `Accumulator` over its inputs.
+pub struct Accumulate<T, A, const N: usize> {
+    inputs: Inputs<T, N>,
+
+    acc: A,
+    i: usize,
+}
+
+impl<T, A: Accumulator<N>, const N: usize> Clone for Accumulate<T, A, N> {
+    fn clone(&self) -> Self {
+        Self::new(self.inputs.clone().0)
+    }
+}
+
+impl<T, A: Accumulator<N>, const N: usize> Accumulate<T, A, N> {
+    pub fn new(inputs: [BoxOp<T>; N]) -> Self {
+        Self {
+            inputs: Inputs(inputs),
+
+            acc: A::default(),
+            i: 0,
+        }
+    }
+}
+
+impl<T, A: Accumulator<N>, const N: usize> Named for Accumulate<T, A, N> {
+    const NAME: &'static str = A::NAME;
+}
+
+impl<T: TickerBatch, A: Accumulator<N>, const N: usize> Operator<T> for Accumulate<T, A, N> {
+    fn reset(&mut self) {
+        self.inputs.reset();
+        self.acc = A::default();
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.acc = A::default();
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let inputs = self.inputs.update(tb)?;
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for row in 0..tb.len() {
+            let mut vals = [0.; N];
+            for (val, input) in vals.iter_mut().zip(&inputs) {
+                *val = input[row];
+            }
+
+            if self.i < self.ready_offset() {
+                self.check_warmup(tb, &vals)?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            results.push(self.acc.accumulate(vals));
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inputs.ready_offset()
+    }
+
+    // The output depends on the whole history
+    fn lookback(&self) -> Option<usize> {
+        None
+    }
+
+    fn state_bytes(&self) -> usize {
+        mem::size_of::<A>() + self.inputs.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        format!("({} {})", Self::NAME, self.inputs.to_string())
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inputs.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inputs.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        self.inputs.child_indices()
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inputs.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        self.inputs.get(i)?
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        self.inputs.insert(i, op)?
+    }
+}
+
+impl<T: TickerBatch, A: Accumulator<N>, const N: usize> FromIterator<Parameter<T>>
+    for Result<Accumulate<T, A, N>>
+{
+    #[throws(Error)]
+    fn from_iter<I: IntoIterator<Item = Parameter<T>>>(iter: I) -> Accumulate<T, A, N> {
+        let mut iter = iter.into_iter();
+
+        let inputs = Inputs::from_params(A::NAME, A::INPUTS, &mut iter)?;
+
+        if iter.count() != 0 {
+            throw!(anyhow!("Too many parameters for {}", A::NAME))
+        }
+
+        Accumulate::new(inputs.0)
+    }
+}
+
+/// The running total of the volume, added when the price goes up and subtracted when it goes down,
+/// starting from the first volume as TA-Lib does. The bars with a NaN are skipped, and produce a NaN.
+#[derive(Clone, Default)]
+pub struct OnBalanceVolume {
+    prev: Option<(f64, f64)>, // the last price and the total
+}
+
+impl Accumulator<2> for OnBalanceVolume {
+    const NAME: &'static str = "OBV";
+    const INPUTS: [&'static str; 2] = ["price", "volume"];
+
+    fn accumulate(&mut self, [price, volume]: [f64; 2]) -> f64 {
+        if price.is_nan() || volume.is_nan() {
+            return f64::NAN;
+        }
+
+        let obv = match self.prev {
+            None => volume,
+            Some((prev, obv)) if price > prev => obv + volume,
+            Some((prev, obv)) if price < prev => obv - volume,
+            Some((_, obv)) => obv,
+        };
+        self.prev = Some((price, obv));
+        obv
+    }
+}
+
+pub type OBV<T> = Accumulate<T, OnBalanceVolume, 2>;
+
+/// The running total since the first row. The NaNs are skipped, and produce a NaN.
+#[derive(Clone, Default)]
+pub struct CumulativeSum {
+    sum: f64,
+}
+
+impl Accumulator<1> for CumulativeSum {
+    const NAME: &'static str = "CumSum";
+    const INPUTS: [&'static str; 1] = ["x"];
+
+    fn accumulate(&mut self, [x]: [f64; 1]) -> f64 {
+        if x.is_nan() {
+            return f64::NAN;
+        }
+        self.sum += x;
+        self.sum
+    }
+}
+
+pub type CumSum<T> = Accumulate<T, CumulativeSum, 1>;
+
+/// The mean of all the values since the first row. The NaNs are skipped, and produce a NaN.
+#[derive(Clone, Default)]
+pub struct CumulativeMean {
+    mean: f64,
+    n: usize,
+}
+
+impl Accumulator<1> for CumulativeMean {
+    const NAME: &'static str = "CumMean";
+    const INPUTS: [&'static str; 1] = ["x"];
+
+    fn accumulate(&mut self, [x]: [f64; 1]) -> f64 {
+        if x.is_nan() {
+            return f64::NAN;
+        }
+        // Updated rather than divided from the total, which could grow without bound
+        self.n += 1;
+        self.mean += (x - self.mean) / self.n as f64;
+        self.mean
+    }
+}
+
+pub type CumMean<T> = Accumulate<T, CumulativeMean, 1>;
+
+/// The highest value since the first row. The NaNs are skipped, and produce a NaN.
+#[derive(Clone)]
+pub struct CumulativeMax {
+    max: f64,
+}
+
+impl Default for CumulativeMax {
+    fn default() -> Self {
+        Self {
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl Accumulator<1> for CumulativeMax {
+    const NAME: &'static str = "CumMax";
+    const INPUTS: [&'static str; 1] = ["x"];
+
+    fn accumulate(&mut self, [x]: [f64; 1]) -> f64 {
+        if x.is_nan() {
+            return f64::NAN;
+        }
+        self.max = self.max.max(x);
+        self.max
+    }
+}
+
+pub type CumMax<T> = Accumulate<T, CumulativeMax, 1>;
+
+/// The lowest value since the first row. The NaNs are skipped, and produce a NaN.
+#[derive(Clone)]
+pub struct CumulativeMin {
+    min: f64,
+}
+
+impl Default for CumulativeMin {
+    fn default() -> Self {
+        Self { min: f64::INFINITY }
+    }
+}
+
+impl Accumulator<1> for CumulativeMin {
+    const NAME: &'static str = "CumMin";
+    const INPUTS: [&'static str; 1] = ["x"];
+
+    fn accumulate(&mut self, [x]: [f64; 1]) -> f64 {
+        if x.is_nan() {
+            return f64::NAN;
+        }
+        self.min = self.min.min(x);
+        self.min
+    }
+}
+
+pub type CumMin<T> = Accumulate<T, CumulativeMin, 1>;
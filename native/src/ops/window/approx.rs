@@ -0,0 +1,471 @@
+use super::super::{
+    at_child,
+    constant::fmt_constant,
+    parser::{to_fraction, to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use std::{borrow::Cow, iter::FromIterator, mem};
+
+/// A deterministic KLL-style quantile sketch. Values enter level 0, a level holding `k` values is
+/// sorted and every other value is promoted to the next level, where each value stands for twice as many.
+/// The memory is `O(k log(n / k))` and the rank error is roughly `log2(n / k) / k` of `n`.
+#[derive(Clone)]
+struct Sketch {
+    k: usize,
+    levels: Vec<Vec<f64>>,
+    flips: Vec<bool>, // alternate the survivors of a compaction between the odd and the even positions
+    n: usize,
+}
+
+impl Sketch {
+    fn new(k: usize) -> Self {
+        assert!(k >= 2);
+        Self {
+            k,
+            levels: vec![],
+            flips: vec![],
+            n: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.levels.clear();
+        self.flips.clear();
+        self.n = 0;
+    }
+
+    fn insert(&mut self, val: f64) {
+        if self.levels.is_empty() {
+            self.levels.push(vec![]);
+            self.flips.push(false);
+        }
+        self.levels[0].push(val);
+        self.n += 1;
+
+        let mut h = 0;
+        while self.levels[h].len() >= self.k {
+            if h + 1 == self.levels.len() {
+                self.levels.push(vec![]);
+                self.flips.push(false);
+            }
+
+            let level = &mut self.levels[h];
+            level.sort_by(f64::total_cmp);
+            // an odd one out stays, so that the total weight is kept
+            let leftover = if level.len() % 2 == 1 {
+                level.pop()
+            } else {
+                None
+            };
+            let offset = self.flips[h] as usize;
+            self.flips[h] = !self.flips[h];
+
+            let promoted: Vec<_> = level.iter().skip(offset).step_by(2).cloned().collect();
+            level.clear();
+            level.extend(leftover);
+            self.levels[h + 1].extend(promoted);
+            h += 1;
+        }
+    }
+
+    // (value, weight) pairs sorted by value
+    fn weighted(&self) -> Vec<(f64, usize)> {
+        let mut items: Vec<_> = self
+            .levels
+            .iter()
+            .enumerate()
+            .flat_map(|(h, level)| level.iter().map(move |&v| (v, 1 << h)))
+            .collect();
+        items.sort_by(|a, b| a.0.total_cmp(&b.0));
+        items
+    }
+
+    /// The approximate number of values less than `val`.
+    fn rank(&self, val: f64) -> usize {
+        self.levels
+            .iter()
+            .enumerate()
+            .map(|(h, level)| level.iter().filter(|&&v| v < val).count() << h)
+            .sum()
+    }
+
+    /// The approximate `q` quantile, the same as `Quantile` picks the `floor((n - 1) * q)`-th smallest value.
+    fn quantile(&self, q: f64) -> f64 {
+        let r = ((self.n - 1) as f64 * q).floor() as usize;
+        let mut seen = 0;
+        for (v, w) in self.weighted() {
+            seen += w;
+            if seen > r {
+                return v;
+            }
+        }
+        f64::NAN
+    }
+}
+
+/// The approximate quantile of all the values since the start, with bounded memory.
+/// `k` trades the memory for the accuracy, see `Sketch`.
+pub struct QuantileApprox<T> {
+    quantile: f64,
+    k: usize,
+    inner: BoxOp<T>,
+
+    sketch: Sketch,
+    i: usize,
+}
+
+impl<T> Clone for QuantileApprox<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.quantile, self.k, self.inner.clone())
+    }
+}
+
+impl<T> QuantileApprox<T> {
+    pub fn new(quantile: f64, k: usize, inner: BoxOp<T>) -> Self {
+        assert!(0. <= quantile && quantile <= 1.);
+        Self {
+            quantile,
+            k,
+            inner,
+            sketch: Sketch::new(k),
+            i: 0,
+        }
+    }
+}
+
+impl<T> Named for QuantileApprox<T> {
+    const NAME: &'static str = "QuantileApprox";
+}
+
+impl<T: TickerBatch> Operator<T> for QuantileApprox<T> {
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.sketch.clear();
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.sketch.clear();
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for &val in vals {
+            if self.i < self.inner.ready_offset() {
+                self.check_warmup(tb, &[val])?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            self.sketch.insert(val);
+            results.push(self.fchecked(self.sketch.quantile(self.quantile))?);
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inner.ready_offset()
+    }
+
+    // the sketch summarizes the whole history
+    fn lookback(&self) -> Option<usize> {
+        None
+    }
+
+    fn state_bytes(&self) -> usize {
+        self.sketch.levels.len().max(1) * self.sketch.k * mem::size_of::<f64>()
+            + self.inner.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "({} {} {} {})",
+            Self::NAME,
+            fmt_constant(self.quantile),
+            self.k,
+            self.inner.to_string()
+        )
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inner.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        vec![1]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inner.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            self.inner.get(i)?
+        } else {
+            throw!()
+        }
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            if i == 0 {
+                return mem::replace(&mut self.inner, op) as BoxOp<T>;
+            }
+            self.inner.insert(i, op)?
+        } else {
+            throw!()
+        }
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<QuantileApprox<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> QuantileApprox<T> {
+        let mut params: Vec<_> = iter.into_iter().collect();
+        if params.len() != 3 {
+            throw!(anyhow!(
+                "{} expect two constants and one series, got {:?}",
+                QuantileApprox::<T>::NAME,
+                params
+            ))
+        }
+        let k1 = params.remove(0);
+        let k2 = params.remove(0);
+        let k3 = params.remove(0);
+        match (k1, k2, k3) {
+            (Parameter::Constant(q), Parameter::Constant(k), Parameter::Operator(s)) => {
+                let q = to_fraction(QuantileApprox::<T>::NAME, "q", q)?;
+                QuantileApprox::new(q, to_usize(QuantileApprox::<T>::NAME, "k", k, 2)?, s)
+            }
+            (a, b, c) => throw!(anyhow!(
+                "{name} expect two constants and a series, got ({name} {} {} {})",
+                a,
+                b,
+                c,
+                name = QuantileApprox::<T>::NAME,
+            )),
+        }
+    }
+}
+
+/// The approximate rank of the current value among all the values since the start, with bounded memory.
+/// `k` trades the memory for the accuracy, see `Sketch`.
+pub struct RankApprox<T> {
+    k: usize,
+    inner: BoxOp<T>,
+
+    sketch: Sketch,
+    i: usize,
+}
+
+impl<T> Clone for RankApprox<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.k, self.inner.clone())
+    }
+}
+
+impl<T> RankApprox<T> {
+    pub fn new(k: usize, inner: BoxOp<T>) -> Self {
+        Self {
+            k,
+            inner,
+            sketch: Sketch::new(k),
+            i: 0,
+        }
+    }
+}
+
+impl<T> Named for RankApprox<T> {
+    const NAME: &'static str = "RankApprox";
+}
+
+impl<T: TickerBatch> Operator<T> for RankApprox<T> {
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.sketch.clear();
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.sketch.clear();
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for &val in vals {
+            if self.i < self.inner.ready_offset() {
+                self.check_warmup(tb, &[val])?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            self.sketch.insert(val);
+            results.push(self.sketch.rank(val) as f64);
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inner.ready_offset()
+    }
+
+    // the sketch summarizes the whole history
+    fn lookback(&self) -> Option<usize> {
+        None
+    }
+
+    fn state_bytes(&self) -> usize {
+        self.sketch.levels.len().max(1) * self.sketch.k * mem::size_of::<f64>()
+            + self.inner.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        format!("({} {} {})", Self::NAME, self.k, self.inner.to_string())
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inner.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        vec![1]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inner.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            self.inner.get(i)?
+        } else {
+            throw!()
+        }
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            if i == 0 {
+                return mem::replace(&mut self.inner, op) as BoxOp<T>;
+            }
+            self.inner.insert(i, op)?
+        } else {
+            throw!()
+        }
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<RankApprox<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> RankApprox<T> {
+        let mut params: Vec<_> = iter.into_iter().collect();
+        if params.len() != 2 {
+            throw!(anyhow!(
+                "{} expect a constant and a series, got {:?}",
+                RankApprox::<T>::NAME,
+                params
+            ))
+        }
+        let k1 = params.remove(0);
+        let k2 = params.remove(0);
+        match (k1, k2) {
+            (Parameter::Constant(k), Parameter::Operator(s)) => {
+                RankApprox::new(to_usize(RankApprox::<T>::NAME, "k", k, 2)?, s)
+            }
+            (a, b) => throw!(anyhow!(
+                "{name} expect a constant and a series, got ({name} {} {})",
+                a,
+                b,
+                name = RankApprox::<T>::NAME,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Sketch;
+
+    #[test]
+    fn sketch_error_is_bounded() {
+        let n = 100_000;
+        // a deterministic shuffle of 0..n
+        let vals: Vec<_> = (0..n).map(|i| ((i * 7919) % n) as f64).collect();
+
+        let mut sketch = Sketch::new(200);
+        for &v in &vals {
+            sketch.insert(v);
+        }
+        assert_eq!(sketch.n, n);
+        assert!(sketch.levels.iter().map(|l| l.len()).sum::<usize>() < 200 * 10);
+
+        for &q in &[0., 0.01, 0.25, 0.5, 0.75, 0.99, 1.] {
+            let exact = ((n - 1) as f64 * q).floor();
+            assert!(
+                (sketch.quantile(q) - exact).abs() < n as f64 * 0.02,
+                "{}",
+                q
+            );
+            let r = sketch.rank(exact) as f64;
+            assert!((r - exact).abs() < n as f64 * 0.02, "{}", q);
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use super::super::{
+    at_child,
+    parser::{to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use std::{borrow::Cow, collections::VecDeque, iter::FromIterator, mem};
+
+/// The correlation between a series and itself `lag` rows before over the window, the same as
+/// `(Corr <n> <expr> (Delay <lag> <expr>))` but with the series evaluated once. 0 if either side is flat.
+/// The pairs with a NaN are skipped, and produce a NaN.
+pub struct AutoCorr<T> {
+    win_size: usize,
+    min_periods: usize,
+    lag: usize,
+    inner: BoxOp<T>,
+
+    lagged: VecDeque<f64>,        // the last `lag` values
+    window: VecDeque<(f64, f64)>, // (value, lagged value)
+    i: usize,
+}
+
+impl<T> Clone for AutoCorr<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.win_size, self.lag, self.inner.clone()).with_min_periods(self.min_periods)
+    }
+}
+
+impl<T> AutoCorr<T> {
+    pub fn new(win_size: usize, lag: usize, inner: BoxOp<T>) -> Self {
+        Self {
+            win_size,
+            min_periods: win_size,
+            lag,
+            inner,
+
+            lagged: VecDeque::with_capacity(lag + 1),
+            window: VecDeque::with_capacity(win_size),
+            i: 0,
+        }
+    }
+
+    pub fn with_min_periods(mut self, min_periods: usize) -> Self {
+        self.min_periods = min_periods;
+        self
+    }
+
+    fn correlation(&self) -> f64 {
+        let n = self.window.len() as f64;
+        let xbar = self.window.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let ybar = self.window.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let (mut nom, mut xx, mut yy) = (0., 0., 0.);
+        for (x, y) in &self.window {
+            nom += (x - xbar) * (y - ybar);
+            xx += (x - xbar).powi(2);
+            yy += (y - ybar).powi(2);
+        }
+
+        let denom = xx.sqrt() * yy.sqrt();
+        if denom == 0. {
+            0.
+        } else {
+            nom / denom
+        }
+    }
+}
+
+impl<T> Named for AutoCorr<T> {
+    const NAME: &'static str = "AutoCorr";
+}
+
+impl<T: TickerBatch> Operator<T> for AutoCorr<T> {
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.lagged.clear();
+        self.window.clear();
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.lagged.clear();
+        self.window.clear();
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for &val in vals {
+            if self.i < self.inner.ready_offset() {
+                self.check_warmup(tb, &[val])?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            self.lagged.push_back(val);
+            if self.lagged.len() <= self.lag {
+                results.push(f64::NAN);
+                continue;
+            }
+            let prev = self.lagged.pop_front().unwrap();
+
+            if val.is_nan() || prev.is_nan() {
+                results.push(f64::NAN);
+                continue;
+            }
+            self.window.push_back((val, prev));
+
+            let val = if self.window.len() >= self.min_periods {
+                self.fchecked(self.correlation())?
+            } else {
+                f64::NAN
+            };
+
+            #[cfg(feature = "validate")]
+            crate::reference::validate(Self::NAME, val, || {
+                let (xs, ys): (Vec<_>, Vec<_>) = self.window.iter().cloned().unzip();
+                crate::reference::correlation(&xs, &ys)
+            })?;
+
+            if self.window.len() == self.win_size {
+                self.window.pop_front();
+            }
+
+            results.push(val);
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inner.ready_offset() + self.lag + self.min_periods - 1
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.inner.lookback()? + self.lag + self.win_size - 1)
+    }
+
+    fn state_bytes(&self) -> usize {
+        self.lag * mem::size_of::<f64>()
+            + self.win_size * mem::size_of::<(f64, f64)>()
+            + self.inner.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "({} {} {} {})",
+            Self::NAME,
+            super::fmt_window(self.win_size, self.min_periods),
+            self.lag,
+            self.inner.to_string()
+        )
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inner.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        vec![1]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inner.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            self.inner.get(i)?
+        } else {
+            throw!()
+        }
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            if i == 0 {
+                return mem::replace(&mut self.inner, op) as BoxOp<T>;
+            }
+            self.inner.insert(i, op)?
+        } else {
+            throw!()
+        }
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<AutoCorr<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> AutoCorr<T> {
+        let name = AutoCorr::<T>::NAME;
+        let mut params: Vec<_> = iter.into_iter().collect();
+        let min_periods = super::split_min_periods(name, &mut params, 3, 1)?;
+        if params.len() != 3 {
+            throw!(anyhow!(
+                "{} expect two constants and a series, got {:?}",
+                name,
+                params
+            ))
+        }
+        let k1 = params.remove(0);
+        let k2 = params.remove(0);
+        let k3 = params.remove(0);
+        let op = match (k1, k2, k3) {
+            (Parameter::Constant(n), Parameter::Constant(lag), Parameter::Operator(s)) => {
+                AutoCorr::new(
+                    to_usize(name, "win_size", n, 1)?,
+                    to_usize(name, "lag", lag, 1)?,
+                    s,
+                )
+            }
+            (a, b, c) => throw!(anyhow!(
+                "{name} expect two constants and a series, got ({name} {} {} {})",
+                a,
+                b,
+                c,
+                name = name,
+            )),
+        };
+
+        match min_periods {
+            Some(m) => op.with_min_periods(m),
+            None => op,
+        }
+    }
+}
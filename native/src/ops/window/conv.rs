@@ -0,0 +1,183 @@
+use super::super::{at_child, constant::fmt_constant, parser::Parameter, BoxOp, Named, Operator};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use itertools::Itertools;
+use std::{borrow::Cow, collections::VecDeque, iter::FromIterator, mem};
+
+/// The sum of the window weighted by a custom kernel, the first weight applies to the current value,
+/// the second one to the previous value, and so on. The window size is the number of weights.
+pub struct Conv<T> {
+    weights: Vec<f64>,
+    inner: BoxOp<T>,
+
+    window: VecDeque<f64>,
+    i: usize,
+}
+
+impl<T> Clone for Conv<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.weights.clone(), self.inner.clone())
+    }
+}
+
+impl<T> Conv<T> {
+    pub fn new(weights: Vec<f64>, inner: BoxOp<T>) -> Self {
+        Self {
+            window: VecDeque::with_capacity(weights.len()),
+            weights,
+            inner,
+            i: 0,
+        }
+    }
+}
+
+impl<T> Named for Conv<T> {
+    const NAME: &'static str = "Conv";
+}
+
+impl<T: TickerBatch> Operator<T> for Conv<T> {
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.window.clear();
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.window.clear();
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for &val in vals {
+            if self.i < self.inner.ready_offset() {
+                self.check_warmup(tb, &[val])?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            self.window.push_front(val);
+
+            let val = if self.window.len() == self.weights.len() {
+                let val = self
+                    .weights
+                    .iter()
+                    .zip(&self.window)
+                    .map(|(w, v)| w * v)
+                    .sum();
+                self.window.pop_back();
+                self.fchecked(val)?
+            } else {
+                f64::NAN
+            };
+            results.push(val);
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inner.ready_offset() + self.weights.len() - 1
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.inner.lookback()? + self.weights.len() - 1)
+    }
+
+    fn state_bytes(&self) -> usize {
+        2 * self.weights.len() * mem::size_of::<f64>() + self.inner.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "({} ({}) {})",
+            Self::NAME,
+            self.weights.iter().map(|&w| fmt_constant(w)).join(" "),
+            self.inner.to_string()
+        )
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inner.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        vec![1]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inner.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            self.inner.get(i)?
+        } else {
+            throw!()
+        }
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            if i == 0 {
+                return mem::replace(&mut self.inner, op) as BoxOp<T>;
+            }
+            self.inner.insert(i, op)?
+        } else {
+            throw!()
+        }
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<Conv<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> Conv<T> {
+        let mut params: Vec<_> = iter.into_iter().collect();
+        if params.len() != 2 {
+            throw!(anyhow!(
+                "{} expect a list of weights and a series, got {:?}",
+                Conv::<T>::NAME,
+                params
+            ))
+        }
+        let k1 = params.remove(0);
+        let k2 = params.remove(0);
+        match (k1, k2) {
+            (Parameter::Vector(weights), Parameter::Operator(s)) if !weights.is_empty() => {
+                Conv::new(weights, s)
+            }
+            (a, b) => throw!(anyhow!(
+                "{name} expect a list of weights and a series, got ({name} {} {})",
+                a,
+                b,
+                name = Conv::<T>::NAME,
+            )),
+        }
+    }
+}
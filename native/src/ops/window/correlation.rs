@@ -1,4 +1,8 @@
-use super::super::{parser::Parameter, BoxOp, Named, Operator};
+use super::super::{
+    at_child,
+    parser::{to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
 use crate::ticker_batch::TickerBatch;
 use anyhow::{anyhow, Error, Result};
 use fehler::{throw, throws};
@@ -6,6 +10,7 @@ use std::{borrow::Cow, cmp::max, collections::VecDeque, iter::FromIterator, mem}
 
 pub struct Correlation<T> {
     win_size: usize,
+    min_periods: usize,
     x: BoxOp<T>,
     y: BoxOp<T>,
 
@@ -18,7 +23,7 @@ pub struct Correlation<T> {
 
 impl<T> Clone for Correlation<T> {
     fn clone(&self) -> Self {
-        Self::new(self.win_size, self.x.clone(), self.y.clone())
+        Self::new(self.win_size, self.x.clone(), self.y.clone()).with_min_periods(self.min_periods)
     }
 }
 
@@ -26,6 +31,7 @@ impl<T> Correlation<T> {
     pub fn new(win_size: usize, x: BoxOp<T>, y: BoxOp<T>) -> Self {
         Self {
             win_size,
+            min_periods: win_size,
             x,
             y,
 
@@ -35,6 +41,11 @@ impl<T> Correlation<T> {
             i: 0,
         }
     }
+
+    pub fn with_min_periods(mut self, min_periods: usize) -> Self {
+        self.min_periods = min_periods;
+        self
+    }
 }
 
 impl<T> Named for Correlation<T> {
@@ -51,22 +62,27 @@ impl<T: TickerBatch> Operator<T> for Correlation<T> {
         self.i = 0;
     }
 
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.window.clear();
+        self.xsum = 0.;
+        self.ysum = 0.;
+    }
+
     #[throws(Error)]
     fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
         let (x, y) = (&mut self.x, &mut self.y);
-        let (xs, ys) = rayon::join(|| x.update(tb), || y.update(tb));
-        let (xs, ys) = (&*xs?, &*ys?);
-        #[cfg(feature = "check")]
-        assert_eq!(tb.len(), xs.len());
-        #[cfg(feature = "check")]
-        assert_eq!(tb.len(), ys.len());
+        let (xs, ys) = rayon::join(|| x.checked_update(tb), || y.checked_update(tb));
+        let (xs, ys) = (
+            &*xs.map_err(at_child(1))?,
+            &*ys.map_err(at_child(1 + self.x.len()))?,
+        );
 
         let mut results = Vec::with_capacity(tb.len());
 
         for (&xval, &yval) in xs.into_iter().zip(ys) {
             if self.i < self.x.ready_offset() || self.i < self.y.ready_offset() {
-                #[cfg(feature = "check")]
-                assert!(xval.is_nan() || yval.is_nan());
+                self.check_warmup(tb, &[xval, yval])?;
                 results.push(f64::NAN);
                 self.i += 1;
                 continue;
@@ -76,8 +92,8 @@ impl<T: TickerBatch> Operator<T> for Correlation<T> {
             self.xsum += xval;
             self.ysum += yval;
 
-            let val = if self.window.len() == self.win_size {
-                let n = self.window.len() as f64; // this should be equal to self.win_size
+            let val = if self.window.len() >= self.min_periods {
+                let n = self.window.len() as f64;
                 let xbar = self.xsum / n;
                 let ybar = self.ysum / n;
                 let nom = self
@@ -100,19 +116,27 @@ impl<T: TickerBatch> Operator<T> for Correlation<T> {
 
                 let denom = denomx * denomy;
 
-                let val = if denom == 0. {
+                if denom == 0. {
                     0.
                 } else {
                     self.fchecked(nom / denom)?
-                };
-                let (xval, yval) = self.window.pop_front().unwrap();
-                self.xsum -= xval;
-                self.ysum -= yval;
-                val
+                }
             } else {
                 f64::NAN
             };
 
+            #[cfg(feature = "validate")]
+            crate::reference::validate(Self::NAME, val, || {
+                let (xs, ys): (Vec<_>, Vec<_>) = self.window.iter().cloned().unzip();
+                crate::reference::correlation(&xs, &ys)
+            })?;
+
+            if self.window.len() == self.win_size {
+                let (xval, yval) = self.window.pop_front().unwrap();
+                self.xsum -= xval;
+                self.ysum -= yval;
+            }
+
             results.push(val);
         }
 
@@ -120,14 +144,22 @@ impl<T: TickerBatch> Operator<T> for Correlation<T> {
     }
 
     fn ready_offset(&self) -> usize {
-        max(self.x.ready_offset(), self.y.ready_offset()) + self.win_size - 1
+        max(self.x.ready_offset(), self.y.ready_offset()) + self.min_periods - 1
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(max(self.x.lookback()?, self.y.lookback()?) + self.win_size - 1)
+    }
+
+    fn state_bytes(&self) -> usize {
+        self.win_size * mem::size_of::<(f64, f64)>() + self.x.state_bytes() + self.y.state_bytes()
     }
 
     fn to_string(&self) -> String {
         format!(
             "({} {} {} {})",
             Self::NAME,
-            self.win_size,
+            super::fmt_window(self.win_size, self.min_periods),
             self.x.to_string(),
             self.y.to_string()
         )
@@ -202,6 +234,7 @@ impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<Correlation<T>> {
     #[throws(Error)]
     fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> Correlation<T> {
         let mut params: Vec<_> = iter.into_iter().collect();
+        let min_periods = super::split_min_periods(Correlation::<T>::NAME, &mut params, 3, 1)?;
         if params.len() != 3 {
             throw!(anyhow!(
                 "{} expect a constant and two series, got {:?}",
@@ -212,12 +245,19 @@ impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<Correlation<T>> {
         let k1 = params.remove(0);
         let k2 = params.remove(0).to_operator();
         let k3 = params.remove(0).to_operator();
-        match (k1, k2, k3) {
-            (Parameter::Constant(c), Some(sx), Some(sy)) => Correlation::new(c as usize, sx, sy),
+        let op = match (k1, k2, k3) {
+            (Parameter::Constant(c), Some(sx), Some(sy)) => {
+                Correlation::new(to_usize(Correlation::<T>::NAME, "win_size", c, 1)?, sx, sy)
+            }
             _ => throw!(anyhow!(
                 "{} expect a constant and two series",
                 Correlation::<T>::NAME,
             )),
+        };
+
+        match min_periods {
+            Some(m) => op.with_min_periods(m),
+            None => op,
         }
     }
 }
@@ -0,0 +1,252 @@
+use super::super::{
+    at_child,
+    parser::{to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use std::{borrow::Cow, collections::VecDeque, iter::FromIterator, mem};
+
+// The operators over the number of true values in the window, i.e. the positive ones, e.g. the 1s of `(> :a :b)`.
+// A NaN is not true. `$output` is given the number of true values and the number of values in the window.
+macro_rules! impl_count {
+    ($($(#[$doc:meta])* $op:ident |$count:ident, $n:ident| $output:expr;)+) => {
+        $(
+            $(#[$doc])*
+            pub struct $op<T> {
+                win_size: usize,
+                min_periods: usize,
+                inner: BoxOp<T>,
+
+                window: VecDeque<bool>,
+                count: usize,
+                i: usize,
+            }
+
+            impl<T> Clone for $op<T> {
+                fn clone(&self) -> Self {
+                    Self::new(self.win_size, self.inner.clone()).with_min_periods(self.min_periods)
+                }
+            }
+
+            impl<T> $op<T> {
+                pub fn new(win_size: usize, inner: BoxOp<T>) -> Self {
+                    Self {
+                        win_size,
+                        min_periods: win_size,
+                        inner,
+
+                        window: VecDeque::with_capacity(win_size),
+                        count: 0,
+                        i: 0,
+                    }
+                }
+
+                pub fn with_min_periods(mut self, min_periods: usize) -> Self {
+                    self.min_periods = min_periods;
+                    self
+                }
+            }
+
+            impl<T> Named for $op<T> {
+                const NAME: &'static str = stringify!($op);
+            }
+
+            impl<T: TickerBatch> Operator<T> for $op<T> {
+                fn reset(&mut self) {
+                    self.inner.reset();
+                    self.window.clear();
+                    self.count = 0;
+                    self.i = 0;
+                }
+
+                #[throws(Error)]
+                fn evict(&mut self) {
+                    self.window.clear();
+                    self.count = 0;
+                }
+
+                #[throws(Error)]
+                fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+                    let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+
+                    let mut results = Vec::with_capacity(tb.len());
+
+                    for &val in vals {
+                        if self.i < self.inner.ready_offset() {
+                            self.check_warmup(tb, &[val])?;
+                            results.push(f64::NAN);
+                            self.i += 1;
+                            continue;
+                        }
+
+                        let truth = val > 0.;
+                        self.window.push_back(truth);
+                        self.count += truth as usize;
+
+                        let val = if self.window.len() >= self.min_periods {
+                            let ($count, $n) = (self.count, self.window.len());
+                            $output
+                        } else {
+                            f64::NAN
+                        };
+
+                        if self.window.len() == self.win_size {
+                            self.count -= self.window.pop_front().unwrap() as usize;
+                        }
+
+                        results.push(val);
+                    }
+
+                    results.into()
+                }
+
+                fn ready_offset(&self) -> usize {
+                    self.inner.ready_offset() + self.min_periods - 1
+                }
+
+                fn lookback(&self) -> Option<usize> {
+                    Some(self.inner.lookback()? + self.win_size - 1)
+                }
+
+                fn state_bytes(&self) -> usize {
+                    self.win_size * mem::size_of::<bool>() + self.inner.state_bytes()
+                }
+
+                fn to_string(&self) -> String {
+                    format!(
+                        "({} {} {})",
+                        Self::NAME,
+                        super::fmt_window(self.win_size, self.min_periods),
+                        self.inner.to_string()
+                    )
+                }
+
+                fn depth(&self) -> usize {
+                    1 + self.inner.depth()
+                }
+
+                fn len(&self) -> usize {
+                    self.inner.len() + 1
+                }
+
+                fn child_indices(&self) -> Vec<usize> {
+                    vec![1]
+                }
+
+                fn columns(&self) -> Vec<String> {
+                    self.inner.columns()
+                }
+
+                #[throws(as Option)]
+                fn get(&self, i: usize) -> BoxOp<T> {
+                    if i == 0 {
+                        return self.clone().boxed();
+                    }
+                    let i = i - 1;
+
+                    let ns = self.inner.len();
+
+                    if i < ns {
+                        self.inner.get(i)?
+                    } else {
+                        throw!()
+                    }
+                }
+
+                #[throws(as Option)]
+                fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+                    if i == 0 {
+                        unreachable!("cannot insert root");
+                    }
+                    let i = i - 1;
+
+                    let ns = self.inner.len();
+
+                    if i < ns {
+                        if i == 0 {
+                            return mem::replace(&mut self.inner, op) as BoxOp<T>;
+                        }
+                        self.inner.insert(i, op)?
+                    } else {
+                        throw!()
+                    }
+                }
+            }
+
+            impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<$op<T>> {
+                #[throws(Error)]
+                fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> $op<T> {
+                    let name = $op::<T>::NAME;
+                    let mut params: Vec<_> = iter.into_iter().collect();
+                    let min_periods = super::split_min_periods(name, &mut params, 2, 1)?;
+                    if params.len() != 2 {
+                        throw!(anyhow!("{} expect a constant and a series, got {:?}", name, params))
+                    }
+                    let k1 = params.remove(0);
+                    let k2 = params.remove(0);
+                    let op = match (k1, k2) {
+                        (Parameter::Constant(c), Parameter::Operator(s)) => {
+                            $op::new(to_usize(name, "win_size", c, 1)?, s)
+                        }
+                        (a, b) => throw!(anyhow!(
+                            "{name} expect a constant and a series, got ({name} {} {})",
+                            a,
+                            b,
+                            name = name,
+                        )),
+                    };
+
+                    match min_periods {
+                        Some(m) => op.with_min_periods(m),
+                        None => op,
+                    }
+                }
+            }
+        )+
+    };
+}
+
+impl_count! {
+    /// The number of true values in the window, e.g. `(CountIf 100 (> :price (Delay 1 :price)))` for the upticks.
+    CountIf |count, _n| count as f64;
+
+    /// 1 if any value in the window is true, otherwise 0, e.g. `(Any 50 (> :spread 0.02))`.
+    Any |count, _n| (count > 0) as u8 as f64;
+
+    /// 1 if all the values in the window are true, otherwise 0.
+    All |count, n| (count == n) as u8 as f64;
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ops::from_str;
+    use arrow::{
+        array::{ArrayRef, Float64Array},
+        record_batch::RecordBatch,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn truth() {
+        let opts = Default::default();
+        let xs = vec![1., 0., 2., -1., 0.5, 0., 0., 0.];
+        let tb =
+            RecordBatch::try_from_iter(vec![("a", Arc::new(Float64Array::from(xs)) as ArrayRef)])
+                .unwrap();
+
+        let mut op = from_str::<RecordBatch>("(CountIf 3 2 :a)", &opts).unwrap();
+        let got = op.update(&tb).unwrap();
+        assert!(got[0].is_nan());
+        assert_eq!(&got[1..], &[1., 2., 1., 2., 1., 1., 0.]);
+
+        let mut op = from_str::<RecordBatch>("(Any 3 :a)", &opts).unwrap();
+        let got = op.update(&tb).unwrap();
+        assert_eq!(&got[2..], &[1., 1., 1., 1., 1., 0.]);
+
+        let mut op = from_str::<RecordBatch>("(All 2 1 :a)", &opts).unwrap();
+        let got = op.update(&tb).unwrap();
+        assert_eq!(&*got, &[1., 0., 0., 0., 0., 0., 0., 0.]);
+    }
+}
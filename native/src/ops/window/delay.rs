@@ -1,4 +1,8 @@
-use super::super::{parser::Parameter, BoxOp, Named, Operator};
+use super::super::{
+    at_child,
+    parser::{to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
 use crate::ticker_batch::TickerBatch;
 use anyhow::{anyhow, Error, Result};
 use fehler::{throw, throws};
@@ -40,18 +44,20 @@ impl<T: TickerBatch> Operator<T> for Delay<T> {
         self.i = 0;
     }
 
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.window.clear();
+    }
+
     #[throws(Error)]
     fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
-        let vals = &*self.inner.update(tb)?;
-        #[cfg(feature = "check")]
-        assert_eq!(tb.len(), vals.len());
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
 
         let mut results = Vec::with_capacity(tb.len());
 
         for &val in vals {
             if self.i < self.inner.ready_offset() {
-                #[cfg(feature = "check")]
-                assert!(val.is_nan());
+                self.check_warmup(tb, &[val])?;
                 results.push(f64::NAN);
                 self.i += 1;
                 continue;
@@ -75,6 +81,14 @@ impl<T: TickerBatch> Operator<T> for Delay<T> {
         self.inner.ready_offset() + self.win_size
     }
 
+    fn lookback(&self) -> Option<usize> {
+        Some(self.inner.lookback()? + self.win_size)
+    }
+
+    fn state_bytes(&self) -> usize {
+        self.win_size * mem::size_of::<f64>() + self.inner.state_bytes()
+    }
+
     fn to_string(&self) -> String {
         format!(
             "({} {} {})",
@@ -150,7 +164,9 @@ impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<Delay<T>> {
         let k1 = params.remove(0);
         let k2 = params.remove(0);
         match (k1, k2) {
-            (Parameter::Constant(c), Parameter::Operator(s)) => Delay::new(c as usize, s),
+            (Parameter::Constant(c), Parameter::Operator(s)) => {
+                Delay::new(to_usize(Delay::<T>::NAME, "win_size", c, 0)?, s)
+            }
             (a, b) => throw!(anyhow!(
                 "{name} expect a constant and a series, got ({name} {} {})",
                 a,
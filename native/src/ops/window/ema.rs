@@ -0,0 +1,297 @@
+use super::super::{
+    at_child,
+    constant::fmt_constant,
+    parser::{to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use std::{borrow::Cow, iter::FromIterator, mem};
+
+/// How fast the weights of an exponentially weighted function decay, the same as `span`, `halflife`
+/// and `alpha` of `pd.DataFrame.ewm`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Decay {
+    /// `alpha = 2 / (span + 1)`, written as a bare window size, e.g. `(EMA 10 :close)`
+    Span(usize),
+    /// `alpha = 1 - exp(-ln(2) / halflife)`, e.g. `(EMA halflife 5 :close)`
+    HalfLife(f64),
+    /// e.g. `(EMA alpha 0.1 :close)`
+    Alpha(f64),
+}
+
+impl Decay {
+    /// The weight of the current value.
+    pub fn alpha(&self) -> f64 {
+        match *self {
+            Decay::Span(span) => 2. / (span as f64 + 1.),
+            Decay::HalfLife(halflife) => 1. - (-(2f64.ln()) / halflife).exp(),
+            Decay::Alpha(alpha) => alpha,
+        }
+    }
+}
+
+impl std::fmt::Display for Decay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Decay::Span(span) => write!(f, "{}", span),
+            Decay::HalfLife(halflife) => write!(f, "halflife {}", fmt_constant(halflife)),
+            Decay::Alpha(alpha) => write!(f, "alpha {}", fmt_constant(alpha)),
+        }
+    }
+}
+
+/// Take the decay out of the leading `params`: a span, or `halflife` or `alpha` followed by a constant.
+#[throws(Error)]
+pub(super) fn split_decay<T: TickerBatch>(name: &str, params: &mut Vec<Parameter<T>>) -> Decay {
+    if params.is_empty() {
+        throw!(anyhow!("{} expect a span, a halflife or an alpha", name))
+    }
+
+    let kind = match &params[0] {
+        Parameter::Symbol(kind) => {
+            let kind = kind.clone();
+            params.remove(0);
+            kind
+        }
+        _ => "span".to_string(),
+    };
+    let c = match params.first() {
+        Some(Parameter::Constant(c)) => *c,
+        _ => throw!(anyhow!("<{}> for {} should be a constant", kind, name)),
+    };
+    params.remove(0);
+
+    match kind.as_str() {
+        "span" => Decay::Span(to_usize(name, "span", c, 1)?),
+        "halflife" if c > 0. && c.is_finite() => Decay::HalfLife(c),
+        "alpha" if c > 0. && c <= 1. => Decay::Alpha(c),
+        "halflife" => throw!(anyhow!(
+            "<halflife> for {} should be positive, got {}",
+            name,
+            c
+        )),
+        "alpha" => throw!(anyhow!(
+            "<alpha> for {} should be within (0, 1], got {}",
+            name,
+            c
+        )),
+        _ => throw!(anyhow!(
+            "{} expect a span, halflife or alpha, got {}",
+            name,
+            kind
+        )),
+    }
+}
+
+/// The exponential moving average, updated as `ema = alpha * x + (1 - alpha) * ema` from the first value,
+/// i.e. `pd.Series.ewm(adjust=False).mean()`. The NaNs are skipped, the previous average is repeated for them.
+pub struct EMA<T> {
+    decay: Decay,
+    inner: BoxOp<T>,
+
+    alpha: f64,
+    ema: f64,
+    i: usize,
+}
+
+impl<T> Clone for EMA<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.decay, self.inner.clone())
+    }
+}
+
+impl<T> EMA<T> {
+    pub fn new(decay: Decay, inner: BoxOp<T>) -> Self {
+        Self {
+            decay,
+            inner,
+
+            alpha: decay.alpha(),
+            ema: f64::NAN,
+            i: 0,
+        }
+    }
+}
+
+impl<T> Named for EMA<T> {
+    const NAME: &'static str = "EMA";
+}
+
+impl<T: TickerBatch> Operator<T> for EMA<T> {
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.ema = f64::NAN;
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.ema = f64::NAN;
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for &val in vals {
+            if self.i < self.inner.ready_offset() {
+                self.check_warmup(tb, &[val])?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            if !val.is_nan() {
+                self.ema = if self.ema.is_nan() {
+                    val
+                } else {
+                    self.ema + self.alpha * (val - self.ema)
+                };
+            }
+            results.push(self.ema);
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inner.ready_offset()
+    }
+
+    // Every value ever seen keeps a (vanishing) weight
+    fn lookback(&self) -> Option<usize> {
+        None
+    }
+
+    fn state_bytes(&self) -> usize {
+        mem::size_of::<f64>() + self.inner.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        format!("({} {} {})", Self::NAME, self.decay, self.inner.to_string())
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inner.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        vec![1]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inner.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            self.inner.get(i)?
+        } else {
+            throw!()
+        }
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            if i == 0 {
+                return mem::replace(&mut self.inner, op) as BoxOp<T>;
+            }
+            self.inner.insert(i, op)?
+        } else {
+            throw!()
+        }
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<EMA<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> EMA<T> {
+        let mut params: Vec<_> = iter.into_iter().collect();
+        let decay = split_decay(EMA::<T>::NAME, &mut params)?;
+        if params.len() != 1 {
+            throw!(anyhow!(
+                "{} expect a decay and a series, got {:?}",
+                EMA::<T>::NAME,
+                params
+            ))
+        }
+
+        match params.remove(0) {
+            Parameter::Operator(sub) => EMA::new(decay, sub),
+            p => throw!(anyhow!(
+                "{name} expect a decay and a series, got ({name} {} {})",
+                decay,
+                p,
+                name = EMA::<T>::NAME,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ops::{from_str, Operator};
+    use arrow::{
+        array::{ArrayRef, Float64Array},
+        record_batch::RecordBatch,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn decays() {
+        let opts = Default::default();
+        let xs = vec![1., 1., 3., 2., 4.];
+        let tb =
+            RecordBatch::try_from_iter(vec![("x", Arc::new(Float64Array::from(xs)) as ArrayRef)])
+                .unwrap();
+
+        for (repr, expected) in [
+            ("(EMA 3 :x)", vec![1., 1., 2., 2., 3.]),
+            ("(EMA alpha 0.5 :x)", vec![1., 1., 2., 2., 3.]),
+            ("(EMA halflife 1 :x)", vec![1., 1., 2., 2., 3.]),
+            ("(EMA alpha 1 :x)", vec![1., 1., 3., 2., 4.]),
+        ] {
+            let mut op = from_str::<RecordBatch>(repr, &opts).unwrap();
+            assert_eq!(op.to_string(), repr);
+            let got = op.update(&tb).unwrap();
+            for (g, e) in got.iter().zip(&expected) {
+                assert!((g - e).abs() < 1e-12, "{}: {:?}", repr, got);
+            }
+        }
+
+        for repr in [
+            "(EMA 0 :x)",
+            "(EMA 2.5 :x)",
+            "(EMA alpha 1.5 :x)",
+            "(EMA halflife -1 :x)",
+            "(EMA decay 3 :x)",
+            "(EMA 3)",
+        ] {
+            assert!(from_str::<RecordBatch>(repr, &opts).is_err(), "{}", repr);
+        }
+    }
+}
@@ -0,0 +1,437 @@
+use super::{
+    super::{at_child, parser::Parameter, BoxOp, Named, Operator},
+    ema::{split_decay, Decay},
+};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use std::{borrow::Cow, cmp::max, iter::FromIterator, mem};
+
+/// The exponentially weighted covariance of `pd.DataFrame.ewm(adjust=False).cov()`, bias corrected,
+/// updated one pair at a time. The pairs with a NaN are skipped.
+#[derive(Clone, Debug)]
+pub(super) struct EwmCov {
+    alpha: f64,
+    mean_x: f64,
+    mean_y: f64,
+    cov: f64,
+    sum_wt: f64,
+    sum_wt2: f64,
+}
+
+impl EwmCov {
+    pub(super) fn new(alpha: f64) -> Self {
+        Self {
+            alpha,
+            mean_x: f64::NAN,
+            mean_y: f64::NAN,
+            cov: 0.,
+            sum_wt: 1.,
+            sum_wt2: 1.,
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        *self = Self::new(self.alpha);
+    }
+
+    pub(super) fn push(&mut self, x: f64, y: f64) {
+        if x.is_nan() || y.is_nan() {
+            return;
+        }
+        if self.mean_x.is_nan() {
+            self.mean_x = x;
+            self.mean_y = y;
+            return;
+        }
+
+        // The weights of the past observations decay by 1 - alpha, the new one weighs alpha
+        let (old_wt, new_wt) = (1. - self.alpha, self.alpha);
+        let (old_mean_x, old_mean_y) = (self.mean_x, self.mean_y);
+        // Compared first to keep the constant series exactly constant
+        if self.mean_x != x {
+            self.mean_x = old_wt * old_mean_x + new_wt * x;
+        }
+        if self.mean_y != y {
+            self.mean_y = old_wt * old_mean_y + new_wt * y;
+        }
+        self.cov = old_wt * (self.cov + (old_mean_x - self.mean_x) * (old_mean_y - self.mean_y))
+            + new_wt * (x - self.mean_x) * (y - self.mean_y);
+        self.sum_wt = old_wt * self.sum_wt + new_wt;
+        self.sum_wt2 = old_wt * old_wt * self.sum_wt2 + new_wt * new_wt;
+    }
+
+    /// NaN until two observations are seen.
+    pub(super) fn cov(&self) -> f64 {
+        let numerator = self.sum_wt * self.sum_wt;
+        let denominator = numerator - self.sum_wt2;
+        if self.mean_x.is_nan() || denominator <= 0. {
+            f64::NAN
+        } else {
+            numerator / denominator * self.cov
+        }
+    }
+}
+
+/// The exponentially weighted standard deviation, the same as `pd.Series.ewm(adjust=False).std()`.
+pub struct EWMStd<T> {
+    decay: Decay,
+    inner: BoxOp<T>,
+
+    var: EwmCov,
+    i: usize,
+}
+
+impl<T> Clone for EWMStd<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.decay, self.inner.clone())
+    }
+}
+
+impl<T> EWMStd<T> {
+    pub fn new(decay: Decay, inner: BoxOp<T>) -> Self {
+        Self {
+            decay,
+            inner,
+
+            var: EwmCov::new(decay.alpha()),
+            i: 0,
+        }
+    }
+}
+
+impl<T> Named for EWMStd<T> {
+    const NAME: &'static str = "EWMStd";
+}
+
+impl<T: TickerBatch> Operator<T> for EWMStd<T> {
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.var.clear();
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.var.clear();
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for &val in vals {
+            if self.i < self.inner.ready_offset() {
+                self.check_warmup(tb, &[val])?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            self.var.push(val, val);
+            let var = self.var.cov();
+            results.push(if var < 0. { 0. } else { var.sqrt() });
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inner.ready_offset() + 1
+    }
+
+    // Every value ever seen keeps a (vanishing) weight
+    fn lookback(&self) -> Option<usize> {
+        None
+    }
+
+    fn state_bytes(&self) -> usize {
+        mem::size_of::<EwmCov>() + self.inner.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        format!("({} {} {})", Self::NAME, self.decay, self.inner.to_string())
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inner.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        vec![1]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inner.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            self.inner.get(i)?
+        } else {
+            throw!()
+        }
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            if i == 0 {
+                return mem::replace(&mut self.inner, op) as BoxOp<T>;
+            }
+            self.inner.insert(i, op)?
+        } else {
+            throw!()
+        }
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<EWMStd<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> EWMStd<T> {
+        let mut params: Vec<_> = iter.into_iter().collect();
+        let decay = split_decay(EWMStd::<T>::NAME, &mut params)?;
+        if params.len() != 1 {
+            throw!(anyhow!(
+                "{} expect a decay and a series, got {:?}",
+                EWMStd::<T>::NAME,
+                params
+            ))
+        }
+
+        match params.remove(0) {
+            Parameter::Operator(sub) => EWMStd::new(decay, sub),
+            p => throw!(anyhow!(
+                "{name} expect a decay and a series, got ({name} {} {})",
+                decay,
+                p,
+                name = EWMStd::<T>::NAME,
+            )),
+        }
+    }
+}
+
+macro_rules! impl_ewm_bivariate {
+    ($($op:ident {$($func:tt)+})+) => {
+        $(
+            pub struct $op<T> {
+                decay: Decay,
+                x: BoxOp<T>,
+                y: BoxOp<T>,
+
+                xy: EwmCov,
+                xx: EwmCov,
+                yy: EwmCov,
+                i: usize,
+            }
+
+            impl<T> Clone for $op<T> {
+                fn clone(&self) -> Self {
+                    Self::new(self.decay, self.x.clone(), self.y.clone())
+                }
+            }
+
+            impl<T> $op<T> {
+                pub fn new(decay: Decay, x: BoxOp<T>, y: BoxOp<T>) -> Self {
+                    let alpha = decay.alpha();
+                    Self {
+                        decay,
+                        x,
+                        y,
+
+                        xy: EwmCov::new(alpha),
+                        xx: EwmCov::new(alpha),
+                        yy: EwmCov::new(alpha),
+                        i: 0,
+                    }
+                }
+            }
+
+            impl<T> Named for $op<T> {
+                const NAME: &'static str = stringify!($op);
+            }
+
+            impl<T: TickerBatch> Operator<T> for $op<T> {
+                fn reset(&mut self) {
+                    self.x.reset();
+                    self.y.reset();
+                    self.xy.clear();
+                    self.xx.clear();
+                    self.yy.clear();
+                    self.i = 0;
+                }
+
+                #[throws(Error)]
+                fn evict(&mut self) {
+                    self.xy.clear();
+                    self.xx.clear();
+                    self.yy.clear();
+                }
+
+                #[throws(Error)]
+                fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+                    let (x, y) = (&mut self.x, &mut self.y);
+                    let (xs, ys) = rayon::join(|| x.checked_update(tb), || y.checked_update(tb));
+                    let (xs, ys) = (
+                        &*xs.map_err(at_child(1))?,
+                        &*ys.map_err(at_child(1 + self.x.len()))?,
+                    );
+
+                    let mut results = Vec::with_capacity(tb.len());
+
+                    for (&xval, &yval) in xs.into_iter().zip(ys) {
+                        if self.i < self.x.ready_offset() || self.i < self.y.ready_offset() {
+                            self.check_warmup(tb, &[xval, yval])?;
+                            results.push(f64::NAN);
+                            self.i += 1;
+                            continue;
+                        }
+
+                        // Only the pairs observed on both sides, so that the three share the same weights
+                        if !xval.is_nan() && !yval.is_nan() {
+                            self.xy.push(xval, yval);
+                            self.xx.push(xval, xval);
+                            self.yy.push(yval, yval);
+                        }
+                        let f = $($func)+;
+                        results.push(f(&self.xy, &self.xx, &self.yy));
+                    }
+
+                    results.into()
+                }
+
+                fn ready_offset(&self) -> usize {
+                    max(self.x.ready_offset(), self.y.ready_offset()) + 1
+                }
+
+                // Every value ever seen keeps a (vanishing) weight
+                fn lookback(&self) -> Option<usize> {
+                    None
+                }
+
+                fn state_bytes(&self) -> usize {
+                    3 * mem::size_of::<EwmCov>() + self.x.state_bytes() + self.y.state_bytes()
+                }
+
+                fn to_string(&self) -> String {
+                    format!(
+                        "({} {} {} {})",
+                        Self::NAME,
+                        self.decay,
+                        self.x.to_string(),
+                        self.y.to_string()
+                    )
+                }
+
+                fn depth(&self) -> usize {
+                    1 + max(self.x.depth(), self.y.depth())
+                }
+
+                fn len(&self) -> usize {
+                    self.x.len() + self.y.len() + 1
+                }
+
+                fn child_indices(&self) -> Vec<usize> {
+                    vec![1, self.x.len() + 1]
+                }
+
+                fn columns(&self) -> Vec<String> {
+                    self.x.columns().into_iter().chain(self.y.columns()).collect()
+                }
+
+                #[throws(as Option)]
+                fn get(&self, i: usize) -> BoxOp<T> {
+                    if i == 0 {
+                        return self.clone().boxed();
+                    }
+                    let i = i - 1;
+
+                    let nx = self.x.len();
+                    let ny = self.y.len();
+
+                    if i < nx {
+                        self.x.get(i)?
+                    } else if i >= nx && i < nx + ny {
+                        self.y.get(i - nx)?
+                    } else {
+                        throw!()
+                    }
+                }
+
+                #[throws(as Option)]
+                fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+                    if i == 0 {
+                        unreachable!("cannot insert root");
+                    }
+                    let i = i - 1;
+
+                    let nx = self.x.len();
+                    let ny = self.y.len();
+
+                    if i < nx {
+                        if i == 0 {
+                            return mem::replace(&mut self.x, op) as BoxOp<T>;
+                        }
+                        self.x.insert(i, op)?
+                    } else if i >= nx && i < nx + ny {
+                        if i - nx == 0 {
+                            return mem::replace(&mut self.y, op) as BoxOp<T>;
+                        }
+                        self.y.insert(i - nx, op)?
+                    } else {
+                        throw!()
+                    }
+                }
+            }
+
+            impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<$op<T>> {
+                #[throws(Error)]
+                fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> $op<T> {
+                    let mut params: Vec<_> = iter.into_iter().collect();
+                    let decay = split_decay($op::<T>::NAME, &mut params)?;
+                    if params.len() != 2 {
+                        throw!(anyhow!("{} expect a decay and two series, got {:?}", $op::<T>::NAME, params))
+                    }
+                    let x = params.remove(0).to_operator();
+                    let y = params.remove(0).to_operator();
+                    match (x, y) {
+                        (Some(x), Some(y)) => $op::new(decay, x, y),
+                        _ => throw!(anyhow!("{} expect a decay and two series", $op::<T>::NAME)),
+                    }
+                }
+            }
+        )+
+    };
+}
+
+impl_ewm_bivariate! {
+    EWMCov { |xy: &EwmCov, _: &EwmCov, _: &EwmCov| xy.cov() }
+    // Like `Corr`, 0 if either series is constant
+    EWMCorr {
+        |xy: &EwmCov, xx: &EwmCov, yy: &EwmCov| {
+            let denom = (xx.cov() * yy.cov()).sqrt();
+            if denom == 0. { 0. } else { xy.cov() / denom }
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use super::super::{
+    at_child,
+    constant::fmt_constant,
+    parser::{to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use std::{borrow::Cow, iter::FromIterator, mem};
+
+/// Replace the NaNs, and the `sentinel` if any, by the last value which is not, e.g. for the trade price of the rows
+/// which are quotes. With `max_gap`, at most that many missing values in a row are filled, the rest are NaN.
+/// The NaNs in the data reach this node (see `Operator::allow_nan`), and it only gets ready at its first value.
+pub struct FFill<T> {
+    max_gap: Option<usize>,
+    sentinel: Option<f64>,
+    inner: BoxOp<T>,
+
+    last: f64,
+    gap: usize,           // the number of missing values since the last value
+    ready: Option<usize>, // the row of the first value
+    i: usize,
+}
+
+impl<T> Clone for FFill<T> {
+    fn clone(&self) -> Self {
+        Self {
+            max_gap: self.max_gap,
+            sentinel: self.sentinel,
+            inner: self.inner.clone(),
+
+            last: f64::NAN,
+            gap: 0,
+            ready: None,
+            i: 0,
+        }
+    }
+}
+
+impl<T: TickerBatch> FFill<T> {
+    pub fn new(max_gap: Option<usize>, sentinel: Option<f64>, mut inner: BoxOp<T>) -> Self {
+        inner.allow_nan();
+        Self {
+            max_gap,
+            sentinel,
+            inner,
+
+            last: f64::NAN,
+            gap: 0,
+            ready: None,
+            i: 0,
+        }
+    }
+
+    fn is_missing(&self, val: f64) -> bool {
+        val.is_nan() || Some(val) == self.sentinel
+    }
+}
+
+impl<T> Named for FFill<T> {
+    const NAME: &'static str = "FFill";
+}
+
+impl<T: TickerBatch> Operator<T> for FFill<T> {
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.last = f64::NAN;
+        self.gap = 0;
+        self.ready = None;
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.last = f64::NAN;
+        self.gap = 0;
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for &val in vals {
+            let row = self.i;
+            self.i += 1;
+            if row < self.inner.ready_offset() {
+                self.check_warmup(tb, &[val])?;
+                results.push(f64::NAN);
+                continue;
+            }
+
+            if self.is_missing(val) {
+                self.gap += 1;
+                if self.max_gap.map_or(true, |m| self.gap <= m) {
+                    results.push(self.last);
+                } else {
+                    results.push(f64::NAN);
+                }
+                continue;
+            }
+
+            self.last = self.fchecked(val)?;
+            self.gap = 0;
+            self.ready.get_or_insert(row);
+            results.push(val);
+        }
+
+        results.into()
+    }
+
+    // Not known before the first value shows up, until then all the rows seen are part of the warm-up
+    fn ready_offset(&self) -> usize {
+        self.ready.unwrap_or(self.i).max(self.inner.ready_offset())
+    }
+
+    // The NaNs past `max_gap`
+    fn is_nullable(&self) -> bool {
+        self.max_gap.is_some()
+    }
+
+    // The NaNs of the input are filled
+    fn nullable_input(&self) -> Option<BoxOp<T>> {
+        None
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.inner.lookback()? + self.max_gap?)
+    }
+
+    fn state_bytes(&self) -> usize {
+        mem::size_of::<f64>() + self.inner.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        match (self.max_gap, self.sentinel) {
+            (Some(m), Some(s)) => format!(
+                "({} {} {} {})",
+                Self::NAME,
+                m,
+                fmt_constant(s),
+                self.inner.to_string()
+            ),
+            (Some(m), None) => format!("({} {} {})", Self::NAME, m, self.inner.to_string()),
+            (None, _) => format!("({} {})", Self::NAME, self.inner.to_string()),
+        }
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inner.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        vec![1]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inner.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            self.inner.get(i)?
+        } else {
+            throw!()
+        }
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i >= ns {
+            throw!()
+        }
+        let old = if i == 0 {
+            mem::replace(&mut self.inner, op)
+        } else {
+            self.inner.insert(i, op)?
+        };
+        self.inner.allow_nan();
+        old
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<FFill<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> FFill<T> {
+        let name = FFill::<T>::NAME;
+        let mut params: Vec<_> = iter.into_iter().collect();
+        if params.is_empty() || params.len() > 3 {
+            throw!(anyhow!(
+                "{} expect an optional max gap and sentinel, and a series, got {:?}",
+                name,
+                params
+            ))
+        }
+
+        let max_gap = match params.len() {
+            1 => None,
+            _ => match params.remove(0) {
+                Parameter::Constant(c) => Some(to_usize(name, "max_gap", c, 1)?),
+                p => throw!(anyhow!(
+                    "<max_gap> for {} should be a constant, got {}",
+                    name,
+                    p
+                )),
+            },
+        };
+        let sentinel = match params.len() {
+            1 => None,
+            _ => match params.remove(0) {
+                Parameter::Constant(c) if c.is_finite() => Some(c),
+                p => throw!(anyhow!(
+                    "<sentinel> for {} should be a finite constant, got {}",
+                    name,
+                    p
+                )),
+            },
+        };
+
+        match params.remove(0).to_operator() {
+            Some(s) => FFill::new(max_gap, sentinel, s),
+            None => throw!(anyhow!("<expr> for {} should be a series", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ops::from_str;
+    use arrow::{
+        array::{ArrayRef, Float64Array},
+        record_batch::RecordBatch,
+    };
+    use std::sync::Arc;
+
+    fn batch(a: Vec<f64>) -> RecordBatch {
+        let b = (0..a.len()).map(|i| i as f64).collect::<Vec<_>>();
+        RecordBatch::try_from_iter(vec![
+            ("a", Arc::new(Float64Array::from(a)) as ArrayRef),
+            ("b", Arc::new(Float64Array::from(b)) as ArrayRef),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn gaps() {
+        let opts = Default::default();
+        let nan = f64::NAN;
+        let tb = batch(vec![nan, 1., nan, nan, nan, 2., nan, 3.]);
+
+        for repr in ["(FFill :a)", "(FFill (col-or \"a\" 0))", "(FFill (* 2 :a))"] {
+            let mut op = from_str::<RecordBatch>(repr, &opts).unwrap();
+            let got = op.update(&tb).unwrap();
+            assert!(got[0].is_nan(), "{}", repr);
+            let k = if repr.contains('*') { 2. } else { 1. };
+            let expected: Vec<_> = [1., 1., 1., 1., 2., 2., 3.].iter().map(|v| k * v).collect();
+            assert_eq!(&got[1..], &expected[..], "{}", repr);
+            assert_eq!(op.ready_offset(), 1, "{}", repr);
+        }
+
+        let mut op = from_str::<RecordBatch>("(FFill 2 :a)", &opts).unwrap();
+        let got = op.update(&tb).unwrap();
+        assert!(got[4].is_nan());
+        assert_eq!(&got[1..4], &[1., 1., 1.]);
+        assert_eq!(&got[5..], &[2., 2., 3.]);
+        assert_eq!(op.lookback(), Some(3));
+
+        // The sentinel is filled as a NaN
+        let mut op = from_str::<RecordBatch>("(FFill 2 -1 :a)", &opts).unwrap();
+        assert_eq!(op.to_string(), "(FFill 2 -1 :a)");
+        let got = op.update(&batch(vec![-1., 1., -1., nan, -1., 2.])).unwrap();
+        assert!(got[0].is_nan() && got[4].is_nan());
+        assert_eq!([got[1], got[2], got[3], got[5]], [1., 1., 1., 2.]);
+        assert!(from_str::<RecordBatch>("(FFill 2 :b :a)", &opts).is_err());
+    }
+
+    #[test]
+    fn parents() {
+        let opts = Default::default();
+        let nan = f64::NAN;
+
+        // The rows before the first value are the warm-up of the parents, even across batches
+        let mut op = from_str::<RecordBatch>("(Mean 2 (- (FFill :a) :b))", &opts).unwrap();
+        let got = op.update(&batch(vec![nan, nan])).unwrap();
+        assert!(got.iter().all(|v| v.is_nan()));
+        assert_eq!(op.ready_offset(), 3);
+        let got = op.update(&batch(vec![nan, 4., nan, 6.])).unwrap();
+        assert!(got[0].is_nan() && got[1].is_nan());
+        assert_eq!(&got[2..], &[2.5, 2.5]);
+        assert_eq!(op.ready_offset(), 4);
+
+        // Past the max gap, the NaNs go through the pointwise parents, while the windows cannot take them
+        let mut op = from_str::<RecordBatch>("(- (FFill 1 :a) :b)", &opts).unwrap();
+        let got = op.update(&batch(vec![1., nan, nan, 4.])).unwrap();
+        assert!(got[2].is_nan());
+        assert_eq!([got[0], got[1], got[3]], [1., 0., 1.]);
+        assert!(from_str::<RecordBatch>("(Mean 2 (- (FFill 1 :a) :b))", &opts).is_err());
+    }
+}
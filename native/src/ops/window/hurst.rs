@@ -0,0 +1,273 @@
+use super::super::{
+    at_child,
+    parser::{to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use std::{borrow::Cow, collections::VecDeque, iter::FromIterator, mem};
+
+// The smallest chunk the rescaled range is taken over, the smaller ones are too biased
+const MIN_CHUNK: usize = 8;
+
+/// The Hurst exponent of the increments in the window by the rescaled range analysis: the window is cut into
+/// chunks of `n`, `n / 2`, `n / 4`, ... down to 8 values from the newest one on, and the exponent is the slope of
+/// the log of the mean rescaled range `R / S` of the chunks against the log of the chunk size. Around 0.5 for
+/// a random walk, above for a trending series and below for a mean-reverting one. `<expr>` is the increments,
+/// e.g. the returns, not the prices. NaN if the window is flat. The NaNs are skipped, and produce a NaN.
+pub struct Hurst<T> {
+    win_size: usize,
+    inner: BoxOp<T>,
+
+    window: VecDeque<f64>,
+    i: usize,
+}
+
+impl<T> Clone for Hurst<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.win_size, self.inner.clone())
+    }
+}
+
+impl<T> Hurst<T> {
+    pub fn new(win_size: usize, inner: BoxOp<T>) -> Self {
+        Self {
+            win_size,
+            inner,
+
+            window: VecDeque::with_capacity(win_size),
+            i: 0,
+        }
+    }
+
+    fn hurst(&mut self) -> f64 {
+        let window = self.window.make_contiguous();
+        let n = window.len();
+
+        // (log chunk size, log mean R / S)
+        let mut points = vec![];
+        let mut size = n;
+        while size >= MIN_CHUNK {
+            let (mut rs, mut chunks) = (0., 0);
+            for chunk in window.rchunks_exact(size) {
+                let mean = chunk.iter().sum::<f64>() / size as f64;
+                let (mut y, mut highest, mut lowest, mut ss) = (0f64, 0f64, 0f64, 0.);
+                for &x in chunk {
+                    y += x - mean;
+                    highest = highest.max(y);
+                    lowest = lowest.min(y);
+                    ss += (x - mean).powi(2);
+                }
+                let s = (ss / size as f64).sqrt();
+                if s > 0. {
+                    rs += (highest - lowest) / s;
+                    chunks += 1;
+                }
+            }
+            if chunks > 0 && rs > 0. {
+                points.push(((size as f64).ln(), (rs / chunks as f64).ln()));
+            }
+            size /= 2;
+        }
+
+        if points.len() < 2 {
+            return f64::NAN;
+        }
+        let k = points.len() as f64;
+        let xbar = points.iter().map(|(x, _)| x).sum::<f64>() / k;
+        let ybar = points.iter().map(|(_, y)| y).sum::<f64>() / k;
+        let xy: f64 = points.iter().map(|(x, y)| (x - xbar) * (y - ybar)).sum();
+        let xx: f64 = points.iter().map(|(x, _)| (x - xbar).powi(2)).sum();
+        xy / xx
+    }
+}
+
+impl<T> Named for Hurst<T> {
+    const NAME: &'static str = "Hurst";
+}
+
+impl<T: TickerBatch> Operator<T> for Hurst<T> {
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.window.clear();
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.window.clear();
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for &val in vals {
+            if self.i < self.inner.ready_offset() {
+                self.check_warmup(tb, &[val])?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            if val.is_nan() {
+                results.push(f64::NAN);
+                continue;
+            }
+            if self.window.len() == self.win_size {
+                self.window.pop_front();
+            }
+            self.window.push_back(val);
+
+            let val = if self.window.len() == self.win_size {
+                self.hurst()
+            } else {
+                f64::NAN
+            };
+
+            #[cfg(feature = "validate")]
+            crate::reference::validate(Self::NAME, val, || {
+                crate::reference::hurst(self.window.make_contiguous())
+            })?;
+
+            results.push(val);
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inner.ready_offset() + self.win_size - 1
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.inner.lookback()? + self.win_size - 1)
+    }
+
+    fn state_bytes(&self) -> usize {
+        self.win_size * mem::size_of::<f64>() + self.inner.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "({} {} {})",
+            Self::NAME,
+            self.win_size,
+            self.inner.to_string()
+        )
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inner.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        vec![1]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inner.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            self.inner.get(i)?
+        } else {
+            throw!()
+        }
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            if i == 0 {
+                return mem::replace(&mut self.inner, op) as BoxOp<T>;
+            }
+            self.inner.insert(i, op)?
+        } else {
+            throw!()
+        }
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<Hurst<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> Hurst<T> {
+        let name = Hurst::<T>::NAME;
+        let params: Vec<_> = iter.into_iter().collect();
+        if params.len() != 2 {
+            throw!(anyhow!(
+                "{} expect a constant and a series, got {:?}",
+                name,
+                params
+            ))
+        }
+        let mut params = params.into_iter();
+        match (params.next().unwrap(), params.next().unwrap()) {
+            (Parameter::Constant(c), Parameter::Operator(s)) => {
+                Hurst::new(to_usize(name, "win_size", c, 2 * MIN_CHUNK)?, s)
+            }
+            (a, b) => throw!(anyhow!(
+                "{name} expect a constant and a series, got ({name} {} {})",
+                a,
+                b,
+                name = name,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ops::from_str;
+    use arrow::{
+        array::{ArrayRef, Float64Array},
+        record_batch::RecordBatch,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn regimes() {
+        let opts = Default::default();
+        // Alternating increments revert at once, the persistent ones trend
+        let reverting: Vec<f64> = (0..64).map(|i| if i % 2 == 0 { 1. } else { -1. }).collect();
+        let trending: Vec<f64> = (0..64).map(|i| i as f64).collect();
+        let tb = RecordBatch::try_from_iter(vec![
+            ("r", Arc::new(Float64Array::from(reverting)) as ArrayRef),
+            ("t", Arc::new(Float64Array::from(trending))),
+        ])
+        .unwrap();
+
+        let mut op = from_str::<RecordBatch>("(Hurst 64 :r)", &opts).unwrap();
+        let h = op.update(&tb).unwrap()[63];
+        assert!(h < 0.2, "{}", h);
+
+        let mut op = from_str::<RecordBatch>("(Hurst 64 :t)", &opts).unwrap();
+        let h = op.update(&tb).unwrap()[63];
+        assert!(h > 0.8, "{}", h);
+
+        assert!(from_str::<RecordBatch>("(Hurst 15 :r)", &opts).is_err());
+    }
+}
@@ -0,0 +1,220 @@
+use super::super::{
+    at_child,
+    parser::{to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use std::{borrow::Cow, collections::VecDeque, iter::FromIterator, mem};
+
+/// The excess kurtosis of the window, 0 for a normal distribution, corrected for the bias like `Skew`.
+/// It is 0 if the window is flat.
+pub struct Kurt<T> {
+    win_size: usize,
+    min_periods: usize,
+    inner: BoxOp<T>,
+
+    window: VecDeque<f64>,
+    sum: f64,
+    i: usize,
+}
+
+impl<T> Clone for Kurt<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.win_size, self.inner.clone()).with_min_periods(self.min_periods)
+    }
+}
+
+impl<T> Kurt<T> {
+    pub fn new(win_size: usize, inner: BoxOp<T>) -> Self {
+        Self {
+            win_size,
+            min_periods: win_size,
+            inner,
+
+            window: VecDeque::with_capacity(win_size),
+            sum: 0.,
+            i: 0,
+        }
+    }
+
+    pub fn with_min_periods(mut self, min_periods: usize) -> Self {
+        self.min_periods = min_periods;
+        self
+    }
+}
+
+impl<T> Named for Kurt<T> {
+    const NAME: &'static str = "Kurt";
+}
+
+impl<T: TickerBatch> Operator<T> for Kurt<T> {
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.window.clear();
+        self.sum = 0.;
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.window.clear();
+        self.sum = 0.;
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for &val in vals {
+            if self.i < self.inner.ready_offset() {
+                self.check_warmup(tb, &[val])?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            self.window.push_back(val);
+            self.sum += val;
+            let val = if self.window.len() >= self.min_periods {
+                let n = self.window.len() as f64;
+                let mu = self.sum / n;
+                let m4 = self.window.iter().map(|x| (x - mu).powf(4.0)).sum::<f64>() / n;
+                let m2 = self.window.iter().map(|x| (x - mu).powf(2.0)).sum::<f64>() / n;
+
+                if m2 == 0. {
+                    0.
+                } else {
+                    // The excess kurtosis corrected for the bias, the same as pandas
+                    let g2 = m4 / m2.powf(2.) - 3.;
+                    let result = (n - 1.) / ((n - 2.) * (n - 3.)) * ((n + 1.) * g2 + 6.);
+
+                    self.fchecked(result)?
+                }
+            } else {
+                f64::NAN
+            };
+
+            #[cfg(feature = "validate")]
+            crate::reference::validate(Self::NAME, val, || {
+                crate::reference::kurt(self.window.make_contiguous())
+            })?;
+
+            if self.window.len() == self.win_size {
+                self.sum -= self.window.pop_front().unwrap();
+            }
+
+            results.push(val);
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inner.ready_offset() + self.min_periods - 1
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.inner.lookback()? + self.win_size - 1)
+    }
+
+    fn state_bytes(&self) -> usize {
+        self.win_size * mem::size_of::<f64>() + self.inner.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "({} {} {})",
+            Self::NAME,
+            super::fmt_window(self.win_size, self.min_periods),
+            self.inner.to_string()
+        )
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inner.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        vec![1]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inner.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            self.inner.get(i)?
+        } else {
+            throw!()
+        }
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            if i == 0 {
+                return mem::replace(&mut self.inner, op) as BoxOp<T>;
+            }
+            self.inner.insert(i, op)?
+        } else {
+            throw!()
+        }
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<Kurt<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> Kurt<T> {
+        let mut params: Vec<_> = iter.into_iter().collect();
+        let min_periods = super::split_min_periods(Kurt::<T>::NAME, &mut params, 2, 4)?;
+        if params.len() != 2 {
+            throw!(anyhow!(
+                "{} expect a constant and a series, got {:?}",
+                Kurt::<T>::NAME,
+                params
+            ))
+        }
+        let k1 = params.remove(0);
+        let k2 = params.remove(0);
+        let op = match (k1, k2) {
+            (Parameter::Constant(c), Parameter::Operator(s)) => {
+                Kurt::new(to_usize(Kurt::<T>::NAME, "win_size", c, 4)?, s)
+            }
+            (a, b) => throw!(anyhow!(
+                "{name} expect a constant and a series, got ({name} {} {})",
+                a,
+                b,
+                name = Kurt::<T>::NAME,
+            )),
+        };
+
+        match min_periods {
+            Some(m) => op.with_min_periods(m),
+            None => op,
+        }
+    }
+}
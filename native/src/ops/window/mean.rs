@@ -1,4 +1,8 @@
-use super::super::{parser::Parameter, BoxOp, Named, Operator};
+use super::super::{
+    at_child,
+    parser::{to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
 use crate::ticker_batch::TickerBatch;
 use anyhow::{anyhow, Error, Result};
 use fehler::{throw, throws};
@@ -6,6 +10,7 @@ use std::{borrow::Cow, collections::VecDeque, iter::FromIterator, mem};
 
 pub struct Mean<T> {
     win_size: usize,
+    min_periods: usize,
     inner: BoxOp<T>,
 
     window: VecDeque<f64>,
@@ -15,7 +20,7 @@ pub struct Mean<T> {
 
 impl<T> Clone for Mean<T> {
     fn clone(&self) -> Self {
-        Self::new(self.win_size, self.inner.clone())
+        Self::new(self.win_size, self.inner.clone()).with_min_periods(self.min_periods)
     }
 }
 
@@ -23,6 +28,7 @@ impl<T> Mean<T> {
     pub fn new(win_size: usize, inner: BoxOp<T>) -> Self {
         Self {
             win_size,
+            min_periods: win_size,
             inner,
 
             window: VecDeque::with_capacity(win_size),
@@ -30,6 +36,11 @@ impl<T> Mean<T> {
             i: 0,
         }
     }
+
+    pub fn with_min_periods(mut self, min_periods: usize) -> Self {
+        self.min_periods = min_periods;
+        self
+    }
 }
 
 impl<T> Named for Mean<T> {
@@ -44,18 +55,21 @@ impl<T: TickerBatch> Operator<T> for Mean<T> {
         self.i = 0;
     }
 
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.window.clear();
+        self.sum = 0.;
+    }
+
     #[throws(Error)]
     fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
-        let vals = &*self.inner.update(tb)?;
-        #[cfg(feature = "check")]
-        assert_eq!(tb.len(), vals.len());
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
 
         let mut results = Vec::with_capacity(tb.len());
 
         for &val in vals {
             if self.i < self.inner.ready_offset() {
-                #[cfg(feature = "check")]
-                assert!(val.is_nan());
+                self.check_warmup(tb, &[val])?;
                 results.push(f64::NAN);
                 self.i += 1;
                 continue;
@@ -63,13 +77,20 @@ impl<T: TickerBatch> Operator<T> for Mean<T> {
 
             self.window.push_back(val);
             self.sum += val;
-            let val = if self.window.len() == self.win_size {
-                let val = self.sum / self.win_size as f64;
-                self.sum -= self.window.pop_front().unwrap();
-                val
+            let val = if self.window.len() >= self.min_periods {
+                self.sum / self.window.len() as f64
             } else {
                 f64::NAN
             };
+
+            #[cfg(feature = "validate")]
+            crate::reference::validate(Self::NAME, val, || {
+                crate::reference::mean(self.window.make_contiguous())
+            })?;
+
+            if self.window.len() == self.win_size {
+                self.sum -= self.window.pop_front().unwrap();
+            }
             results.push(val);
         }
 
@@ -77,14 +98,22 @@ impl<T: TickerBatch> Operator<T> for Mean<T> {
     }
 
     fn ready_offset(&self) -> usize {
-        self.inner.ready_offset() + self.win_size - 1
+        self.inner.ready_offset() + self.min_periods - 1
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.inner.lookback()? + self.win_size - 1)
+    }
+
+    fn state_bytes(&self) -> usize {
+        self.win_size * mem::size_of::<f64>() + self.inner.state_bytes()
     }
 
     fn to_string(&self) -> String {
         format!(
             "({} {} {})",
             Self::NAME,
-            self.win_size,
+            super::fmt_window(self.win_size, self.min_periods),
             self.inner.to_string()
         )
     }
@@ -145,6 +174,7 @@ impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<Mean<T>> {
     #[throws(Error)]
     fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> Mean<T> {
         let mut params: Vec<_> = iter.into_iter().collect();
+        let min_periods = super::split_min_periods(Mean::<T>::NAME, &mut params, 2, 1)?;
         if params.len() != 2 {
             throw!(anyhow!(
                 "{} expect a constant and a series, got {:?}",
@@ -154,14 +184,21 @@ impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<Mean<T>> {
         }
         let k1 = params.remove(0);
         let k2 = params.remove(0);
-        match (k1, k2) {
-            (Parameter::Constant(c), Parameter::Operator(sub)) => Mean::new(c as usize, sub),
+        let op = match (k1, k2) {
+            (Parameter::Constant(c), Parameter::Operator(sub)) => {
+                Mean::new(to_usize(Mean::<T>::NAME, "win_size", c, 1)?, sub)
+            }
             (a, b) => throw!(anyhow!(
                 "{name} expect a constant and a series, got ({name} {} {})",
                 a,
                 b,
                 name = Mean::<T>::NAME,
             )),
+        };
+
+        match min_periods {
+            Some(m) => op.with_min_periods(m),
+            None => op,
         }
     }
 }
@@ -0,0 +1,220 @@
+use super::super::{
+    at_child,
+    parser::{to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
+use crate::{
+    float::{Ascending, Float, IntoFloat},
+    ticker_batch::TickerBatch,
+};
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use order_stats_tree::OSTree;
+use std::{borrow::Cow, collections::VecDeque, iter::FromIterator, mem};
+
+/// The median of the window, the mean of the two middle elements if there are an even number of them,
+/// the same as `pd.Series.rolling(...).median()`. `(Quantile <n> 0.5 <expr>)` takes the lower one instead.
+pub struct Median<T> {
+    win_size: usize,
+    min_periods: usize,
+    inner: BoxOp<T>,
+
+    window: VecDeque<f64>,
+    ostree: OSTree<Float<Ascending>>, // sorted window
+    i: usize,
+}
+
+impl<T> Clone for Median<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.win_size, self.inner.clone()).with_min_periods(self.min_periods)
+    }
+}
+
+impl<T> Median<T> {
+    pub fn new(win_size: usize, inner: BoxOp<T>) -> Self {
+        Self {
+            win_size,
+            min_periods: win_size,
+            inner,
+
+            window: VecDeque::with_capacity(win_size),
+            ostree: OSTree::new(),
+            i: 0,
+        }
+    }
+
+    pub fn with_min_periods(mut self, min_periods: usize) -> Self {
+        self.min_periods = min_periods;
+        self
+    }
+
+    // The `r`-th smallest element of the window
+    fn select(&self, r: usize) -> f64 {
+        let (v, _) = self.ostree.select(r).unwrap();
+        v.0
+    }
+}
+
+impl<T> Named for Median<T> {
+    const NAME: &'static str = "Median";
+}
+
+impl<T: TickerBatch> Operator<T> for Median<T> {
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.window.clear();
+        self.ostree.clear();
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.window.clear();
+        self.ostree.clear();
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for &val in vals {
+            if self.i < self.inner.ready_offset() {
+                self.check_warmup(tb, &[val])?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            self.window.push_back(val);
+            self.ostree.increase(val.asc(), 1);
+            let n = self.window.len();
+            let val = if n < self.min_periods {
+                f64::NAN
+            } else if n % 2 == 1 {
+                self.fchecked(self.select(n / 2))?
+            } else {
+                self.fchecked((self.select(n / 2 - 1) + self.select(n / 2)) / 2.)?
+            };
+
+            #[cfg(feature = "validate")]
+            crate::reference::validate(Self::NAME, val, || {
+                crate::reference::median(self.window.make_contiguous())
+            })?;
+
+            if self.window.len() == self.win_size {
+                let to_remove = self.window.pop_front().unwrap().asc();
+                self.ostree.decrease(&to_remove, 1);
+            }
+            results.push(val);
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inner.ready_offset() + self.min_periods - 1
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.inner.lookback()? + self.win_size - 1)
+    }
+
+    fn state_bytes(&self) -> usize {
+        2 * self.win_size * mem::size_of::<f64>() + self.inner.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "({} {} {})",
+            Self::NAME,
+            super::fmt_window(self.win_size, self.min_periods),
+            self.inner.to_string(),
+        )
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inner.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        vec![1]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inner.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            self.inner.get(i)?
+        } else {
+            throw!()
+        }
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            if i == 0 {
+                return mem::replace(&mut self.inner, op) as BoxOp<T>;
+            }
+            self.inner.insert(i, op)?
+        } else {
+            throw!()
+        }
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<Median<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> Median<T> {
+        let mut params: Vec<_> = iter.into_iter().collect();
+        let min_periods = super::split_min_periods(Median::<T>::NAME, &mut params, 2, 1)?;
+        if params.len() != 2 {
+            throw!(anyhow!(
+                "{} expect a constant and a series, got {:?}",
+                Median::<T>::NAME,
+                params
+            ))
+        }
+        let k1 = params.remove(0);
+        let k2 = params.remove(0);
+        let op = match (k1, k2) {
+            (Parameter::Constant(c), Parameter::Operator(s)) => {
+                Median::new(to_usize(Median::<T>::NAME, "win_size", c, 1)?, s)
+            }
+            (a, b) => throw!(anyhow!(
+                "{name} expect a constant and a series, got ({name} {} {})",
+                a,
+                b,
+                name = Median::<T>::NAME,
+            )),
+        };
+
+        match min_periods {
+            Some(m) => op.with_min_periods(m),
+            None => op,
+        }
+    }
+}
@@ -1,24 +1,126 @@
-use super::super::{parser::Parameter, BoxOp, Named, Operator};
+use super::super::{
+    at_child,
+    parser::{to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
 use crate::ticker_batch::TickerBatch;
 use anyhow::{anyhow, Error, Result};
 use fehler::{throw, throws};
 use std::{borrow::Cow, collections::VecDeque, iter::FromIterator, mem};
 
+/// The smallest (or the largest) of the last `win_size` values in O(1) amortized, as a queue of the candidates
+/// ordered by their values. The values are numbered from 1 in the order pushed. Of the equal extrema, the oldest one
+/// is at the front unless `prefer_latest`.
+#[derive(Clone)]
+pub struct MonotonicQueue {
+    win_size: usize,
+    largest: bool,
+    latest: bool,
+
+    window: VecDeque<(usize, f64)>,
+    seq: usize,
+}
+
+impl MonotonicQueue {
+    pub fn min(win_size: usize) -> Self {
+        Self::new(win_size, false)
+    }
+
+    pub fn max(win_size: usize) -> Self {
+        Self::new(win_size, true)
+    }
+
+    fn new(win_size: usize, largest: bool) -> Self {
+        Self {
+            win_size,
+            largest,
+            latest: false,
+
+            window: VecDeque::new(),
+            seq: 0,
+        }
+    }
+
+    pub fn prefer_latest(mut self) -> Self {
+        self.latest = true;
+        self
+    }
+
+    pub fn clear(&mut self) {
+        self.window.clear();
+        self.seq = 0;
+    }
+
+    pub fn push(&mut self, val: f64) {
+        self.seq += 1;
+
+        while let Some((seq_old, _)) = self.window.front() {
+            if self.seq - seq_old >= self.win_size {
+                self.window.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        while let Some(&(_, last_val)) = self.window.back() {
+            let evict = if self.largest {
+                val > last_val
+            } else {
+                val < last_val
+            };
+            if evict || (self.latest && val == last_val) {
+                self.window.pop_back();
+            } else {
+                break;
+            }
+        }
+
+        self.window.push_back((self.seq, val));
+    }
+
+    /// The number and the value of the extremum, None before any value is pushed.
+    pub fn front(&self) -> Option<(usize, f64)> {
+        self.window.front().copied()
+    }
+
+    /// The number and the value of the last value pushed, which is always a candidate.
+    pub fn back(&self) -> Option<(usize, f64)> {
+        self.window.back().copied()
+    }
+
+    /// Drop the candidates numbered `seq` or before, for the windows which are not counted in values,
+    /// e.g. over a duration of time. The queue is then made with a `win_size` of `usize::MAX`.
+    pub fn evict(&mut self, seq: usize) {
+        while matches!(self.window.front(), Some(&(seq_old, _)) if seq_old <= seq) {
+            self.window.pop_front();
+        }
+    }
+
+    /// The number of the last value pushed.
+    pub fn seq(&self) -> usize {
+        self.seq
+    }
+}
+
 macro_rules! impl_minmax {
-    ($($op:ident $cmp:tt {$($vfunc:tt)+})+) => {
+    ($($op:ident $queue:ident $reference:ident {$($vfunc:tt)+})+) => {
         $(
             pub struct $op<T> {
                 win_size: usize,
+                min_periods: usize,
                 inner: BoxOp<T>,
 
-                window: VecDeque<(usize, f64)>,
-                seq: usize,
+                queue: MonotonicQueue,
                 i: usize,
+
+                // the raw window, the monotonic queue above cannot be validated without it
+                #[cfg(feature = "validate")]
+                history: VecDeque<f64>,
             }
 
             impl<T> Clone for $op<T> {
                 fn clone(&self) -> Self {
-                    Self::new(self.win_size, self.inner.clone())
+                    Self::new(self.win_size, self.inner.clone()).with_min_periods(self.min_periods)
                 }
             }
 
@@ -26,13 +128,21 @@ macro_rules! impl_minmax {
                 pub fn new(win_size: usize, inner: BoxOp<T>) -> Self {
                     Self {
                         win_size,
+                        min_periods: win_size,
                         inner,
 
-                        window: VecDeque::new(),
-                        seq: 0,
+                        queue: MonotonicQueue::$queue(win_size),
                         i: 0,
+
+                        #[cfg(feature = "validate")]
+                        history: VecDeque::with_capacity(win_size),
                     }
                 }
+
+                pub fn with_min_periods(mut self, min_periods: usize) -> Self {
+                    self.min_periods = min_periods;
+                    self
+                }
             }
 
             impl<T> Named for $op<T> {
@@ -42,55 +152,57 @@ macro_rules! impl_minmax {
             impl<T: TickerBatch> Operator<T> for $op<T> {
                 fn reset(&mut self) {
                     self.inner.reset();
-                    self.window.clear();
-                    self.seq = 0;
+                    self.queue.clear();
                     self.i = 0;
+                    #[cfg(feature = "validate")]
+                    self.history.clear();
+                }
+
+                #[throws(Error)]
+                fn evict(&mut self) {
+                    self.queue.clear();
+                    // warm up again, unless the input itself is still warming up
+                    self.i = self.i.min(self.inner.ready_offset());
+                    #[cfg(feature = "validate")]
+                    self.history.clear();
                 }
 
                 #[throws(Error)]
                 fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
-                    let vals = &*self.inner.update(tb)?;
-                    #[cfg(feature = "check")]
-                    assert_eq!(tb.len(), vals.len());
+                    let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
 
                     let mut results = Vec::with_capacity(tb.len());
 
                     for &val in vals {
                         if self.i < self.inner.ready_offset() {
-                            #[cfg(feature = "check")]
-                            assert!(val.is_nan());
+                            self.check_warmup(tb, &[val])?;
                             results.push(f64::NAN);
                             self.i += 1;
                             continue;
                         }
 
-                        self.seq += 1;
-
-                        while let Some((seq_old, _)) = self.window.front() {
-                            if seq_old + self.win_size <= self.seq {
-                                self.window.pop_front();
-                            } else {
-                                break;
-                            }
-                        }
-
-                        while let Some((_, last_val)) = self.window.back() {
-                            if val $cmp *last_val {
-                                self.window.pop_back();
-                            } else {
-                                break;
-                            }
-                        }
-
-                        self.window.push_back((self.seq, val));
+                        self.queue.push(val);
 
                         let val = if self.i >= self.ready_offset() {
-                            let val = ($($vfunc)+) (&self.window, self.seq, self.win_size);
+                            // the window is not full yet if there are less than win_size elements seen
+                            let val = ($($vfunc)+) (&self.queue, self.win_size.min(self.queue.seq()));
                             val
                         } else {
                             self.i += 1;
                             f64::NAN
                         };
+
+                        #[cfg(feature = "validate")]
+                        {
+                            self.history.push_back(vals[results.len()]);
+                            if self.history.len() > self.win_size {
+                                self.history.pop_front();
+                            }
+                            crate::reference::validate(Self::NAME, val, || {
+                                crate::reference::$reference(self.history.make_contiguous())
+                            })?;
+                        }
+
                         results.push(val);
                     }
 
@@ -98,11 +210,19 @@ macro_rules! impl_minmax {
                 }
 
                 fn ready_offset(&self) -> usize {
-                    self.inner.ready_offset() + self.win_size - 1
+                    self.inner.ready_offset() + self.min_periods - 1
+                }
+
+                fn lookback(&self) -> Option<usize> {
+                    Some(self.inner.lookback()? + self.win_size - 1)
+                }
+
+                fn state_bytes(&self) -> usize {
+                    self.win_size * mem::size_of::<(usize, f64)>() + self.inner.state_bytes()
                 }
 
                 fn to_string(&self) -> String {
-                    format!("({} {} {})", Self::NAME, self.win_size, self.inner.to_string())
+                    format!("({} {} {})", Self::NAME, super::fmt_window(self.win_size, self.min_periods), self.inner.to_string())
                 }
 
                 fn depth(&self) -> usize {
@@ -161,14 +281,20 @@ macro_rules! impl_minmax {
                 #[throws(Error)]
                 fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> $op<T> {
                     let mut params: Vec<_> = iter.into_iter().collect();
+                    let min_periods = super::split_min_periods($op::<T>::NAME, &mut params, 2, 1)?;
                     if params.len() != 2 {
                         throw!(anyhow!("{} expect a constant and a series, got {:?}", $op::<T>::NAME, params))
                     }
                     let k1 = params.remove(0);
                     let k2 = params.remove(0);
-                    match (k1, k2) {
-                        (Parameter::Constant(c), Parameter::Operator(sub)) => $op::new(c as usize, sub),
+                    let op = match (k1, k2) {
+                        (Parameter::Constant(c), Parameter::Operator(sub)) => $op::new(to_usize($op::<T>::NAME, "win_size", c, 1)?, sub),
                         (a, b) => throw!(anyhow!("{name} expect a constant and a series, got ({name} {} {})", a, b, name = $op::<T>::NAME)),
+                    };
+
+                    match min_periods {
+                        Some(m) => op.with_min_periods(m),
+                        None => op,
                     }
                 }
             }
@@ -177,8 +303,18 @@ macro_rules! impl_minmax {
 }
 
 impl_minmax! {
-    Min < { |window: &VecDeque<(usize, f64)>, _: usize, _: usize| window.front().unwrap().1 }
-    Max > { |window: &VecDeque<(usize, f64)>, _: usize, _: usize| window.front().unwrap().1 }
-    ArgMin < { |window: &VecDeque<(usize, f64)>, seq: usize, win_size: usize| (window.front().unwrap().0 + win_size - seq - 1) as f64 }
-    ArgMax > { |window: &VecDeque<(usize, f64)>, seq: usize, win_size: usize| (window.front().unwrap().0 + win_size - seq - 1) as f64 }
+    Min min min { |queue: &MonotonicQueue, _: usize| queue.front().unwrap().1 }
+    Max max max { |queue: &MonotonicQueue, _: usize| queue.front().unwrap().1 }
+    ArgMin min argmin { |queue: &MonotonicQueue, win_size: usize| (queue.front().unwrap().0 + win_size - queue.seq() - 1) as f64 }
+    ArgMax max argmax { |queue: &MonotonicQueue, win_size: usize| (queue.front().unwrap().0 + win_size - queue.seq() - 1) as f64 }
+    Drawdown max drawdown { |queue: &MonotonicQueue, _: usize| drawdown(queue.front().unwrap().1, queue.back().unwrap().1) }
+}
+
+/// The fall from the highest value `(highest - x) / highest`, 0 if the highest value is 0.
+fn drawdown(highest: f64, x: f64) -> f64 {
+    if highest == 0. {
+        0.
+    } else {
+        (highest - x) / highest
+    }
 }
@@ -1,21 +1,146 @@
+mod accumulate;
+mod approx;
+mod autocorr;
+mod conv;
 mod correlation;
+mod count;
 mod delay;
+mod ema;
+mod ewm;
+mod ffill;
+mod first;
+mod hurst;
+mod kurt;
 mod mean;
+mod median;
 mod minmax;
+mod percentile;
+mod product;
 mod quantile;
 mod rank;
+mod regression;
 mod returns;
+mod rsi;
 mod skew;
 mod stdev;
 mod sum;
+mod theilsen;
+mod timed;
+mod trend;
+mod weighted;
+mod winsorize;
 
+pub use accumulate::{
+    Accumulate, Accumulator, CumMax, CumMean, CumMin, CumSum, CumulativeMax, CumulativeMean,
+    CumulativeMin, CumulativeSum, OnBalanceVolume, OBV,
+};
+pub use approx::{QuantileApprox, RankApprox};
+pub use autocorr::AutoCorr;
+pub use conv::Conv;
 pub use correlation::Correlation;
+pub use count::{All, Any, CountIf};
 pub use delay::Delay;
+pub use ema::{Decay, EMA};
+pub use ewm::{EWMCorr, EWMCov, EWMStd};
+pub use ffill::FFill;
+pub use first::First;
+pub use hurst::Hurst;
+pub use kurt::Kurt;
 pub use mean::Mean;
-pub use minmax::{ArgMax, ArgMin, Max, Min};
+pub use median::Median;
+pub use minmax::{ArgMax, ArgMin, Drawdown, Max, Min, MonotonicQueue};
+pub use percentile::Percentile;
+pub use product::Product;
 pub use quantile::Quantile;
 pub use rank::Rank;
-pub use returns::LogReturn;
+pub use regression::{Alpha, Beta, Resid};
+pub use returns::{LogReturn, Momentum, PctChange, ROC};
+pub use rsi::RSI;
 pub use skew::Skew;
 pub use stdev::Stdev;
 pub use sum::Sum;
+pub use theilsen::TheilSen;
+pub use timed::{CountT, MaxT, MeanT, MinT, StdT, SumT};
+pub use trend::{Slope, TrendR2};
+pub use weighted::{TWAP, VWAP};
+pub use winsorize::Winsorize;
+
+use super::parser::{to_usize, Parameter};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error};
+use fehler::{throw, throws};
+
+/// Take the optional `<min_periods>` constant out of `params`. It sits right after the window size
+/// and is only present if there is one more parameter than the operator's `nparams`.
+#[throws(Error)]
+fn split_min_periods<T: TickerBatch>(
+    name: &str,
+    params: &mut Vec<Parameter<T>>,
+    nparams: usize,
+    min: usize,
+) -> Option<usize> {
+    if params.len() != nparams + 1 {
+        return None;
+    }
+
+    let min_periods = match params.remove(1) {
+        Parameter::Constant(c) => c,
+        p => throw!(anyhow!(
+            "<min_periods> for {} should be a constant, got {}",
+            name,
+            p
+        )),
+    };
+    let min_periods = to_usize(name, "min_periods", min_periods, min)?;
+    if let Parameter::Constant(win_size) = params[0] {
+        if min_periods as f64 > win_size {
+            throw!(anyhow!(
+                "<min_periods> for {} should be no larger than the window size {}, got {}",
+                name,
+                win_size,
+                min_periods
+            ))
+        }
+    }
+
+    Some(min_periods)
+}
+
+// The window size followed by min_periods if it differs from the window size
+fn fmt_window(win_size: usize, min_periods: usize) -> String {
+    if min_periods == win_size {
+        format!("{}", win_size)
+    } else {
+        format!("{} {}", win_size, min_periods)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ops::from_str;
+    use arrow::record_batch::RecordBatch;
+
+    #[test]
+    fn lookback() {
+        let opts = Default::default();
+        for (sexpr, lookback, state_bytes) in [
+            (":a", Some(1), 0),
+            ("(+ :a 1)", Some(1), 0),
+            ("(Mean 10 :a)", Some(10), 80),
+            ("(Mean 10 5 :a)", Some(10), 80),
+            ("(+ (Std 20 (Delay 3 :a)) (Sum 5 :b))", Some(23), 224),
+            ("(Corr 10 (Mean 5 :a) :b)", Some(14), 200),
+            ("(Rank 4 (TimeDelta :t))", Some(5), 72),
+            ("(QuantileApprox 0.5 8 :a)", None, 64),
+        ] {
+            let op = from_str::<RecordBatch>(sexpr, &opts).unwrap();
+            assert_eq!(op.lookback(), lookback, "{}", sexpr);
+            assert!(
+                lookback.map_or(true, |l| l > op.ready_offset()),
+                "{}",
+                sexpr
+            );
+            assert_eq!(op.state_bytes(), state_bytes, "{}", sexpr);
+        }
+    }
+}
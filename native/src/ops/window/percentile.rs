@@ -0,0 +1,212 @@
+use super::super::{
+    at_child,
+    parser::{to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
+use crate::{
+    float::{Ascending, Float, IntoFloat},
+    ticker_batch::TickerBatch,
+};
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use order_stats_tree::OSTree;
+use std::{borrow::Cow, collections::VecDeque, iter::FromIterator, mem};
+
+/// The rank of the current value in the window scaled into [0, 1], `rank / (n - 1)` for the `n` values in the window,
+/// i.e. 0 for the lowest and 1 for the highest whatever the window size. The equal values share the lowest rank.
+pub struct Percentile<T> {
+    win_size: usize,
+    min_periods: usize,
+    inner: BoxOp<T>,
+
+    window: VecDeque<f64>,
+    ostree: OSTree<Float<Ascending>>, // sorted window
+    i: usize,
+}
+
+impl<T> Clone for Percentile<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.win_size, self.inner.clone()).with_min_periods(self.min_periods)
+    }
+}
+
+impl<T> Percentile<T> {
+    pub fn new(win_size: usize, inner: BoxOp<T>) -> Self {
+        Self {
+            win_size,
+            min_periods: win_size,
+            inner,
+
+            window: VecDeque::with_capacity(win_size),
+            ostree: OSTree::new(),
+            i: 0,
+        }
+    }
+
+    pub fn with_min_periods(mut self, min_periods: usize) -> Self {
+        self.min_periods = min_periods;
+        self
+    }
+}
+
+impl<T> Named for Percentile<T> {
+    const NAME: &'static str = "Percentile";
+}
+
+impl<T: TickerBatch> Operator<T> for Percentile<T> {
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.window.clear();
+        self.ostree.clear();
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.window.clear();
+        self.ostree.clear();
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for &val in vals {
+            if self.i < self.inner.ready_offset() {
+                self.check_warmup(tb, &[val])?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            self.window.push_back(val);
+            self.ostree.increase(val.asc(), 1);
+            let val = if self.window.len() >= self.min_periods {
+                let idx = self.ostree.rank(&val.asc()).unwrap();
+                self.fchecked(idx as f64 / (self.window.len() - 1) as f64)?
+            } else {
+                f64::NAN
+            };
+
+            #[cfg(feature = "validate")]
+            crate::reference::validate(Self::NAME, val, || {
+                crate::reference::percentile(self.window.make_contiguous())
+            })?;
+
+            if self.window.len() == self.win_size {
+                let to_remove = self.window.pop_front().unwrap().asc();
+                self.ostree.decrease(&to_remove, 1);
+            }
+            results.push(val);
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inner.ready_offset() + self.min_periods - 1
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.inner.lookback()? + self.win_size - 1)
+    }
+
+    fn state_bytes(&self) -> usize {
+        2 * self.win_size * mem::size_of::<f64>() + self.inner.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "({} {} {})",
+            Self::NAME,
+            super::fmt_window(self.win_size, self.min_periods),
+            self.inner.to_string(),
+        )
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inner.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        vec![1]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inner.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            self.inner.get(i)?
+        } else {
+            throw!()
+        }
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            if i == 0 {
+                return mem::replace(&mut self.inner, op) as BoxOp<T>;
+            }
+            self.inner.insert(i, op)?
+        } else {
+            throw!()
+        }
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<Percentile<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> Percentile<T> {
+        let mut params: Vec<_> = iter.into_iter().collect();
+        let min_periods = super::split_min_periods(Percentile::<T>::NAME, &mut params, 2, 2)?;
+        if params.len() != 2 {
+            throw!(anyhow!(
+                "{} expect a constant and one series, got {:?}",
+                Percentile::<T>::NAME,
+                params
+            ))
+        }
+        let k1 = params.remove(0);
+        let k2 = params.remove(0);
+        let op = match (k1, k2) {
+            (Parameter::Constant(c), Parameter::Operator(s)) => {
+                Percentile::new(to_usize(Percentile::<T>::NAME, "win_size", c, 2)?, s)
+            }
+            (a, b) => throw!(anyhow!(
+                "{name} expect a constant and a series, got ({name} {} {})",
+                a,
+                b,
+                name = Percentile::<T>::NAME,
+            )),
+        };
+
+        match min_periods {
+            Some(m) => op.with_min_periods(m),
+            None => op,
+        }
+    }
+}
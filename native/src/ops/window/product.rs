@@ -0,0 +1,266 @@
+use super::super::{
+    at_child,
+    parser::{to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use std::{borrow::Cow, collections::VecDeque, iter::FromIterator, mem};
+
+/// The product of the window, kept as the sum of the logarithms of the absolute values along with the number of
+/// the negative values and the zeros, so that neither the product nor the division by the value leaving the window
+/// overflows. The sum is computed over again every `win_size` values so that the rounding errors do not pile up.
+pub struct Product<T> {
+    win_size: usize,
+    min_periods: usize,
+    inner: BoxOp<T>,
+
+    window: VecDeque<f64>,
+    logsum: f64, // of the nonzero values
+    negatives: usize,
+    zeros: usize,
+    pushed: usize,
+    i: usize,
+}
+
+impl<T> Clone for Product<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.win_size, self.inner.clone()).with_min_periods(self.min_periods)
+    }
+}
+
+impl<T> Product<T> {
+    pub fn new(win_size: usize, inner: BoxOp<T>) -> Self {
+        Self {
+            win_size,
+            min_periods: win_size,
+            inner,
+
+            window: VecDeque::with_capacity(win_size),
+            logsum: 0.,
+            negatives: 0,
+            zeros: 0,
+            pushed: 0,
+            i: 0,
+        }
+    }
+
+    pub fn with_min_periods(mut self, min_periods: usize) -> Self {
+        self.min_periods = min_periods;
+        self
+    }
+
+    fn clear(&mut self) {
+        self.window.clear();
+        self.logsum = 0.;
+        self.negatives = 0;
+        self.zeros = 0;
+        self.pushed = 0;
+    }
+
+    fn add(&mut self, val: f64) {
+        if val == 0. {
+            self.zeros += 1;
+            return;
+        }
+        if val < 0. {
+            self.negatives += 1;
+        }
+        self.logsum += val.abs().ln();
+    }
+
+    fn remove(&mut self, val: f64) {
+        if val == 0. {
+            self.zeros -= 1;
+            return;
+        }
+        if val < 0. {
+            self.negatives -= 1;
+        }
+        self.logsum -= val.abs().ln();
+    }
+
+    fn resum(&mut self) {
+        let nonzeros = self.window.iter().filter(|&&v| v != 0.);
+        self.logsum = nonzeros.clone().map(|v| v.abs().ln()).sum();
+        self.negatives = nonzeros.filter(|&&v| v < 0.).count();
+        self.zeros = self.window.iter().filter(|&&v| v == 0.).count();
+    }
+
+    fn product(&self) -> f64 {
+        if self.zeros > 0 {
+            0.
+        } else if self.negatives % 2 == 1 {
+            -self.logsum.exp()
+        } else {
+            self.logsum.exp()
+        }
+    }
+}
+
+impl<T> Named for Product<T> {
+    const NAME: &'static str = "Product";
+}
+
+impl<T: TickerBatch> Operator<T> for Product<T> {
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.clear();
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.clear();
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for &val in vals {
+            if self.i < self.inner.ready_offset() {
+                self.check_warmup(tb, &[val])?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            self.window.push_back(val);
+            self.pushed += 1;
+            if self.pushed % self.win_size == 0 {
+                self.resum();
+            } else {
+                self.add(val);
+            }
+
+            let val = if self.window.len() >= self.min_periods {
+                self.fchecked(self.product())?
+            } else {
+                f64::NAN
+            };
+
+            #[cfg(feature = "validate")]
+            crate::reference::validate(Self::NAME, val, || {
+                crate::reference::product(self.window.make_contiguous())
+            })?;
+
+            if self.window.len() == self.win_size {
+                let val = self.window.pop_front().unwrap();
+                self.remove(val);
+            }
+
+            results.push(val);
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inner.ready_offset() + self.min_periods - 1
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.inner.lookback()? + self.win_size - 1)
+    }
+
+    fn state_bytes(&self) -> usize {
+        self.win_size * mem::size_of::<f64>() + self.inner.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "({} {} {})",
+            Self::NAME,
+            super::fmt_window(self.win_size, self.min_periods),
+            self.inner.to_string()
+        )
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inner.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        vec![1]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inner.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            self.inner.get(i)?
+        } else {
+            throw!()
+        }
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            if i == 0 {
+                return mem::replace(&mut self.inner, op) as BoxOp<T>;
+            }
+            self.inner.insert(i, op)?
+        } else {
+            throw!()
+        }
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<Product<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> Product<T> {
+        let mut params: Vec<_> = iter.into_iter().collect();
+        let min_periods = super::split_min_periods(Product::<T>::NAME, &mut params, 2, 1)?;
+        if params.len() != 2 {
+            throw!(anyhow!(
+                "{} expect a constant and a series, got {:?}",
+                Product::<T>::NAME,
+                params
+            ))
+        }
+        let k1 = params.remove(0);
+        let k2 = params.remove(0);
+        let op = match (k1, k2) {
+            (Parameter::Constant(c), Parameter::Operator(sub)) => {
+                Product::new(to_usize(Product::<T>::NAME, "win_size", c, 1)?, sub)
+            }
+            (a, b) => throw!(anyhow!(
+                "{name} expect a constant and a series, got ({name} {} {})",
+                a,
+                b,
+                name = Product::<T>::NAME,
+            )),
+        };
+
+        match min_periods {
+            Some(m) => op.with_min_periods(m),
+            None => op,
+        }
+    }
+}
@@ -1,4 +1,9 @@
-use super::super::{parser::Parameter, BoxOp, Named, Operator};
+use super::super::{
+    at_child,
+    constant::fmt_constant,
+    parser::{to_fraction, to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
 use crate::{
     float::{Ascending, Float, IntoFloat},
     ticker_batch::TickerBatch,
@@ -10,6 +15,7 @@ use std::{borrow::Cow, collections::VecDeque, iter::FromIterator, mem};
 
 pub struct Quantile<T> {
     win_size: usize,
+    min_periods: usize,
     quantile: f64,
     r: usize, // win_size * quantile
     inner: BoxOp<T>,
@@ -22,6 +28,7 @@ pub struct Quantile<T> {
 impl<T> Clone for Quantile<T> {
     fn clone(&self) -> Self {
         Self::new(self.win_size, self.quantile, self.inner.clone())
+            .with_min_periods(self.min_periods)
     }
 }
 
@@ -30,6 +37,7 @@ impl<T> Quantile<T> {
         assert!(0. <= quantile && quantile <= 1.);
         Self {
             win_size,
+            min_periods: win_size,
             inner,
             quantile,
             r: ((win_size - 1) as f64 * quantile).floor() as usize,
@@ -39,6 +47,11 @@ impl<T> Quantile<T> {
             i: 0,
         }
     }
+
+    pub fn with_min_periods(mut self, min_periods: usize) -> Self {
+        self.min_periods = min_periods;
+        self
+    }
 }
 
 impl<T> Named for Quantile<T> {
@@ -53,18 +66,21 @@ impl<T: TickerBatch> Operator<T> for Quantile<T> {
         self.i = 0;
     }
 
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.window.clear();
+        self.ostree.clear();
+    }
+
     #[throws(Error)]
     fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
-        let vals = &*self.inner.update(tb)?;
-        #[cfg(feature = "check")]
-        assert_eq!(tb.len(), vals.len());
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
 
         let mut results = Vec::with_capacity(tb.len());
 
         for &val in vals {
             if self.i < self.inner.ready_offset() {
-                #[cfg(feature = "check")]
-                assert!(val.is_nan());
+                self.check_warmup(tb, &[val])?;
                 results.push(f64::NAN);
                 self.i += 1;
                 continue;
@@ -74,15 +90,24 @@ impl<T: TickerBatch> Operator<T> for Quantile<T> {
             self.ostree.increase(val.asc(), 1);
             let val = if self.window.len() == self.win_size {
                 let (v, _) = self.ostree.select(self.r).unwrap();
-                let val = self.fchecked(v.0)?;
-
-                let to_remove = self.window.pop_front().unwrap().asc();
-                self.ostree.decrease(&to_remove, 1);
-
-                val
+                self.fchecked(v.0)?
+            } else if self.window.len() >= self.min_periods {
+                let r = ((self.window.len() - 1) as f64 * self.quantile).floor() as usize;
+                let (v, _) = self.ostree.select(r).unwrap();
+                self.fchecked(v.0)?
             } else {
                 f64::NAN
             };
+
+            #[cfg(feature = "validate")]
+            crate::reference::validate(Self::NAME, val, || {
+                crate::reference::quantile(self.window.make_contiguous(), self.quantile)
+            })?;
+
+            if self.window.len() == self.win_size {
+                let to_remove = self.window.pop_front().unwrap().asc();
+                self.ostree.decrease(&to_remove, 1);
+            }
             results.push(val);
         }
 
@@ -90,15 +115,23 @@ impl<T: TickerBatch> Operator<T> for Quantile<T> {
     }
 
     fn ready_offset(&self) -> usize {
-        self.inner.ready_offset() + self.win_size - 1
+        self.inner.ready_offset() + self.min_periods - 1
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.inner.lookback()? + self.win_size - 1)
+    }
+
+    fn state_bytes(&self) -> usize {
+        2 * self.win_size * mem::size_of::<f64>() + self.inner.state_bytes()
     }
 
     fn to_string(&self) -> String {
         format!(
             "({} {} {} {})",
             Self::NAME,
-            self.win_size,
-            self.quantile,
+            super::fmt_window(self.win_size, self.min_periods),
+            fmt_constant(self.quantile),
             self.inner.to_string(),
         )
     }
@@ -159,6 +192,7 @@ impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<Quantile<T>> {
     #[throws(Error)]
     fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> Quantile<T> {
         let mut params: Vec<_> = iter.into_iter().collect();
+        let min_periods = super::split_min_periods(Quantile::<T>::NAME, &mut params, 3, 1)?;
         if params.len() != 3 {
             throw!(anyhow!(
                 "{} expect two constants and one series, got {:?}",
@@ -169,9 +203,10 @@ impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<Quantile<T>> {
         let k1 = params.remove(0);
         let k2 = params.remove(0);
         let k3 = params.remove(0);
-        match (k1, k2, k3) {
+        let op = match (k1, k2, k3) {
             (Parameter::Constant(c), Parameter::Constant(c2), Parameter::Operator(s)) => {
-                Quantile::new(c as usize, c2, s)
+                let c2 = to_fraction(Quantile::<T>::NAME, "q", c2)?;
+                Quantile::new(to_usize(Quantile::<T>::NAME, "win_size", c, 1)?, c2, s)
             }
             (a, b, c) => throw!(anyhow!(
                 "{name} expect two constants and a series, got ({name} {} {} {})",
@@ -180,6 +215,11 @@ impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<Quantile<T>> {
                 c,
                 name = Quantile::<T>::NAME,
             )),
+        };
+
+        match min_periods {
+            Some(m) => op.with_min_periods(m),
+            None => op,
         }
     }
 }
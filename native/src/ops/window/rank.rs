@@ -1,4 +1,8 @@
-use super::super::{parser::Parameter, BoxOp, Named, Operator};
+use super::super::{
+    at_child,
+    parser::{to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
 use crate::{
     float::{Ascending, Float, IntoFloat},
     ticker_batch::TickerBatch,
@@ -10,6 +14,7 @@ use std::{borrow::Cow, collections::VecDeque, iter::FromIterator, mem};
 
 pub struct Rank<T> {
     win_size: usize,
+    min_periods: usize,
     inner: BoxOp<T>,
 
     window: VecDeque<f64>,
@@ -19,7 +24,7 @@ pub struct Rank<T> {
 
 impl<T> Clone for Rank<T> {
     fn clone(&self) -> Self {
-        Self::new(self.win_size, self.inner.clone())
+        Self::new(self.win_size, self.inner.clone()).with_min_periods(self.min_periods)
     }
 }
 
@@ -27,6 +32,7 @@ impl<T> Rank<T> {
     pub fn new(win_size: usize, inner: BoxOp<T>) -> Self {
         Self {
             win_size,
+            min_periods: win_size,
             inner,
 
             window: VecDeque::with_capacity(win_size),
@@ -34,6 +40,11 @@ impl<T> Rank<T> {
             i: 0,
         }
     }
+
+    pub fn with_min_periods(mut self, min_periods: usize) -> Self {
+        self.min_periods = min_periods;
+        self
+    }
 }
 
 impl<T> Named for Rank<T> {
@@ -48,18 +59,21 @@ impl<T: TickerBatch> Operator<T> for Rank<T> {
         self.i = 0;
     }
 
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.window.clear();
+        self.ostree.clear();
+    }
+
     #[throws(Error)]
     fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
-        let vals = &*self.inner.update(tb)?;
-        #[cfg(feature = "check")]
-        assert_eq!(tb.len(), vals.len());
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
 
         let mut results = Vec::with_capacity(tb.len());
 
         for &val in vals {
             if self.i < self.inner.ready_offset() {
-                #[cfg(feature = "check")]
-                assert!(val.is_nan());
+                self.check_warmup(tb, &[val])?;
                 results.push(f64::NAN);
                 self.i += 1;
                 continue;
@@ -67,17 +81,22 @@ impl<T: TickerBatch> Operator<T> for Rank<T> {
 
             self.window.push_back(val);
             self.ostree.increase(val.asc(), 1);
-            let val = if self.window.len() == self.win_size {
+            let val = if self.window.len() >= self.min_periods {
                 let idx = self.ostree.rank(&val.asc()).unwrap();
-                let val = self.fchecked(idx as f64)?;
-
-                let to_remove = self.window.pop_front().unwrap().asc();
-                self.ostree.decrease(&to_remove, 1);
-
-                val
+                self.fchecked(idx as f64)?
             } else {
                 f64::NAN
             };
+
+            #[cfg(feature = "validate")]
+            crate::reference::validate(Self::NAME, val, || {
+                crate::reference::rank(self.window.make_contiguous())
+            })?;
+
+            if self.window.len() == self.win_size {
+                let to_remove = self.window.pop_front().unwrap().asc();
+                self.ostree.decrease(&to_remove, 1);
+            }
             results.push(val);
         }
 
@@ -85,14 +104,22 @@ impl<T: TickerBatch> Operator<T> for Rank<T> {
     }
 
     fn ready_offset(&self) -> usize {
-        self.inner.ready_offset() + self.win_size - 1
+        self.inner.ready_offset() + self.min_periods - 1
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.inner.lookback()? + self.win_size - 1)
+    }
+
+    fn state_bytes(&self) -> usize {
+        2 * self.win_size * mem::size_of::<f64>() + self.inner.state_bytes()
     }
 
     fn to_string(&self) -> String {
         format!(
             "({} {} {})",
             Self::NAME,
-            self.win_size,
+            super::fmt_window(self.win_size, self.min_periods),
             self.inner.to_string(),
         )
     }
@@ -153,6 +180,7 @@ impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<Rank<T>> {
     #[throws(Error)]
     fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> Rank<T> {
         let mut params: Vec<_> = iter.into_iter().collect();
+        let min_periods = super::split_min_periods(Rank::<T>::NAME, &mut params, 2, 1)?;
         if params.len() != 2 {
             throw!(anyhow!(
                 "{} expect a constant and one series, got {:?}",
@@ -162,14 +190,21 @@ impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<Rank<T>> {
         }
         let k1 = params.remove(0);
         let k2 = params.remove(0);
-        match (k1, k2) {
-            (Parameter::Constant(c), Parameter::Operator(s)) => Rank::new(c as usize, s),
+        let op = match (k1, k2) {
+            (Parameter::Constant(c), Parameter::Operator(s)) => {
+                Rank::new(to_usize(Rank::<T>::NAME, "win_size", c, 1)?, s)
+            }
             (a, b) => throw!(anyhow!(
                 "{name} expect a constant and a series, got ({name} {} {})",
                 a,
                 b,
                 name = Rank::<T>::NAME,
             )),
+        };
+
+        match min_periods {
+            Some(m) => op.with_min_periods(m),
+            None => op,
         }
     }
 }
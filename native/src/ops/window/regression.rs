@@ -0,0 +1,384 @@
+use super::{
+    super::{
+        at_child,
+        parser::{to_usize, Parameter},
+        BoxOp, Named, Operator,
+    },
+    MonotonicQueue,
+};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use std::{borrow::Cow, cmp::max, collections::VecDeque, iter::FromIterator, mem};
+
+/// The ordinary least squares fit `y = alpha + beta * x` over the last `win_size` pairs, from the means and the
+/// co-moments updated as the pairs come and go. They are summed over again every `win_size` pairs so that the
+/// rounding errors do not pile up, and a flat `x` is told exactly, for which the fit is the mean of `y`.
+#[derive(Clone)]
+struct OLS {
+    win_size: usize,
+
+    window: VecDeque<(f64, f64)>, // (y, x)
+    highest: MonotonicQueue,      // of x
+    lowest: MonotonicQueue,
+    xmean: f64,
+    ymean: f64,
+    xx: f64, // the sum of the squared deviations of x
+    xy: f64, // the sum of the products of the deviations
+    pushed: usize,
+}
+
+impl OLS {
+    fn new(win_size: usize) -> Self {
+        Self {
+            win_size,
+
+            window: VecDeque::with_capacity(win_size),
+            highest: MonotonicQueue::max(win_size),
+            lowest: MonotonicQueue::min(win_size),
+            xmean: 0.,
+            ymean: 0.,
+            xx: 0.,
+            xy: 0.,
+            pushed: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        *self = Self::new(self.win_size);
+    }
+
+    fn len(&self) -> usize {
+        self.window.len()
+    }
+
+    fn push(&mut self, y: f64, x: f64) {
+        if self.window.len() == self.win_size {
+            let (y, x) = self.window.pop_front().unwrap();
+            let n = self.window.len() as f64;
+            if n == 0. {
+                self.xmean = 0.;
+                self.ymean = 0.;
+                self.xx = 0.;
+                self.xy = 0.;
+            } else {
+                // Undo the push of the pair: the means before, then the co-moments
+                let xmean = self.xmean - (x - self.xmean) / n;
+                self.xx -= (x - xmean) * (x - self.xmean);
+                self.xy -= (x - xmean) * (y - self.ymean);
+                self.xmean = xmean;
+                self.ymean -= (y - self.ymean) / n;
+            }
+        }
+
+        self.window.push_back((y, x));
+        self.highest.push(x);
+        self.lowest.push(x);
+        self.pushed += 1;
+
+        if self.pushed % self.win_size == 0 {
+            self.resum();
+        } else {
+            let n = self.window.len() as f64;
+            let dx = x - self.xmean;
+            self.xmean += dx / n;
+            self.ymean += (y - self.ymean) / n;
+            self.xx += dx * (x - self.xmean);
+            self.xy += dx * (y - self.ymean);
+        }
+    }
+
+    fn resum(&mut self) {
+        let n = self.window.len() as f64;
+        self.xmean = self.window.iter().map(|(_, x)| x).sum::<f64>() / n;
+        self.ymean = self.window.iter().map(|(y, _)| y).sum::<f64>() / n;
+        self.xx = 0.;
+        self.xy = 0.;
+        for (y, x) in &self.window {
+            self.xx += (x - self.xmean).powi(2);
+            self.xy += (x - self.xmean) * (y - self.ymean);
+        }
+    }
+
+    /// The intercept and the slope.
+    fn fit(&self) -> (f64, f64) {
+        let flat = self.highest.front().map(|(_, v)| v) == self.lowest.front().map(|(_, v)| v);
+        if flat || self.xx == 0. {
+            return (self.ymean, 0.);
+        }
+        let beta = self.xy / self.xx;
+        (self.ymean - beta * self.xmean, beta)
+    }
+}
+
+// The operators over the rolling OLS fit of `y` on `x`, which only differ in the output
+macro_rules! impl_regression {
+    ($($(#[$doc:meta])* $op:ident |$alpha:ident, $beta:ident, $y:ident, $x:ident| $output:expr;)+) => {
+        $(
+            $(#[$doc])*
+            pub struct $op<T> {
+                win_size: usize,
+                min_periods: usize,
+                y: BoxOp<T>,
+                x: BoxOp<T>,
+
+                ols: OLS,
+                i: usize,
+            }
+
+            impl<T> Clone for $op<T> {
+                fn clone(&self) -> Self {
+                    Self::new(self.win_size, self.y.clone(), self.x.clone())
+                        .with_min_periods(self.min_periods)
+                }
+            }
+
+            impl<T> $op<T> {
+                pub fn new(win_size: usize, y: BoxOp<T>, x: BoxOp<T>) -> Self {
+                    Self {
+                        win_size,
+                        min_periods: win_size,
+                        y,
+                        x,
+
+                        ols: OLS::new(win_size),
+                        i: 0,
+                    }
+                }
+
+                pub fn with_min_periods(mut self, min_periods: usize) -> Self {
+                    self.min_periods = min_periods;
+                    self
+                }
+            }
+
+            impl<T> Named for $op<T> {
+                const NAME: &'static str = stringify!($op);
+            }
+
+            impl<T: TickerBatch> Operator<T> for $op<T> {
+                fn reset(&mut self) {
+                    self.y.reset();
+                    self.x.reset();
+                    self.ols.clear();
+                    self.i = 0;
+                }
+
+                #[throws(Error)]
+                fn evict(&mut self) {
+                    self.ols.clear();
+                }
+
+                #[throws(Error)]
+                fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+                    let (y, x) = (&mut self.y, &mut self.x);
+                    let (ys, xs) = rayon::join(|| y.checked_update(tb), || x.checked_update(tb));
+                    let (ys, xs) = (
+                        &*ys.map_err(at_child(1))?,
+                        &*xs.map_err(at_child(1 + self.y.len()))?,
+                    );
+
+                    let mut results = Vec::with_capacity(tb.len());
+
+                    for (&$y, &$x) in ys.iter().zip(xs) {
+                        if self.i < self.y.ready_offset() || self.i < self.x.ready_offset() {
+                            self.check_warmup(tb, &[$y, $x])?;
+                            results.push(f64::NAN);
+                            self.i += 1;
+                            continue;
+                        }
+
+                        if $y.is_nan() || $x.is_nan() {
+                            results.push(f64::NAN);
+                            continue;
+                        }
+                        self.ols.push($y, $x);
+                        if self.ols.len() < self.min_periods {
+                            results.push(f64::NAN);
+                            continue;
+                        }
+
+                        let ($alpha, $beta) = self.ols.fit();
+                        let val = self.fchecked($output)?;
+
+                        #[cfg(feature = "validate")]
+                        crate::reference::validate(Self::NAME, val, || {
+                            let (ys, xs): (Vec<_>, Vec<_>) = self.ols.window.iter().cloned().unzip();
+                            let ($alpha, $beta) = crate::reference::ols(&ys, &xs);
+                            $output
+                        })?;
+
+                        results.push(val);
+                    }
+
+                    results.into()
+                }
+
+                fn ready_offset(&self) -> usize {
+                    max(self.y.ready_offset(), self.x.ready_offset()) + self.min_periods - 1
+                }
+
+                fn lookback(&self) -> Option<usize> {
+                    Some(max(self.y.lookback()?, self.x.lookback()?) + self.win_size - 1)
+                }
+
+                fn state_bytes(&self) -> usize {
+                    3 * self.win_size * mem::size_of::<(f64, f64)>()
+                        + self.y.state_bytes()
+                        + self.x.state_bytes()
+                }
+
+                fn to_string(&self) -> String {
+                    format!(
+                        "({} {} {} {})",
+                        Self::NAME,
+                        super::fmt_window(self.win_size, self.min_periods),
+                        self.y.to_string(),
+                        self.x.to_string()
+                    )
+                }
+
+                fn depth(&self) -> usize {
+                    1 + max(self.y.depth(), self.x.depth())
+                }
+
+                fn len(&self) -> usize {
+                    self.y.len() + self.x.len() + 1
+                }
+
+                fn child_indices(&self) -> Vec<usize> {
+                    vec![1, self.y.len() + 1]
+                }
+
+                fn columns(&self) -> Vec<String> {
+                    self.y
+                        .columns()
+                        .into_iter()
+                        .chain(self.x.columns())
+                        .collect()
+                }
+
+                #[throws(as Option)]
+                fn get(&self, i: usize) -> BoxOp<T> {
+                    if i == 0 {
+                        return self.clone().boxed();
+                    }
+                    let i = i - 1;
+
+                    let ny = self.y.len();
+                    let nx = self.x.len();
+
+                    if i < ny {
+                        self.y.get(i)?
+                    } else if i < ny + nx {
+                        self.x.get(i - ny)?
+                    } else {
+                        throw!()
+                    }
+                }
+
+                #[throws(as Option)]
+                fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+                    if i == 0 {
+                        unreachable!("cannot insert root");
+                    }
+                    let i = i - 1;
+
+                    let ny = self.y.len();
+                    let nx = self.x.len();
+
+                    if i < ny {
+                        if i == 0 {
+                            return mem::replace(&mut self.y, op) as BoxOp<T>;
+                        }
+                        self.y.insert(i, op)?
+                    } else if i < ny + nx {
+                        if i - ny == 0 {
+                            return mem::replace(&mut self.x, op) as BoxOp<T>;
+                        }
+                        self.x.insert(i - ny, op)?
+                    } else {
+                        throw!()
+                    }
+                }
+            }
+
+            impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<$op<T>> {
+                #[throws(Error)]
+                fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> $op<T> {
+                    let name = $op::<T>::NAME;
+                    let mut params: Vec<_> = iter.into_iter().collect();
+                    let min_periods = super::split_min_periods(name, &mut params, 3, 2)?;
+                    if params.len() != 3 {
+                        throw!(anyhow!("{} expect a constant and two series, got {:?}", name, params))
+                    }
+                    let k1 = params.remove(0);
+                    let k2 = params.remove(0).to_operator();
+                    let k3 = params.remove(0).to_operator();
+                    let op = match (k1, k2, k3) {
+                        (Parameter::Constant(c), Some(y), Some(x)) => {
+                            $op::new(to_usize(name, "win_size", c, 2)?, y, x)
+                        }
+                        _ => throw!(anyhow!("{} expect a constant and two series", name)),
+                    };
+
+                    match min_periods {
+                        Some(m) => op.with_min_periods(m),
+                        None => op,
+                    }
+                }
+            }
+        )+
+    };
+}
+
+impl_regression! {
+    /// The slope of the OLS fit of `y` on `x` over the window, `cov(x, y) / var(x)`, 0 if `x` is flat.
+    /// The pairs with a NaN are skipped, and produce a NaN.
+    Beta |_alpha, beta, _y, _x| beta;
+
+    /// The intercept of the OLS fit of `y` on `x` over the window, `mean(y) - beta * mean(x)`,
+    /// the mean of `y` if `x` is flat. The pairs with a NaN are skipped, and produce a NaN.
+    Alpha |alpha, _beta, _y, _x| alpha;
+
+    /// The residual of the current `y` from the OLS fit on `x` over the window, `y - (alpha + beta * x)`,
+    /// e.g. a factor with its market beta stripped. The pairs with a NaN are skipped, and produce a NaN.
+    Resid |alpha, beta, y, x| y - (alpha + beta * x);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ops::from_str;
+    use arrow::{
+        array::{ArrayRef, Float64Array},
+        record_batch::RecordBatch,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn flat_x() {
+        let opts = Default::default();
+        let xs: Vec<f64> = (0..50)
+            .map(|i| if i < 20 { 0.1 * i as f64 } else { 3.3 })
+            .collect();
+        let ys: Vec<f64> = (0..50).map(|i| 0.7 * (i % 3) as f64).collect();
+        let tb = RecordBatch::try_from_iter(vec![
+            ("x", Arc::new(Float64Array::from(xs)) as ArrayRef),
+            ("y", Arc::new(Float64Array::from(ys.clone()))),
+        ])
+        .unwrap();
+
+        // Once x settles at 3.3, the fit is exactly the mean of y
+        let mut op = from_str::<RecordBatch>("(Beta 9 :y :x)", &opts).unwrap();
+        let got = op.update(&tb).unwrap();
+        assert!(got[..8].iter().all(|v| v.is_nan()));
+        assert!(got[30..].iter().all(|&v| v == 0.), "{:?}", got);
+
+        let mut op = from_str::<RecordBatch>("(Alpha 9 :y :x)", &opts).unwrap();
+        let got = op.update(&tb).unwrap();
+        for i in 30..50 {
+            let mean = ys[i - 8..=i].iter().sum::<f64>() / 9.;
+            assert!((got[i] - mean).abs() < 1e-12, "{:?}", got);
+        }
+    }
+}
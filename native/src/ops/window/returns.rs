@@ -1,162 +1,221 @@
-use super::super::{parser::Parameter, BoxOp, Named, Operator};
+use super::super::{
+    at_child,
+    parser::{to_usize, Parameter},
+    BoxOp, Named, Operator, ZeroPolicy,
+};
 use crate::ticker_batch::TickerBatch;
 use anyhow::{anyhow, Error, Result};
 use fehler::{throw, throws};
 use std::{borrow::Cow, collections::VecDeque, iter::FromIterator, mem};
 
-pub struct LogReturn<T> {
-    win_size: usize,
-    inner: BoxOp<T>,
-
-    window: VecDeque<f64>,
-    i: usize,
-}
-
-impl<T> Clone for LogReturn<T> {
-    fn clone(&self) -> Self {
-        Self::new(self.win_size, self.inner.clone())
-    }
-}
+// The operators comparing the current value with the one `win_size` values back. The ones followed by `(zero)`
+// divide by the old value, and deal with a zero there according to a `ZeroPolicy` like `/`.
+macro_rules! impl_change {
+    ($($(#[$doc:meta])* $op:ident $(($zero:ident))? [$min:literal] |$this:ident, $cur:ident, $old:ident| $change:expr;)+) => {
+        $(
+            $(#[$doc])*
+            pub struct $op<T> {
+                win_size: usize,
+                inner: BoxOp<T>,
+                $($zero: ZeroPolicy,)?
+
+                window: VecDeque<f64>,
+                i: usize,
+            }
 
-impl<T> LogReturn<T> {
-    pub fn new(win_size: usize, inner: BoxOp<T>) -> Self {
-        Self {
-            win_size,
-            inner,
+            impl<T> Clone for $op<T> {
+                fn clone(&self) -> Self {
+                    Self::new(self.win_size, self.inner.clone())$(.with_zero(self.$zero))?
+                }
+            }
 
-            window: VecDeque::with_capacity(win_size + 1),
-            i: 0,
-        }
-    }
-}
+            impl<T> $op<T> {
+                pub fn new(win_size: usize, inner: BoxOp<T>) -> Self {
+                    Self {
+                        win_size,
+                        inner,
+                        $($zero: ZeroPolicy::default(),)?
+
+                        window: VecDeque::with_capacity(win_size + 1),
+                        i: 0,
+                    }
+                }
+
+                $(
+                    pub fn with_zero(mut self, $zero: ZeroPolicy) -> Self {
+                        self.$zero = $zero;
+                        self
+                    }
+                )?
+            }
 
-impl<T> Named for LogReturn<T> {
-    const NAME: &'static str = "LogReturn";
-}
+            impl<T> Named for $op<T> {
+                const NAME: &'static str = stringify!($op);
+            }
 
-impl<T: TickerBatch> Operator<T> for LogReturn<T> {
-    fn reset(&mut self) {
-        self.inner.reset();
-        self.window.clear();
-        self.i = 0;
-    }
-
-    #[throws(Error)]
-    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
-        let vals = &*self.inner.update(tb)?;
-        #[cfg(feature = "check")]
-        assert_eq!(tb.len(), vals.len());
-        let mut results = Vec::with_capacity(tb.len());
-
-        for &val in vals {
-            if self.i < self.inner.ready_offset() {
-                #[cfg(feature = "check")]
-                assert!(val.is_nan());
-                results.push(f64::NAN);
-                self.i += 1;
-                continue;
+            impl<T: TickerBatch> Operator<T> for $op<T> {
+                fn reset(&mut self) {
+                    self.inner.reset();
+                    self.window.clear();
+                    self.i = 0;
+                }
+
+                #[throws(Error)]
+                fn evict(&mut self) {
+                    self.window.clear();
+                }
+
+                #[throws(Error)]
+                fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+                    let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+                    let mut results = Vec::with_capacity(tb.len());
+
+                    for &val in vals {
+                        if self.i < self.inner.ready_offset() {
+                            self.check_warmup(tb, &[val])?;
+                            results.push(f64::NAN);
+                            self.i += 1;
+                            continue;
+                        }
+
+                        self.window.push_back(val);
+
+                        let val = if self.window.len() == self.win_size + 1 {
+                            let $old = self.window.pop_front().unwrap();
+                            let $cur = val;
+                            let $this = &*self;
+                            $change
+                        } else {
+                            f64::NAN
+                        };
+                        // Only the epsilon policy keeps the outputs finite
+                        $(let val = if self.$zero == ZeroPolicy::Epsilon { self.fchecked(val)? } else { val };)?
+                        results.push(val);
+                    }
+
+                    results.into()
+                }
+
+                fn ready_offset(&self) -> usize {
+                    self.inner.ready_offset() + self.win_size
+                }
+
+                fn is_nullable(&self) -> bool {
+                    let zeros: &[ZeroPolicy] = &[$(self.$zero)?];
+                    zeros.iter().any(|&zero| zero != ZeroPolicy::Epsilon)
+                }
+
+                fn lookback(&self) -> Option<usize> {
+                    Some(self.inner.lookback()? + self.win_size)
+                }
+
+                fn state_bytes(&self) -> usize {
+                    self.win_size * mem::size_of::<f64>() + self.inner.state_bytes()
+                }
+
+                fn to_string(&self) -> String {
+                    let prefix: &[&str] = &[$(self.$zero.prefix())?];
+                    format!(
+                        "({} {}{} {})",
+                        Self::NAME,
+                        prefix.concat(),
+                        self.win_size,
+                        self.inner.to_string()
+                    )
+                }
+
+                fn depth(&self) -> usize {
+                    1 + self.inner.depth()
+                }
+
+                fn len(&self) -> usize {
+                    self.inner.len() + 1
+                }
+
+                fn child_indices(&self) -> Vec<usize> {
+                    vec![1]
+                }
+
+                fn columns(&self) -> Vec<String> {
+                    self.inner.columns()
+                }
+
+                #[throws(as Option)]
+                fn get(&self, i: usize) -> BoxOp<T> {
+                    if i == 0 {
+                        return self.clone().boxed();
+                    }
+                    let i = i - 1;
+
+                    let ns = self.inner.len();
+
+                    if i < ns {
+                        self.inner.get(i)?
+                    } else {
+                        throw!()
+                    }
+                }
+
+                #[throws(as Option)]
+                fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+                    if i == 0 {
+                        unreachable!("cannot insert root");
+                    }
+                    let i = i - 1;
+
+                    let ns = self.inner.len();
+
+                    if i < ns {
+                        if i == 0 {
+                            return mem::replace(&mut self.inner, op) as BoxOp<T>;
+                        }
+                        self.inner.insert(i, op)?
+                    } else {
+                        throw!()
+                    }
+                }
             }
 
-            self.window.push_back(val);
-
-            let val = if self.window.len() == self.win_size + 1 {
-                let result = (val / self.window.pop_front().unwrap()).ln();
-                self.fchecked(result)?
-            } else {
-                f64::NAN
-            };
-            results.push(val);
-        }
-
-        results.into()
-    }
-
-    fn ready_offset(&self) -> usize {
-        self.inner.ready_offset() + self.win_size
-    }
-
-    fn to_string(&self) -> String {
-        format!(
-            "({} {} {})",
-            Self::NAME,
-            self.win_size,
-            self.inner.to_string()
-        )
-    }
-
-    fn depth(&self) -> usize {
-        1 + self.inner.depth()
-    }
-
-    fn len(&self) -> usize {
-        self.inner.len() + 1
-    }
-
-    fn child_indices(&self) -> Vec<usize> {
-        vec![1]
-    }
-
-    fn columns(&self) -> Vec<String> {
-        self.inner.columns()
-    }
-
-    #[throws(as Option)]
-    fn get(&self, i: usize) -> BoxOp<T> {
-        if i == 0 {
-            return self.clone().boxed();
-        }
-        let i = i - 1;
-
-        let ns = self.inner.len();
-
-        if i < ns {
-            self.inner.get(i)?
-        } else {
-            throw!()
-        }
-    }
-
-    #[throws(as Option)]
-    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
-        if i == 0 {
-            unreachable!("cannot insert root");
-        }
-        let i = i - 1;
-
-        let ns = self.inner.len();
-
-        if i < ns {
-            if i == 0 {
-                return mem::replace(&mut self.inner, op) as BoxOp<T>;
+            impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<$op<T>> {
+                #[throws(Error)]
+                fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> $op<T> {
+                    let mut params: Vec<_> = iter.into_iter().collect();
+                    if params.len() != 2 {
+                        throw!(anyhow!(
+                            "{} expect a constant and a series, got {:?}",
+                            $op::<T>::NAME,
+                            params
+                        ))
+                    }
+                    let k1 = params.remove(0);
+                    let k2 = params.remove(0);
+                    match (k1, k2) {
+                        (Parameter::Constant(c), Parameter::Operator(s)) => {
+                            $op::new(to_usize($op::<T>::NAME, "win_size", c, $min)?, s)
+                        }
+                        (a, b) => throw!(anyhow!(
+                            "{name} expect a constant and a series, got ({name} {} {})",
+                            a,
+                            b,
+                            name = $op::<T>::NAME,
+                        )),
+                    }
+                }
             }
-            self.inner.insert(i, op)?
-        } else {
-            throw!()
-        }
-    }
+        )+
+    };
 }
 
-impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<LogReturn<T>> {
-    #[throws(Error)]
-    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> LogReturn<T> {
-        let mut params: Vec<_> = iter.into_iter().collect();
-        if params.len() != 2 {
-            throw!(anyhow!(
-                "{} expect a constant and a series, got {:?}",
-                LogReturn::<T>::NAME,
-                params
-            ))
-        }
-        let k1 = params.remove(0);
-        let k2 = params.remove(0);
-        match (k1, k2) {
-            (Parameter::Constant(c), Parameter::Operator(s)) => LogReturn::new(c as usize, s),
-            (a, b) => throw!(anyhow!(
-                "{name} expect a constant and a series, got ({name} {} {})",
-                a,
-                b,
-                name = LogReturn::<T>::NAME,
-            )),
-        }
-    }
+impl_change! {
+    /// The log return `ln(x / x[-win_size])`, which fails on non-positive values.
+    LogReturn [0] |this, cur, old| this.fchecked((cur / old).ln())?;
+
+    /// The rate of change of TA-Lib in percentage, `100 * (x / x[-win_size] - 1)`.
+    ROC (zero) [1] |this, cur, old| 100. * (this.zero.divide(cur, old) - 1.);
+
+    /// The simple return `x / x[-win_size] - 1`, like `pd.Series.pct_change` under the `ieee` policy.
+    PctChange (zero) [1] |this, cur, old| this.zero.divide(cur, old) - 1.;
+
+    /// The momentum of TA-Lib, `x - x[-win_size]`.
+    Momentum [1] |_this, cur, old| cur - old;
 }
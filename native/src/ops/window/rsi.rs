@@ -0,0 +1,221 @@
+use super::super::{
+    at_child,
+    parser::{to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use std::{borrow::Cow, iter::FromIterator, mem};
+
+/// The relative strength index of TA-Lib, `100 * gain / (gain + loss)` in `[0, 100]`. The average gain and loss
+/// start as the means of the first `win_size` up and down moves, then follow Wilder's smoothing
+/// `avg = (avg * (win_size - 1) + move) / win_size`. The NaNs are skipped, and produce a NaN.
+pub struct RSI<T> {
+    win_size: usize,
+    inner: BoxOp<T>,
+
+    prev: f64,
+    moves: usize, // the number of moves seen, up to the window size
+    gain: f64,
+    loss: f64,
+    i: usize,
+}
+
+impl<T> Clone for RSI<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.win_size, self.inner.clone())
+    }
+}
+
+impl<T> RSI<T> {
+    pub fn new(win_size: usize, inner: BoxOp<T>) -> Self {
+        Self {
+            win_size,
+            inner,
+
+            prev: f64::NAN,
+            moves: 0,
+            gain: 0.,
+            loss: 0.,
+            i: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.prev = f64::NAN;
+        self.moves = 0;
+        self.gain = 0.;
+        self.loss = 0.;
+    }
+}
+
+impl<T> Named for RSI<T> {
+    const NAME: &'static str = "RSI";
+}
+
+impl<T: TickerBatch> Operator<T> for RSI<T> {
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.clear();
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.clear();
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+        let mut results = Vec::with_capacity(tb.len());
+
+        for &val in vals {
+            if self.i < self.inner.ready_offset() {
+                self.check_warmup(tb, &[val])?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            if val.is_nan() {
+                results.push(f64::NAN);
+                continue;
+            }
+            let prev = mem::replace(&mut self.prev, val);
+            if prev.is_nan() {
+                results.push(f64::NAN);
+                continue;
+            }
+
+            let n = self.win_size as f64;
+            let (gain, loss) = if val > prev {
+                (val - prev, 0.)
+            } else {
+                (0., prev - val)
+            };
+            if self.moves < self.win_size {
+                self.moves += 1;
+                self.gain += gain / n;
+                self.loss += loss / n;
+                if self.moves < self.win_size {
+                    results.push(f64::NAN);
+                    continue;
+                }
+            } else {
+                self.gain = (self.gain * (n - 1.) + gain) / n;
+                self.loss = (self.loss * (n - 1.) + loss) / n;
+            }
+
+            let total = self.gain + self.loss;
+            results.push(if total == 0. {
+                0.
+            } else {
+                100. * self.gain / total
+            });
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inner.ready_offset() + self.win_size
+    }
+
+    // Every move ever seen keeps a (vanishing) weight
+    fn lookback(&self) -> Option<usize> {
+        None
+    }
+
+    fn state_bytes(&self) -> usize {
+        3 * mem::size_of::<f64>() + self.inner.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "({} {} {})",
+            Self::NAME,
+            self.win_size,
+            self.inner.to_string()
+        )
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inner.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        vec![1]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inner.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            self.inner.get(i)?
+        } else {
+            throw!()
+        }
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            if i == 0 {
+                return mem::replace(&mut self.inner, op) as BoxOp<T>;
+            }
+            self.inner.insert(i, op)?
+        } else {
+            throw!()
+        }
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<RSI<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> RSI<T> {
+        let mut params: Vec<_> = iter.into_iter().collect();
+        if params.len() != 2 {
+            throw!(anyhow!(
+                "{} expect a constant and a series, got {:?}",
+                RSI::<T>::NAME,
+                params
+            ))
+        }
+        let k1 = params.remove(0);
+        let k2 = params.remove(0);
+        match (k1, k2) {
+            (Parameter::Constant(c), Parameter::Operator(s)) => {
+                RSI::new(to_usize(RSI::<T>::NAME, "win_size", c, 1)?, s)
+            }
+            (a, b) => throw!(anyhow!(
+                "{name} expect a constant and a series, got ({name} {} {})",
+                a,
+                b,
+                name = RSI::<T>::NAME,
+            )),
+        }
+    }
+}
@@ -1,4 +1,8 @@
-use super::super::{parser::Parameter, BoxOp, Named, Operator};
+use super::super::{
+    at_child,
+    parser::{to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
 use crate::ticker_batch::TickerBatch;
 use anyhow::{anyhow, Error, Result};
 use fehler::{throw, throws};
@@ -6,6 +10,7 @@ use std::{borrow::Cow, collections::VecDeque, iter::FromIterator, mem};
 
 pub struct Skew<T> {
     win_size: usize,
+    min_periods: usize,
     inner: BoxOp<T>,
 
     window: VecDeque<f64>,
@@ -15,7 +20,7 @@ pub struct Skew<T> {
 
 impl<T> Clone for Skew<T> {
     fn clone(&self) -> Self {
-        Self::new(self.win_size, self.inner.clone())
+        Self::new(self.win_size, self.inner.clone()).with_min_periods(self.min_periods)
     }
 }
 
@@ -23,6 +28,7 @@ impl<T> Skew<T> {
     pub fn new(win_size: usize, inner: BoxOp<T>) -> Self {
         Self {
             win_size,
+            min_periods: win_size,
             inner,
 
             window: VecDeque::with_capacity(win_size),
@@ -30,6 +36,11 @@ impl<T> Skew<T> {
             i: 0,
         }
     }
+
+    pub fn with_min_periods(mut self, min_periods: usize) -> Self {
+        self.min_periods = min_periods;
+        self
+    }
 }
 
 impl<T> Named for Skew<T> {
@@ -44,18 +55,21 @@ impl<T: TickerBatch> Operator<T> for Skew<T> {
         self.i = 0;
     }
 
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.window.clear();
+        self.sum = 0.;
+    }
+
     #[throws(Error)]
     fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
-        let vals = &*self.inner.update(tb)?;
-        #[cfg(feature = "check")]
-        assert_eq!(tb.len(), vals.len());
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
 
         let mut results = Vec::with_capacity(tb.len());
 
         for &val in vals {
             if self.i < self.inner.ready_offset() {
-                #[cfg(feature = "check")]
-                assert!(val.is_nan());
+                self.check_warmup(tb, &[val])?;
                 results.push(f64::NAN);
                 self.i += 1;
                 continue;
@@ -63,13 +77,13 @@ impl<T: TickerBatch> Operator<T> for Skew<T> {
 
             self.window.push_back(val);
             self.sum += val;
-            let val = if self.window.len() == self.win_size {
+            let val = if self.window.len() >= self.min_periods {
                 let n = self.window.len() as f64;
                 let mu = self.sum / n;
                 let m3 = self.window.iter().map(|x| (x - mu).powf(3.0)).sum::<f64>() / n;
                 let m2 = self.window.iter().map(|x| (x - mu).powf(2.0)).sum::<f64>() / n;
 
-                let val = if m2 == 0. {
+                if m2 == 0. {
                     0.
                 } else {
                     // do not use window function because this will overflow
@@ -80,15 +94,20 @@ impl<T: TickerBatch> Operator<T> for Skew<T> {
                     let result = correction * m3 / m2.powf(1.5);
 
                     self.fchecked(result)?
-                };
-
-                self.sum -= self.window.pop_front().unwrap();
-
-                val
+                }
             } else {
                 f64::NAN
             };
 
+            #[cfg(feature = "validate")]
+            crate::reference::validate(Self::NAME, val, || {
+                crate::reference::skew(self.window.make_contiguous())
+            })?;
+
+            if self.window.len() == self.win_size {
+                self.sum -= self.window.pop_front().unwrap();
+            }
+
             results.push(val);
         }
 
@@ -96,14 +115,22 @@ impl<T: TickerBatch> Operator<T> for Skew<T> {
     }
 
     fn ready_offset(&self) -> usize {
-        self.inner.ready_offset() + self.win_size - 1
+        self.inner.ready_offset() + self.min_periods - 1
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.inner.lookback()? + self.win_size - 1)
+    }
+
+    fn state_bytes(&self) -> usize {
+        self.win_size * mem::size_of::<f64>() + self.inner.state_bytes()
     }
 
     fn to_string(&self) -> String {
         format!(
             "({} {} {})",
             Self::NAME,
-            self.win_size,
+            super::fmt_window(self.win_size, self.min_periods),
             self.inner.to_string()
         )
     }
@@ -164,23 +191,19 @@ impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<Skew<T>> {
     #[throws(Error)]
     fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> Skew<T> {
         let mut params: Vec<_> = iter.into_iter().collect();
+        let min_periods = super::split_min_periods(Skew::<T>::NAME, &mut params, 2, 3)?;
         if params.len() != 2 {
             throw!(anyhow!(
-                "{} expect two series, got {:?}",
-                stringify!($op),
+                "{} expect a constant and a series, got {:?}",
+                Skew::<T>::NAME,
                 params
             ))
         }
         let k1 = params.remove(0);
         let k2 = params.remove(0);
-        match (k1, k2) {
-            (Parameter::Constant(c), Parameter::Operator(s)) if c >= 3. => Skew::new(c as usize, s),
-            (Parameter::Constant(c), Parameter::Operator(_)) if c < 3. => {
-                throw!(anyhow!(
-                    "{} for requires constant larger than 2, got {}",
-                    Skew::<T>::NAME,
-                    c
-                ))
+        let op = match (k1, k2) {
+            (Parameter::Constant(c), Parameter::Operator(s)) => {
+                Skew::new(to_usize(Skew::<T>::NAME, "win_size", c, 3)?, s)
             }
             (a, b) => throw!(anyhow!(
                 "{name} expect a constant and a series, got ({name} {} {})",
@@ -188,6 +211,11 @@ impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<Skew<T>> {
                 b,
                 name = Skew::<T>::NAME,
             )),
+        };
+
+        match min_periods {
+            Some(m) => op.with_min_periods(m),
+            None => op,
         }
     }
 }
@@ -0,0 +1,286 @@
+use super::super::{
+    at_child,
+    parser::{to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
+use crate::{
+    float::{Ascending, Float, IntoFloat},
+    ticker_batch::TickerBatch,
+};
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use order_stats_tree::OSTree;
+use std::{borrow::Cow, collections::VecDeque, iter::FromIterator, mem};
+
+/// The Theil-Sen estimate of the trend of the window, the median of the slopes `(x[j] - x[i]) / (j - i)` between all
+/// the pairs of values, which unlike `Slope` is not thrown off by a few bad values. The slopes of a new value with
+/// the others are added as it comes and the ones of the oldest value removed as it goes, i.e. O(n log n) per row,
+/// for the `n * (n - 1) / 2` slopes kept. The NaNs are skipped, and produce a NaN.
+pub struct TheilSen<T> {
+    win_size: usize,
+    min_periods: usize,
+    inner: BoxOp<T>,
+
+    window: VecDeque<(usize, f64)>,   // (position, value)
+    ostree: OSTree<Float<Ascending>>, // sorted slopes
+    pushed: usize,
+    i: usize,
+}
+
+impl<T> Clone for TheilSen<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.win_size, self.inner.clone()).with_min_periods(self.min_periods)
+    }
+}
+
+impl<T> TheilSen<T> {
+    pub fn new(win_size: usize, inner: BoxOp<T>) -> Self {
+        Self {
+            win_size,
+            min_periods: win_size,
+            inner,
+
+            window: VecDeque::with_capacity(win_size),
+            ostree: OSTree::new(),
+            pushed: 0,
+            i: 0,
+        }
+    }
+
+    pub fn with_min_periods(mut self, min_periods: usize) -> Self {
+        self.min_periods = min_periods;
+        self
+    }
+
+    fn clear(&mut self) {
+        self.window.clear();
+        self.ostree.clear();
+        self.pushed = 0;
+    }
+
+    // Add (or remove) the slopes between `(j, y)` and the values in the window. A slope is always computed from
+    // the older value to the newer one, so that it is removed with the same bits, e.g. 0 and not -0.
+    fn update_slopes(&mut self, (j, y): (usize, f64), add: bool) {
+        for &(i, x) in &self.window {
+            let ((i, x), (j, y)) = if i < j {
+                ((i, x), (j, y))
+            } else {
+                ((j, y), (i, x))
+            };
+            let slope = (y - x) / (j - i) as f64;
+            if add {
+                self.ostree.increase(slope.asc(), 1);
+            } else {
+                self.ostree.decrease(&slope.asc(), 1);
+            }
+        }
+    }
+
+    // The `r`-th smallest slope
+    fn select(&self, r: usize) -> f64 {
+        let (v, _) = self.ostree.select(r).unwrap();
+        v.0
+    }
+}
+
+impl<T> Named for TheilSen<T> {
+    const NAME: &'static str = "TheilSen";
+}
+
+impl<T: TickerBatch> Operator<T> for TheilSen<T> {
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.clear();
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.clear();
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for &val in vals {
+            if self.i < self.inner.ready_offset() {
+                self.check_warmup(tb, &[val])?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            if val.is_nan() {
+                results.push(f64::NAN);
+                continue;
+            }
+            if self.window.len() == self.win_size {
+                let oldest = self.window.pop_front().unwrap();
+                self.update_slopes(oldest, false);
+            }
+            let newest = (self.pushed, val);
+            self.update_slopes(newest, true);
+            self.window.push_back(newest);
+            self.pushed += 1;
+
+            if self.window.len() < self.min_periods {
+                results.push(f64::NAN);
+                continue;
+            }
+            let m = self.window.len() * (self.window.len() - 1) / 2;
+            let val = if m % 2 == 1 {
+                self.fchecked(self.select(m / 2))?
+            } else {
+                self.fchecked((self.select(m / 2 - 1) + self.select(m / 2)) / 2.)?
+            };
+
+            #[cfg(feature = "validate")]
+            crate::reference::validate(Self::NAME, val, || {
+                let window: Vec<_> = self.window.iter().map(|&(_, x)| x).collect();
+                crate::reference::theil_sen(&window)
+            })?;
+
+            results.push(val);
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inner.ready_offset() + self.min_periods - 1
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.inner.lookback()? + self.win_size - 1)
+    }
+
+    fn state_bytes(&self) -> usize {
+        self.win_size * mem::size_of::<(usize, f64)>()
+            + self.win_size * (self.win_size - 1) / 2 * mem::size_of::<f64>()
+            + self.inner.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "({} {} {})",
+            Self::NAME,
+            super::fmt_window(self.win_size, self.min_periods),
+            self.inner.to_string(),
+        )
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inner.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        vec![1]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inner.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            self.inner.get(i)?
+        } else {
+            throw!()
+        }
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            if i == 0 {
+                return mem::replace(&mut self.inner, op) as BoxOp<T>;
+            }
+            self.inner.insert(i, op)?
+        } else {
+            throw!()
+        }
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<TheilSen<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> TheilSen<T> {
+        let mut params: Vec<_> = iter.into_iter().collect();
+        let min_periods = super::split_min_periods(TheilSen::<T>::NAME, &mut params, 2, 2)?;
+        if params.len() != 2 {
+            throw!(anyhow!(
+                "{} expect a constant and a series, got {:?}",
+                TheilSen::<T>::NAME,
+                params
+            ))
+        }
+        let k1 = params.remove(0);
+        let k2 = params.remove(0);
+        let op = match (k1, k2) {
+            (Parameter::Constant(c), Parameter::Operator(s)) => {
+                TheilSen::new(to_usize(TheilSen::<T>::NAME, "win_size", c, 2)?, s)
+            }
+            (a, b) => throw!(anyhow!(
+                "{name} expect a constant and a series, got ({name} {} {})",
+                a,
+                b,
+                name = TheilSen::<T>::NAME,
+            )),
+        };
+
+        match min_periods {
+            Some(m) => op.with_min_periods(m),
+            None => op,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ops::from_str, reference::theil_sen};
+    use arrow::{
+        array::{ArrayRef, Float64Array},
+        record_batch::RecordBatch,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn repeated_values() {
+        let opts = Default::default();
+        // The flat runs give zero slopes, which must leave the window along with their values
+        let xs: Vec<f64> = (0..60).map(|i| ((i / 5) % 3) as f64).collect();
+        let tb = RecordBatch::try_from_iter(vec![(
+            "a",
+            Arc::new(Float64Array::from(xs.clone())) as ArrayRef,
+        )])
+        .unwrap();
+
+        let mut op = from_str::<RecordBatch>("(TheilSen 7 :a)", &opts).unwrap();
+        let got = op.update(&tb).unwrap();
+        for (i, window) in xs.windows(7).enumerate() {
+            assert_eq!(got[i + 6], theil_sen(window), "row {}", i + 6);
+        }
+    }
+}
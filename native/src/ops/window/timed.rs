@@ -0,0 +1,385 @@
+use super::{
+    super::{
+        inputs::Inputs,
+        parser::{to_duration, Parameter},
+        BoxOp, Named, Operator,
+    },
+    MonotonicQueue,
+};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use std::{borrow::Cow, collections::VecDeque, iter::FromIterator, mem, time::Duration};
+
+/// The values observed within a duration of the latest time, i.e. in `(t - duration, t]` like the time windows of
+/// `pd.Series.rolling("5s")`, so that the number of values in the window follows the activity. The moments are
+/// updated as the values come and go, and summed over again once as many values are pushed as there are in the window.
+#[derive(Clone)]
+struct TimeWindow {
+    duration: f64, // in seconds
+
+    window: VecDeque<(f64, f64)>, // (time, value)
+    highest: MonotonicQueue,
+    lowest: MonotonicQueue,
+    sum: f64,
+    mean: f64,
+    m2: f64,       // the sum of the squared deviations
+    pushed: usize, // since the last resum
+}
+
+impl TimeWindow {
+    fn new(duration: f64) -> Self {
+        Self {
+            duration,
+
+            window: VecDeque::new(),
+            highest: MonotonicQueue::max(usize::MAX),
+            lowest: MonotonicQueue::min(usize::MAX),
+            sum: 0.,
+            mean: 0.,
+            m2: 0.,
+            pushed: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        *self = Self::new(self.duration);
+    }
+
+    fn len(&self) -> usize {
+        self.window.len()
+    }
+
+    /// The time of the last value, None if the window is empty.
+    fn last(&self) -> Option<f64> {
+        self.window.back().map(|&(t, _)| t)
+    }
+
+    fn push(&mut self, t: f64, x: f64) {
+        while let Some(&(t_old, x_old)) = self.window.front() {
+            if t_old > t - self.duration {
+                break;
+            }
+            self.window.pop_front();
+            let n = self.window.len() as f64;
+            if n == 0. {
+                self.sum = 0.;
+                self.mean = 0.;
+                self.m2 = 0.;
+            } else {
+                // Undo the push of the value
+                let mean = self.mean - (x_old - self.mean) / n;
+                self.m2 -= (x_old - mean) * (x_old - self.mean);
+                self.mean = mean;
+                self.sum -= x_old;
+            }
+        }
+
+        self.window.push_back((t, x));
+        self.highest.push(x);
+        self.lowest.push(x);
+        let gone = self.highest.seq() - self.window.len();
+        self.highest.evict(gone);
+        self.lowest.evict(gone);
+
+        self.pushed += 1;
+        if self.pushed >= self.window.len() {
+            self.resum();
+        } else {
+            let dx = x - self.mean;
+            self.mean += dx / self.window.len() as f64;
+            self.m2 += dx * (x - self.mean);
+            self.sum += x;
+        }
+    }
+
+    fn resum(&mut self) {
+        let n = self.window.len() as f64;
+        self.sum = self.window.iter().map(|(_, x)| x).sum();
+        self.mean = self.sum / n;
+        self.m2 = self
+            .window
+            .iter()
+            .map(|(_, x)| (x - self.mean).powi(2))
+            .sum();
+        self.pushed = 0;
+    }
+
+    fn max(&self) -> f64 {
+        self.highest.front().unwrap().1
+    }
+
+    fn min(&self) -> f64 {
+        self.lowest.front().unwrap().1
+    }
+
+    /// The sample stdev, exactly 0 if the window is flat and NaN for a single value.
+    fn std(&self) -> f64 {
+        if self.window.len() < 2 {
+            f64::NAN
+        } else if self.max() == self.min() {
+            0.
+        } else {
+            (self.m2.max(0.) / (self.window.len() - 1) as f64).sqrt()
+        }
+    }
+}
+
+// The duration in the largest unit it is a whole number of, e.g. "1500ms" or "90m"
+fn fmt_duration(d: Duration) -> String {
+    let nanos = d.as_nanos();
+    let units = [
+        ("d", 86_400_000_000_000),
+        ("h", 3_600_000_000_000),
+        ("m", 60_000_000_000),
+        ("s", 1_000_000_000),
+        ("ms", 1_000_000),
+        ("us", 1_000),
+    ];
+    match units.iter().find(|(_, unit)| nanos % unit == 0) {
+        Some((name, unit)) => format!("{}{}", nanos / unit, name),
+        None => format!("{}ns", nanos),
+    }
+}
+
+// The operators over the values within a duration of the current time, which only differ in the output
+macro_rules! impl_timed {
+    ($($(#[$doc:meta])* $op:ident [$reference:ident] |$w:ident| $output:expr;)+) => {
+        $(
+            $(#[$doc])*
+            pub struct $op<T> {
+                duration: Duration,
+                inputs: Inputs<T, 2>,
+
+                window: TimeWindow,
+                i: usize,
+            }
+
+            impl<T> Clone for $op<T> {
+                fn clone(&self) -> Self {
+                    let [time, inner] = self.inputs.clone().0;
+                    Self::new(self.duration, time, inner)
+                }
+            }
+
+            impl<T> $op<T> {
+                pub fn new(duration: Duration, time: BoxOp<T>, inner: BoxOp<T>) -> Self {
+                    Self {
+                        duration,
+                        inputs: Inputs([time, inner]),
+
+                        window: TimeWindow::new(duration.as_secs_f64()),
+                        i: 0,
+                    }
+                }
+            }
+
+            impl<T> Named for $op<T> {
+                const NAME: &'static str = stringify!($op);
+            }
+
+            impl<T: TickerBatch> Operator<T> for $op<T> {
+                fn reset(&mut self) {
+                    self.inputs.reset();
+                    self.window.clear();
+                    self.i = 0;
+                }
+
+                #[throws(Error)]
+                fn evict(&mut self) {
+                    self.window.clear();
+                }
+
+                #[throws(Error)]
+                fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+                    let inputs = self.inputs.update(tb)?;
+
+                    let mut results = Vec::with_capacity(tb.len());
+
+                    for (&t, &x) in inputs[0].iter().zip(&*inputs[1]) {
+                        if self.i < self.inputs.ready_offset() {
+                            self.check_warmup(tb, &[t, x])?;
+                            results.push(f64::NAN);
+                            self.i += 1;
+                            continue;
+                        }
+
+                        if t.is_nan() || x.is_nan() {
+                            results.push(f64::NAN);
+                            continue;
+                        }
+                        if let Some(last) = self.window.last().filter(|&last| t < last) {
+                            throw!(anyhow!(
+                                "<time> for {} should be non-decreasing, got {} after {}",
+                                Self::NAME,
+                                t,
+                                last
+                            ))
+                        }
+                        self.window.push(t, x);
+
+                        let $w = &self.window;
+                        let val = $output;
+                        let val = if val.is_nan() { val } else { self.fchecked(val)? };
+
+                        #[cfg(feature = "validate")]
+                        crate::reference::validate(Self::NAME, val, || {
+                            let xs: Vec<_> = self.window.window.iter().map(|&(_, x)| x).collect();
+                            crate::reference::$reference(&xs)
+                        })?;
+
+                        results.push(val);
+                    }
+
+                    results.into()
+                }
+
+                fn ready_offset(&self) -> usize {
+                    self.inputs.ready_offset()
+                }
+
+                // The number of rows in a duration is unbounded
+                fn lookback(&self) -> Option<usize> {
+                    None
+                }
+
+                fn state_bytes(&self) -> usize {
+                    3 * self.window.len() * mem::size_of::<(f64, f64)>() + self.inputs.state_bytes()
+                }
+
+                fn to_string(&self) -> String {
+                    format!(
+                        "({} {:?} {})",
+                        Self::NAME,
+                        fmt_duration(self.duration),
+                        self.inputs.to_string()
+                    )
+                }
+
+                fn depth(&self) -> usize {
+                    1 + self.inputs.depth()
+                }
+
+                fn len(&self) -> usize {
+                    self.inputs.len() + 1
+                }
+
+                fn child_indices(&self) -> Vec<usize> {
+                    self.inputs.child_indices()
+                }
+
+                fn columns(&self) -> Vec<String> {
+                    self.inputs.columns()
+                }
+
+                #[throws(as Option)]
+                fn get(&self, i: usize) -> BoxOp<T> {
+                    if i == 0 {
+                        return self.clone().boxed();
+                    }
+                    self.inputs.get(i)?
+                }
+
+                #[throws(as Option)]
+                fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+                    if i == 0 {
+                        unreachable!("cannot insert root");
+                    }
+                    self.inputs.insert(i, op)?
+                }
+            }
+
+            impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<$op<T>> {
+                #[throws(Error)]
+                fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> $op<T> {
+                    let name = $op::<T>::NAME;
+                    let params: Vec<_> = iter.into_iter().collect();
+                    if params.len() != 3 {
+                        throw!(anyhow!("{} expect a duration and two series, got {:?}", name, params))
+                    }
+
+                    let mut iter = params.into_iter();
+                    let duration = match iter.next().unwrap() {
+                        Parameter::String(s) => to_duration(name, "duration", &s)?,
+                        p => throw!(anyhow!("<duration> for {} should be a duration like \"5s\", got {}", name, p)),
+                    };
+                    let [time, inner] = Inputs::from_params(name, ["time", "expr"], &mut iter)?.0;
+                    $op::new(duration, time, inner)
+                }
+            }
+        )+
+    };
+}
+
+impl_timed! {
+    /// The sum of the values within the duration, e.g. `(SumT "1m" :time :volume)` for the volume of the last minute.
+    /// The rows with a NaN are skipped, and produce a NaN.
+    SumT [sum] |w| w.sum;
+
+    /// The mean of the values within the duration, the same as `pd.Series.rolling("5s").mean()` over the time index.
+    /// The rows with a NaN are skipped, and produce a NaN.
+    MeanT [mean] |w| w.mean;
+
+    /// The stdev of the values within the duration, NaN for a single value and 0 for a flat window.
+    /// The rows with a NaN are skipped, and produce a NaN.
+    StdT [stdev] |w| w.std();
+
+    /// The smallest value within the duration. The rows with a NaN are skipped, and produce a NaN.
+    MinT [min] |w| w.min();
+
+    /// The largest value within the duration. The rows with a NaN are skipped, and produce a NaN.
+    MaxT [max] |w| w.max();
+
+    /// The number of values within the duration, e.g. the number of ticks in the last second for the activity.
+    /// The rows with a NaN are skipped, and produce a NaN.
+    CountT [count] |w| w.len() as f64;
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ops::from_str;
+    use arrow::{
+        array::{ArrayRef, Float64Array},
+        record_batch::RecordBatch,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn durations() {
+        let opts = Default::default();
+        let column = |vals: Vec<f64>| Arc::new(Float64Array::from(vals)) as ArrayRef;
+        let tb = RecordBatch::try_from_iter(vec![
+            ("t", column(vec![0., 0.5, 1., 1., 2.5, 10.])),
+            ("x", column(vec![1., 2., 3., 4., 5., 6.])),
+        ])
+        .unwrap();
+
+        // The window spans (t - 1s, t], the value exactly 1s before is out
+        let mut op = from_str::<RecordBatch>("(SumT \"1s\" :t :x)", &opts).unwrap();
+        assert_eq!(&*op.update(&tb).unwrap(), &[1., 3., 5., 9., 5., 6.]);
+        assert_eq!(op.to_string(), "(SumT \"1s\" :t :x)");
+
+        let mut op = from_str::<RecordBatch>("(CountT \"1.5s\" :t :x)", &opts).unwrap();
+        assert_eq!(&*op.update(&tb).unwrap(), &[1., 2., 3., 4., 1., 1.]);
+        assert_eq!(op.to_string(), "(CountT \"1500ms\" :t :x)");
+
+        let mut op = from_str::<RecordBatch>("(MaxT \"2s\" :t (Neg :x))", &opts).unwrap();
+        assert_eq!(&*op.update(&tb).unwrap(), &[-1., -1., -1., -1., -3., -6.]);
+
+        for repr in [
+            "(MeanT 5 :t :x)",
+            "(MeanT \"5\" :t :x)",
+            "(MeanT \"5s\" :x)",
+        ] {
+            assert!(from_str::<RecordBatch>(repr, &opts).is_err(), "{}", repr);
+        }
+
+        let tb = RecordBatch::try_from_iter(vec![
+            ("t", column(vec![0., 2., 1.])),
+            ("x", column(vec![1., 2., 3.])),
+        ])
+        .unwrap();
+        let mut op = from_str::<RecordBatch>("(MeanT \"5s\" :t :x)", &opts).unwrap();
+        assert!(op.update(&tb).is_err());
+    }
+}
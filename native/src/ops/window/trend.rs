@@ -0,0 +1,358 @@
+use super::{
+    super::{
+        at_child,
+        parser::{to_usize, Parameter},
+        BoxOp, Named, Operator,
+    },
+    MonotonicQueue,
+};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use std::{borrow::Cow, collections::VecDeque, iter::FromIterator, mem};
+
+/// The least squares line through the values of the window against their positions 0, 1, ..., from the sums of
+/// the values, of the values weighted by their positions and of their squares. As the window moves on, the positions
+/// go down by one, i.e. the weighted sum loses the sum. The sums are computed over again every `win_size` values
+/// so that the rounding errors do not pile up, and a flat window is told exactly.
+#[derive(Clone)]
+struct Trend {
+    win_size: usize,
+
+    window: VecDeque<f64>,
+    highest: MonotonicQueue,
+    lowest: MonotonicQueue,
+    sum: f64,
+    wsum: f64, // the values weighted by their positions
+    sqsum: f64,
+    pushed: usize,
+}
+
+impl Trend {
+    fn new(win_size: usize) -> Self {
+        Self {
+            win_size,
+
+            window: VecDeque::with_capacity(win_size),
+            highest: MonotonicQueue::max(win_size),
+            lowest: MonotonicQueue::min(win_size),
+            sum: 0.,
+            wsum: 0.,
+            sqsum: 0.,
+            pushed: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        *self = Self::new(self.win_size);
+    }
+
+    fn len(&self) -> usize {
+        self.window.len()
+    }
+
+    fn push(&mut self, y: f64) {
+        if self.window.len() == self.win_size {
+            let old = self.window.pop_front().unwrap();
+            self.sum -= old;
+            self.wsum -= self.sum;
+            self.sqsum -= old * old;
+        }
+
+        self.wsum += self.window.len() as f64 * y;
+        self.sum += y;
+        self.sqsum += y * y;
+        self.window.push_back(y);
+        self.highest.push(y);
+        self.lowest.push(y);
+        self.pushed += 1;
+
+        if self.pushed % self.win_size == 0 {
+            self.sum = self.window.iter().sum();
+            self.wsum = self
+                .window
+                .iter()
+                .enumerate()
+                .map(|(i, y)| i as f64 * y)
+                .sum();
+            self.sqsum = self.window.iter().map(|y| y * y).sum();
+        }
+    }
+
+    /// The slope and the coefficient of determination, both 0 for a flat window.
+    fn fit(&self) -> (f64, f64) {
+        let flat = self.highest.front().map(|(_, v)| v) == self.lowest.front().map(|(_, v)| v);
+        if flat {
+            return (0., 0.);
+        }
+
+        let n = self.window.len() as f64;
+        let t = n * (n - 1.) / 2.; // the sum of the positions
+        let tt = n * (n - 1.) * (2. * n - 1.) / 6.;
+        // n times the co-moments
+        let sxx = n * tt - t * t;
+        let sxy = n * self.wsum - t * self.sum;
+        let syy = n * self.sqsum - self.sum * self.sum;
+
+        let r2 = if syy > 0. {
+            (sxy * sxy / (sxx * syy)).min(1.)
+        } else {
+            0.
+        };
+        (sxy / sxx, r2)
+    }
+}
+
+// The operators over the line fitted to the window, `$output` is given its slope and its coefficient of determination
+macro_rules! impl_trend {
+    ($($(#[$doc:meta])* $op:ident |$slope:ident, $r2:ident| $output:expr;)+) => {
+        $(
+            $(#[$doc])*
+            pub struct $op<T> {
+                win_size: usize,
+                min_periods: usize,
+                inner: BoxOp<T>,
+
+                trend: Trend,
+                i: usize,
+            }
+
+            impl<T> Clone for $op<T> {
+                fn clone(&self) -> Self {
+                    Self::new(self.win_size, self.inner.clone()).with_min_periods(self.min_periods)
+                }
+            }
+
+            impl<T> $op<T> {
+                pub fn new(win_size: usize, inner: BoxOp<T>) -> Self {
+                    Self {
+                        win_size,
+                        min_periods: win_size,
+                        inner,
+
+                        trend: Trend::new(win_size),
+                        i: 0,
+                    }
+                }
+
+                pub fn with_min_periods(mut self, min_periods: usize) -> Self {
+                    self.min_periods = min_periods;
+                    self
+                }
+            }
+
+            impl<T> Named for $op<T> {
+                const NAME: &'static str = stringify!($op);
+            }
+
+            impl<T: TickerBatch> Operator<T> for $op<T> {
+                fn reset(&mut self) {
+                    self.inner.reset();
+                    self.trend.clear();
+                    self.i = 0;
+                }
+
+                #[throws(Error)]
+                fn evict(&mut self) {
+                    self.trend.clear();
+                }
+
+                #[throws(Error)]
+                fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+                    let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+
+                    let mut results = Vec::with_capacity(tb.len());
+
+                    for &val in vals {
+                        if self.i < self.inner.ready_offset() {
+                            self.check_warmup(tb, &[val])?;
+                            results.push(f64::NAN);
+                            self.i += 1;
+                            continue;
+                        }
+
+                        if val.is_nan() {
+                            results.push(f64::NAN);
+                            continue;
+                        }
+                        self.trend.push(val);
+                        if self.trend.len() < self.min_periods {
+                            results.push(f64::NAN);
+                            continue;
+                        }
+
+                        let ($slope, $r2) = self.trend.fit();
+                        let val = self.fchecked($output)?;
+
+                        #[cfg(feature = "validate")]
+                        crate::reference::validate(Self::NAME, val, || {
+                            let ys: Vec<_> = self.trend.window.iter().cloned().collect();
+                            let xs: Vec<_> = (0..ys.len()).map(|i| i as f64).collect();
+                            let ($slope, $r2) = (
+                                crate::reference::ols(&ys, &xs).1,
+                                crate::reference::correlation(&xs, &ys).powi(2),
+                            );
+                            $output
+                        })?;
+
+                        results.push(val);
+                    }
+
+                    results.into()
+                }
+
+                fn ready_offset(&self) -> usize {
+                    self.inner.ready_offset() + self.min_periods - 1
+                }
+
+                fn lookback(&self) -> Option<usize> {
+                    Some(self.inner.lookback()? + self.win_size - 1)
+                }
+
+                fn state_bytes(&self) -> usize {
+                    3 * self.win_size * mem::size_of::<(usize, f64)>() + self.inner.state_bytes()
+                }
+
+                fn to_string(&self) -> String {
+                    format!(
+                        "({} {} {})",
+                        Self::NAME,
+                        super::fmt_window(self.win_size, self.min_periods),
+                        self.inner.to_string()
+                    )
+                }
+
+                fn depth(&self) -> usize {
+                    1 + self.inner.depth()
+                }
+
+                fn len(&self) -> usize {
+                    self.inner.len() + 1
+                }
+
+                fn child_indices(&self) -> Vec<usize> {
+                    vec![1]
+                }
+
+                fn columns(&self) -> Vec<String> {
+                    self.inner.columns()
+                }
+
+                #[throws(as Option)]
+                fn get(&self, i: usize) -> BoxOp<T> {
+                    if i == 0 {
+                        return self.clone().boxed();
+                    }
+                    let i = i - 1;
+
+                    let ns = self.inner.len();
+
+                    if i < ns {
+                        self.inner.get(i)?
+                    } else {
+                        throw!()
+                    }
+                }
+
+                #[throws(as Option)]
+                fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+                    if i == 0 {
+                        unreachable!("cannot insert root");
+                    }
+                    let i = i - 1;
+
+                    let ns = self.inner.len();
+
+                    if i < ns {
+                        if i == 0 {
+                            return mem::replace(&mut self.inner, op) as BoxOp<T>;
+                        }
+                        self.inner.insert(i, op)?
+                    } else {
+                        throw!()
+                    }
+                }
+            }
+
+            impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<$op<T>> {
+                #[throws(Error)]
+                fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> $op<T> {
+                    let name = $op::<T>::NAME;
+                    let mut params: Vec<_> = iter.into_iter().collect();
+                    let min_periods = super::split_min_periods(name, &mut params, 2, 2)?;
+                    if params.len() != 2 {
+                        throw!(anyhow!("{} expect a constant and a series, got {:?}", name, params))
+                    }
+                    let k1 = params.remove(0);
+                    let k2 = params.remove(0);
+                    let op = match (k1, k2) {
+                        (Parameter::Constant(c), Parameter::Operator(s)) => {
+                            $op::new(to_usize(name, "win_size", c, 2)?, s)
+                        }
+                        (a, b) => throw!(anyhow!(
+                            "{name} expect a constant and a series, got ({name} {} {})",
+                            a,
+                            b,
+                            name = name,
+                        )),
+                    };
+
+                    match min_periods {
+                        Some(m) => op.with_min_periods(m),
+                        None => op,
+                    }
+                }
+            }
+        )+
+    };
+}
+
+impl_trend! {
+    /// The slope of the least squares line through the window against the positions, i.e. the trend per row,
+    /// 0 if the window is flat. The NaNs are skipped, and produce a NaN.
+    Slope |slope, _r2| slope;
+
+    /// The coefficient of determination of the least squares line through the window against the positions,
+    /// i.e. how well the trend explains the window, 0 if the window is flat. The NaNs are skipped, and produce a NaN.
+    TrendR2 |_slope, r2| r2;
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ops::from_str;
+    use arrow::{
+        array::{ArrayRef, Float64Array},
+        record_batch::RecordBatch,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn lines() {
+        let opts = Default::default();
+        let ys: Vec<f64> = (0..30)
+            .map(|i| if i < 20 { 1e4 + 3. * i as f64 } else { 7. })
+            .collect();
+        let tb =
+            RecordBatch::try_from_iter(vec![("y", Arc::new(Float64Array::from(ys)) as ArrayRef)])
+                .unwrap();
+
+        let mut op = from_str::<RecordBatch>("(Slope 5 :y)", &opts).unwrap();
+        let got = op.update(&tb).unwrap();
+        assert!(got[..4].iter().all(|v| v.is_nan()));
+        assert!(
+            got[4..20].iter().all(|v| (v - 3.).abs() < 1e-9),
+            "{:?}",
+            got
+        );
+        assert!(got[24..].iter().all(|&v| v == 0.), "{:?}", got);
+
+        let mut op = from_str::<RecordBatch>("(TrendR2 5 2 :y)", &opts).unwrap();
+        let got = op.update(&tb).unwrap();
+        assert!(
+            got[1..20].iter().all(|v| (v - 1.).abs() < 1e-9),
+            "{:?}",
+            got
+        );
+        assert!(got[24..].iter().all(|&v| v == 0.), "{:?}", got);
+    }
+}
@@ -0,0 +1,304 @@
+use super::super::{
+    inputs::Inputs,
+    parser::{to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
+use crate::ticker_batch::TickerBatch;
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use std::{borrow::Cow, collections::VecDeque, iter::FromIterator, mem};
+
+// The weighted means of the window over two inputs `$a` and `$b`. `$value` gives the value and its weight at a row
+// from the inputs there and at the previous row, `$lag` is the number of rows it needs before the first one.
+macro_rules! impl_weighted_mean {
+    ($($(#[$doc:meta])* $op:ident [$lag:literal] ($a:ident, $b:ident) |$prev:ident| $value:expr;)+) => {
+        $(
+            $(#[$doc])*
+            pub struct $op<T> {
+                win_size: usize,
+                min_periods: usize,
+                inputs: Inputs<T, 2>,
+
+                window: VecDeque<(f64, f64)>, // (value * weight, weight)
+                prev: [f64; 2], // the inputs at the last row without a NaN
+
+                xwsum: f64,
+                wsum: f64,
+                weighted: usize, // the number of rows with a nonzero weight in the window
+                i: usize,
+            }
+
+            impl<T> Clone for $op<T> {
+                fn clone(&self) -> Self {
+                    let [a, b] = self.inputs.clone().0;
+                    Self::new(self.win_size, a, b).with_min_periods(self.min_periods)
+                }
+            }
+
+            impl<T> $op<T> {
+                pub fn new(win_size: usize, $a: BoxOp<T>, $b: BoxOp<T>) -> Self {
+                    Self {
+                        win_size,
+                        min_periods: win_size,
+                        inputs: Inputs([$a, $b]),
+
+                        window: VecDeque::new(),
+                        prev: [f64::NAN; 2],
+                        xwsum: 0.,
+                        wsum: 0.,
+                        weighted: 0,
+                        i: 0,
+                    }
+                }
+
+                pub fn with_min_periods(mut self, min_periods: usize) -> Self {
+                    self.min_periods = min_periods;
+                    self
+                }
+
+                fn clear(&mut self) {
+                    self.window.clear();
+                    self.prev = [f64::NAN; 2];
+                    self.xwsum = 0.;
+                    self.wsum = 0.;
+                    self.weighted = 0;
+                }
+            }
+
+            impl<T> Named for $op<T> {
+                const NAME: &'static str = stringify!($op);
+            }
+
+            impl<T: TickerBatch> Operator<T> for $op<T> {
+                fn reset(&mut self) {
+                    self.inputs.reset();
+                    self.clear();
+                    self.i = 0;
+                }
+
+                #[throws(Error)]
+                fn evict(&mut self) {
+                    self.clear();
+                }
+
+                #[throws(Error)]
+                fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+                    let inputs = self.inputs.update(tb)?;
+
+                    let mut results = Vec::with_capacity(tb.len());
+
+                    for (&$a, &$b) in inputs[0].iter().zip(&*inputs[1]) {
+                        if self.i < self.inputs.ready_offset() {
+                            self.check_warmup(tb, &[$a, $b])?;
+                            results.push(f64::NAN);
+                            self.i += 1;
+                            continue;
+                        }
+                        // The rows before the first value only give the previous inputs
+                        if self.i < self.inputs.ready_offset() + $lag {
+                            if !$a.is_nan() && !$b.is_nan() {
+                                self.prev = [$a, $b];
+                            }
+                            results.push(f64::NAN);
+                            self.i += 1;
+                            continue;
+                        }
+
+                        let (x, w) = if $a.is_nan() || $b.is_nan() {
+                            (f64::NAN, f64::NAN)
+                        } else {
+                            let $prev = mem::replace(&mut self.prev, [$a, $b]);
+                            $value
+                        };
+                        let skipped = x.is_nan() || w.is_nan();
+                        // A skipped row still takes its place, so that the window spans `win_size` rows
+                        let (xw, w) = if skipped { (0., 0.) } else { (x * w, w) };
+                        self.window.push_back((xw, w));
+                        self.xwsum += xw;
+                        self.wsum += w;
+                        if w != 0. {
+                            self.weighted += 1;
+                        }
+
+                        let val = if skipped || self.window.len() < self.min_periods || self.weighted == 0 {
+                            f64::NAN
+                        } else {
+                            self.fchecked(self.xwsum / self.wsum)?
+                        };
+
+                        #[cfg(feature = "validate")]
+                        if !val.is_nan() {
+                            crate::reference::validate(Self::NAME, val, || {
+                                let (xws, ws): (Vec<_>, Vec<_>) = self.window.iter().cloned().unzip();
+                                crate::reference::sum(&xws) / crate::reference::sum(&ws)
+                            })?;
+                        }
+
+                        if self.window.len() == self.win_size {
+                            let (xw, w) = self.window.pop_front().unwrap();
+                            if w != 0. {
+                                self.weighted -= 1;
+                            }
+                            if self.weighted == 0 {
+                                // Drop the rounding errors left by the weights gone
+                                self.xwsum = 0.;
+                                self.wsum = 0.;
+                            } else {
+                                self.xwsum -= xw;
+                                self.wsum -= w;
+                            }
+                        }
+
+                        results.push(val);
+                    }
+
+                    results.into()
+                }
+
+                fn ready_offset(&self) -> usize {
+                    self.inputs.ready_offset() + $lag + self.min_periods - 1
+                }
+
+                fn lookback(&self) -> Option<usize> {
+                    Some(self.inputs.lookback()? + $lag + self.win_size - 1)
+                }
+
+                fn state_bytes(&self) -> usize {
+                    self.win_size * mem::size_of::<(f64, f64)>() + self.inputs.state_bytes()
+                }
+
+                fn to_string(&self) -> String {
+                    format!(
+                        "({} {} {})",
+                        Self::NAME,
+                        super::fmt_window(self.win_size, self.min_periods),
+                        self.inputs.to_string()
+                    )
+                }
+
+                fn depth(&self) -> usize {
+                    1 + self.inputs.depth()
+                }
+
+                fn len(&self) -> usize {
+                    self.inputs.len() + 1
+                }
+
+                fn child_indices(&self) -> Vec<usize> {
+                    self.inputs.child_indices()
+                }
+
+                fn columns(&self) -> Vec<String> {
+                    self.inputs.columns()
+                }
+
+                #[throws(as Option)]
+                fn get(&self, i: usize) -> BoxOp<T> {
+                    if i == 0 {
+                        return self.clone().boxed();
+                    }
+                    self.inputs.get(i)?
+                }
+
+                #[throws(as Option)]
+                fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+                    if i == 0 {
+                        unreachable!("cannot insert root");
+                    }
+                    self.inputs.insert(i, op)?
+                }
+            }
+
+            impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<$op<T>> {
+                #[throws(Error)]
+                fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> $op<T> {
+                    let name = $op::<T>::NAME;
+                    let mut params: Vec<_> = iter.into_iter().collect();
+                    let min_periods = super::split_min_periods(name, &mut params, 3, 1)?;
+                    if params.len() != 3 {
+                        throw!(anyhow!(
+                            "{} expect a constant and two series, got {:?}",
+                            name,
+                            params
+                        ))
+                    }
+
+                    let mut iter = params.into_iter();
+                    let Some(Parameter::Constant(n)) = iter.next() else {
+                        throw!(anyhow!("<win_size> for {} should be an constant", name));
+                    };
+                    let [a, b] = Inputs::from_params(
+                        name,
+                        [stringify!($a), stringify!($b)],
+                        &mut iter,
+                    )?
+                    .0;
+
+                    let op = $op::new(to_usize(name, "win_size", n, 1)?, a, b);
+                    match min_periods {
+                        Some(m) => op.with_min_periods(m),
+                        None => op,
+                    }
+                }
+            }
+        )+
+    };
+}
+
+impl_weighted_mean! {
+    /// The volume weighted average price of the window, `sum(price * volume) / sum(volume)`,
+    /// NaN if the window has no volume. The bars with a NaN are left out of the sums, and produce a NaN.
+    VWAP [0] (price, volume) |_prev| (price, volume);
+
+    /// The time weighted average price of the window, each price weighted by the time it stood, i.e. until
+    /// the next observation, over the last `win_size` intervals of the (non-decreasing) time.
+    /// NaN if no time passed in the window. The bars with a NaN are skipped, and produce a NaN.
+    TWAP [1] (time, price) |prev| {
+        let [prev_time, prev_price] = prev;
+        if time < prev_time {
+            throw!(anyhow!(
+                "<time> for TWAP should be non-decreasing, got {} after {}",
+                time,
+                prev_time
+            ))
+        }
+        (prev_price, time - prev_time)
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ops::from_str;
+    use arrow::{
+        array::{ArrayRef, Float64Array},
+        record_batch::RecordBatch,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn time_weights() {
+        let opts = Default::default();
+        let column = |vals: Vec<f64>| Arc::new(Float64Array::from(vals)) as ArrayRef;
+        let tb = RecordBatch::try_from_iter(vec![
+            ("t", column(vec![0., 1., 1., 4., 4., 4.])),
+            ("p", column(vec![1., 2., 3., 4., 5., 6.])),
+        ])
+        .unwrap();
+
+        // The prices of the zero intervals weigh nothing, and the window without time passing gives a NaN
+        let mut op = from_str::<RecordBatch>("(TWAP 2 :t :p)", &opts).unwrap();
+        let got = op.update(&tb).unwrap();
+        let expected = [f64::NAN, f64::NAN, 1., 3., 3., f64::NAN];
+        for (g, e) in got.iter().zip(&expected) {
+            assert!(g == e || g.is_nan() && e.is_nan(), "{:?}", got);
+        }
+
+        let tb = RecordBatch::try_from_iter(vec![
+            ("t", column(vec![0., 2., 1.])),
+            ("p", column(vec![1., 2., 3.])),
+        ])
+        .unwrap();
+        let mut op = from_str::<RecordBatch>("(TWAP 2 :t :p)", &opts).unwrap();
+        assert!(op.update(&tb).is_err());
+    }
+}
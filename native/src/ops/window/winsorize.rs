@@ -0,0 +1,246 @@
+use super::super::{
+    at_child,
+    constant::fmt_constant,
+    parser::{to_fraction, to_usize, Parameter},
+    BoxOp, Named, Operator,
+};
+use crate::{
+    float::{Ascending, Float, IntoFloat},
+    ticker_batch::TickerBatch,
+};
+use anyhow::{anyhow, Error, Result};
+use fehler::{throw, throws};
+use order_stats_tree::OSTree;
+use std::{borrow::Cow, collections::VecDeque, iter::FromIterator, mem};
+
+/// The current value clamped into the `lower` and `upper` quantiles of the window, which are taken the same way as
+/// `Quantile`, e.g. `(Winsorize 100 0.05 0.95 :ret)` caps the returns beyond the 5% tails of the last 100.
+pub struct Winsorize<T> {
+    win_size: usize,
+    min_periods: usize,
+    lower: f64,
+    upper: f64,
+    inner: BoxOp<T>,
+
+    window: VecDeque<f64>,
+    ostree: OSTree<Float<Ascending>>, // sorted window
+    i: usize,
+}
+
+impl<T> Clone for Winsorize<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.win_size, self.lower, self.upper, self.inner.clone())
+            .with_min_periods(self.min_periods)
+    }
+}
+
+impl<T> Winsorize<T> {
+    /// The quantiles are swapped if `lower > upper`.
+    pub fn new(win_size: usize, lower: f64, upper: f64, inner: BoxOp<T>) -> Self {
+        assert!(0. <= lower && lower <= 1. && 0. <= upper && upper <= 1.);
+        let (lower, upper) = if lower > upper {
+            (upper, lower)
+        } else {
+            (lower, upper)
+        };
+        Self {
+            win_size,
+            min_periods: win_size,
+            lower,
+            upper,
+            inner,
+
+            window: VecDeque::with_capacity(win_size),
+            ostree: OSTree::new(),
+            i: 0,
+        }
+    }
+
+    pub fn with_min_periods(mut self, min_periods: usize) -> Self {
+        self.min_periods = min_periods;
+        self
+    }
+
+    // The `q` quantile of the window
+    fn quantile(&self, q: f64) -> f64 {
+        let r = ((self.window.len() - 1) as f64 * q).floor() as usize;
+        let (v, _) = self.ostree.select(r).unwrap();
+        v.0
+    }
+}
+
+impl<T> Named for Winsorize<T> {
+    const NAME: &'static str = "Winsorize";
+}
+
+impl<T: TickerBatch> Operator<T> for Winsorize<T> {
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.window.clear();
+        self.ostree.clear();
+        self.i = 0;
+    }
+
+    #[throws(Error)]
+    fn evict(&mut self) {
+        self.window.clear();
+        self.ostree.clear();
+    }
+
+    #[throws(Error)]
+    fn update<'a>(&mut self, tb: &'a T) -> Cow<'a, [f64]> {
+        let vals = &*self.inner.checked_update(tb).map_err(at_child(1))?;
+
+        let mut results = Vec::with_capacity(tb.len());
+
+        for &val in vals {
+            if self.i < self.inner.ready_offset() {
+                self.check_warmup(tb, &[val])?;
+                results.push(f64::NAN);
+                self.i += 1;
+                continue;
+            }
+
+            self.window.push_back(val);
+            self.ostree.increase(val.asc(), 1);
+            let val = if self.window.len() >= self.min_periods {
+                let (lo, hi) = (self.quantile(self.lower), self.quantile(self.upper));
+                self.fchecked(val.max(lo).min(hi))?
+            } else {
+                f64::NAN
+            };
+
+            #[cfg(feature = "validate")]
+            crate::reference::validate(Self::NAME, val, || {
+                crate::reference::winsorize(self.window.make_contiguous(), self.lower, self.upper)
+            })?;
+
+            if self.window.len() == self.win_size {
+                let to_remove = self.window.pop_front().unwrap().asc();
+                self.ostree.decrease(&to_remove, 1);
+            }
+            results.push(val);
+        }
+
+        results.into()
+    }
+
+    fn ready_offset(&self) -> usize {
+        self.inner.ready_offset() + self.min_periods - 1
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.inner.lookback()? + self.win_size - 1)
+    }
+
+    fn state_bytes(&self) -> usize {
+        2 * self.win_size * mem::size_of::<f64>() + self.inner.state_bytes()
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "({} {} {} {} {})",
+            Self::NAME,
+            super::fmt_window(self.win_size, self.min_periods),
+            fmt_constant(self.lower),
+            fmt_constant(self.upper),
+            self.inner.to_string(),
+        )
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.inner.depth()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len() + 1
+    }
+
+    fn child_indices(&self) -> Vec<usize> {
+        vec![1]
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.inner.columns()
+    }
+
+    #[throws(as Option)]
+    fn get(&self, i: usize) -> BoxOp<T> {
+        if i == 0 {
+            return self.clone().boxed();
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            self.inner.get(i)?
+        } else {
+            throw!()
+        }
+    }
+
+    #[throws(as Option)]
+    fn insert(&mut self, i: usize, op: BoxOp<T>) -> BoxOp<T> {
+        if i == 0 {
+            unreachable!("cannot insert root");
+        }
+        let i = i - 1;
+
+        let ns = self.inner.len();
+
+        if i < ns {
+            if i == 0 {
+                return mem::replace(&mut self.inner, op) as BoxOp<T>;
+            }
+            self.inner.insert(i, op)?
+        } else {
+            throw!()
+        }
+    }
+}
+
+impl<T: TickerBatch> FromIterator<Parameter<T>> for Result<Winsorize<T>> {
+    #[throws(Error)]
+    fn from_iter<A: IntoIterator<Item = Parameter<T>>>(iter: A) -> Winsorize<T> {
+        let name = Winsorize::<T>::NAME;
+        let mut params: Vec<_> = iter.into_iter().collect();
+        let min_periods = super::split_min_periods(name, &mut params, 4, 1)?;
+        if params.len() != 4 {
+            throw!(anyhow!(
+                "{} expect three constants and one series, got {:?}",
+                name,
+                params
+            ))
+        }
+        let k1 = params.remove(0);
+        let k2 = params.remove(0);
+        let k3 = params.remove(0);
+        let k4 = params.remove(0);
+        let op = match (k1, k2, k3, k4) {
+            (
+                Parameter::Constant(c),
+                Parameter::Constant(lower),
+                Parameter::Constant(upper),
+                Parameter::Operator(s),
+            ) => {
+                let lower = to_fraction(name, "lower", lower)?;
+                let upper = to_fraction(name, "upper", upper)?;
+                Winsorize::new(to_usize(name, "win_size", c, 1)?, lower, upper, s)
+            }
+            (a, b, c, d) => throw!(anyhow!(
+                "{name} expect three constants and a series, got ({name} {} {} {} {})",
+                a,
+                b,
+                c,
+                d,
+                name = name,
+            )),
+        };
+
+        match min_periods {
+            Some(m) => op.with_min_periods(m),
+            None => op,
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use crate::{
+    ops::Operator,
+    replay::{prefetch, PREFETCH_BATCHES},
+};
+use anyhow::{Error, Result};
+use arrow::{array::Float64Array, error::ArrowError, record_batch::RecordBatch};
+use core_affinity::CoreId;
+use fehler::throws;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::{
+    cmp::max,
+    collections::HashMap,
+    fs,
+    sync::{mpsc, Mutex, PoisonError},
+    thread,
+};
+
+pub type ReplayOutput = (HashMap<usize, Float64Array>, HashMap<usize, Error>);
+
+/// How the replay workers are laid out on the machine.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Placement {
+    /// Pin every rayon worker to a core.
+    pub pin_threads: bool,
+    /// Partition the factors by NUMA node, each partition is replayed by a pool living on that node.
+    pub numa: bool,
+}
+
+/// The cpu ids of each NUMA node. Falls back to a single node holding all the cores
+/// if the topology is not available (e.g. non-Linux hosts).
+pub fn numa_nodes() -> Vec<Vec<usize>> {
+    let mut nodes: Vec<(usize, Vec<usize>)> = fs::read_dir("/sys/devices/system/node")
+        .map(|entries| {
+            entries
+                .filter_map(|entry| {
+                    let entry = entry.ok()?;
+                    let name = entry.file_name().into_string().ok()?;
+                    let id = name.strip_prefix("node")?.parse().ok()?;
+                    let cpulist = fs::read_to_string(entry.path().join("cpulist")).ok()?;
+                    Some((id, parse_cpulist(cpulist.trim())))
+                })
+                .filter(|(_, cpus)| !cpus.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+    nodes.sort_by_key(|(id, _)| *id);
+
+    if nodes.is_empty() {
+        let cores = core_affinity::get_core_ids()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|c| c.id)
+            .collect();
+        return vec![cores];
+    }
+
+    nodes.into_iter().map(|(_, cpus)| cpus).collect()
+}
+
+// Parses the kernel cpulist format, e.g. "0-15,32-47"
+fn parse_cpulist(s: &str) -> Vec<usize> {
+    s.split(',')
+        .filter(|r| !r.is_empty())
+        .flat_map(|r| {
+            let mut bounds = r.splitn(2, '-');
+            let lo: Option<usize> = bounds.next().and_then(|v| v.parse().ok());
+            let hi = bounds.next().map(|v| v.parse().ok()).unwrap_or(lo);
+            match (lo, hi) {
+                (Some(lo), Some(hi)) => lo..=hi,
+                _ => 1..=0,
+            }
+        })
+        .collect()
+}
+
+// The cores pinned by the pools alive. The replays running at the same time, e.g. the datasets replayed
+// in parallel by `replay_iter`, would otherwise all pin their workers onto the first cores.
+static PINNED: Mutex<Vec<usize>> = Mutex::new(vec![]);
+
+/// The cores a pool pins its workers to, taken for the lifetime of the pool and given back on drop.
+pub struct Lease(Vec<usize>);
+
+impl Lease {
+    /// Take up to `n` of `cores` which are not pinned by another pool, none if they are all taken.
+    pub fn take(cores: &[usize], n: usize) -> Self {
+        let mut pinned = PINNED.lock().unwrap_or_else(PoisonError::into_inner);
+        let free: Vec<_> = cores
+            .iter()
+            .copied()
+            .filter(|c| !pinned.contains(c))
+            .take(n)
+            .collect();
+        pinned.extend(&free);
+        Lease(free)
+    }
+
+    /// No core, the workers are not pinned.
+    pub fn none() -> Self {
+        Lease(vec![])
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        let mut pinned = PINNED.lock().unwrap_or_else(PoisonError::into_inner);
+        pinned.retain(|c| !self.0.contains(c));
+    }
+}
+
+/// Build a pool with `njobs` workers, pinning the i-th worker to the i-th core of the lease.
+/// The workers left without a core are not pinned, rather than doubling up on the leased cores.
+#[throws(Error)]
+pub fn build_pool(njobs: usize, lease: &Lease) -> ThreadPool {
+    let mut builder = ThreadPoolBuilder::new().num_threads(njobs);
+    if !lease.0.is_empty() {
+        let cores = lease.0.clone();
+        builder = builder.start_handler(move |i| {
+            if let Some(&id) = cores.get(i) {
+                core_affinity::set_for_current(CoreId { id });
+            }
+        });
+    }
+    builder.build()?
+}
+
+/// Run `f` over the operators and the batches according to the placement.
+/// With `numa` on, the operators are split round-robin into one group per node
+/// and the returned indices are mapped back to the positions in `ops`. The batches
+/// are decoded once and handed to every group.
+#[throws(Error)]
+pub fn run<I, F>(
+    ops: Vec<&mut dyn Operator<RecordBatch>>,
+    njobs: usize,
+    placement: Placement,
+    batches: I,
+    f: F,
+) -> ReplayOutput
+where
+    I: Iterator<Item = Result<RecordBatch, ArrowError>> + Send,
+    F: Fn(
+            Vec<&mut dyn Operator<RecordBatch>>,
+            &mut dyn Iterator<Item = RecordBatch>,
+        ) -> Result<ReplayOutput>
+        + Sync,
+{
+    let lease_for = |cores: &[usize], njobs: usize| {
+        if placement.pin_threads {
+            Lease::take(cores, njobs)
+        } else {
+            Lease::none()
+        }
+    };
+
+    if !placement.numa {
+        let lease = lease_for(&numa_nodes().concat(), njobs);
+        let pool = build_pool(njobs, &lease)?;
+        return pool.install(|| prefetch(batches, |batches| f(ops, batches)))??;
+    }
+
+    let nodes = numa_nodes();
+    let njobs = max(1, njobs / nodes.len());
+
+    let mut groups: Vec<Vec<_>> = nodes.iter().map(|_| vec![]).collect();
+    for (i, op) in ops.into_iter().enumerate() {
+        groups[i % nodes.len()].push((i, op));
+    }
+
+    let f = &f;
+    let lease_for = &lease_for;
+    let outputs = prefetch(batches, |batches| {
+        thread::scope(|s| {
+            let mut senders = vec![];
+            let handles: Vec<_> = groups
+                .into_iter()
+                .zip(nodes)
+                .filter(|(group, _)| !group.is_empty())
+                .map(|(group, cores)| {
+                    let (tx, rx) = mpsc::sync_channel(PREFETCH_BATCHES);
+                    senders.push(tx);
+                    s.spawn(move || -> Result<_> {
+                        let (indices, ops): (Vec<usize>, Vec<_>) = group.into_iter().unzip();
+                        let lease = lease_for(&cores, njobs);
+                        let pool = build_pool(njobs, &lease)?;
+                        let output = pool.install(|| f(ops, &mut rx.into_iter()))?;
+                        Ok((indices, output))
+                    })
+                })
+                .collect();
+
+            // A group which failed has stopped taking the batches
+            for batch in batches {
+                senders.retain(|tx| tx.send(batch.clone()).is_ok());
+                if senders.is_empty() {
+                    break;
+                }
+            }
+            drop(senders);
+
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("replay thread panicked"))
+                .collect::<Result<Vec<_>>>()
+        })
+    })??;
+
+    let mut succeeded = HashMap::new();
+    let mut failed = HashMap::new();
+    for (indices, (s, e)) in outputs {
+        succeeded.extend(s.into_iter().map(|(i, v)| (indices[i], v)));
+        failed.extend(e.into_iter().map(|(i, v)| (indices[i], v)));
+    }
+
+    (succeeded, failed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::Lease;
+
+    #[test]
+    fn disjoint_leases() {
+        // far from the cores of the machine, which the other tests may pin
+        let cores: Vec<_> = (10000..10004).collect();
+        let a = Lease::take(&cores, 3);
+        let b = Lease::take(&cores, 3);
+        assert_eq!(a.0, vec![10000, 10001, 10002]);
+        assert_eq!(b.0, vec![10003]);
+        assert!(Lease::take(&cores, 3).0.is_empty());
+
+        drop(a);
+        assert_eq!(Lease::take(&cores, 2).0, vec![10000, 10001]);
+    }
+}
@@ -1,45 +1,244 @@
-use super::ops::{from_str, Operator};
-use anyhow::Result;
+use super::{
+    ops::{
+        canonicalize, crossover, define_macro, diff, fold_constants, from_infix, from_str, lint,
+        mutate, path_of, pretty, prune, random, resolve_path, round_constants, scale_windows,
+        signatures, simplify, substitute, to_dot, undefine_macro, BoxOp, Kind, Mutation, Operator,
+        ParseError as ExprError, ParseOptions, Syntax,
+    },
+    pool::{self, Placement},
+};
+use anyhow::Error;
 use arrow::{
-    array::{make_array, Array},
+    array::{make_array, new_null_array, ArrayRef, Float64Array},
+    compute::cast,
     datatypes::{DataType, Field, Schema},
     ffi::{self, FFI_ArrowArray, FFI_ArrowSchema},
-    record_batch::RecordBatch,
+    ffi_stream::FFI_ArrowArrayStream,
+    record_batch::{RecordBatch, RecordBatchIterator, RecordBatchOptions},
 };
 use dict_derive::IntoPyObject;
 use fehler::throw;
-use pyo3::{class::basic::CompareOp, exceptions::PyValueError, prelude::*};
+use itertools::Itertools;
+use pyo3::{
+    class::basic::CompareOp,
+    create_exception,
+    exceptions::PyValueError,
+    prelude::*,
+    types::{PyCapsule, PyDict},
+};
 use std::{
     borrow::Cow,
     collections::{hash_map::DefaultHasher, HashMap},
     convert::TryFrom,
+    ffi::CString,
     hash::{Hash, Hasher},
     sync::Arc,
 };
 
-// *mut FFI_ArrowArray, *mut FFI_ArrowSchema
-type ArrowFFIPtr = (usize, usize);
-
 #[derive(IntoPyObject)]
 pub struct ReplayResult {
-    succeeded: HashMap<usize, ArrowFFIPtr>,
+    stream: ArrowStream,
     failed: HashMap<usize, String>,
 }
 
+impl ReplayResult {
+    // Assemble one column per factor, failed factors are filled with nulls.
+    // The columns flagged in `predicates` are turned into booleans.
+    fn new(
+        names: Vec<String>,
+        predicates: Vec<bool>,
+        nrows: usize,
+        mut succeeded: HashMap<usize, Float64Array>,
+        failed: HashMap<usize, Error>,
+    ) -> PyResult<Self> {
+        let mut fields = vec![];
+        let mut columns = vec![];
+        for (i, (name, predicate)) in names.into_iter().zip(predicates).enumerate() {
+            let dt = if predicate {
+                DataType::Boolean
+            } else {
+                DataType::Float64
+            };
+            let column: ArrayRef = match succeeded.remove(&i) {
+                Some(arr) if predicate => {
+                    cast(&arr, &dt).map_err(|e| PyValueError::new_err(format!("{}", e)))?
+                }
+                Some(arr) => Arc::new(arr),
+                None => new_null_array(&dt, nrows),
+            };
+            fields.push(Field::new(name, dt, true));
+            columns.push(column);
+        }
+
+        let schema = Arc::new(Schema::new(fields));
+        let options = RecordBatchOptions::new().with_row_count(Some(nrows));
+        let rb = RecordBatch::try_new_with_options(schema.clone(), columns, &options)
+            .map_err(|e| PyValueError::new_err(format!("{}", e)))?;
+        let reader = RecordBatchIterator::new(vec![Ok(rb)], schema);
+
+        Ok(ReplayResult {
+            stream: ArrowStream {
+                stream: Some(FFI_ArrowArrayStream::new(Box::new(reader))),
+            },
+            failed: failed
+                .into_iter()
+                .map(|(k, v)| (k, format!("{}", v)))
+                .collect(),
+        })
+    }
+}
+
+/// The replay result exported through the Arrow PyCapsule interface.
+/// The stream is released on drop if nobody consumed it.
+#[pyclass]
+pub struct ArrowStream {
+    stream: Option<FFI_ArrowArrayStream>,
+}
+
+#[pymethods]
+impl ArrowStream {
+    #[pyo3(signature = (requested_schema = None))]
+    fn __arrow_c_stream__(
+        &mut self,
+        py: Python,
+        requested_schema: Option<PyObject>,
+    ) -> PyResult<PyObject> {
+        let _ = requested_schema;
+        let stream = self
+            .stream
+            .take()
+            .ok_or_else(|| PyValueError::new_err("the stream is already consumed"))?;
+        let name = CString::new("arrow_array_stream").unwrap();
+        Ok(PyCapsule::new(py, stream, Some(name))?.into_py(py))
+    }
+}
+
+create_exception!(
+    factor_expr,
+    ParseError,
+    PyValueError,
+    "An expression failed to parse. `span` is the (start, end) range of chars in the expression \
+     that caused the error and `fragment` is the text of that range, both are None if unknown."
+);
+
+// Raise a `ParseError` carrying the span of the offending part of the expression
+fn parse_error(py: Python, e: Error) -> PyErr {
+    let (message, span, fragment) = match e.downcast::<ExprError>() {
+        Ok(e) => (e.to_string(), e.span, e.fragment),
+        Err(e) => (e.to_string(), None, None),
+    };
+    let err = ParseError::new_err(message);
+    let value = err.value(py);
+    if let Err(e) = value
+        .setattr("span", span)
+        .and_then(|_| value.setattr("fragment", fragment))
+    {
+        return e;
+    }
+    err
+}
+
 #[pyclass]
 pub struct Factor {
     op: Box<dyn Operator<RecordBatch>>,
+    name: Option<String>,
+    tags: HashMap<String, String>,
+}
+
+impl From<BoxOp<RecordBatch>> for Factor {
+    fn from(op: BoxOp<RecordBatch>) -> Self {
+        Factor {
+            op,
+            name: None,
+            tags: HashMap::new(),
+        }
+    }
+}
+
+impl Factor {
+    // A factor computing the same values, which keeps the name and the tags
+    fn derive(&self, op: BoxOp<RecordBatch>) -> Factor {
+        Factor {
+            op,
+            name: self.name.clone(),
+            tags: self.tags.clone(),
+        }
+    }
+
+    // The name of the output column in a replay
+    fn column_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.op.to_string())
+    }
 }
 
 #[pymethods]
 impl Factor {
     #[new]
-    pub fn new(sexpr: &str) -> PyResult<Self> {
+    #[pyo3(signature = (sexpr, zero = "epsilon", nan = "false", syntax = "sexpr", name = None, tags = None))]
+    pub fn new(
+        py: Python,
+        sexpr: &str,
+        zero: &str,
+        nan: &str,
+        syntax: &str,
+        name: Option<String>,
+        tags: Option<HashMap<String, String>>,
+    ) -> PyResult<Self> {
+        let opts = ParseOptions {
+            zero: zero
+                .parse()
+                .map_err(|e| PyValueError::new_err(format!("{}", e)))?,
+            nan: nan
+                .parse()
+                .map_err(|e| PyValueError::new_err(format!("{}", e)))?,
+        };
+        let syntax: Syntax = syntax
+            .parse()
+            .map_err(|e| PyValueError::new_err(format!("{}", e)))?;
+        let op = match syntax {
+            Syntax::Sexpr => from_str(sexpr, &opts),
+            Syntax::Infix => from_infix(sexpr, &opts),
+        };
         Ok(Self {
-            op: from_str(sexpr).map_err(|e| PyValueError::new_err(format!("{}", e)))?,
+            op: op.map_err(|e| parse_error(py, e))?,
+            name,
+            tags: tags.unwrap_or_default(),
         })
     }
 
+    #[getter]
+    pub fn name(&self) -> Option<String> {
+        self.name.clone()
+    }
+
+    #[setter]
+    pub fn set_name(&mut self, name: Option<String>) {
+        self.name = name;
+    }
+
+    #[getter]
+    pub fn tags(&self) -> HashMap<String, String> {
+        self.tags.clone()
+    }
+
+    #[setter]
+    pub fn set_tags(&mut self, tags: HashMap<String, String>) {
+        self.tags = tags;
+    }
+
+    #[staticmethod]
+    #[pyo3(signature = (columns, max_depth = 4, seed = 0, weights = None))]
+    pub fn random(
+        columns: Vec<String>,
+        max_depth: usize,
+        seed: u64,
+        weights: Option<HashMap<String, f64>>,
+    ) -> PyResult<Factor> {
+        let op = random(&columns, max_depth, seed, &weights.unwrap_or_default())
+            .map_err(|e| PyValueError::new_err(format!("{}", e)))?;
+        Ok(Factor::from(op))
+    }
+
     pub fn ready_offset(&self) -> usize {
         self.op.ready_offset()
     }
@@ -48,18 +247,153 @@ impl Factor {
         self.op.reset()
     }
 
+    pub fn lookback(&self) -> Option<usize> {
+        self.op.lookback()
+    }
+
+    pub fn state_bytes(&self) -> usize {
+        self.op.state_bytes()
+    }
+
+    pub fn is_predicate(&self) -> bool {
+        self.op.is_predicate()
+    }
+
+    pub fn validate(&self, columns: Vec<String>) -> PyResult<()> {
+        // only the names are known, so the columns are taken as floats
+        let fields: Vec<_> = columns
+            .into_iter()
+            .map(|name| Field::new(name, DataType::Float64, true))
+            .collect();
+        self.op
+            .validate(&Schema::new(fields))
+            .map_err(|e| PyValueError::new_err(format!("{}", e)))
+    }
+
     pub fn replace<'p>(&self, i: usize, other: PyRef<'p, Factor>) -> PyResult<Factor> {
         if i == 0 {
-            return Ok(Factor {
-                op: other.op.clone(),
-            });
+            return Ok(self.derive(other.op.clone()));
         }
 
         let mut op = self.op.clone();
         let _ = op
             .insert(i, other.op.clone())
             .ok_or_else(|| PyValueError::new_err(format!("idx {} overflows", i)))?;
-        Ok(Factor { op })
+        Ok(self.derive(op))
+    }
+
+    pub fn get_path(&self, path: &str) -> PyResult<Factor> {
+        let i = resolve_path(&*self.op, path).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        self.__getitem__(i as isize)
+    }
+
+    pub fn replace_path<'p>(&self, path: &str, other: PyRef<'p, Factor>) -> PyResult<Factor> {
+        let i = resolve_path(&*self.op, path).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        self.replace(i, other)
+    }
+
+    pub fn path_of(&self, i: usize) -> PyResult<String> {
+        path_of(&*self.op, i).ok_or_else(|| PyValueError::new_err(format!("idx {} overflows", i)))
+    }
+
+    #[pyo3(signature = (**values))]
+    pub fn bind(&self, py: Python, values: Option<&PyDict>) -> PyResult<Factor> {
+        let values: HashMap<String, f64> = match values {
+            Some(values) => values.extract()?,
+            None => HashMap::new(),
+        };
+        let op = self.op.bind(&values).map_err(|e| parse_error(py, e))?;
+        Ok(Factor::from(op))
+    }
+
+    #[pyo3(signature = (i, replacement = None))]
+    pub fn prune(&mut self, i: usize, replacement: Option<PyRef<Factor>>) -> PyResult<Factor> {
+        let op = prune(&mut self.op, i, replacement.map(|r| r.op.clone()))
+            .map_err(|e| PyValueError::new_err(format!("{}", e)))?;
+        Ok(Factor::from(op))
+    }
+
+    pub fn optimize(&self) -> PyResult<Factor> {
+        let op =
+            fold_constants(self.op.clone()).map_err(|e| PyValueError::new_err(format!("{}", e)))?;
+        Ok(self.derive(op))
+    }
+
+    pub fn canonical(&self) -> Factor {
+        self.derive(canonicalize(self.op.clone()))
+    }
+
+    pub fn semantic_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        canonicalize(self.op.clone()).to_string().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    pub fn equivalent(&self, other: PyRef<Factor>) -> bool {
+        canonicalize(self.op.clone()).to_string() == canonicalize(other.op.clone()).to_string()
+    }
+
+    pub fn simplify(&self) -> Factor {
+        self.derive(simplify(self.op.clone()))
+    }
+
+    #[pyo3(signature = (other, seed = 0))]
+    pub fn crossover(&self, other: PyRef<Factor>, seed: u64) -> PyResult<Factor> {
+        let op = crossover(&self.op, &other.op, seed)
+            .map_err(|e| PyValueError::new_err(format!("{}", e)))?;
+        Ok(Factor::from(op))
+    }
+
+    #[pyo3(signature = (kind, seed = 0))]
+    pub fn mutate(&self, kind: &str, seed: u64) -> PyResult<Factor> {
+        let mutation: Mutation = kind
+            .parse()
+            .map_err(|e| PyValueError::new_err(format!("{}", e)))?;
+        let op = mutate(&self.op, mutation, seed)
+            .map_err(|e| PyValueError::new_err(format!("{}", e)))?;
+        Ok(Factor::from(op))
+    }
+
+    pub fn diff(&self, other: PyRef<Factor>) -> Vec<DifferenceInfo> {
+        diff(&*self.op, &*other.op)
+            .into_iter()
+            .map(|d| DifferenceInfo {
+                kind: d.kind.to_string(),
+                index: d.index,
+                other: d.other,
+                before: d.before,
+                after: d.after,
+            })
+            .collect()
+    }
+
+    pub fn lint(&self) -> Vec<WarningInfo> {
+        lint(&*self.op)
+            .into_iter()
+            .map(|w| WarningInfo {
+                index: w.index,
+                node: w.node,
+                message: w.message,
+            })
+            .collect()
+    }
+
+    pub fn remap_columns(&self, mapping: HashMap<String, String>) -> Factor {
+        let mut op = self.op.clone();
+        op.remap_columns(&mapping);
+        self.derive(op)
+    }
+
+    pub fn substitute<'p>(&self, column: &str, other: PyRef<'p, Factor>) -> PyResult<Factor> {
+        substitute(self.op.clone(), column, &other.op)
+            .map(|op| self.derive(op))
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    pub fn scale_windows(&self, k: f64) -> PyResult<Factor> {
+        scale_windows(&self.op, k)
+            .map(|op| self.derive(op))
+            .map_err(|e| PyValueError::new_err(e.to_string()))
     }
 
     pub fn depth(&self) -> usize {
@@ -74,10 +408,26 @@ impl Factor {
         self.op.columns()
     }
 
+    pub fn subtrees(&self) -> Vec<(usize, Factor)> {
+        (0..self.op.len())
+            .filter_map(|i| self.op.get(i).map(|op| (i, Factor::from(op))))
+            .collect()
+    }
+
+    pub fn leaves(&self) -> Vec<Factor> {
+        self.subtrees()
+            .into_iter()
+            .map(|(_, f)| f)
+            .filter(|f| f.op.child_indices().is_empty())
+            .collect()
+    }
+
+    pub fn to_dot(&self) -> String {
+        to_dot(&*self.op)
+    }
+
     pub fn clone(&self) -> Factor {
-        Factor {
-            op: self.op.clone(),
-        }
+        self.derive(self.op.clone())
     }
 
     fn __len__(&self) -> usize {
@@ -89,12 +439,46 @@ impl Factor {
             throw!(PyValueError::new_err(format!("idx {} less than 0", idx)))
         }
 
-        Ok(Factor {
-            op: self
-                .op
-                .get(idx as usize)
-                .ok_or_else(|| PyValueError::new_err(format!("idx {} overflows", idx)))?,
-        })
+        Ok(Factor::from(self.op.get(idx as usize).ok_or_else(
+            || PyValueError::new_err(format!("idx {} overflows", idx)),
+        )?))
+    }
+
+    #[pyo3(signature = (indent = 2))]
+    pub fn pretty(&self, indent: usize) -> String {
+        pretty(&self.op.to_string(), indent)
+    }
+
+    /// The S-expression, `str(self)` unless the constants are rounded to `precision` significant digits
+    #[pyo3(signature = (precision = None))]
+    pub fn to_string(&self, precision: Option<usize>) -> PyResult<String> {
+        match precision {
+            Some(0) => Err(PyValueError::new_err("precision should be at least 1")),
+            Some(digits) => Ok(round_constants(&self.op.to_string(), digits)),
+            None => Ok(self.op.to_string()),
+        }
+    }
+
+    // Pickled as the expression along with the name and the tags
+    fn __reduce__(
+        &self,
+        py: Python,
+    ) -> (
+        PyObject,
+        (String,),
+        (Option<String>, HashMap<String, String>),
+    ) {
+        (
+            py.get_type::<Factor>().to_object(py),
+            (self.op.to_string(),),
+            (self.name.clone(), self.tags.clone()),
+        )
+    }
+
+    fn __setstate__(&mut self, state: (Option<String>, HashMap<String, String>)) {
+        let (name, tags) = state;
+        self.name = name;
+        self.tags = tags;
     }
 
     fn __str__(&self) -> PyResult<String> {
@@ -126,21 +510,110 @@ impl Factor {
     }
 }
 
+#[derive(IntoPyObject)]
+pub struct ParamInfo {
+    name: String,
+    kind: String,
+    optional: bool,
+    min: Option<usize>,
+}
+
+#[derive(IntoPyObject)]
+pub struct DifferenceInfo {
+    kind: String,
+    index: usize,
+    other: usize,
+    before: String,
+    after: String,
+}
+
+#[derive(IntoPyObject)]
+pub struct WarningInfo {
+    index: usize,
+    node: String,
+    message: String,
+}
+
+#[derive(IntoPyObject)]
+pub struct OperatorInfo {
+    name: String,
+    category: String,
+    arity: usize,
+    params: Vec<ParamInfo>,
+    variadic: bool,
+    root_only: bool,
+}
+
+/// The signatures of the built-in functions, the same ones the parser accepts.
+#[pyfunction]
+pub fn operators() -> Vec<OperatorInfo> {
+    signatures::<RecordBatch>()
+        .into_iter()
+        .map(|sig| OperatorInfo {
+            name: sig.name.to_string(),
+            category: sig.category.to_string(),
+            arity: sig.arity(),
+            params: sig
+                .params
+                .iter()
+                .map(|p| ParamInfo {
+                    name: p.name.to_string(),
+                    kind: p.kind.as_str().to_string(),
+                    optional: p.optional,
+                    min: match p.kind {
+                        Kind::Integer(min) => Some(min),
+                        _ => None,
+                    },
+                })
+                .collect(),
+            variadic: sig.variadic,
+            root_only: sig.root_only,
+        })
+        .collect()
+}
+
+/// Register a macro like `(def zscore (x w) (/ (- x (Mean w x)) (Std w x)))`, returns its name.
 #[pyfunction]
+pub fn define(def: &str) -> PyResult<String> {
+    define_macro(def).map_err(|e| PyValueError::new_err(format!("{}", e)))
+}
+
+/// Remove a macro, returns whether it was defined.
+#[pyfunction]
+pub fn undefine(name: &str) -> bool {
+    undefine_macro(name)
+}
+
+#[pyfunction]
+#[pyo3(signature = (schema, array, ops, njobs, pin_threads = false, numa = false, reset = true, strict = false, lookahead_samples = 0, boolean = false))]
 pub fn replay<'py>(
     py: Python<'py>,
     schema: Vec<usize>,
     array: Vec<usize>,
     mut ops: Vec<Py<Factor>>,
     njobs: usize,
+    pin_threads: bool,
+    numa: bool,
+    reset: bool,
+    strict: bool,
+    lookahead_samples: usize,
+    boolean: bool,
 ) -> PyResult<ReplayResult> {
-    if array.len() % schema.len() != 0 {
+    if array.len() % schema.len().max(1) != 0 {
         throw!(PyValueError::new_err(
             "Number of arrays is not divisible by schema length"
         ))
     }
 
     let mut ops: Vec<_> = ops.iter_mut().map(|f| f.borrow_mut(py)).collect();
+    if reset {
+        // Start from a clean state, otherwise the warm-up counters and windows carry over
+        // from the previous replay of the same factors.
+        ops.iter_mut().for_each(|f| f.op.reset());
+    }
+    let names = ops.iter().map(|f| f.column_name()).collect();
+    let predicates = ops.iter().map(|f| boolean && f.op.is_predicate()).collect();
+    let fresh: Vec<_> = ops.iter().map(|f| f.op.clone()).collect();
     let ops = ops
         .iter_mut()
         .map(|f| (&mut *f.op) as &mut dyn Operator<RecordBatch>)
@@ -159,7 +632,7 @@ pub fn replay<'py>(
     let schema = Arc::new(Schema::new(fields));
 
     let mut rbs = vec![];
-    for rb in array.chunks_exact(schema.fields().len()) {
+    for rb in array.chunks_exact(schema.fields().len().max(1)) {
         let mut columns = vec![];
 
         for (&array, ffi_schema) in rb.into_iter().zip(&ffi_schemas) {
@@ -172,67 +645,94 @@ pub fn replay<'py>(
         rbs.push(rb);
     }
 
+    let nrows = rbs.iter().map(|rb| rb.num_rows()).sum();
+    let placement = Placement { pin_threads, numa };
     let (succeeded, failed) = py
-        .allow_threads(|| -> Result<_> {
-            let pool = rayon::ThreadPoolBuilder::new().num_threads(njobs).build()?;
-            Ok(pool.install(|| crate::replay::replay(rbs.iter().map(Cow::Borrowed), ops, None))?)
+        .allow_threads(|| {
+            let batches = rbs.iter().cloned().map(Ok);
+            let (mut succeeded, mut failed) =
+                pool::run(ops, njobs, placement, batches, |ops, batches| {
+                    crate::replay::replay(&schema, batches.map(Cow::Owned), ops, None, strict)
+                })?;
+            if lookahead_samples > 0 {
+                audit(fresh, &mut succeeded, &mut failed, |ops| {
+                    crate::replay::audit_lookahead(
+                        rbs.iter().cloned(),
+                        nrows,
+                        ops,
+                        lookahead_samples,
+                    )
+                })?;
+            }
+            Ok::<_, Error>((succeeded, failed))
         })
         .map_err(|e| PyValueError::new_err(format!("{}", e)))?;
 
-    Ok(ReplayResult {
-        succeeded: succeeded
-            .into_iter()
-            .map(|(k, v)| {
-                let data = v.into_data();
-                let (array, schema) = ffi::to_ffi(&data).unwrap();
-                let array = Box::into_raw(Box::new(array));
-                let schema = Box::into_raw(Box::new(schema));
-
-                (k, (array as usize, schema as usize))
-            })
-            .collect(),
-        failed: failed
-            .into_iter()
-            .map(|(k, v)| (k, format!("{}", v)))
-            .collect(),
-    })
+    ReplayResult::new(names, predicates, nrows, succeeded, failed)
 }
 
 #[pyfunction]
+#[pyo3(signature = (file, ops, njobs, pin_threads = false, numa = false, reset = true, strict = false, lookahead_samples = 0, boolean = false))]
 pub fn replay_file<'py>(
     py: Python<'py>,
     file: &str,
     mut ops: Vec<Py<Factor>>,
     njobs: usize,
+    pin_threads: bool,
+    numa: bool,
+    reset: bool,
+    strict: bool,
+    lookahead_samples: usize,
+    boolean: bool,
 ) -> PyResult<ReplayResult> {
     let mut ops: Vec<_> = ops.iter_mut().map(|f| f.borrow_mut(py)).collect();
+    if reset {
+        // Start from a clean state, otherwise the warm-up counters and windows carry over
+        // from the previous replay of the same factors.
+        ops.iter_mut().for_each(|f| f.op.reset());
+    }
+    let names = ops.iter().map(|f| f.column_name()).collect();
+    let predicates = ops.iter().map(|f| boolean && f.op.is_predicate()).collect();
+    let fresh: Vec<_> = ops.iter().map(|f| f.op.clone()).collect();
     let ops = ops
         .iter_mut()
         .map(|f| (&mut *f.op) as &mut dyn Operator<RecordBatch>)
         .collect();
 
+    let nrows =
+        crate::replay::num_rows(file).map_err(|e| PyValueError::new_err(format!("{}", e)))?;
+    let placement = Placement { pin_threads, numa };
     let (succeeded, failed) = py
-        .allow_threads(|| -> Result<_> {
-            let pool = rayon::ThreadPoolBuilder::new().num_threads(njobs).build()?;
-            Ok(pool.install(|| crate::replay::replay_file(file, ops, None))?)
+        .allow_threads(|| {
+            let (mut succeeded, mut failed) =
+                crate::replay::replay_file(file, ops, None, strict, njobs, placement)?;
+            if lookahead_samples > 0 {
+                audit(fresh, &mut succeeded, &mut failed, |ops| {
+                    crate::replay::audit_file(file, ops, lookahead_samples)
+                })?;
+            }
+            Ok::<_, Error>((succeeded, failed))
         })
         .map_err(|e| PyValueError::new_err(format!("{}", e)))?;
 
-    Ok(ReplayResult {
-        succeeded: succeeded
-            .into_iter()
-            .map(|(k, v)| {
-                let data = v.into_data();
-                let (array, schema) = ffi::to_ffi(&data).unwrap();
-                let array = Box::into_raw(Box::new(array));
-                let schema = Box::into_raw(Box::new(schema));
+    ReplayResult::new(names, predicates, nrows, succeeded, failed)
+}
 
-                (k, (array as usize, schema as usize))
-            })
-            .collect(),
-        failed: failed
-            .into_iter()
-            .map(|(k, v)| (k, format!("{}", v)))
-            .collect(),
-    })
+// Audit the factors that replayed fine for lookahead with `run`, the leaking ones are moved to the failed.
+fn audit<F>(
+    fresh: Vec<BoxOp<RecordBatch>>,
+    succeeded: &mut HashMap<usize, Float64Array>,
+    failed: &mut HashMap<usize, Error>,
+    run: F,
+) -> Result<(), Error>
+where
+    F: FnOnce(Vec<BoxOp<RecordBatch>>) -> Result<HashMap<usize, Error>, Error>,
+{
+    let candidates: Vec<usize> = succeeded.keys().copied().sorted().collect();
+    let ops = candidates.iter().map(|&i| fresh[i].clone()).collect();
+    for (j, e) in run(ops)? {
+        succeeded.remove(&candidates[j]);
+        failed.insert(candidates[j], e);
+    }
+    Ok(())
 }
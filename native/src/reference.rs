@@ -0,0 +1,1019 @@
+//! Naive recomputations of the window operators.
+//!
+//! Every function here recomputes the result from the raw window, so a series costs O(n·window).
+//! They are slow but easy to verify, and serve as the ground truth for the incremental algorithms
+//! in `ops::window`. With the `validate` feature on, the window operators cross-check their
+//! outputs against these on sampled windows and fail the factor on any divergence.
+
+#[cfg(feature = "validate")]
+use anyhow::{anyhow, Error};
+#[cfg(feature = "validate")]
+use fehler::{throw, throws};
+#[cfg(feature = "validate")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Apply `f` on every look-back window of `xs`. Windows shorter than `min_periods` produce NaN.
+pub fn rolling<F>(xs: &[f64], win_size: usize, min_periods: usize, f: F) -> Vec<f64>
+where
+    F: Fn(&[f64]) -> f64,
+{
+    (0..xs.len())
+        .map(|i| {
+            let window = &xs[(i + 1).saturating_sub(win_size)..=i];
+            if window.len() >= min_periods {
+                f(window)
+            } else {
+                f64::NAN
+            }
+        })
+        .collect()
+}
+
+/// `rolling` over the windows of two series.
+pub fn rolling_pairs<F>(
+    xs: &[f64],
+    ys: &[f64],
+    win_size: usize,
+    min_periods: usize,
+    f: F,
+) -> Vec<f64>
+where
+    F: Fn(&[f64], &[f64]) -> f64,
+{
+    (0..xs.len())
+        .map(|i| {
+            let lo = (i + 1).saturating_sub(win_size);
+            if i + 1 - lo >= min_periods {
+                f(&xs[lo..=i], &ys[lo..=i])
+            } else {
+                f64::NAN
+            }
+        })
+        .collect()
+}
+
+/// Apply `f` on the values within `duration` of every time in `ts`, i.e. in `(t - duration, t]`.
+pub fn rolling_time<F>(ts: &[f64], xs: &[f64], duration: f64, f: F) -> Vec<f64>
+where
+    F: Fn(&[f64]) -> f64,
+{
+    (0..xs.len())
+        .map(|i| {
+            let lo = ts.iter().position(|&t| t > ts[i] - duration).unwrap();
+            f(&xs[lo..=i])
+        })
+        .collect()
+}
+
+pub fn count(window: &[f64]) -> f64 {
+    window.len() as f64
+}
+
+pub fn sum(window: &[f64]) -> f64 {
+    window.iter().sum()
+}
+
+pub fn product(window: &[f64]) -> f64 {
+    window.iter().product()
+}
+
+pub fn mean(window: &[f64]) -> f64 {
+    sum(window) / window.len() as f64
+}
+
+/// The weights go from 1 for the oldest value up to the window size for the newest one.
+pub fn wma(window: &[f64]) -> f64 {
+    let n = window.len() as f64;
+    let wsum: f64 = window
+        .iter()
+        .enumerate()
+        .map(|(i, x)| (i + 1) as f64 * x)
+        .sum();
+    wsum / (n * (n + 1.) / 2.)
+}
+
+pub fn stdev(window: &[f64]) -> f64 {
+    let n = window.len() as f64;
+    let mu = mean(window);
+    (window.iter().map(|x| (x - mu).powi(2)).sum::<f64>() / (n - 1.)).sqrt()
+}
+
+pub fn skew(window: &[f64]) -> f64 {
+    let n = window.len() as f64;
+    let mu = mean(window);
+    let m3 = window.iter().map(|x| (x - mu).powi(3)).sum::<f64>() / n;
+    let m2 = window.iter().map(|x| (x - mu).powi(2)).sum::<f64>() / n;
+    if m2 == 0. {
+        return 0.;
+    }
+
+    (n * (n - 1.)).sqrt() / (n - 2.) * m3 / m2.powf(1.5)
+}
+
+/// The excess kurtosis corrected for the bias, see `ops::Kurt`.
+pub fn kurt(window: &[f64]) -> f64 {
+    let n = window.len() as f64;
+    let mu = mean(window);
+    let m4 = window.iter().map(|x| (x - mu).powi(4)).sum::<f64>() / n;
+    let m2 = window.iter().map(|x| (x - mu).powi(2)).sum::<f64>() / n;
+    if m2 == 0. {
+        return 0.;
+    }
+
+    (n - 1.) / ((n - 2.) * (n - 3.)) * ((n + 1.) * (m4 / m2.powi(2) - 3.) + 6.)
+}
+
+/// The number of elements in the window smaller than the last one.
+pub fn rank(window: &[f64]) -> f64 {
+    let last = window[window.len() - 1];
+    window.iter().filter(|&&x| x < last).count() as f64
+}
+
+/// The rank scaled into [0, 1], see `ops::Percentile`.
+pub fn percentile(window: &[f64]) -> f64 {
+    rank(window) / (window.len() - 1) as f64
+}
+
+pub fn quantile(window: &[f64], q: f64) -> f64 {
+    let mut sorted = window.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    sorted[((sorted.len() - 1) as f64 * q).floor() as usize]
+}
+
+/// The last element clamped into the `lower` and `upper` quantiles, see `ops::Winsorize`.
+pub fn winsorize(window: &[f64], lower: f64, upper: f64) -> f64 {
+    let last = window[window.len() - 1];
+    last.max(quantile(window, lower))
+        .min(quantile(window, upper))
+}
+
+/// The mean of the middle elements, see `ops::Median`.
+pub fn median(window: &[f64]) -> f64 {
+    let mut sorted = window.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let n = sorted.len();
+    (sorted[(n - 1) / 2] + sorted[n / 2]) / 2.
+}
+
+/// The median of the slopes between all the pairs of elements, see `ops::TheilSen`.
+pub fn theil_sen(window: &[f64]) -> f64 {
+    let slopes: Vec<f64> = (0..window.len())
+        .flat_map(|j| (0..j).map(move |i| (window[j] - window[i]) / (j - i) as f64))
+        .collect();
+    median(&slopes)
+}
+
+pub fn min(window: &[f64]) -> f64 {
+    window.iter().cloned().fold(f64::INFINITY, f64::min)
+}
+
+pub fn max(window: &[f64]) -> f64 {
+    window.iter().cloned().fold(f64::NEG_INFINITY, f64::max)
+}
+
+/// The position of the first minimum, counting from the oldest element.
+pub fn argmin(window: &[f64]) -> f64 {
+    let m = min(window);
+    window.iter().position(|&x| x == m).unwrap() as f64
+}
+
+/// The fall of the last element from the maximum, relative to the maximum, see `ops::Drawdown`.
+pub fn drawdown(window: &[f64]) -> f64 {
+    let m = max(window);
+    if m == 0. {
+        return 0.;
+    }
+    (m - window[window.len() - 1]) / m
+}
+
+/// The Hurst exponent of the increments by the rescaled range analysis, see `ops::Hurst`.
+pub fn hurst(window: &[f64]) -> f64 {
+    let n = window.len();
+    let sizes: Vec<usize> = (0..).map(|k| n >> k).take_while(|&m| m >= 8).collect();
+    let points: Vec<(f64, f64)> = sizes
+        .into_iter()
+        .filter_map(|m| {
+            let rs: Vec<f64> = (0..n / m)
+                .map(|c| &window[n - (c + 1) * m..n - c * m])
+                .filter(|chunk| stdev0(chunk) > 0.)
+                .map(|chunk| {
+                    let mu = mean(chunk);
+                    let walk: Vec<f64> = std::iter::once(0.)
+                        .chain(chunk.iter().scan(0., |y, x| {
+                            *y += x - mu;
+                            Some(*y)
+                        }))
+                        .collect();
+                    (max(&walk) - min(&walk)) / stdev0(chunk)
+                })
+                .collect();
+            if rs.is_empty() || mean(&rs) <= 0. {
+                None
+            } else {
+                Some(((m as f64).ln(), mean(&rs).ln()))
+            }
+        })
+        .collect();
+    if points.len() < 2 {
+        return f64::NAN;
+    }
+    let (xs, ys): (Vec<_>, Vec<_>) = points.into_iter().unzip();
+    ols(&ys, &xs).1
+}
+
+// The population standard deviation
+fn stdev0(window: &[f64]) -> f64 {
+    let mu = mean(window);
+    (window.iter().map(|x| (x - mu).powi(2)).sum::<f64>() / window.len() as f64).sqrt()
+}
+
+/// The position of the first maximum, counting from the oldest element.
+pub fn argmax(window: &[f64]) -> f64 {
+    let m = max(window);
+    window.iter().position(|&x| x == m).unwrap() as f64
+}
+
+pub fn correlation(xs: &[f64], ys: &[f64]) -> f64 {
+    let (xbar, ybar) = (mean(xs), mean(ys));
+    let nom = xs
+        .iter()
+        .zip(ys)
+        .map(|(x, y)| (x - xbar) * (y - ybar))
+        .sum::<f64>();
+    let denomx = xs.iter().map(|x| (x - xbar).powi(2)).sum::<f64>().sqrt();
+    let denomy = ys.iter().map(|y| (y - ybar).powi(2)).sum::<f64>().sqrt();
+    if denomx * denomy == 0. {
+        return 0.;
+    }
+
+    nom / (denomx * denomy)
+}
+
+/// The intercept and the slope of the OLS fit of `ys` on `xs`, see `ops::Beta`.
+pub fn ols(ys: &[f64], xs: &[f64]) -> (f64, f64) {
+    let (xbar, ybar) = (mean(xs), mean(ys));
+    let xx = xs.iter().map(|x| (x - xbar).powi(2)).sum::<f64>();
+    let xy = xs
+        .iter()
+        .zip(ys)
+        .map(|(x, y)| (x - xbar) * (y - ybar))
+        .sum::<f64>();
+    if xx == 0. {
+        return (ybar, 0.);
+    }
+    (ybar - xy / xx * xbar, xy / xx)
+}
+
+/// The volume weighted average price, see `ops::VWAP`.
+pub fn vwap(prices: &[f64], volumes: &[f64]) -> f64 {
+    let pv = prices.iter().zip(volumes).map(|(p, v)| p * v).sum::<f64>();
+    pv / sum(volumes)
+}
+
+/// The time weighted average price over the intervals of `times`, see `ops::TWAP`.
+pub fn twap(times: &[f64], prices: &[f64]) -> f64 {
+    let durations: Vec<f64> = times.windows(2).map(|t| t[1] - t[0]).collect();
+    vwap(&prices[..prices.len() - 1], &durations)
+}
+
+pub fn delay(xs: &[f64], n: usize) -> Vec<f64> {
+    (0..xs.len())
+        .map(|i| if i >= n { xs[i - n] } else { f64::NAN })
+        .collect()
+}
+
+pub fn log_return(xs: &[f64], n: usize) -> Vec<f64> {
+    (0..xs.len())
+        .map(|i| {
+            if i >= n {
+                (xs[i] / xs[i - n]).ln()
+            } else {
+                f64::NAN
+            }
+        })
+        .collect()
+}
+
+/// The relative strength index of TA-Lib from the first value, see `ops::RSI`.
+pub fn rsi(xs: &[f64], n: usize) -> Vec<f64> {
+    let k = n as f64;
+    let (mut gain, mut loss) = (0., 0.);
+    let mut results = vec![f64::NAN; xs.len().min(n)];
+    for i in 1..xs.len() {
+        let d = xs[i] - xs[i - 1];
+        let (g, l) = (d.max(0.), (-d).max(0.));
+        if i <= n {
+            gain += g / k;
+            loss += l / k;
+        } else {
+            gain = (gain * (k - 1.) + g) / k;
+            loss = (loss * (k - 1.) + l) / k;
+        }
+        if i >= n {
+            results.push(if gain + loss == 0. {
+                0.
+            } else {
+                100. * gain / (gain + loss)
+            });
+        }
+    }
+    results
+}
+
+/// The true range of TA-Lib, see `ops::TrueRange`.
+pub fn true_range(high: &[f64], low: &[f64], close: &[f64]) -> Vec<f64> {
+    (0..high.len())
+        .map(|i| {
+            if i == 0 {
+                return f64::NAN;
+            }
+            let hi = high[i].max(close[i - 1]);
+            let lo = low[i].min(close[i - 1]);
+            hi - lo
+        })
+        .collect()
+}
+
+/// The average true range of TA-Lib, see `ops::ATR`.
+pub fn atr(high: &[f64], low: &[f64], close: &[f64], n: usize) -> Vec<f64> {
+    let tr = true_range(high, low, close);
+    let k = n as f64;
+    let mut atr = f64::NAN;
+    (0..tr.len())
+        .map(|i| {
+            if i == n {
+                atr = mean(&tr[1..=n]);
+            } else if i > n {
+                atr = (atr * (k - 1.) + tr[i]) / k;
+            }
+            atr
+        })
+        .collect()
+}
+
+/// The %K (after smoothing) of the stochastic oscillator of TA-Lib, see `ops::StochK`.
+pub fn stoch_k(high: &[f64], low: &[f64], close: &[f64], n: usize, smooth_k: usize) -> Vec<f64> {
+    let highest = rolling(high, n, n, max);
+    let lowest = rolling(low, n, n, min);
+    let fast_k: Vec<f64> = (0..close.len())
+        .map(|i| {
+            if highest[i].is_nan() {
+                f64::NAN
+            } else if highest[i] > lowest[i] {
+                100. * (close[i] - lowest[i]) / (highest[i] - lowest[i])
+            } else {
+                0.
+            }
+        })
+        .collect();
+    let k = rolling(&fast_k[n - 1..], smooth_k, smooth_k, mean);
+    fast_k[..n - 1].iter().copied().chain(k).collect()
+}
+
+/// The commodity channel index of TA-Lib, see `ops::CCI`.
+pub fn cci(high: &[f64], low: &[f64], close: &[f64], n: usize) -> Vec<f64> {
+    let tp: Vec<f64> = (0..close.len())
+        .map(|i| (high[i] + low[i] + close[i]) / 3.)
+        .collect();
+    (0..tp.len())
+        .map(|i| {
+            if i + 1 < n {
+                return f64::NAN;
+            }
+            let window = &tp[i + 1 - n..=i];
+            let mu = mean(window);
+            let mad = window.iter().map(|x| (x - mu).abs()).sum::<f64>() / n as f64;
+            if mad == 0. {
+                0.
+            } else {
+                (tp[i] - mu) / (0.015 * mad)
+            }
+        })
+        .collect()
+}
+
+/// The midpoint of the highest high and the lowest low of the last `n` bars, see `ops::Tenkan`.
+pub fn midpoint(high: &[f64], low: &[f64], n: usize) -> Vec<f64> {
+    (0..high.len())
+        .map(|i| {
+            if i + 1 < n {
+                return f64::NAN;
+            }
+            (max(&high[i + 1 - n..=i]) + min(&low[i + 1 - n..=i])) / 2.
+        })
+        .collect()
+}
+
+/// The Aroon up and down of TA-Lib, see `ops::AroonUp`.
+pub fn aroon(high: &[f64], low: &[f64], n: usize) -> [Vec<f64>; 2] {
+    // The bars since the extremum, the latest one of the ties
+    let since = |xs: &[f64], i: usize, better: fn(f64, f64) -> bool| {
+        let mut best = i - n;
+        for j in i - n..=i {
+            if !better(xs[best], xs[j]) {
+                best = j;
+            }
+        }
+        (i - best) as f64
+    };
+    let k = n as f64;
+    let mut up = vec![f64::NAN; high.len().min(n)];
+    let mut down = up.clone();
+    for i in n..high.len() {
+        up.push(100. * (k - since(high, i, |a, b| a > b)) / k);
+        down.push(100. * (k - since(low, i, |a, b| a < b)) / k);
+    }
+    [up, down]
+}
+
+/// The on-balance volume of TA-Lib, see `ops::OBV`.
+pub fn obv(price: &[f64], volume: &[f64]) -> Vec<f64> {
+    let mut obv = 0.;
+    (0..price.len())
+        .map(|i| {
+            if i == 0 {
+                obv = volume[0];
+            } else if price[i] > price[i - 1] {
+                obv += volume[i];
+            } else if price[i] < price[i - 1] {
+                obv -= volume[i];
+            }
+            obv
+        })
+        .collect()
+}
+
+/// The ultimate oscillator of TA-Lib, see `ops::UltOsc`.
+pub fn ultosc(high: &[f64], low: &[f64], close: &[f64], mut periods: [usize; 3]) -> Vec<f64> {
+    periods.sort_unstable();
+    let tr = true_range(high, low, close);
+    let avg = |i: usize, n: usize| {
+        let (mut pressure, mut range) = (0., 0.);
+        for j in i + 1 - n..=i {
+            pressure += close[j] - low[j].min(close[j - 1]);
+            range += tr[j];
+        }
+        if range == 0. {
+            0.
+        } else {
+            pressure / range
+        }
+    };
+    (0..high.len())
+        .map(|i| {
+            if i < periods[2] {
+                return f64::NAN;
+            }
+            let [fast, medium, slow] = periods;
+            100. * (4. * avg(i, fast) + 2. * avg(i, medium) + avg(i, slow)) / 7.
+        })
+        .collect()
+}
+
+/// The money flow index of TA-Lib, see `ops::MFI`.
+pub fn mfi(high: &[f64], low: &[f64], close: &[f64], volume: &[f64], n: usize) -> Vec<f64> {
+    let tp: Vec<f64> = (0..close.len())
+        .map(|i| (high[i] + low[i] + close[i]) / 3.)
+        .collect();
+    (0..tp.len())
+        .map(|i| {
+            if i < n {
+                return f64::NAN;
+            }
+            let (mut positive, mut negative) = (0., 0.);
+            for j in i + 1 - n..=i {
+                if tp[j] > tp[j - 1] {
+                    positive += tp[j] * volume[j];
+                } else if tp[j] < tp[j - 1] {
+                    negative += tp[j] * volume[j];
+                }
+            }
+            if positive + negative == 0. {
+                0.
+            } else {
+                100. * positive / (positive + negative)
+            }
+        })
+        .collect()
+}
+
+/// The exponential moving average from the first value, see `ops::EMA`.
+pub fn ema(xs: &[f64], alpha: f64) -> Vec<f64> {
+    let mut ema = f64::NAN;
+    xs.iter()
+        .map(|&x| {
+            ema = if ema.is_nan() {
+                x
+            } else {
+                alpha * x + (1. - alpha) * ema
+            };
+            ema
+        })
+        .collect()
+}
+
+/// The exponential moving average of TA-Lib, seeded by the mean of the first `n` non-NaN values, see `ops::DEMA` and `ops::TEMA`.
+pub fn ta_ema(xs: &[f64], n: usize) -> Vec<f64> {
+    let alpha = 2. / (n as f64 + 1.);
+    let mut seen = vec![];
+    let mut ema = f64::NAN;
+    xs.iter()
+        .map(|&x| {
+            if x.is_nan() {
+                return f64::NAN;
+            }
+            seen.push(x);
+            if seen.len() == n {
+                ema = mean(&seen);
+            } else if seen.len() > n {
+                ema = alpha * x + (1. - alpha) * ema;
+            }
+            ema
+        })
+        .collect()
+}
+
+/// The MACD, the signal and the histogram of TA-Lib, see `ops::MACD`.
+pub fn macd(xs: &[f64], fast: usize, slow: usize, signal: usize) -> [Vec<f64>; 3] {
+    // The fast EMA is seeded along with the slow one
+    let mut fast_ema = vec![f64::NAN; slow - fast];
+    fast_ema.extend(ta_ema(&xs[slow - fast..], fast));
+    let macd: Vec<f64> = fast_ema
+        .iter()
+        .zip(ta_ema(xs, slow))
+        .map(|(f, s)| f - s)
+        .collect();
+    let sig = ta_ema(&macd, signal);
+    let macd: Vec<f64> = macd
+        .iter()
+        .zip(&sig)
+        .map(|(m, s)| if s.is_nan() { f64::NAN } else { *m })
+        .collect();
+    let hist = macd.iter().zip(&sig).map(|(m, s)| m - s).collect();
+    [macd, sig, hist]
+}
+
+/// The bias corrected exponentially weighted covariance at every row, from the explicit weights:
+/// `(1 - alpha)^t` for the first value and `alpha * (1 - alpha)^(t - j)` for the j-th one.
+pub fn ewm_cov(xs: &[f64], ys: &[f64], alpha: f64) -> Vec<f64> {
+    (0..xs.len())
+        .map(|t| {
+            let ws: Vec<f64> = (0..=t)
+                .map(|j| {
+                    let w = (1. - alpha).powi((t - j) as i32);
+                    if j == 0 {
+                        w
+                    } else {
+                        alpha * w
+                    }
+                })
+                .collect();
+            let sum: f64 = ws.iter().sum();
+            let sum2: f64 = ws.iter().map(|w| w * w).sum();
+            let mx = (0..=t).map(|j| ws[j] * xs[j]).sum::<f64>() / sum;
+            let my = (0..=t).map(|j| ws[j] * ys[j]).sum::<f64>() / sum;
+            let cov = (0..=t)
+                .map(|j| ws[j] * (xs[j] - mx) * (ys[j] - my))
+                .sum::<f64>()
+                / sum;
+            if t == 0 {
+                f64::NAN
+            } else {
+                cov * sum * sum / (sum * sum - sum2)
+            }
+        })
+        .collect()
+}
+
+/// Validate one output out of every `VALIDATE_EVERY`, recomputing every window is too slow otherwise.
+#[cfg(feature = "validate")]
+pub const VALIDATE_EVERY: usize = 101;
+
+#[cfg(feature = "validate")]
+static VALIDATE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Compare an output of the streaming operator `name` against its reference value on sampled calls.
+/// NaN outputs belong to the warm-up period and are not validated.
+#[cfg(feature = "validate")]
+#[throws(Error)]
+pub fn validate<F>(name: &str, got: f64, expected: F)
+where
+    F: FnOnce() -> f64,
+{
+    if got.is_nan() || VALIDATE_COUNTER.fetch_add(1, Ordering::Relaxed) % VALIDATE_EVERY != 0 {
+        return;
+    }
+
+    let expected = expected();
+    if !close(got, expected) {
+        throw!(anyhow!(
+            "{} diverges from its reference implementation: got {}, expected {}",
+            name,
+            got,
+            expected
+        ))
+    }
+}
+
+/// Whether two outputs are equal up to the error accumulated by the incremental algorithms.
+pub fn close(a: f64, b: f64) -> bool {
+    if a.is_nan() || b.is_nan() {
+        return a.is_nan() && b.is_nan();
+    }
+
+    (a - b).abs() <= 1e-6 * a.abs().max(b.abs()).max(1.)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ops::{from_str, Operator};
+    use arrow::{
+        array::{ArrayRef, Float64Array},
+        record_batch::RecordBatch,
+    };
+    use std::sync::Arc;
+
+    fn replay(sexpr: &str, tb: &RecordBatch) -> Vec<f64> {
+        let mut op: Box<dyn Operator<RecordBatch>> = from_str(sexpr, &Default::default()).unwrap();
+        op.update(tb).unwrap().into_owned()
+    }
+
+    #[test]
+    fn matches_window_operators() {
+        let xs: Vec<f64> = (0..500).map(|i| ((i * 7919) % 113) as f64 / 7.).collect();
+        let ys: Vec<f64> = (0..500).map(|i| ((i * 104729) % 97) as f64 + 1.).collect();
+        // The irregular times of the observations, sometimes at the same time
+        let ts: Vec<f64> = (0..500)
+            .scan(0., |t, i| {
+                *t += ((i * 31) % 5) as f64;
+                Some(*t)
+            })
+            .collect();
+        let tb = RecordBatch::try_from_iter(vec![
+            ("x", Arc::new(Float64Array::from(xs.clone())) as ArrayRef),
+            ("y", Arc::new(Float64Array::from(ys.clone())) as ArrayRef),
+            ("t", Arc::new(Float64Array::from(ts.clone())) as ArrayRef),
+        ])
+        .unwrap();
+
+        // The bars for the indicators over high, low and close
+        let high: Vec<f64> = xs.iter().zip(&ys).map(|(x, y)| x + y).collect();
+        let close: Vec<f64> = xs.iter().zip(&ys).map(|(x, y)| x + y / 2.).collect();
+
+        let cases: Vec<(&str, Vec<f64>)> = vec![
+            ("(Sum 10 :x)", super::rolling(&xs, 10, 10, super::sum)),
+            ("(Mean 10 3 :x)", super::rolling(&xs, 10, 3, super::mean)),
+            (
+                "(CountIf 7 3 (- :x 8))",
+                super::rolling(&xs, 7, 3, |w| w.iter().filter(|&&x| x > 8.).count() as f64),
+            ),
+            (
+                "(Any 4 (> :y 80))",
+                super::rolling(&ys, 4, 4, |w| w.iter().any(|&y| y > 80.) as u8 as f64),
+            ),
+            (
+                "(All 3 (> :y 20))",
+                super::rolling(&ys, 3, 3, |w| w.iter().all(|&y| y > 20.) as u8 as f64),
+            ),
+            ("(First 5 2 :x)", super::rolling(&xs, 5, 2, |w| w[0])),
+            (
+                "(Product 6 (- :x 8))",
+                super::rolling(
+                    &xs.iter().map(|x| x - 8.).collect::<Vec<_>>(),
+                    6,
+                    6,
+                    super::product,
+                ),
+            ),
+            ("(Std 20 :x)", super::rolling(&xs, 20, 20, super::stdev)),
+            ("(Skew 20 5 :x)", super::rolling(&xs, 20, 5, super::skew)),
+            ("(Kurt 20 :x)", super::rolling(&xs, 20, 20, super::kurt)),
+            ("(Kurt 20 4 :y)", super::rolling(&ys, 20, 4, super::kurt)),
+            ("(Rank 15 :x)", super::rolling(&xs, 15, 15, super::rank)),
+            (
+                "(Percentile 15 3 :x)",
+                super::rolling(&xs, 15, 3, super::percentile),
+            ),
+            (
+                "(Quantile 15 4 0.3 :x)",
+                super::rolling(&xs, 15, 4, |w| super::quantile(w, 0.3)),
+            ),
+            (
+                "(Winsorize 20 5 0.1 0.8 :x)",
+                super::rolling(&xs, 20, 5, |w| super::winsorize(w, 0.1, 0.8)),
+            ),
+            ("(Median 10 :x)", super::rolling(&xs, 10, 10, super::median)),
+            ("(Median 9 2 :x)", super::rolling(&xs, 9, 2, super::median)),
+            ("(Min 7 :x)", super::rolling(&xs, 7, 7, super::min)),
+            ("(Max 7 2 :x)", super::rolling(&xs, 7, 2, super::max)),
+            ("(ArgMin 7 :x)", super::rolling(&xs, 7, 7, super::argmin)),
+            ("(ArgMax 7 2 :x)", super::rolling(&xs, 7, 2, super::argmax)),
+            (
+                "(Drawdown 9 3 :x)",
+                super::rolling(&xs, 9, 3, super::drawdown),
+            ),
+            ("(Hurst 40 :x)", super::rolling(&xs, 40, 40, super::hurst)),
+            (
+                "(Slope 12 4 :x)",
+                super::rolling(&xs, 12, 4, |w| {
+                    let ts: Vec<f64> = (0..w.len()).map(|i| i as f64).collect();
+                    super::ols(w, &ts).1
+                }),
+            ),
+            (
+                "(TheilSen 9 3 :x)",
+                super::rolling(&xs, 9, 3, super::theil_sen),
+            ),
+            (
+                "(TrendR2 12 :y)",
+                super::rolling(&ys, 12, 12, |w| {
+                    let ts: Vec<f64> = (0..w.len()).map(|i| i as f64).collect();
+                    super::correlation(&ts, w).powi(2)
+                }),
+            ),
+            ("(Delay 5 :x)", super::delay(&xs, 5)),
+            ("(LogReturn 5 :y)", super::log_return(&ys, 5)),
+            (
+                "(ROC 5 :x)",
+                super::delay(&xs, 5)
+                    .iter()
+                    .zip(&xs)
+                    .map(|(old, x)| {
+                        if *old == 0. {
+                            0.
+                        } else {
+                            100. * (x / old - 1.)
+                        }
+                    })
+                    .collect(),
+            ),
+            (
+                "(PctChange 3 :y)",
+                super::delay(&ys, 3)
+                    .iter()
+                    .zip(&ys)
+                    .map(|(old, y)| y / old - 1.)
+                    .collect(),
+            ),
+            ("(OBV :x :y)", super::obv(&xs, &ys)),
+            (
+                "(CumSum :x)",
+                xs.iter()
+                    .scan(0., |sum, x| {
+                        *sum += x;
+                        Some(*sum)
+                    })
+                    .collect(),
+            ),
+            (
+                "(CumMean (Delay 2 :x))",
+                (0..xs.len())
+                    .map(|i| {
+                        if i < 2 {
+                            f64::NAN
+                        } else {
+                            super::mean(&xs[..=i - 2])
+                        }
+                    })
+                    .collect(),
+            ),
+            (
+                "(CumMax :y)",
+                (0..ys.len()).map(|i| super::max(&ys[..=i])).collect(),
+            ),
+            (
+                "(CumMin :y)",
+                (0..ys.len()).map(|i| super::min(&ys[..=i])).collect(),
+            ),
+            (
+                "(SumT \"7s\" :t :x)",
+                super::rolling_time(&ts, &xs, 7., super::sum),
+            ),
+            (
+                "(MeanT \"7s\" :t :x)",
+                super::rolling_time(&ts, &xs, 7., super::mean),
+            ),
+            (
+                "(StdT \"12s\" :t :y)",
+                super::rolling_time(&ts, &ys, 12., |w| {
+                    if w.len() < 2 {
+                        f64::NAN
+                    } else {
+                        super::stdev(w)
+                    }
+                }),
+            ),
+            (
+                "(MinT \"7s\" :t :x)",
+                super::rolling_time(&ts, &xs, 7., super::min),
+            ),
+            (
+                "(MaxT \"500ms\" :t :y)",
+                super::rolling_time(&ts, &ys, 0.5, super::max),
+            ),
+            (
+                "(CountT \"1m\" :t :x)",
+                super::rolling_time(&ts, &xs, 60., super::count),
+            ),
+            (
+                "(TWAP 10 :t :x)",
+                (0..xs.len())
+                    .map(|i| {
+                        if i < 10 {
+                            f64::NAN
+                        } else {
+                            super::twap(&ts[i - 10..=i], &xs[i - 10..=i])
+                        }
+                    })
+                    .collect(),
+            ),
+            (
+                "(VWAP 10 3 :x :y)",
+                (0..xs.len())
+                    .map(|i| {
+                        let lo = (i + 1).saturating_sub(10);
+                        if i + 1 < 3 {
+                            f64::NAN
+                        } else {
+                            super::vwap(&xs[lo..=i], &ys[lo..=i])
+                        }
+                    })
+                    .collect(),
+            ),
+            (
+                "(OBV (Delay 3 :y) :x)",
+                vec![f64::NAN; 3]
+                    .into_iter()
+                    .chain(super::obv(&ys[..ys.len() - 3], &xs[3..]))
+                    .collect(),
+            ),
+            (
+                "(Momentum 3 :y)",
+                super::delay(&ys, 3)
+                    .iter()
+                    .zip(&ys)
+                    .map(|(old, y)| y - old)
+                    .collect(),
+            ),
+            ("(RSI 14 :x)", super::rsi(&xs, 14)),
+            ("(RSI 1 :y)", super::rsi(&ys, 1)),
+            (
+                "(TrueRange (+ :x :y) :x (+ :x (/ :y 2)))",
+                super::true_range(&high, &xs, &close),
+            ),
+            (
+                "(StochK 14 3 (+ :x :y) :x (+ :x (/ :y 2)))",
+                super::stoch_k(&high, &xs, &close, 14, 3),
+            ),
+            ("(StochD 5 1 3 (+ :x :y) :x (+ :x (/ :y 2)))", {
+                let k = super::stoch_k(&high, &xs, &close, 5, 1);
+                let d = super::rolling(&k[4..], 3, 3, super::mean);
+                k[..4].iter().copied().chain(d).collect()
+            }),
+            (
+                "(CCI 20 (+ :x :y) :x (+ :x (/ :y 2)))",
+                super::cci(&high, &xs, &close, 20),
+            ),
+            ("(CCI 4 :y :y :y)", super::cci(&ys, &ys, &ys, 4)),
+            ("(AroonUp 14 :y :x)", super::aroon(&ys, &xs, 14)[0].clone()),
+            ("(Tenkan 9 (+ :x :y) :x)", super::midpoint(&high, &xs, 9)),
+            (
+                "(SenkouA 9 26 26 (+ :x :y) :x)",
+                super::delay(
+                    &super::midpoint(&high, &xs, 9)
+                        .iter()
+                        .zip(super::midpoint(&high, &xs, 26))
+                        .map(|(tenkan, kijun)| (tenkan + kijun) / 2.)
+                        .collect::<Vec<_>>(),
+                    26,
+                ),
+            ),
+            (
+                "(SenkouB 52 26 (+ :x :y) :x)",
+                super::delay(&super::midpoint(&high, &xs, 52), 26),
+            ),
+            (
+                "(AroonDown 14 :y :x)",
+                super::aroon(&ys, &xs, 14)[1].clone(),
+            ),
+            ("(AroonOsc 25 (Max 3 :x) :y)", {
+                let high = super::rolling(&xs, 3, 3, super::max);
+                let [up, down] = super::aroon(&high[2..], &ys[2..], 25);
+                let osc = up.iter().zip(&down).map(|(u, d)| u - d);
+                vec![f64::NAN; 2].into_iter().chain(osc).collect()
+            }),
+            (
+                "(MFI 14 (+ :x :y) :x (+ :x (/ :y 2)) :y)",
+                super::mfi(&high, &xs, &close, &ys, 14),
+            ),
+            (
+                "(UltOsc (+ :x :y) :x (+ :x (/ :y 2)))",
+                super::ultosc(&high, &xs, &close, [7, 14, 28]),
+            ),
+            (
+                "(UltOsc 20 5 10 (+ :x :y) :x (+ :x (/ :y 2)))",
+                super::ultosc(&high, &xs, &close, [20, 5, 10]),
+            ),
+            (
+                "(ATR 14 (+ :x :y) :x (+ :x (/ :y 2)))",
+                super::atr(&high, &xs, &close, 14),
+            ),
+            ("(SMA 12 :x)", super::rolling(&xs, 12, 12, super::mean)),
+            ("(WMA 12 :x)", super::rolling(&xs, 12, 12, super::wma)),
+            ("(WMA 1 :y)", ys.clone()),
+            (
+                "(DEMA 6 :x)",
+                super::ta_ema(&xs, 6)
+                    .into_iter()
+                    .zip(super::ta_ema(&super::ta_ema(&xs, 6), 6))
+                    .map(|(e1, e2)| 2. * e1 - e2)
+                    .collect(),
+            ),
+            ("(DEMA 1 :y)", ys.clone()),
+            ("(TEMA 5 :x)", {
+                let e1 = super::ta_ema(&xs, 5);
+                let e2 = super::ta_ema(&e1, 5);
+                let e3 = super::ta_ema(&e2, 5);
+                (0..xs.len())
+                    .map(|i| 3. * e1[i] - 3. * e2[i] + e3[i])
+                    .collect()
+            }),
+            ("(MACD 4 9 3 :x)", super::macd(&xs, 4, 9, 3)[0].clone()),
+            (
+                "(MACDSignal 9 4 3 :x)",
+                super::macd(&xs, 4, 9, 3)[1].clone(),
+            ),
+            (
+                "(MACDHist 12 26 9 :y)",
+                super::macd(&ys, 12, 26, 9)[2].clone(),
+            ),
+            ("(EMA 9 :x)", super::ema(&xs, 0.2)),
+            ("(EMA alpha 0.3 :y)", super::ema(&ys, 0.3)),
+            (
+                "(EWMStd 9 :x)",
+                super::ewm_cov(&xs, &xs, 0.2)
+                    .into_iter()
+                    .map(f64::sqrt)
+                    .collect(),
+            ),
+            ("(EWMCov 9 :x :y)", super::ewm_cov(&xs, &ys, 0.2)),
+            (
+                "(EWMCorr alpha 0.3 :x :y)",
+                super::ewm_cov(&xs, &ys, 0.3)
+                    .into_iter()
+                    .zip(super::ewm_cov(&xs, &xs, 0.3))
+                    .zip(super::ewm_cov(&ys, &ys, 0.3))
+                    .map(|((cov, varx), vary)| cov / (varx * vary).sqrt())
+                    .collect(),
+            ),
+            (
+                "(Beta 30 :y :x)",
+                super::rolling_pairs(&ys, &xs, 30, 30, |ys, xs| super::ols(ys, xs).1),
+            ),
+            (
+                "(Alpha 20 5 :x :y)",
+                super::rolling_pairs(&xs, &ys, 20, 5, |xs, ys| super::ols(xs, ys).0),
+            ),
+            (
+                "(Resid 15 :y (+ :x :y))",
+                super::rolling_pairs(&ys, &high, 15, 15, |ys, xs| {
+                    let (alpha, beta) = super::ols(ys, xs);
+                    ys[ys.len() - 1] - (alpha + beta * xs[xs.len() - 1])
+                }),
+            ),
+            (
+                "(AutoCorr 20 10 3 :x)",
+                std::iter::repeat(f64::NAN)
+                    .take(3)
+                    .chain(super::rolling_pairs(
+                        &xs[3..],
+                        &xs[..xs.len() - 3],
+                        20,
+                        10,
+                        super::correlation,
+                    ))
+                    .collect(),
+            ),
+            (
+                "(Corr 30 :x :y)",
+                (0..xs.len())
+                    .map(|i| {
+                        let lo = (i + 1).saturating_sub(30);
+                        if i + 1 < 30 {
+                            f64::NAN
+                        } else {
+                            super::correlation(&xs[lo..=i], &ys[lo..=i])
+                        }
+                    })
+                    .collect(),
+            ),
+        ];
+
+        for (sexpr, expected) in cases {
+            let got = replay(sexpr, &tb);
+            for (i, (&g, &e)) in got.iter().zip(&expected).enumerate() {
+                assert!(super::close(g, e), "{} at {}: {} != {}", sexpr, i, g, e);
+            }
+        }
+    }
+}
@@ -1,29 +1,84 @@
-use crate::ops::Operator;
-use anyhow::{Error, Result};
+use crate::{
+    ops::{path_of, BoxOp, LengthMismatch, Operator},
+    pool::{self, Placement, ReplayOutput},
+    ticker_batch,
+};
+use anyhow::{anyhow, Error, Result};
 use arrow::{
-    array::{Float64Array, Float64Builder},
-    record_batch::RecordBatch,
+    array::{as_primitive_array, Array, ArrayRef, Float64Array, Float64Builder, PrimitiveArray},
+    datatypes::{ArrowPrimitiveType, DataType, Decimal128Type, Float64Type, Schema},
+    error::ArrowError,
+    record_batch::{RecordBatch, RecordBatchReader},
 };
-use fehler::throws;
+use fehler::{throw, throws};
+use itertools::Itertools;
 use parquet::{
     arrow::arrow_reader::ParquetRecordBatchReader,
     file::reader::{FileReader, SerializedFileReader},
 };
 use rayon::prelude::*;
-use std::{borrow::Cow, collections::HashMap, fs::File};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs::File,
+    mem,
+    sync::{mpsc, Arc},
+    thread,
+};
 
 static DEFAULT_BATCH_SIZE: usize = 2048;
+pub(crate) static PREFETCH_BATCHES: usize = 2;
+
+/// Validate each operator against the schema (see `Operator::validate`), so that the bad factors
+/// fail before any data is replayed. The errors are keyed by the operator's position in `ops`.
+pub fn validate_schema(
+    schema: &Schema,
+    ops: &[&mut dyn Operator<RecordBatch>],
+) -> HashMap<usize, Error> {
+    ops.iter()
+        .enumerate()
+        .filter_map(|(i, op)| op.validate(schema).err().map(|e| (i, e)))
+        .collect()
+}
+
+/// Point out where a length mismatch happened: the factor, the path of the node in the factor (see `path_of`)
+/// and the batch.
+fn diagnose(op: &dyn Operator<RecordBatch>, e: Error, batch: usize) -> Error {
+    let mismatch = match e.downcast_ref::<LengthMismatch>() {
+        Some(mismatch) => mismatch,
+        None => return e,
+    };
 
+    match mismatch.index.and_then(|i| path_of(op, i)) {
+        Some(path) => anyhow!(
+            "{}: the node at path {:?} {} in batch {}",
+            op.to_string(),
+            path,
+            mismatch,
+            batch
+        ),
+        None => anyhow!("{}: {} in batch {}", op.to_string(), mismatch, batch),
+    }
+}
+
+/// Replay the batches through the operators. With `strict`, the operators run their deep checks
+/// (see `Operator::check_warmup`) on these batches only.
 #[throws(Error)]
 pub fn replay<'a, I>(
+    schema: &Schema,
     tb: I,
     mut ops: Vec<&mut (dyn Operator<RecordBatch>)>,
     nrows: Option<usize>,
+    strict: bool,
 ) -> (HashMap<usize, Float64Array>, HashMap<usize, Error>)
 where
     I: IntoIterator<Item = Cow<'a, RecordBatch>>,
 {
-    let mut failed = HashMap::new();
+    let mut failed = validate_schema(schema, &ops);
+    if failed.len() == ops.len() {
+        // Nothing to replay if none of the factors is able to run on this data
+        return (HashMap::new(), failed);
+    }
 
     let mut builders: Vec<_> = (0..ops.len())
         .into_par_iter()
@@ -36,7 +91,16 @@ where
         })
         .collect();
 
-    for record_batch in tb {
+    for (n, record_batch) in tb.into_iter().enumerate() {
+        if record_batch.num_rows() == 0 {
+            continue;
+        }
+        let record_batch = if strict {
+            Cow::Owned(ticker_batch::strict(&record_batch))
+        } else {
+            record_batch
+        };
+
         let results: Vec<_> = ops
             .par_iter_mut()
             .zip(&mut builders)
@@ -45,7 +109,9 @@ where
                 if failed.contains_key(&i) {
                     return Ok(());
                 }
-                let values = op.update(&record_batch)?;
+                let values = op
+                    .checked_update(&record_batch)
+                    .map_err(|e| diagnose(&**op, e, n))?;
                 let masks: Vec<_> = values.iter().map(|v| !v.is_nan()).collect();
                 bdr.append_values(&values, &masks);
 
@@ -70,28 +136,39 @@ where
     )
 }
 
+#[throws(Error)]
+pub fn num_rows(path: &str) -> usize {
+    let file_reader = SerializedFileReader::new(File::open(path)?)?;
+    file_reader
+        .metadata()
+        .row_groups()
+        .into_iter()
+        .map(|rgm| rgm.num_rows() as usize)
+        .sum()
+}
+
+/// Replay the parquet file through the operators on `njobs` workers laid out by `placement`.
+/// The file is decoded once, even if the operators are split across NUMA nodes.
 #[throws(Error)]
 pub fn replay_file<O>(
     path: &str,
     ops: Vec<&mut (dyn Operator<RecordBatch>)>,
     batch_size: O,
-) -> (HashMap<usize, Float64Array>, HashMap<usize, Error>)
+    strict: bool,
+    njobs: usize,
+    placement: Placement,
+) -> ReplayOutput
 where
     O: Into<Option<usize>>,
 {
-    let file = File::open(path).unwrap();
-    let file_reader = SerializedFileReader::new(file).unwrap();
-    let nrows: usize = file_reader
-        .metadata()
-        .row_groups()
-        .into_iter()
-        .map(|rgm| rgm.num_rows() as usize)
-        .sum();
+    let nrows = num_rows(path)?;
 
-    let file = File::open(path).unwrap();
+    let file = File::open(path)?;
     let batch_size = batch_size.into().unwrap_or(DEFAULT_BATCH_SIZE);
     let arrow_reader = ParquetRecordBatchReader::try_new(file, batch_size)?;
 
+    let schema = arrow_reader.schema();
+
     // let schema = arrow_reader.get_schema()?;
     // // Only read columns that we used
     // let column_indices = ops
@@ -107,14 +184,406 @@ where
     //     )
     //     .unwrap();
 
-    let (succeeded, failed) = replay(
-        arrow_reader
+    pool::run(ops, njobs, placement, arrow_reader, |ops, batches| {
+        replay(&schema, batches.map(Cow::Owned), ops, Some(nrows), strict)
+    })?
+}
+
+/// Decode the next batches on a background thread while `f` works on the current one. A batch failing to decode
+/// fails the whole replay, instead of silently cutting it short.
+#[throws(Error)]
+pub(crate) fn prefetch<I, F, R>(batches: I, f: F) -> R
+where
+    I: Iterator<Item = Result<RecordBatch, ArrowError>> + Send,
+    F: FnOnce(&mut dyn Iterator<Item = RecordBatch>) -> R,
+{
+    let (tx, rx) = mpsc::sync_channel(PREFETCH_BATCHES);
+    let mut decode_error = None;
+    let result = thread::scope(|s| {
+        s.spawn(move || {
+            for batch in batches {
+                let failed = batch.is_err();
+                if tx.send(batch).is_err() || failed {
+                    break; // the evaluator is gone, or there is nothing more to decode
+                }
+            }
+        });
+
+        // Dropping the receiver at the end stops the decoding, if `f` returns early
+        let mut decoded = rx
             .into_iter()
-            .filter_map(|b| b.ok())
-            .map(Cow::Owned),
-        ops,
-        Some(nrows),
-    )?;
+            .map_while(|b| b.map_err(|e| decode_error = Some(e)).ok());
+        f(&mut decoded)
+    });
+
+    if let Some(e) = decode_error {
+        throw!(anyhow!("failed to decode the batch: {}", e))
+    }
+    result
+}
+
+// The numbers after `cut` are moved away from their values, so that each of them changes for sure (NaNs aside)
+fn perturb<A, F>(col: &dyn Array, cut: usize, f: F) -> ArrayRef
+where
+    A: ArrowPrimitiveType,
+    F: Fn(A::Native) -> A::Native,
+{
+    let col = as_primitive_array::<A>(col);
+    let perturbed: PrimitiveArray<A> = col
+        .iter()
+        .enumerate()
+        .map(|(r, v)| if r > cut { v.map(&f) } else { v })
+        .collect();
+    Arc::new(perturbed.with_data_type(col.data_type().clone()))
+}
+
+// The batch with the numbers in the rows after `cut` perturbed
+#[throws(Error)]
+fn perturb_batch(data: &RecordBatch, cut: usize) -> RecordBatch {
+    let columns = data
+        .columns()
+        .iter()
+        .map(|col| match col.data_type() {
+            DataType::Float64 => perturb::<Float64Type, _>(col.as_ref(), cut, |v| v + v.abs() + 1.),
+            DataType::Decimal128(_, _) => perturb::<Decimal128Type, _>(col.as_ref(), cut, |v| {
+                v.saturating_add(v.saturating_abs()).saturating_add(1)
+            }),
+            _ => col.clone(),
+        })
+        .collect();
+    RecordBatch::try_new(data.schema(), columns)?
+}
+
+// A factor under audit, with a copy of it for each cut not reached yet
+struct Audit {
+    original: BoxOp<RecordBatch>,
+    forks: Vec<(usize, BoxOp<RecordBatch>)>,
+    failed: Option<Error>,
+}
+
+/// Verify empirically that the factors do not look into the future. The `nrows` rows of the data are cut at
+/// `samples` evenly spaced rows, and for each cut the numbers in the rows after it are perturbed, the timestamps and
+/// the categories are kept. A causal factor produces exactly the same outputs up to the cut. The operators can only
+/// peek ahead within a batch, so only the rest of the batch holding the cut is perturbed. The batches are replayed
+/// one by one, and none is read after the one holding the last cut. Pass fresh copies of the factors.
+/// The errors are keyed by the operator's position in `ops`.
+#[throws(Error)]
+pub fn audit_lookahead<I>(
+    batches: I,
+    nrows: usize,
+    ops: Vec<BoxOp<RecordBatch>>,
+    samples: usize,
+) -> HashMap<usize, Error>
+where
+    I: Iterator<Item = RecordBatch>,
+{
+    if nrows < 2 || samples == 0 {
+        return HashMap::new();
+    }
+
+    let cuts: Vec<usize> = (1..=samples)
+        .map(|j| j * (nrows - 1) / (samples + 1))
+        .unique()
+        .collect();
+    let last = cuts[cuts.len() - 1];
+    let mut audits: Vec<Option<Audit>> = ops
+        .into_iter()
+        .map(|mut op| {
+            op.reset();
+            let forks = cuts.iter().map(|&cut| (cut, op.clone())).collect();
+            Some(Audit {
+                original: op,
+                forks,
+                failed: None,
+            })
+        })
+        .collect();
+
+    let mut start = 0;
+    for data in batches {
+        let len = data.num_rows();
+        if len == 0 {
+            continue;
+        }
+        // The cuts in this batch as rows of the batch, each followed by at least a row
+        let here: Vec<(usize, RecordBatch)> = cuts
+            .iter()
+            .filter(|&&cut| cut >= start && cut < start + len && len >= 2)
+            .map(|&cut| (cut - start).min(len - 2))
+            .unique()
+            .map(|cut| Ok((cut, perturb_batch(&data, cut)?)))
+            .collect::<Result<_>>()?;
+
+        audits.par_iter_mut().for_each(|slot| {
+            let audit = match slot {
+                Some(audit) if audit.failed.is_none() => audit,
+                _ => return,
+            };
+            // A factor failing on the original data is not auditable
+            let expected = match audit.original.checked_update(&data) {
+                Ok(vals) => vals,
+                Err(_) => {
+                    *slot = None;
+                    return;
+                }
+            };
+
+            for (cut, mut fork) in mem::take(&mut audit.forks) {
+                if cut >= start + len {
+                    // Not there yet, the original data is replayed
+                    if fork.checked_update(&data).is_ok() {
+                        audit.forks.push((cut, fork));
+                    }
+                    continue;
+                }
+                // A batch of a single row has no cut
+                let local = (cut - start).min(len.saturating_sub(2));
+                let tb = match here.iter().find(|(c, _)| *c == local) {
+                    Some((_, tb)) => tb,
+                    None => continue,
+                };
+                let got = match fork.checked_update(tb) {
+                    Ok(got) => got,
+                    Err(_) => continue,
+                };
+                let row = (0..=local).find(|&r| {
+                    got[r].to_bits() != expected[r].to_bits()
+                        && !(got[r].is_nan() && expected[r].is_nan())
+                });
+                if let Some(row) = row {
+                    audit.failed = Some(anyhow!(
+                        "{} looks into the future: its output at row {} changed after perturbing the rows after {}",
+                        audit.original.to_string(),
+                        start + row,
+                        start + local
+                    ));
+                    return;
+                }
+            }
+        });
+
+        start += len;
+        if start > last {
+            break;
+        }
+    }
+
+    audits
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, audit)| Some((i, audit?.failed?)))
+        .collect()
+}
+
+/// `audit_lookahead` on the parquet file, decoded batch by batch.
+#[throws(Error)]
+pub fn audit_file(
+    path: &str,
+    ops: Vec<BoxOp<RecordBatch>>,
+    samples: usize,
+) -> HashMap<usize, Error> {
+    let nrows = num_rows(path)?;
+    let arrow_reader = ParquetRecordBatchReader::try_new(File::open(path)?, DEFAULT_BATCH_SIZE)?;
+    prefetch(arrow_reader, |batches| {
+        audit_lookahead(batches, nrows, ops, samples)
+    })??
+}
+
+#[cfg(test)]
+mod test {
+    use super::{audit_lookahead, prefetch, replay};
+    use crate::ops::{from_str, BoxOp, Operator};
+    use anyhow::Error;
+    use arrow::{
+        array::{as_primitive_array, ArrayRef, Float64Array},
+        datatypes::Float64Type,
+        error::ArrowError,
+        record_batch::RecordBatch,
+    };
+    use fehler::throws;
+    use std::{borrow::Cow, sync::Arc};
+
+    // Reads `:a` but drops the last row of each batch
+    #[derive(Clone)]
+    struct Short(String);
+
+    impl Operator<RecordBatch> for Short {
+        #[throws(Error)]
+        fn update<'a>(&mut self, tb: &'a RecordBatch) -> Cow<'a, [f64]> {
+            vec![1.; tb.num_rows() - 1].into()
+        }
+
+        fn ready_offset(&self) -> usize {
+            0
+        }
+
+        fn to_string(&self) -> String {
+            self.0.clone()
+        }
+
+        fn reset(&mut self) {}
+
+        fn len(&self) -> usize {
+            1
+        }
+
+        fn depth(&self) -> usize {
+            1
+        }
+
+        fn child_indices(&self) -> Vec<usize> {
+            vec![]
+        }
+
+        fn columns(&self) -> Vec<String> {
+            vec!["a".to_string()]
+        }
+
+        fn get(&self, i: usize) -> Option<BoxOp<RecordBatch>> {
+            if i == 0 {
+                Some(Box::new(self.clone()))
+            } else {
+                None
+            }
+        }
+
+        fn insert(&mut self, _: usize, _: BoxOp<RecordBatch>) -> Option<BoxOp<RecordBatch>> {
+            None
+        }
+    }
+
+    #[test]
+    fn length_mismatch() {
+        let xs = Arc::new(Float64Array::from(vec![1., 2., 3.])) as ArrayRef;
+        let tb = RecordBatch::try_from_iter(vec![("a", xs)]).unwrap();
+
+        // The last of the identical looking subtrees is broken
+        for (repr, i, path) in [
+            ("(+ (Mean 3 :a) (Mean 3 :a))", 4, "1/0"),
+            ("(If (> :a 0) (Mean 3 :a) (Mean 3 :a))", 7, "2/0"),
+            ("(Corr 3 (Mean 3 :a) (Neg (Mean 3 :a)))", 5, "1/0/0"),
+        ] {
+            let mut op = from_str::<RecordBatch>(repr, &Default::default()).unwrap();
+            op.insert(i, Box::new(Short(":a".to_string())));
+            assert_eq!(op.to_string(), repr);
 
-    (succeeded, failed)
+            let batches = vec![Cow::Borrowed(&tb), Cow::Borrowed(&tb)];
+            let (_, failed) = replay(&tb.schema(), batches, vec![&mut *op], None, false).unwrap();
+            let e = failed[&0].to_string();
+            assert!(e.contains(&format!("the node at path {:?}", path)), "{}", e);
+            assert!(
+                e.contains(":a produced 2 values for a batch of 3 rows in batch 0"),
+                "{}",
+                e
+            );
+        }
+    }
+
+    #[test]
+    fn decoding_errors() {
+        let xs = Arc::new(Float64Array::from(vec![1., 2.])) as ArrayRef;
+        let tb = RecordBatch::try_from_iter(vec![("a", xs)]).unwrap();
+        let batches = |n: usize| (0..n).map(|_| Ok(tb.clone())).collect::<Vec<_>>();
+
+        let mut op = from_str::<RecordBatch>("(Sum 2 :a)", &Default::default()).unwrap();
+        let (succeeded, failed) = prefetch(batches(3).into_iter(), |batches| {
+            replay(
+                &tb.schema(),
+                batches.map(Cow::Owned),
+                vec![&mut *op],
+                None,
+                false,
+            )
+        })
+        .unwrap()
+        .unwrap();
+        assert!(failed.is_empty());
+        assert_eq!(succeeded[&0].len(), 6);
+
+        // The batches after a corrupt one are not silently dropped, the replay fails
+        let mut corrupt = batches(3);
+        corrupt[1] = Err(ArrowError::ParquetError("corrupt page".to_string()));
+        let e = prefetch(corrupt.into_iter(), |batches| batches.count()).unwrap_err();
+        assert!(e.to_string().contains("corrupt page"), "{}", e);
+
+        // Nor does the decoding block when the batches are not all taken
+        assert_eq!(prefetch(batches(10).into_iter(), |_| 1).unwrap(), 1);
+    }
+
+    // Outputs the next row of `:a`, i.e. looks one row ahead
+    #[derive(Clone)]
+    struct Peek;
+
+    impl Operator<RecordBatch> for Peek {
+        #[throws(Error)]
+        fn update<'a>(&mut self, tb: &'a RecordBatch) -> Cow<'a, [f64]> {
+            let col = as_primitive_array::<Float64Type>(tb.column_by_name("a").unwrap());
+            let vals = col.values();
+            let next: Vec<_> = (1..=vals.len())
+                .map(|r| vals.get(r).copied().unwrap_or(f64::NAN))
+                .collect();
+            next.into()
+        }
+
+        fn ready_offset(&self) -> usize {
+            0
+        }
+
+        fn to_string(&self) -> String {
+            "(Peek :a)".to_string()
+        }
+
+        fn reset(&mut self) {}
+
+        fn len(&self) -> usize {
+            1
+        }
+
+        fn depth(&self) -> usize {
+            1
+        }
+
+        fn child_indices(&self) -> Vec<usize> {
+            vec![]
+        }
+
+        fn columns(&self) -> Vec<String> {
+            vec!["a".to_string()]
+        }
+
+        fn get(&self, i: usize) -> Option<BoxOp<RecordBatch>> {
+            if i == 0 {
+                Some(Box::new(self.clone()))
+            } else {
+                None
+            }
+        }
+
+        fn insert(&mut self, _: usize, _: BoxOp<RecordBatch>) -> Option<BoxOp<RecordBatch>> {
+            None
+        }
+    }
+
+    #[test]
+    fn lookahead() {
+        let batch = |xs: Vec<f64>| {
+            let xs = Arc::new(Float64Array::from(xs)) as ArrayRef;
+            RecordBatch::try_from_iter(vec![("a", xs)]).unwrap()
+        };
+        let batches = vec![batch(vec![1., 2., 3.]), batch(vec![4., 5., 6.])];
+
+        // The first cut is the last but one row of its batch, the single row after it has to change
+        let ops = vec![
+            from_str::<RecordBatch>("(Mean 2 :a)", &Default::default()).unwrap(),
+            Box::new(Peek) as BoxOp<RecordBatch>,
+        ];
+        let failed = audit_lookahead(batches.into_iter(), 6, ops, 2).unwrap();
+        assert_eq!(failed.keys().collect::<Vec<_>>(), vec![&1]);
+        let e = failed[&1].to_string();
+        assert!(
+            e.contains("its output at row 1 changed after perturbing the rows after 1"),
+            "{}",
+            e
+        );
+    }
 }
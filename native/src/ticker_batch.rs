@@ -1,31 +1,207 @@
+use anyhow::{anyhow, Error};
 use arrow::{
-    array::{as_primitive_array, Float64Array},
-    record_batch::RecordBatch,
+    array::{as_primitive_array, Array, AsArray, Float64Array},
+    datatypes::{
+        ArrowPrimitiveType, DataType, Decimal128Type, Schema, TimeUnit, TimestampMicrosecondType,
+        TimestampMillisecondType, TimestampNanosecondType, TimestampSecondType,
+    },
+    record_batch::{RecordBatch, RecordBatchOptions},
 };
-use std::collections::HashMap;
+use fehler::{throw, throws};
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
+
+/// Whether a column of this type can be read by the operators.
+pub fn is_supported(dt: &DataType) -> bool {
+    match dt {
+        DataType::Float64 | DataType::Decimal128(_, _) | DataType::Timestamp(_, _) => true,
+        DataType::Dictionary(_, value) => is_categorical(value) || is_supported(value),
+        _ => false,
+    }
+}
+
+// String dictionaries are read as their category codes
+fn is_categorical(dt: &DataType) -> bool {
+    matches!(dt, DataType::Utf8 | DataType::LargeUtf8)
+}
+
+/// Read a column as f64s, `None` if the type is not supported.
+pub fn to_f64(col: &dyn Array) -> Option<Cow<'_, [f64]>> {
+    match col.data_type() {
+        DataType::Float64 => {
+            let col: &Float64Array = as_primitive_array(col);
+            Some(Cow::Borrowed(col.values()))
+        }
+        &DataType::Decimal128(_, scale) => {
+            // Decimals are converted on the fly, precision beyond f64 is lost
+            let col = as_primitive_array::<Decimal128Type>(col);
+            let unit = 10f64.powi(scale as i32);
+            Some(Cow::Owned(
+                col.iter()
+                    .map(|v| v.map_or(f64::NAN, |v| v as f64 / unit))
+                    .collect(),
+            ))
+        }
+        DataType::Timestamp(unit, _) => {
+            // Timestamps are read as seconds since the epoch
+            Some(Cow::Owned(match unit {
+                TimeUnit::Second => seconds::<TimestampSecondType>(col, 1e0),
+                TimeUnit::Millisecond => seconds::<TimestampMillisecondType>(col, 1e3),
+                TimeUnit::Microsecond => seconds::<TimestampMicrosecondType>(col, 1e6),
+                TimeUnit::Nanosecond => seconds::<TimestampNanosecondType>(col, 1e9),
+            }))
+        }
+        DataType::Dictionary(_, value) => {
+            let dict = col.as_any_dictionary();
+            if dict.values().is_empty() {
+                // only nulls can be encoded by an empty dictionary
+                return Some(Cow::Owned(vec![f64::NAN; dict.len()]));
+            }
+
+            // The keys of the null slots are arbitrary, these are read as NaN
+            let keys = dict
+                .normalized_keys()
+                .into_iter()
+                .enumerate()
+                .map(|(i, k)| Some(k).filter(|_| !dict.is_null(i)));
+            if is_categorical(value) {
+                return Some(Cow::Owned(
+                    keys.map(|k| k.map_or(f64::NAN, |k| k as f64)).collect(),
+                ));
+            }
+
+            let values = to_f64(dict.values().as_ref())?;
+            Some(Cow::Owned(
+                keys.map(|k| k.map_or(f64::NAN, |k| values[k])).collect(),
+            ))
+        }
+        _ => None,
+    }
+}
+
+// The seconds since the epoch, NaN for the nulls
+fn seconds<A: ArrowPrimitiveType<Native = i64>>(col: &dyn Array, per_second: f64) -> Vec<f64> {
+    as_primitive_array::<A>(col)
+        .iter()
+        .map(|v| v.map_or(f64::NAN, |v| v as f64 / per_second))
+        .collect()
+}
+
+/// The outcome of resolving a column reference against the column names of the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lookup {
+    Found(usize),
+    Missing,
+    /// The name appears this many times in the input.
+    Ambiguous(usize),
+}
+
+/// Resolve `column` against `names`. A name appearing more than once (e.g. after a join) is ambiguous,
+/// unless qualified by the 1-based occurrence, e.g. `bid1#2` is the second column named `bid1`.
+pub fn lookup<'a, I>(names: I, column: &str) -> Lookup
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let names: Vec<_> = names.into_iter().collect();
+    let positions = |column: &str| -> Vec<usize> {
+        names
+            .iter()
+            .enumerate()
+            .filter(|(_, &name)| name == column)
+            .map(|(i, _)| i)
+            .collect()
+    };
+
+    match &*positions(column) {
+        [i] => return Lookup::Found(*i),
+        [] => {}
+        matches => return Lookup::Ambiguous(matches.len()),
+    }
+
+    if let Some((name, k)) = column.rsplit_once('#') {
+        if let Ok(k) = k.parse::<usize>() {
+            if let Some(i) = k
+                .checked_sub(1)
+                .and_then(|k| positions(name).get(k).cloned())
+            {
+                return Lookup::Found(i);
+            }
+        }
+    }
+
+    Lookup::Missing
+}
 
 // Tickers should be sync because we will do parallel replay
 pub trait TickerBatch: Sync + 'static {
-    fn index_of(&self, name: &str) -> Option<usize>;
-    fn values<'a>(&'a self, i: usize) -> Option<&'a [f64]>;
+    fn lookup(&self, name: &str) -> Lookup;
+    fn values<'a>(&'a self, i: usize) -> Option<Cow<'a, [f64]>>;
     fn len(&self) -> usize;
+    /// The rows `offset..offset + len` of the batch.
+    fn slice(&self, offset: usize, len: usize) -> Self
+    where
+        Self: Sized;
+    /// A single row without any column, e.g. to evaluate the nodes reading no column.
+    fn blank() -> Self
+    where
+        Self: Sized;
+
+    /// Whether the batch is replayed in strict mode, i.e. the operators run their deep checks on it.
+    fn is_strict(&self) -> bool {
+        false
+    }
+
+    #[throws(Error)]
+    fn index_of(&self, name: &str) -> usize {
+        match self.lookup(name) {
+            Lookup::Found(i) => i,
+            Lookup::Missing => throw!(anyhow!("No such colume {}", name)),
+            Lookup::Ambiguous(n) => throw!(anyhow!(
+                "Column {name} is ambiguous, it appears {n} times in the input. Use :{name}#1 to :{name}#{n} to pick one",
+                name = name,
+                n = n
+            )),
+        }
+    }
 }
 
 impl TickerBatch for RecordBatch {
-    fn index_of(&self, name: &str) -> Option<usize> {
+    fn lookup(&self, name: &str) -> Lookup {
         let schema = self.schema();
-        schema.index_of(name).ok()
+        lookup(schema.fields().iter().map(|f| f.name().as_str()), name)
     }
 
-    fn values(&self, i: usize) -> Option<&[f64]> {
-        let col = self.column(i);
-        let col: &Float64Array = as_primitive_array(col);
-        Some(col.values())
+    fn values<'a>(&'a self, i: usize) -> Option<Cow<'a, [f64]>> {
+        to_f64(self.column(i).as_ref())
     }
 
     fn len(&self) -> usize {
         self.num_rows()
     }
+
+    fn slice(&self, offset: usize, len: usize) -> Self {
+        RecordBatch::slice(self, offset, len)
+    }
+
+    fn blank() -> Self {
+        let options = RecordBatchOptions::new().with_row_count(Some(1));
+        RecordBatch::try_new_with_options(Arc::new(Schema::empty()), vec![], &options).unwrap()
+    }
+
+    fn is_strict(&self) -> bool {
+        self.schema().metadata().contains_key(STRICT_KEY)
+    }
+}
+
+// The schema metadata marking a batch replayed in strict mode
+const STRICT_KEY: &str = "factor_expr.strict";
+
+/// The same batch replayed in strict mode (see `TickerBatch::is_strict`). The flag goes along with the batch,
+/// so that the replays running at the same time do not affect each other.
+pub fn strict(tb: &RecordBatch) -> RecordBatch {
+    let mut metadata = tb.schema().metadata().clone();
+    metadata.insert(STRICT_KEY.to_string(), "true".to_string());
+    let schema = tb.schema().as_ref().clone().with_metadata(metadata);
+    tb.clone().with_schema(Arc::new(schema)).unwrap()
 }
 
 pub struct SingleRow {
@@ -34,15 +210,87 @@ pub struct SingleRow {
 }
 
 impl TickerBatch for SingleRow {
-    fn index_of(&self, name: &str) -> Option<usize> {
-        self.schema.get(name).cloned()
+    fn lookup(&self, name: &str) -> Lookup {
+        match self.schema.get(name) {
+            Some(&i) => Lookup::Found(i),
+            None => Lookup::Missing,
+        }
     }
 
-    fn values(&self, i: usize) -> Option<&[f64]> {
-        Some(&self.data[i..i + 1])
+    fn values<'a>(&'a self, i: usize) -> Option<Cow<'a, [f64]>> {
+        Some(Cow::Borrowed(&self.data[i..i + 1]))
     }
 
     fn len(&self) -> usize {
         1
     }
+
+    fn slice(&self, offset: usize, len: usize) -> Self {
+        assert!(offset == 0 && len == 1, "slicing out of a single row");
+        SingleRow {
+            schema: self.schema.clone(),
+            data: self.data.clone(),
+        }
+    }
+
+    fn blank() -> Self {
+        SingleRow {
+            schema: HashMap::new(),
+            data: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{strict, to_f64, TickerBatch};
+    use arrow::{
+        array::{
+            ArrayRef, Decimal128Array, DictionaryArray, Float64Array, Int32Array, StringArray,
+            TimestampMillisecondArray,
+        },
+        datatypes::Int32Type,
+        record_batch::RecordBatch,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn strictness() {
+        let xs = Arc::new(Float64Array::from(vec![1., 2., 3.])) as ArrayRef;
+        let tb = RecordBatch::try_from_iter(vec![("a", xs)]).unwrap();
+
+        // Only the marked copy is strict, down to its slices
+        let marked = strict(&tb);
+        assert!(marked.is_strict());
+        assert!(TickerBatch::slice(&marked, 1, 2).is_strict());
+        assert!(!tb.is_strict());
+        assert_eq!(marked.index_of("a").unwrap(), 0);
+    }
+
+    #[test]
+    fn nulls() {
+        let read = |col: ArrayRef| -> Vec<Option<f64>> {
+            to_f64(col.as_ref())
+                .unwrap()
+                .iter()
+                .map(|&v| Some(v).filter(|v| !v.is_nan()))
+                .collect()
+        };
+
+        let decimals = Decimal128Array::from(vec![Some(125), None, Some(-350)])
+            .with_precision_and_scale(10, 2)
+            .unwrap();
+        assert_eq!(read(Arc::new(decimals)), [Some(1.25), None, Some(-3.5)]);
+        let stamps = TimestampMillisecondArray::from(vec![None, Some(1500)]);
+        assert_eq!(read(Arc::new(stamps)), [None, Some(1.5)]);
+
+        // The keys of the null slots point into a non-empty dictionary
+        let keys = Int32Array::from(vec![Some(1), None, Some(0)]);
+        let prices = Arc::new(Float64Array::from(vec![1.5, 2.5]));
+        let dict = DictionaryArray::<Int32Type>::try_new(keys.clone(), prices).unwrap();
+        assert_eq!(read(Arc::new(dict)), [Some(2.5), None, Some(1.5)]);
+        let sides = Arc::new(StringArray::from(vec!["buy", "sell"]));
+        let dict = DictionaryArray::<Int32Type>::try_new(keys, sides).unwrap();
+        assert_eq!(read(Arc::new(dict)), [Some(1.), None, Some(0.)]);
+    }
 }
@@ -1,5 +1,5 @@
 from .replay import replay, replay_iter
-from ._lib import Factor, __build__
+from ._lib import Factor, ParseError, define, operators, undefine, __build__
 from importlib.metadata import version, PackageNotFoundError
 
 try:
@@ -26,6 +26,11 @@ async def replay(
     pbar: bool = True,
     verbose: bool = False,
     output: Literal["pyarrow", "raw"] = "pyarrow",
+    pin_threads: bool = False,
+    numa: bool = False,
+    strict: bool = False,
+    lookahead_samples: int = 0,
+    boolean: bool = False,
 ) -> pa.Table:
     """
     Replay a list of factors on a bunch of data.
@@ -52,6 +57,24 @@ async def replay(
         If True, failed factors will be printed out in stderr.
     output: Literal["pyarrow" | "raw"] = "pyarrow"
         The return format, can be pyarrow Table ("pyarrow") or un-concatenated pyarrow Tables ("raw").
+    pin_threads: bool = False
+        Pin each factor worker thread to a CPU core. The datasets replayed at the same time (see `n_data_jobs`)
+        are pinned to different cores, the workers left without a free core are not pinned.
+    numa: bool = False
+        Partition the factors by NUMA node and replay each partition with workers local to that node.
+        Useful on multi-socket machines where cross-socket memory traffic limits the scaling.
+        Each dataset is still read once and shared by the partitions.
+    strict: bool = False
+        Turn on the deep checks of the factors, e.g. the inputs of a factor must be NaN during its warm-up period.
+        A factor failing the checks is reported as failed. Useful for debugging a suspicious factor, at the cost of speed.
+        Only this call is checked, the other replays running at the same time are not affected.
+    lookahead_samples: int = 0
+        Audit the factors for lookahead bias. Each dataset is cut at `lookahead_samples` evenly spaced rows and the numbers
+        in the rest of the batch after each cut are perturbed, a factor whose outputs up to the cut change is reported as failed.
+        0 turns the audit off. The audit replays every factor once more, then once per cut up to the batch of the cut,
+        streaming a file batch by batch up to the last cut.
+    boolean: bool = False
+        Return the factors rooted at a logic function, e.g. `(> :close :open)`, as boolean columns instead of 0/1 floats.
 
     Examples
     --------
@@ -96,12 +119,25 @@ async def replay(
             n_data_jobs=n_data_jobs,
             n_factor_jobs=n_factor_jobs,
             verbose=verbose,
+            pin_threads=pin_threads,
+            numa=numa,
+            strict=strict,
+            lookahead_samples=lookahead_samples,
+            boolean=boolean,
         ):
             factor_tables.append(fvals)
             progress.update(1)
 
     if output == "pyarrow":
-        factor_table = pa.concat_tables(factor_tables)
+        if factor_tables:
+            factor_table = pa.concat_tables(factor_tables)
+        else:
+            factor_table = pa.table(
+                {
+                    _column_name(f): pa.array([], pa.bool_() if boolean and f.is_predicate() else pa.float64())
+                    for f in factors
+                }
+            )
     elif output == "raw":
         factor_table = factor_tables
     else:
@@ -121,6 +157,11 @@ async def replay_iter(
     index_col: Optional[str] = None,
     unordered: bool = False,
     verbose: bool = False,
+    pin_threads: bool = False,
+    numa: bool = False,
+    strict: bool = False,
+    lookahead_samples: int = 0,
+    boolean: bool = False,
 ) -> AsyncGenerator[Tuple[str, pa.Table], None]:
     LOOP = get_event_loop()
 
@@ -139,6 +180,11 @@ async def replay_iter(
                     batch_size=batch_size,
                     verbose=verbose,
                     n_jobs=n_factor_jobs,
+                    pin_threads=pin_threads,
+                    numa=numa,
+                    strict=strict,
+                    lookahead_samples=lookahead_samples,
+                    boolean=boolean,
                 ),
             )
 
@@ -162,24 +208,22 @@ def table_to_pointers(tb: pa.Table):
     schema = []
     arrays = []
     keepalive = []
-    for i, batch in enumerate(batches):
-        for array, name in zip(batch.columns, batch.column_names):
-            c_array = ffi.new("struct ArrowArray*")
-            ptr_array = int(ffi.cast("uintptr_t", c_array))
-            if i == 0:
-                c_schema = ffi.new("struct ArrowSchema*")
-                ptr_schema = int(ffi.cast("uintptr_t", c_schema))
 
-                array._export_to_c(ptr_array, ptr_schema)
+    # Export the schema from the table rather than the first batch, so that
+    # a table without any batches still carries its columns over.
+    for field in tb.schema:
+        c_schema = ffi.new("struct ArrowSchema*")
+        ptr_schema = int(ffi.cast("uintptr_t", c_schema))
+        field._export_to_c(ptr_schema)
 
-                name = ffi.new("char[]", name.encode("utf8"))
-                c_schema.name = name
+        schema.append(ptr_schema)
+        keepalive.append(c_schema)
 
-                schema.append(ptr_schema)
-                keepalive.append(name)
-                keepalive.append(c_schema)
-            else:
-                array._export_to_c(ptr_array)
+    for batch in batches:
+        for array in batch.columns:
+            c_array = ffi.new("struct ArrowArray*")
+            ptr_array = int(ffi.cast("uintptr_t", c_array))
+            array._export_to_c(ptr_array)
 
             arrays.append(ptr_array)
             keepalive.append(c_array)
@@ -194,51 +238,57 @@ def _replay_single(
     batch_size: int = 40960,
     n_jobs: int = 1,
     verbose: bool = False,
+    pin_threads: bool = False,
+    numa: bool = False,
+    strict: bool = False,
+    lookahead_samples: int = 0,
+    boolean: bool = False,
 ) -> Tuple[pa.Table, Set[str]]:
     if isinstance(file, str):
-        replay_result = _native_replay_file(file, factors, njobs=n_jobs)
+        replay_result = _native_replay_file(
+            file,
+            factors,
+            njobs=n_jobs,
+            pin_threads=pin_threads,
+            numa=numa,
+            strict=strict,
+            lookahead_samples=lookahead_samples,
+            boolean=boolean,
+        )
     else:
         schema = file.schema
         ffi_schema, ffi_arrays, keepalive = table_to_pointers(file)
 
-        replay_result = _native_replay(ffi_schema, ffi_arrays, factors, njobs=n_jobs)
-
-    table_datas, table_names = [], []
-
-    for i, (data_ptr, schema_ptr) in replay_result["succeeded"].items():
-        arr = pa.Array._import_from_c(data_ptr, schema_ptr)
-
-        table_datas.append(arr)
-        table_names.append(str(factors[i]))
-
-    # Fill in the failed columns
-    if isinstance(file, pa.Table):
-        N = len(file)
-    elif table_datas:
-        N = len(table_datas[0])
-    else:
-        tb = pq.read_metadata(file)
-        N = tb.num_rows
-
-    nanarr = pa.array(np.empty(N, "f8"), mask=np.ones(N, "b1"))
+        replay_result = _native_replay(
+            ffi_schema,
+            ffi_arrays,
+            factors,
+            njobs=n_jobs,
+            pin_threads=pin_threads,
+            numa=numa,
+            strict=strict,
+            lookahead_samples=lookahead_samples,
+            boolean=boolean,
+        )
 
-    for i, reason in replay_result["failed"].items():
-        table_datas.append(nanarr)
-        table_names.append(str(factors[i]))
+    # The native side returns one column per factor, in the order passed in,
+    # with the failed factors filled by nulls.
+    tb = pa.RecordBatchReader.from_stream(replay_result["stream"]).read_all()
 
-        if verbose:
+    if verbose:
+        for i, reason in replay_result["failed"].items():
             print(f"{factors[i]} failed: {reason}", file=stderr)
 
-    tb = pa.Table.from_arrays(table_datas, names=table_names)
-
-    # sort the columns based on the order passed in
-    tb = tb.select([str(f) for f in factors])
-
     return (
         tb,
-        {str(factors[k]) for k in replay_result["failed"].keys()},
+        {_column_name(factors[k]) for k in replay_result["failed"].keys()},
     )
 
 
+def _column_name(factor: Factor) -> str:
+    # The output column is named after the factor if it has a name, otherwise the expression
+    return factor.name if factor.name is not None else str(factor)
+
+
 def named(name, func, *args, **kwargs):
     return name, func(*args, **kwargs)
@@ -1,6 +1,8 @@
 import numpy as np
 import pandas as pd
 import asyncio
+import pyarrow as pa
+import pytest
 
 from ... import Factor, replay
 
@@ -92,6 +94,28 @@ def test_signed_power():
     ).all()
 
 
+def test_clip():
+    df = pd.read_parquet(FILENAME)
+    s = df.price_ask_l1_open
+    lo, hi = s.quantile(0.2), s.quantile(0.8)
+
+    result = asyncio.run(
+        replay(
+            [FILENAME],
+            [
+                Factor(f"(Clip {lo} {hi} :price_ask_l1_open)"),
+                Factor(f"(ClipLower {lo} :price_ask_l1_open)"),
+                Factor(f"(ClipUpper {hi} :price_ask_l1_open)"),
+            ],
+            pbar=False,
+        )
+    )
+
+    assert np.isclose(s.clip(lo, hi), result.to_pandas().iloc[:, 0]).all()
+    assert np.isclose(s.clip(lower=lo), result.to_pandas().iloc[:, 1]).all()
+    assert np.isclose(s.clip(upper=hi), result.to_pandas().iloc[:, 2]).all()
+
+
 def test_log():
     df = pd.read_parquet(FILENAME)
 
@@ -103,6 +127,38 @@ def test_log():
     ).all()
 
 
+def test_exp():
+    df = pd.read_parquet(FILENAME)
+
+    result = asyncio.run(replay([FILENAME], [Factor("(Exp (LogAbs :price_ask_l1_open))")], pbar=False))
+
+    assert np.isclose(
+        np.abs(df.price_ask_l1_open),
+        result.to_pandas().iloc[:, 0],
+    ).all()
+
+
+def test_roots():
+    df = pd.read_parquet(FILENAME)
+
+    result = asyncio.run(
+        replay(
+            [FILENAME],
+            [Factor("(Sqrt (Abs :price_ask_l1_open))"), Factor("(Cbrt (Neg :price_ask_l1_open))")],
+            pbar=False,
+        )
+    )
+
+    assert np.isclose(
+        np.sqrt(np.abs(df.price_ask_l1_open)),
+        result.to_pandas().iloc[:, 0],
+    ).all()
+    assert np.isclose(
+        np.cbrt(-df.price_ask_l1_open),
+        result.to_pandas().iloc[:, 1],
+    ).all()
+
+
 def test_sign():
     df = pd.read_parquet(FILENAME)
 
@@ -123,3 +179,47 @@ def test_abs():
         np.abs(df.price_ask_l1_open),
         result.to_pandas().iloc[:, 0],
     ).all()
+
+
+def test_div_zero_policy():
+    tb = pa.table({"a": [1.0, 2.0], "b": [0.0, 2.0]})
+
+    result = asyncio.run(replay([tb], [Factor("(/ :a :b)", zero="ieee")], pbar=False))
+    assert result.column(0).to_pylist() == [np.inf, 1.0]
+
+    result = asyncio.run(replay([tb], [Factor("(/ :a :b)", zero="nan")], pbar=False))
+    assert result.column(0).to_pylist() == [None, 1.0]
+
+    # The non-finite values are passed on by the pointwise parents, and rejected by the windows
+    result = asyncio.run(replay([tb], [Factor("(+ (/ :a :b) 1)", zero="ieee")], pbar=False))
+    assert result.column(0).to_pylist() == [np.inf, 2.0]
+
+    result = asyncio.run(replay([tb], [Factor("(* 2 (Neg (/ nan :a :b)))")], pbar=False))
+    assert result.column(0).to_pylist() == [None, -2.0]
+
+    with pytest.raises(ValueError):
+        Factor("(Mean 2 (/ :a :b))", zero="ieee")
+
+
+def test_dot():
+    df = pd.read_parquet(FILENAME)
+
+    result = asyncio.run(
+        replay(
+            [FILENAME],
+            [
+                Factor("(Dot 0.5 0.3 0.2 (Cols :price_ask_l1_open :price_ask_l1_high :price_bid_l1_open))"),
+                Factor("(WSum 2 :price_ask_l1_open -1 (Abs :price_bid_l1_open))"),
+            ],
+            pbar=False,
+        )
+    )
+
+    assert np.isclose(
+        0.5 * df.price_ask_l1_open + 0.3 * df.price_ask_l1_high + 0.2 * df.price_bid_l1_open,
+        result.to_pandas().iloc[:, 0],
+    ).all()
+    assert np.isclose(
+        2 * df.price_ask_l1_open - df.price_bid_l1_open.abs(),
+        result.to_pandas().iloc[:, 1],
+    ).all()
@@ -128,3 +128,37 @@ def test_if():
         )
         == result.to_pandas().iloc[:, 0].values
     ).all()
+
+
+def test_nan_policy():
+    import pyarrow as pa
+
+    tb = pa.table({"a": [np.nan, 1.0, -1.0], "b": [1.0, 1.0, 1.0]})
+
+    result = asyncio.run(
+        replay([tb], [Factor("(If :a :b (Neg :b))"), Factor("(! :a)")], pbar=False)
+    )
+    assert result.column(0).to_pylist() == [-1.0, 1.0, -1.0]
+    assert result.column(1).to_pylist() == [1.0, 0.0, 1.0]
+
+    result = asyncio.run(
+        replay(
+            [tb],
+            [
+                Factor("(If :a :b (Neg :b))", nan="propagate"),
+                Factor("(! :a)", nan="propagate"),
+                Factor("(And :a :b)", nan="propagate"),
+            ],
+            pbar=False,
+        )
+    )
+    assert result.column(0).to_pylist() == [None, 1.0, -1.0]
+    assert result.column(1).to_pylist() == [None, 0.0, 1.0]
+    assert result.column(2).to_pylist() == [None, 1.0, 0.0]
+
+    # The NaNs reach the logic functions through the pointwise nodes, the windows still fail on them
+    result = asyncio.run(
+        replay([tb], [Factor("(! (* 2 :a))"), Factor("(! (Mean 2 :a))")], pbar=False)
+    )
+    assert result.column(0).to_pylist() == [1.0, 0.0, 1.0]
+    assert result.column(1).to_pylist() == [None, None, None]
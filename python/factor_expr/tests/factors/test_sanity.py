@@ -1,6 +1,7 @@
 import numpy as np
 import pandas as pd
 import asyncio
+import pytest
 
 from ... import Factor, replay
 
@@ -17,3 +18,638 @@ def test_index():
             pbar=False,
         )
     )
+
+
+def test_failed_columns():
+    fs = [Factor("(Mean 10 :price_ask_l1_open)"), Factor("(+ :no_such_column 1)")]
+    result = asyncio.run(replay([FILENAME], fs, pbar=False))
+
+    assert result.column_names == [str(f) for f in fs]
+    assert result.column(1).null_count == len(result)
+
+
+def test_schema_validation():
+    from ..._lib import replay_file
+
+    f = Factor("(+ :no_such_column (- :price_ask_l1_open :another_missing))")
+    result = replay_file(FILENAME, [f], njobs=1)
+
+    assert "no_such_column" in result["failed"][0]
+    assert "another_missing" in result["failed"][0]
+
+
+def test_empty_table():
+    import pyarrow as pa
+
+    tb = pa.table({"price_ask_l1_open": pa.array([], pa.float64())})
+    fs = [Factor("(Mean 10 :price_ask_l1_open)"), Factor("(+ :no_such_column 1)")]
+    result = asyncio.run(replay([tb], fs, pbar=False))
+
+    assert result.column_names == [str(f) for f in fs]
+    assert len(result) == 0
+
+
+def test_duplicate_columns():
+    import pyarrow as pa
+
+    tb = pa.Table.from_arrays(
+        [pa.array([1.0, 2.0]), pa.array([3.0, 4.0])], names=["x", "x"]
+    )
+    fs = [Factor("(+ :x 0)"), Factor("(+ :x#1 0)"), Factor("(+ :x#2 0)")]
+    result = asyncio.run(replay([tb], fs, pbar=False))
+
+    assert result.column(0).null_count == len(result)
+    assert result.column(1).to_pylist() == [1.0, 2.0]
+    assert result.column(2).to_pylist() == [3.0, 4.0]
+
+
+def test_replay_twice():
+    import pyarrow as pa
+    from ..._lib import replay_file
+
+    f = Factor("(Mean 10 :price_ask_l1_open)")
+    first = pa.RecordBatchReader.from_stream(replay_file(FILENAME, [f], njobs=1)["stream"]).read_all()
+    second = pa.RecordBatchReader.from_stream(replay_file(FILENAME, [f], njobs=1)["stream"]).read_all()
+
+    assert first.equals(second)
+
+
+def test_decimal_columns():
+    import pyarrow as pa
+    from decimal import Decimal
+
+    tb = pa.table(
+        {"price": pa.array([Decimal("1.25"), Decimal("-3.50")], pa.decimal128(10, 2))}
+    )
+    result = asyncio.run(replay([tb], [Factor("(+ :price 0)")], pbar=False))
+
+    assert result.column(0).to_pylist() == [1.25, -3.5]
+
+
+def test_dictionary_columns():
+    import pyarrow as pa
+
+    tb = pa.table(
+        {
+            "price": pa.array([1.5, 2.5, 1.5]).dictionary_encode(),
+            "side": pa.array(["buy", "sell", "buy"]).dictionary_encode(),
+        }
+    )
+    result = asyncio.run(
+        replay([tb], [Factor("(+ :price 0)"), Factor("(+ :side 0)")], pbar=False)
+    )
+
+    assert result.column(0).to_pylist() == [1.5, 2.5, 1.5]
+    assert result.column(1).to_pylist() == [0.0, 1.0, 0.0]
+
+
+def test_getter_or():
+    import pyarrow as pa
+
+    f = Factor('(+ :a (col-or "imbalance" 0.5))')
+    with_col = pa.table({"a": [1.0, 2.0], "imbalance": [0.1, 0.2]})
+    without_col = pa.table({"a": [1.0, 2.0]})
+
+    result = asyncio.run(replay([with_col, without_col], [f], pbar=False))
+
+    assert result.column(0).to_pylist() == [1.1, 2.2, 1.5, 2.5]
+
+
+def test_row_index_and_time_delta():
+    import pyarrow as pa
+    from datetime import datetime
+
+    tb = pa.table(
+        {
+            "time": pa.array(
+                [datetime(2021, 1, 1, 0, 0, 0), datetime(2021, 1, 1, 0, 0, 2), datetime(2021, 1, 1, 0, 0, 7)],
+                pa.timestamp("ms"),
+            )
+        }
+    )
+    result = asyncio.run(
+        replay([tb], [Factor("(RowIndex)"), Factor("(TimeDelta :time)")], pbar=False)
+    )
+
+    assert result.column(0).to_pylist() == [0.0, 1.0, 2.0]
+    assert result.column(1).to_pylist() == [None, 2.0, 5.0]
+
+
+def test_session_reset():
+    import pyarrow as pa
+
+    tb = pa.table(
+        {
+            "date": pa.array([1.0, 1.0, 1.0, 2.0, 2.0, 3.0]),
+            "close": pa.array([1.0, 2.0, 3.0, 4.0, 5.0, 6.0]),
+        }
+    )
+    result = asyncio.run(
+        replay([tb], [Factor("(SessionReset :date (Sum 2 :close))")], batch_size=4, pbar=False)
+    )
+
+    assert result.column(0).to_pylist() == [None, 3.0, 5.0, None, 9.0, None]
+
+
+def test_session_only_at_root():
+    from factor_expr import ParseError, operators
+
+    for sexpr in [
+        "(+ :close (SessionReset :date (Sum 2 :close)))",
+        "(Mean 10 (SessionReset :date (Sum 2 :close)))",
+        "(+ :close (SessionWindow :date (Sum 2 :close)))",
+        "(Mean 10 (SessionWindow :date (Sum 2 :close)))",
+    ]:
+        with pytest.raises(ParseError, match="can only be the root"):
+            Factor(sexpr)
+
+    ops = {op["name"]: op for op in operators()}
+    assert ops["SessionReset"]["root_only"] and not ops["Mean"]["root_only"]
+
+
+def test_reset_daily_and_on():
+    import pyarrow as pa
+    from datetime import datetime
+
+    times = [datetime(2021, 1, 1, 23, 58), datetime(2021, 1, 1, 23, 59), datetime(2021, 1, 2, 0, 1)]
+    tb = pa.table(
+        {
+            "time": pa.array(times + [datetime(2021, 1, 2, 0, 2)], pa.timestamp("s")),
+            "close": pa.array([1.0, 2.0, 3.0, 4.0]),
+        }
+    )
+    result = asyncio.run(replay([tb], [Factor("(ResetDaily :time (Sum 2 :close))")], pbar=False))
+    assert result.column(0).to_pylist() == [None, 3.0, None, 7.0]
+
+    # An hour behind UTC, all the rows are on the same day
+    result = asyncio.run(replay([tb], [Factor("(ResetDaily -1 :time (Sum 2 :close))")], pbar=False))
+    assert result.column(0).to_pylist() == [None, 3.0, 5.0, 7.0]
+
+    tb = pa.table(
+        {
+            "gap": pa.array([0.0, 0.0, 1.0, 0.0, 1.0, 0.0]),
+            "close": pa.array([1.0, 2.0, 3.0, 4.0, 5.0, 6.0]),
+        }
+    )
+    result = asyncio.run(
+        replay([tb], [Factor("(ResetOn :gap (Sum 2 :close))")], batch_size=4, pbar=False)
+    )
+    assert result.column(0).to_pylist() == [None, 3.0, None, 7.0, None, 11.0]
+
+
+def test_session_window():
+    import pyarrow as pa
+
+    tb = pa.table(
+        {
+            "date": pa.array([1.0, 1.0, 1.0, 2.0, 2.0, 3.0]),
+            "close": pa.array([1.0, 2.0, 3.0, 4.0, 5.0, 6.0]),
+        }
+    )
+    result = asyncio.run(
+        replay(
+            [tb],
+            [
+                Factor("(SessionWindow :date (Sum 2 (Delay 1 :close)))"),
+                Factor("(SessionReset :date (Sum 2 (Delay 1 :close)))"),
+            ],
+            pbar=False,
+        )
+    )
+
+    assert result.column(0).to_pylist() == [None, None, 3.0, None, 7.0, None]
+    assert result.column(1).to_pylist() == [None, None, 3.0, None, None, None]
+
+    with pytest.raises(ValueError):
+        Factor("(SessionWindow :date (+ :close 1))")
+
+
+def test_strict():
+    fs = [
+        Factor("(Mean 10 (Std 20 :price_ask_l1_open))"),
+        Factor("(If (> :price_ask_l1_open 0) (Delay 3 :price_ask_l1_open) 0)"),
+    ]
+    strict = asyncio.run(replay([FILENAME], fs, pbar=False, strict=True))
+    loose = asyncio.run(replay([FILENAME], fs, pbar=False))
+
+    assert strict.equals(loose)
+
+
+def test_lookahead_audit():
+    fs = [
+        Factor("(Mean 10 (Std 20 :price_ask_l1_open))"),
+        Factor("(Delay 3 (LogReturn 5 :price_ask_l1_open))"),
+        Factor("(TimeDelta (RowIndex))"),
+    ]
+    audited = asyncio.run(replay([FILENAME], fs, pbar=False, lookahead_samples=5))
+    plain = asyncio.run(replay([FILENAME], fs, pbar=False))
+
+    assert audited.equals(plain)
+
+
+def test_noise():
+    fs = [
+        Factor("(Noise 42 0.5)"),
+        Factor("(Jitter 42 0.01 (Mean 10 :price_ask_l1_open))"),
+        Factor("(Jitter 42 0 (Mean 10 :price_ask_l1_open))"),
+        Factor("(Mean 10 :price_ask_l1_open)"),
+    ]
+    first = asyncio.run(replay([FILENAME], fs, pbar=False))
+    second = asyncio.run(replay([FILENAME], fs, pbar=False, batch_size=1000))
+
+    assert first.equals(second)
+    noise = first.column(0).to_numpy()
+    assert ((noise >= -0.5) & (noise < 0.5)).all()
+    assert first.column(2).equals(first.column(3))
+
+
+def test_boolean_output():
+    import pyarrow as pa
+
+    fs = [
+        Factor("(> :price_ask_l1_open :price_bid_l1_open)"),
+        Factor("(If (> :price_ask_l1_open 0) (! (< :price_ask_l1_open 1)) (== 1 1))"),
+        Factor("(Mean 10 :price_ask_l1_open)"),
+    ]
+    assert [f.is_predicate() for f in fs] == [True, True, False]
+
+    floats = asyncio.run(replay([FILENAME], fs, pbar=False))
+    booleans = asyncio.run(replay([FILENAME], fs, pbar=False, boolean=True))
+
+    assert booleans.schema.types == [pa.bool_(), pa.bool_(), pa.float64()]
+    for i in range(2):
+        assert booleans.column(i).cast(pa.float64()).equals(floats.column(i))
+    assert booleans.column(2).equals(floats.column(2))
+
+
+def test_infix():
+    pairs = [
+        ("(price_ask_l1_open + price_bid_l1_open) / 2", "(/ (+ :price_ask_l1_open :price_bid_l1_open) 2)"),
+        ("Mean(10, :price_ask_l1_open) > -1.5", "(> (Mean 10 :price_ask_l1_open) -1.5)"),
+        ("-price_ask_l1_open ^ 2", "(Neg (^ 2 :price_ask_l1_open))"),
+    ]
+    for infix, sexpr in pairs:
+        assert Factor(infix, syntax="infix") == Factor(sexpr)
+
+    with pytest.raises(ValueError):
+        Factor("(price_ask_l1_open +", syntax="infix")
+    with pytest.raises(ValueError):
+        Factor("(+ 1 2)", syntax="lisp")
+
+
+def test_let():
+    f = Factor(
+        "(let ((mid (/ (+ :price_ask_l1_open :price_bid_l1_open) 2))) (- (Mean 10 mid) mid))"
+    )
+    assert f == Factor(
+        "(- (Mean 10 (/ (+ :price_ask_l1_open :price_bid_l1_open) 2)) (/ (+ :price_ask_l1_open :price_bid_l1_open) 2))"
+    )
+
+    with pytest.raises(ValueError):
+        Factor("(let ((mid 1)) (+ mid spread))")
+
+
+def test_aliases():
+    assert Factor("(TSMean 10 :close)") == Factor("(Mean 10 :close)")
+    assert Factor("(ts_std 10 (sub :close :open))") == Factor("(Std 10 (- :close :open))")
+    assert str(Factor("(Correlation 10 :close :open)")) == "(Corr 10 :close :open)"
+    assert str(Factor("col_or(\"imbalance\", 0)", syntax="infix")) == '(col-or "imbalance" 0)'
+
+    with pytest.raises(ValueError):
+        Factor("(TSMeans 10 :close)")
+
+
+def test_macros():
+    from factor_expr import define, undefine
+
+    assert define("(def zscore (x w) (/ (- x (TSMean w x)) (TSStd w x)))") == "zscore"
+    assert Factor("(zscore :close 10)") == Factor("(/ (- :close (Mean 10 :close)) (Std 10 :close))")
+    assert Factor("zscore(close, 10)", syntax="infix") == Factor("(zscore :close 10)")
+
+    with pytest.raises(ValueError):
+        Factor("(zscore :close)")
+    with pytest.raises(ValueError):
+        define("(def Mean (x) x)")
+
+    assert undefine("zscore")
+    assert not undefine("zscore")
+    with pytest.raises(ValueError):
+        Factor("(zscore :close 10)")
+
+
+def test_parse_error():
+    from factor_expr import ParseError
+
+    with pytest.raises(ParseError) as e:
+        Factor("(+ :close (Mean 10 (TSMeen 5 :open)))")
+    assert e.value.span == (19, 35)
+    assert e.value.fragment == "(TSMeen 5 :open)"
+    assert "Unknown function 'TSMeen' at position 19" in str(e.value)
+
+    with pytest.raises(ParseError) as e:
+        Factor("close + TSMeen(5, open)", syntax="infix")
+    assert e.value.span is None
+
+
+def test_remap_columns():
+    f = Factor("(- (Mean 10 :price_ask_l1_open) :price_bid_l1_open)")
+    g = f.remap_columns({"price_ask_l1_open": "ask", "price_bid_l1_open": "bid"})
+    assert g == Factor("(- (Mean 10 :ask) :bid)")
+    assert f.columns() == ["price_ask_l1_open", "price_bid_l1_open"]
+    assert f.remap_columns({"unknown": "ask"}) == f
+
+
+def test_paths():
+    f = Factor("(+ (/ :close (Mean 10 :open)) :high)")
+    assert f.get_path("") == f
+    assert f.get_path("0/1") == Factor("(Mean 10 :open)")
+    assert f.get_path("0/1/0") == Factor(":open")
+    assert f.replace_path("1", Factor("(- :high :low)")) == Factor(
+        "(+ (/ :close (Mean 10 :open)) (- :high :low))"
+    )
+    for i in range(len(f)):
+        assert f.get_path(f.path_of(i)) == f[i]
+    for path in ["2", "1/0", "0/a"]:
+        with pytest.raises(ValueError):
+            f.get_path(path)
+
+
+def test_substitute():
+    f = Factor("(/ (- :mid (Mean 10 :mid)) (col-or \"mid\" 1))", name="alpha")
+    g = f.substitute("mid", Factor("(Mean 3 (/ (+ :bid :ask) 2))"))
+    assert g == Factor(
+        '(/ (- (Mean 3 (/ (+ :bid :ask) 2)) (Mean 10 (Mean 3 (/ (+ :bid :ask) 2)))) (col-or "mid" 1))'
+    )
+    assert g.name == "alpha"
+    assert str(f) == "(/ (- :mid (Mean 10 :mid)) (col-or \"mid\" 1))"
+    with pytest.raises(ValueError):
+        f.substitute("close", g)
+
+
+def test_scale_windows():
+    f = Factor("(- :close (Mean 10 5 (Delay 1 (Jitter 7 0.1 :close))))", name="alpha")
+    g = f.scale_windows(10)
+    assert g == Factor("(- :close (Mean 100 50 (Delay 10 (Jitter 7 0.1 :close))))")
+    assert g.name == "alpha"
+    assert f.scale_windows(0.1) == Factor("(- :close (Mean 1 (Delay 0 (Jitter 7 0.1 :close))))")
+    with pytest.raises(ValueError):
+        f.scale_windows(-1)
+
+
+def test_quoted_columns():
+    f = Factor('(- "ask price.1" (Mean 10 "bid price.1"))')
+    assert str(f) == '(- "ask price.1" (Mean 10 "bid price.1"))'
+    assert f.columns() == ["ask price.1", "bid price.1"]
+    assert Factor(str(f)) == f
+    assert Factor('(Mean 10 "price_ask_l1_open")') == Factor("(Mean 10 :price_ask_l1_open)")
+
+
+def test_optimize():
+    f = Factor("(+ :price_ask_l1_open (* 2 (/ 1 4)))")
+    assert f.optimize() == Factor("(+ :price_ask_l1_open 0.5)")
+    assert len(f.optimize()) == 3
+    assert Factor("(* 2 (Mean 3 (- 4 1)))").optimize() == Factor("(* 2 (Mean 3 3))")
+
+
+def test_simplify():
+    f = Factor("(* (+ :price_ask_l1_open 0) (Neg (Neg (/ :price_bid_l1_open 1))))")
+    assert f.simplify() == Factor("(* :price_ask_l1_open :price_bid_l1_open)")
+    assert Factor("(+ (- :price_ask_l1_open :price_ask_l1_open) 1)").simplify() == Factor("(+ 0 1)")
+    assert str(Factor("(+ (- :price_ask_l1_open :price_ask_l1_open) 1)").simplify().optimize()) == "1"
+
+
+def test_canonical():
+    a = Factor("(+ :price_ask_l1_open (* :price_bid_l1_open (+ 1 2)))")
+    b = Factor("(+ (* 3 :price_bid_l1_open) :price_ask_l1_open)")
+    assert a != b
+    assert a.equivalent(b)
+    assert a.semantic_hash() == b.semantic_hash()
+    assert a.canonical() == b.canonical()
+    assert len({f.semantic_hash() for f in [a, b, a.canonical()]}) == 1
+
+    c = Factor("(- :price_ask_l1_open :price_bid_l1_open)")
+    assert not c.equivalent(Factor("(- :price_bid_l1_open :price_ask_l1_open)"))
+
+
+def test_random():
+    from factor_expr import operators
+
+    columns = ["price_ask_l1_open", "price_bid_l1_open"]
+    for seed in range(50):
+        f = Factor.random(columns, max_depth=3, seed=seed)
+        assert f.depth() <= 3
+        assert set(f.columns()) <= set(columns)
+        assert f == Factor.random(columns, max_depth=3, seed=seed)
+
+    f = Factor.random(columns, seed=1, weights={"window": 0, "logic": 0})
+    assert all(f"({op['name']} " not in str(f) for op in operators() if op["category"] == "window")
+
+    with pytest.raises(ValueError):
+        Factor.random(columns, weights={"no_such_function": 1})
+
+
+def test_evolution():
+    a = Factor("(+ (Mean 10 :price_ask_l1_open) (* 2 :price_bid_l1_open))")
+    b = Factor("(Std 20 (- :price_ask_l1_close :price_bid_l1_close))")
+    for seed in range(20):
+        child = a.crossover(b, seed=seed)
+        assert child != a
+        assert child == a.crossover(b, seed=seed)
+
+        for kind in ["point", "subtree", "constant", "window"]:
+            mutant = a.mutate(kind, seed=seed)
+            assert mutant != a
+            assert mutant == Factor(str(mutant))
+
+    assert str(a.mutate("window", seed=0)).endswith(":price_ask_l1_open) (* 2 :price_bid_l1_open))")
+    with pytest.raises(ValueError):
+        Factor(":price_ask_l1_open").mutate("window")
+    with pytest.raises(ValueError):
+        a.mutate("swap")
+
+
+def test_subtrees():
+    f = Factor("(+ (/ :price_ask_l1_open :price_bid_l1_open) (* 2 (Mean 10 :price_ask_l1_close)))")
+    subtrees = f.subtrees()
+    assert [i for i, _ in subtrees] == list(range(len(f)))
+    assert all(sub == f[i] for i, sub in subtrees)
+    assert [str(leaf) for leaf in f.leaves()] == [
+        ":price_ask_l1_open",
+        ":price_bid_l1_open",
+        "2",
+        ":price_ask_l1_close",
+    ]
+    assert Factor(":price_ask_l1_open").leaves() == [Factor(":price_ask_l1_open")]
+
+
+def test_prune():
+    f = Factor("(+ (Mean 10 :price_ask_l1_open) (* 2 :price_bid_l1_open))")
+    assert f.prune(1) == Factor("(Mean 10 :price_ask_l1_open)")
+    assert str(f) == "(+ 0 (* 2 :price_bid_l1_open))"
+    assert str(f.prune(4)) == ":price_bid_l1_open"
+    assert str(f) == "(+ 0 (* 2 1))"
+
+    f.prune(2, Factor(":price_ask_l1_close"))
+    assert str(f) == "(+ 0 :price_ask_l1_close)"
+    with pytest.raises(ValueError):
+        f.prune(0)
+    with pytest.raises(ValueError):
+        f.prune(10)
+
+
+def test_lookback():
+    f = Factor("(+ (Std 20 (Delay 3 :price_ask_l1_open)) (Sum 10 5 :price_bid_l1_open))")
+    assert f.lookback() == 23
+    assert f.lookback() > f.ready_offset()
+    assert f.state_bytes() == 264
+    assert Factor(":price_ask_l1_open").lookback() == 1
+    assert Factor("(QuantileApprox 0.5 8 :price_ask_l1_open)").lookback() is None
+
+
+def test_template():
+    from factor_expr import ParseError
+
+    template = Factor("(+ (Mean ?w :price_ask_l1_open) (* ?k :price_bid_l1_open))")
+    assert template.bind(w=20, k=0.5) == Factor("(+ (Mean 20 :price_ask_l1_open) (* 0.5 :price_bid_l1_open))")
+    assert str(template.bind(w=20)) == "(+ (Mean 20 :price_ask_l1_open) (* ?k :price_bid_l1_open))"
+    assert template.bind(w=20).bind(k=1) == template.bind(w=20, k=1)
+
+    with pytest.raises(ParseError):
+        template.bind(w=2.5, k=1)
+    with pytest.raises(ParseError):
+        template.bind(n=1)
+
+    result = asyncio.run(replay([FILENAME], [template], pbar=False))
+    assert result.column(0).null_count == len(result)
+
+
+def test_variadic():
+    f = Factor("(+ :price_ask_l1_open :price_bid_l1_open :price_ask_l1_close :price_bid_l1_close)")
+    assert f == Factor(
+        "(+ (+ :price_ask_l1_open :price_bid_l1_open) (+ :price_ask_l1_close :price_bid_l1_close))"
+    )
+    assert f.depth() == 3
+    assert str(Factor("(* 2 :price_ask_l1_open :price_bid_l1_open)")) == "(* 2 (* :price_ask_l1_open :price_bid_l1_open))"
+    with pytest.raises(ValueError):
+        Factor("(- :price_ask_l1_open :price_bid_l1_open :price_ask_l1_close)")
+
+
+def test_to_dot():
+    dot = Factor("(+ (Mean 10 :price_ask_l1_open) 2)").to_dot()
+    assert dot.startswith("digraph factor {")
+    assert 'n1 [label="Mean 10", shape=box];' in dot
+    assert 'n2 [label=":price_ask_l1_open", shape=ellipse];' in dot
+    assert "n0 -> n3;" in dot
+
+
+def test_diff():
+    a = Factor("(+ (Mean 10 :price_ask_l1_open) (* 2 :price_bid_l1_open))")
+    b = Factor("(- (Mean 20 :price_ask_l1_open) (* 3 (Abs :price_bid_l1_open)))")
+    assert a.diff(a) == []
+    assert [(d["kind"], d["index"], d["other"]) for d in a.diff(b)] == [
+        ("operator", 0, 0),
+        ("params", 1, 1),
+        ("constant", 4, 4),
+        ("inserted", 5, 5),
+    ]
+    assert a.diff(b)[1]["after"] == "(Mean 20 :price_ask_l1_open)"
+
+
+def test_metadata():
+    import pickle
+
+    f = Factor("(Mean 10 :price_ask_l1_open)", name="ask_mean", tags={"author": "alice"})
+    assert f.name == "ask_mean"
+    assert f.tags == {"author": "alice"}
+    for g in [f.clone(), f.replace(1, Factor(":price_bid_l1_open")), f.optimize(), pickle.loads(pickle.dumps(f))]:
+        assert g.name == "ask_mean"
+        assert g.tags == {"author": "alice"}
+    assert pickle.loads(pickle.dumps(f)) == f
+    assert f[1].name is None
+
+    f.tags = {"author": "bob"}
+    assert f.tags == {"author": "bob"}
+
+    g = Factor("(Mean 10 :price_bid_l1_open)")
+    result = asyncio.run(replay([FILENAME], [f, g], pbar=False))
+    assert result.column_names == ["ask_mean", str(g)]
+
+
+def test_lint():
+    assert Factor("(+ (Mean 10 :price_ask_l1_open) :price_bid_l1_open)").lint() == []
+
+    f = Factor("(+ (- :price_ask_l1_open :price_ask_l1_open) (Mean 1 (Abs (Abs :price_bid_l1_open))))")
+    warnings = f.lint()
+    assert [w["index"] for w in warnings] == [1, 4, 5]
+    assert warnings[0]["node"] == "(- :price_ask_l1_open :price_ask_l1_open)"
+    assert "always 0" in warnings[0]["message"]
+
+
+def test_round_trip():
+    for c in [0.1 + 0.2, 1e-300, -1.5e-7, 123456.789, -0.0]:
+        f = Factor(f"(* :close {c!r})")
+        assert Factor(str(f)) == f
+        assert Factor(f.to_string()) == f
+
+    f = Factor("(+ (Mean 10 :close) (* :open 0.30000000000000004))")
+    assert f.to_string() == str(f)
+    assert f.to_string(precision=3) == "(+ (Mean 10 :close) (* :open 0.3))"
+    with pytest.raises(ValueError):
+        f.to_string(precision=0)
+
+
+def test_operators():
+    from factor_expr import operators
+
+    ops = {op["name"]: op for op in operators()}
+    assert ops["+"]["category"] == "arithmetic"
+    assert [p["kind"] for p in ops["Mean"]["params"]] == ["integer", "integer", "series"]
+    assert ops["Mean"]["arity"] == 2
+    assert ops["Std"]["params"][0]["min"] == 2
+    assert ops["WSum"]["variadic"]
+
+    # every function, filled with the simplest parameters, parses
+    fill = {
+        "constant": "0.5",
+        "fraction": "0.5",
+        "series": ":price_ask_l1_open",
+        "window": "(Mean 3 :price_ask_l1_open)",
+        "vector": "(0.5 0.5)",
+        "columns": "(Cols :price_ask_l1_open :price_bid_l1_open)",
+        "name": '"price_ask_l1_open"',
+        "duration": '"5s"',
+    }
+    for op in ops.values():
+        params = [
+            str(max(p["min"], 3)) if p["kind"] == "integer" else fill[p["kind"]]
+            for p in op["params"]
+            if not p["optional"]
+        ]
+        Factor(f"({' '.join([op['name'], *params])})")
+
+
+def test_typed_parameters():
+    from factor_expr import ParseError
+
+    with pytest.raises(ParseError, match="should be an integer no less than 1, got 2.7"):
+        Factor("(Mean 2.7 :close)")
+    with pytest.raises(ParseError, match="should be within"):
+        Factor("(QuantileApprox 1.5 100 :close)")
+    Factor("(Mean 3.0 :close)")
+
+
+def test_validate():
+    f = Factor("(+ (Mean 10 :close) (col-or \"imbalance\" 0))")
+    f.validate(["open", "close"])
+
+    with pytest.raises(ValueError, match="missing columns close"):
+        f.validate(["open"])
+    with pytest.raises(ValueError, match="ambiguous columns close"):
+        f.validate(["close", "close"])
+
+
+def test_pretty():
+    f = Factor("(+ (Mean 10 (/ :close :open)) :high)")
+    assert f.pretty() == "(+\n  (Mean 10\n    (/ :close :open))\n  :high)"
+    assert f.pretty(indent=4) == "(+\n    (Mean 10\n        (/ :close :open))\n    :high)"
+    assert Factor(f.pretty()) == f
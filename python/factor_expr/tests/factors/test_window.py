@@ -18,6 +18,115 @@ def test_sum():
     ).all()
 
 
+def test_product():
+    df = pd.read_parquet(FILENAME)
+    f = Factor("(TSProduct 10 (/ :price_ask_l1_close :price_ask_l1_open))")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+    ratio = df.price_ask_l1_close / df.price_ask_l1_open
+    assert np.isclose(
+        ratio.rolling(10).apply(np.prod, raw=True).values[f.ready_offset() :],
+        result.to_pandas().values.ravel()[f.ready_offset() :],
+    ).all()
+
+
+def test_countif():
+    df = pd.read_parquet(FILENAME)
+    f = Factor("(TSCountIf 10 (> :price_ask_l1_close :price_ask_l1_open))")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+    up = (df.price_ask_l1_close > df.price_ask_l1_open).astype(float)
+    assert np.isclose(
+        up.rolling(10).sum().values[f.ready_offset() :],
+        result.to_pandas().values.ravel()[f.ready_offset() :],
+    ).all()
+
+
+def test_any_all():
+    df = pd.read_parquet(FILENAME)
+    fs = [
+        Factor("(TSAny 10 (> :price_ask_l1_close :price_ask_l1_open))"),
+        Factor("(TSAll 10 (> :price_ask_l1_close :price_ask_l1_open))"),
+    ]
+    result = asyncio.run(replay([FILENAME], fs, pbar=False))
+    up = (df.price_ask_l1_close > df.price_ask_l1_open).astype(float)
+    for f, expected in zip(fs, [up.rolling(10).max(), up.rolling(10).min()]):
+        assert np.isclose(
+            expected.values[f.ready_offset() :],
+            result.to_pandas()[str(f)].values[f.ready_offset() :],
+        ).all()
+
+
+def test_first():
+    df = pd.read_parquet(FILENAME)
+    f = Factor("(TSFirst 10 :price_ask_l1_close)")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+    assert f.ready_offset() == 9
+    assert np.isclose(
+        df.price_ask_l1_close.shift(9).values[f.ready_offset() :],
+        result.to_pandas().values.ravel()[f.ready_offset() :],
+    ).all()
+
+
+def test_drawdown():
+    df = pd.read_parquet(FILENAME)
+    f = Factor("(TSDrawdown 10 :price_ask_l1_close)")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+    x = df.price_ask_l1_close
+    highest = x.rolling(10).max()
+    assert np.isclose(
+        ((highest - x) / highest).values[f.ready_offset() :],
+        result.to_pandas().values.ravel()[f.ready_offset() :],
+    ).all()
+
+
+def test_hurst():
+    f = Factor("(TSHurst 64 (LogReturn 1 :price_ask_l1_close))")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+    assert f.ready_offset() == 64
+
+    values = result.to_pandas().values.ravel()[f.ready_offset() :]
+    values = values[~np.isnan(values)]
+    assert len(values) > 0
+    assert ((values > -0.5) & (values < 1.5)).all()
+
+
+def test_slope():
+    df = pd.read_parquet(FILENAME)
+    fs = [Factor("(TSSlope 10 :price_ask_l1_close)"), Factor("(TSTrendR2 10 :price_ask_l1_close)")]
+    result = asyncio.run(replay([FILENAME], fs, pbar=False))
+
+    t = np.arange(10)
+
+    def fit(w):
+        if w.max() == w.min():
+            return 0.0, 0.0
+        return np.polyfit(t, w, 1)[0], np.corrcoef(t, w)[0, 1] ** 2
+
+    x = df.price_ask_l1_close
+    expected = [x.rolling(10).apply(lambda w: fit(w)[k], raw=True) for k in range(2)]
+    for f, e in zip(fs, expected):
+        assert f.ready_offset() == 9
+        assert np.isclose(
+            e.values[f.ready_offset() :],
+            result.to_pandas()[str(f)].values[f.ready_offset() :],
+        ).all()
+
+
+def test_theil_sen():
+    df = pd.read_parquet(FILENAME)
+    f = Factor("(TSTheilSen 10 :price_ask_l1_close)")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+
+    def theil_sen(w):
+        return np.median([(w[j] - w[i]) / (j - i) for j in range(len(w)) for i in range(j)])
+
+    expected = df.price_ask_l1_close.rolling(10).apply(theil_sen, raw=True)
+    assert f.ready_offset() == 9
+    assert np.isclose(
+        expected.values[f.ready_offset() :],
+        result.to_pandas().values.ravel()[f.ready_offset() :],
+    ).all()
+
+
 def test_mean():
     df = pd.read_parquet(FILENAME)
 
@@ -115,6 +224,45 @@ def test_std():
     ).all()
 
 
+def test_autocorr():
+    df = pd.read_parquet(FILENAME)
+
+    f = Factor("(TSAutoCorr 10 3 :price_ask_l1_high)")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+
+    x = df.price_ask_l1_high
+    expected = x.rolling(10).corr(x.shift(3)).fillna(0)
+
+    assert f.ready_offset() == 12
+    assert np.isclose(
+        expected.values[f.ready_offset() :],
+        result.to_pandas()[str(f)].values[f.ready_offset() :],
+    ).all()
+
+
+def test_beta():
+    df = pd.read_parquet(FILENAME)
+
+    fs = [
+        Factor("(TSBeta 10 :price_ask_l1_high :price_bid_l1_low)"),
+        Factor("(TSAlpha 10 :price_ask_l1_high :price_bid_l1_low)"),
+        Factor("(TSResid 10 :price_ask_l1_high :price_bid_l1_low)"),
+    ]
+    result = asyncio.run(replay([FILENAME], fs, pbar=False))
+
+    y, x = df.price_ask_l1_high, df.price_bid_l1_low
+    beta = (y.rolling(10).cov(x) / x.rolling(10).var()).fillna(0)
+    alpha = y.rolling(10).mean() - beta * x.rolling(10).mean()
+    resid = y - (alpha + beta * x)
+
+    for f, expected in zip(fs, [beta, alpha, resid]):
+        assert f.ready_offset() == 9
+        assert np.isclose(
+            expected.values[f.ready_offset() :],
+            result.to_pandas()[str(f)].values[f.ready_offset() :],
+        ).all()
+
+
 def test_skew():
     df = pd.read_parquet(FILENAME)
 
@@ -127,6 +275,36 @@ def test_skew():
     ).all()
 
 
+def test_kurt():
+    df = pd.read_parquet(FILENAME)
+
+    f = Factor("(TSKurtosis 10 :price_bid_l1_high)")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+
+    assert f.ready_offset() == 9
+    assert np.isclose(
+        np.nan_to_num(df.price_bid_l1_high.rolling(10).kurt().values)[f.ready_offset() :],
+        result.to_pandas().values.ravel()[f.ready_offset() :],
+    ).all()
+
+
+def test_ts_median():
+    df = pd.read_parquet(FILENAME)
+
+    f = Factor("(TSMedian 10 :price_ask_l1_open)")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+    got = result.to_pandas().values.ravel()[f.ready_offset() :]
+
+    assert f.ready_offset() == 9
+    assert np.isclose(
+        df.price_ask_l1_open.rolling(10).median().values[f.ready_offset() :],
+        got,
+    ).all()
+    # The window is even, the two middle values are averaged rather than one of them taken
+    lower = df.price_ask_l1_open.rolling(10).quantile(0.5, interpolation="lower")
+    assert not np.isclose(lower.values[f.ready_offset() :], got).all()
+
+
 def test_delay():
     df = pd.read_parquet(FILENAME)
 
@@ -155,6 +333,21 @@ def test_rank():
     ).all()
 
 
+def test_percentile():
+    df = pd.read_parquet(FILENAME)
+
+    f = Factor("(Percentile 10 :price_ask_l1_open)")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+
+    def func(w):
+        return (w < w[-1]).sum() / (len(w) - 1)
+
+    assert np.isclose(
+        df.price_ask_l1_open.rolling(10).apply(func, raw=True).values[f.ready_offset() :],
+        result.to_pandas().values.ravel()[f.ready_offset() :],
+    ).all()
+
+
 def test_logreturn():
     df = pd.read_parquet(FILENAME)
 
@@ -179,6 +372,21 @@ def test_quantile():
     ).all()
 
 
+def test_winsorize():
+    df = pd.read_parquet(FILENAME)
+
+    f = Factor("(Winsorize 30 0.1 0.9 :price_bid_l1_open)")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+
+    s = df.price_bid_l1_open
+    lo = s.rolling(30).quantile(0.1, "lower")
+    hi = s.rolling(30).quantile(0.9, "lower")
+    assert np.isclose(
+        s.clip(lo, hi).values[f.ready_offset() :],
+        result.to_pandas().values.ravel()[f.ready_offset() :],
+    ).all()
+
+
 def test_median():
     df = pd.read_parquet(FILENAME)
 
@@ -189,3 +397,553 @@ def test_median():
         df.price_bid_l1_open.rolling(37).median().values[f.ready_offset() :],
         result.to_pandas().values.ravel()[f.ready_offset() :],
     ).all()
+
+
+def test_mean_min_periods():
+    df = pd.read_parquet(FILENAME)
+
+    f = Factor("(Mean 10 3 :price_ask_l1_open)")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+
+    assert f.ready_offset() == 2
+    assert np.isclose(
+        df.price_ask_l1_open.rolling(10, min_periods=3).mean().values[f.ready_offset() :],
+        result.to_pandas().values.ravel()[f.ready_offset() :],
+    ).all()
+
+
+def test_std_min_periods():
+    df = pd.read_parquet(FILENAME)
+
+    f = Factor("(Std 10 2 :price_ask_l1_high)")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+
+    assert np.isclose(
+        df.price_ask_l1_high.rolling(10, min_periods=2).std().values[f.ready_offset() :],
+        result.to_pandas().values.ravel()[f.ready_offset() :],
+        atol=1e-5,
+    ).all()
+
+
+def test_max_min_periods():
+    df = pd.read_parquet(FILENAME)
+
+    f = Factor("(Max 10 1 :price_ask_l1_open)")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+
+    assert np.isclose(
+        df.price_ask_l1_open.rolling(10, min_periods=1).max().values,
+        result.to_pandas().values.ravel(),
+    ).all()
+
+
+def test_conv():
+    df = pd.read_parquet(FILENAME)
+
+    f = Factor("(Conv (0.5 0.3 0.2) :price_ask_l1_open)")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+
+    s = df.price_ask_l1_open
+    assert np.isclose(
+        (0.5 * s + 0.3 * s.shift(1) + 0.2 * s.shift(2)).values[f.ready_offset() :],
+        result.to_pandas().values.ravel()[f.ready_offset() :],
+    ).all()
+
+
+def test_ema():
+    df = pd.read_parquet(FILENAME)
+
+    fs = [
+        Factor("(EMA 10 :price_ask_l1_open)"),
+        Factor("(TSEMA halflife 5 :price_ask_l1_open)"),
+        Factor("(EMA alpha 0.3 :price_ask_l1_open)"),
+    ]
+    result = asyncio.run(replay([FILENAME], fs, pbar=False))
+
+    s = df.price_ask_l1_open
+    expected = [
+        s.ewm(span=10, adjust=False).mean(),
+        s.ewm(halflife=5, adjust=False).mean(),
+        s.ewm(alpha=0.3, adjust=False).mean(),
+    ]
+    for f, e in zip(fs, expected):
+        assert np.isclose(e.values, result.to_pandas()[str(f)].values).all()
+
+
+def test_ewm_std():
+    df = pd.read_parquet(FILENAME)
+
+    f = Factor("(TSEWMStd halflife 10 :price_ask_l1_open)")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+
+    expected = df.price_ask_l1_open.ewm(halflife=10, adjust=False).std()
+    assert np.isclose(
+        expected.values[f.ready_offset() :],
+        result.to_pandas().values.ravel()[f.ready_offset() :],
+    ).all()
+
+
+def test_ewm_corr():
+    df = pd.read_parquet(FILENAME)
+
+    fs = [
+        Factor("(EWMCov 20 :price_ask_l1_high :price_bid_l1_low)"),
+        Factor("(TSEWMCorr 20 :price_ask_l1_high :price_bid_l1_low)"),
+    ]
+    result = asyncio.run(replay([FILENAME], fs, pbar=False)).to_pandas()
+
+    x, y = df.price_ask_l1_high, df.price_bid_l1_low
+    ewm = x.ewm(span=20, adjust=False)
+    for f, expected in zip(fs, [ewm.cov(y), ewm.corr(y)]):
+        assert np.isclose(
+            np.nan_to_num(expected.values)[f.ready_offset() :],
+            result[str(f)].values[f.ready_offset() :],
+        ).all()
+
+
+def test_wma():
+    df = pd.read_parquet(FILENAME)
+
+    f = Factor("(WMA 10 :price_ask_l1_open)")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+
+    weights = np.arange(1, 11)
+    expected = df.price_ask_l1_open.rolling(10).apply(lambda w: (w * weights).sum() / weights.sum(), raw=True)
+    assert f.ready_offset() == 9
+    assert np.isclose(
+        expected.values[f.ready_offset() :],
+        result.to_pandas().values.ravel()[f.ready_offset() :],
+    ).all()
+
+    g = Factor("(TSDecayLinear 10 :price_ask_l1_open)")
+    assert str(g) == str(f)
+
+
+def test_dema():
+    df = pd.read_parquet(FILENAME)
+
+    f = Factor("(DEMA 10 :price_ask_l1_open)")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+
+    def ta_ema(s):
+        # TA-Lib seeds the EMA with the mean of the first 10 values
+        s = s.dropna()
+        seed = pd.Series([s.iloc[:10].mean()], index=[s.index[9]])
+        return pd.concat([seed, s.iloc[10:]]).ewm(span=10, adjust=False).mean()
+
+    ema = ta_ema(df.price_ask_l1_open)
+    expected = (2 * ema - ta_ema(ema)).reindex(df.index)
+    assert f.ready_offset() == 18
+    assert np.isclose(
+        expected.values[f.ready_offset() :],
+        result.to_pandas().values.ravel()[f.ready_offset() :],
+    ).all()
+
+
+def test_tema():
+    df = pd.read_parquet(FILENAME)
+
+    f = Factor("(TEMA 10 :price_ask_l1_open)")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+
+    def ta_ema(s):
+        s = s.dropna()
+        seed = pd.Series([s.iloc[:10].mean()], index=[s.index[9]])
+        return pd.concat([seed, s.iloc[10:]]).ewm(span=10, adjust=False).mean()
+
+    e1 = ta_ema(df.price_ask_l1_open)
+    e2 = ta_ema(e1)
+    e3 = ta_ema(e2)
+    expected = (3 * e1 - 3 * e2 + e3).reindex(df.index)
+    assert f.ready_offset() == 27
+    assert np.isnan(result.to_pandas().values.ravel()[: f.ready_offset()]).all()
+    assert np.isclose(
+        expected.values[f.ready_offset() :],
+        result.to_pandas().values.ravel()[f.ready_offset() :],
+    ).all()
+
+
+def test_macd():
+    df = pd.read_parquet(FILENAME)
+
+    fs = [
+        Factor("(MACD 12 26 9 :price_ask_l1_open)"),
+        Factor("(MACDSignal 12 26 9 :price_ask_l1_open)"),
+        Factor("(MACDHist 12 26 9 :price_ask_l1_open)"),
+    ]
+    result = asyncio.run(replay([FILENAME], fs, pbar=False))
+
+    def ta_ema(s, n):
+        s = s.dropna()
+        seed = pd.Series([s.iloc[:n].mean()], index=[s.index[n - 1]])
+        return pd.concat([seed, s.iloc[n:]]).ewm(span=n, adjust=False).mean()
+
+    x = df.price_ask_l1_open
+    # The fast EMA is seeded along with the slow one
+    macd = ta_ema(x.iloc[26 - 12 :], 12) - ta_ema(x, 26)
+    signal = ta_ema(macd, 9)
+    expected = [macd, signal, macd - signal]
+    for f, e in zip(fs, expected):
+        assert f.ready_offset() == 33
+        assert np.isclose(
+            e.reindex(df.index).values[f.ready_offset() :],
+            result.to_pandas()[str(f)].values[f.ready_offset() :],
+        ).all()
+
+
+def test_roc_momentum():
+    df = pd.read_parquet(FILENAME)
+
+    fs = [
+        Factor("(ROC 10 :price_ask_l1_open)"),
+        Factor("(Momentum 10 :price_ask_l1_open)"),
+        Factor("(TSPctChange 10 :price_ask_l1_open)"),
+    ]
+    result = asyncio.run(replay([FILENAME], fs, pbar=False))
+
+    x = df.price_ask_l1_open
+    expected = [100 * x.pct_change(10, fill_method=None), x.diff(10), x.pct_change(10, fill_method=None)]
+    for f, e in zip(fs, expected):
+        assert f.ready_offset() == 10
+        assert np.isclose(
+            e.values[f.ready_offset() :],
+            result.to_pandas()[str(f)].values[f.ready_offset() :],
+        ).all()
+
+    assert str(Factor("(Diff 10 :price_ask_l1_open)")) == str(fs[1])
+    assert str(Factor("(- :price_ask_l1_open (Delay 10 :price_ask_l1_open))").simplify()) == str(fs[1])
+
+
+def test_pct_change_zeros():
+    import pyarrow as pa
+    import pytest
+
+    xs = [0.0, 2.0, 0.0, 0.0, 3.0]
+    tb = pa.table({"a": xs})
+
+    # A zero past value is a zero denominator, which follows the zero policy like `/`
+    f = Factor("(PctChange 1 :a)", zero="ieee")
+    result = asyncio.run(replay([tb], [f], pbar=False))
+    np.testing.assert_array_equal(
+        result.to_pandas().values.ravel(),
+        pd.Series(xs).pct_change(1, fill_method=None).values,
+    )
+    assert str(f) == "(PctChange ieee 1 :a)"
+
+    result = asyncio.run(replay([tb], [Factor("(ROC nan 1 :a)")], pbar=False))
+    assert result.column(0).to_pylist() == [None, None, -100.0, None, None]
+
+    result = asyncio.run(replay([tb], [Factor("(PctChange 1 :a)")], pbar=False))
+    assert result.column(0).to_pylist()[1] > 1e15
+
+    with pytest.raises(ValueError):
+        Factor("(Mean 2 (PctChange 1 :a))", zero="ieee")
+
+
+def test_cumulative():
+    df = pd.read_parquet(FILENAME)
+
+    fs = [Factor(f"({op} :price_ask_l1_open)") for op in ["CumSum", "CumMean", "CumMax", "CumMin"]]
+    result = asyncio.run(replay([FILENAME], fs, pbar=False))
+
+    x = df.price_ask_l1_open
+    expected = [x.cumsum(), x.expanding().mean(), x.cummax(), x.cummin()]
+    for f, e in zip(fs, expected):
+        assert f.ready_offset() == 0
+        assert np.isclose(e.values, result.to_pandas()[str(f)].values).all()
+
+
+def test_obv():
+    df = pd.read_parquet(FILENAME)
+
+    f = Factor("(OBV :price_ask_l1_close :volume_ask_l1_high)")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+
+    sign = np.sign(df.price_ask_l1_close.diff()).fillna(1)
+    expected = (sign * df.volume_ask_l1_high).cumsum()
+    assert f.ready_offset() == 0
+    assert np.isclose(expected.values, result.to_pandas().values.ravel()).all()
+
+
+def test_vwap():
+    df = pd.read_parquet(FILENAME)
+
+    f = Factor("(TSVWAP 10 :price_ask_l1_close :volume_ask_l1_high)")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+
+    pv = (df.price_ask_l1_close * df.volume_ask_l1_high).rolling(10).sum()
+    expected = pv / df.volume_ask_l1_high.rolling(10).sum()
+    assert f.ready_offset() == 9
+    assert np.isclose(
+        expected.values[f.ready_offset() :],
+        result.to_pandas().values.ravel()[f.ready_offset() :],
+        equal_nan=True,
+    ).all()
+
+
+def test_twap():
+    import pyarrow as pa
+    from datetime import datetime
+
+    tb = pa.table(
+        {
+            "time": pa.array(
+                [datetime(2021, 1, 1, 0, 0, s) for s in [0, 2, 7, 7, 10]],
+                pa.timestamp("ms"),
+            ),
+            "price": pa.array([1.0, 2.0, 3.0, 4.0, 5.0]),
+        }
+    )
+    f = Factor("(TSTWAP 2 :time :price)")
+    result = asyncio.run(replay([tb], [f], pbar=False))
+
+    assert f.ready_offset() == 2
+    assert np.isclose(result.column(0).to_pylist()[2:], [12 / 7, 2.0, 4.0]).all()
+
+
+def test_ffill():
+    import pyarrow as pa
+
+    tb = pa.table({"price": pa.array([np.nan, 1.0, np.nan, np.nan, np.nan, 2.0, np.nan])})
+    fs = [Factor("(FFill :price)"), Factor("(FFill 2 :price)")]
+    result = asyncio.run(replay([tb], fs, pbar=False))
+
+    assert result.column(0).to_pylist() == [None, 1.0, 1.0, 1.0, 1.0, 2.0, 2.0]
+    assert result.column(1).to_pylist() == [None, 1.0, 1.0, 1.0, None, 2.0, 2.0]
+
+    # Derived inputs, sentinels, and parents warming up until the first value
+    tb = pa.table({"price": [np.nan, 1.0, 0.0, np.nan, 3.0], "mid": [1.0, 1.5, 2.0, 2.5, 3.0]})
+    fs = [
+        Factor("(FFill (* 2 :price))"),
+        Factor("(FFill 2 0 :price)"),
+        Factor("(- (FFill :price) :mid)"),
+        Factor("(Mean 2 (FFill :price))"),
+    ]
+    result = asyncio.run(replay([tb], fs, pbar=False))
+
+    assert result.column(0).to_pylist() == [None, 2.0, 0.0, 0.0, 6.0]
+    assert result.column(1).to_pylist() == [None, 1.0, 1.0, 1.0, 3.0]
+    assert result.column(2).to_pylist() == [None, -0.5, -2.0, -2.5, 0.0]
+    assert result.column(3).to_pylist() == [None, None, 0.5, 0.0, 1.5]
+
+
+def test_time_windows():
+    import pyarrow as pa
+    from datetime import datetime
+
+    seconds = [0, 0.5, 1, 1, 3.2, 4.1]
+    times = [datetime(2021, 1, 1) + pd.Timedelta(seconds=s) for s in seconds]
+    prices = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]
+    tb = pa.table({"time": pa.array(times, pa.timestamp("ms")), "price": pa.array(prices)})
+    fs = [
+        Factor('(TSMeanT "2s" :time :price)'),
+        Factor('(StdT "2s" :time :price)'),
+        Factor('(CountT "1s" :time :price)'),
+    ]
+    result = asyncio.run(replay([tb], fs, pbar=False))
+
+    s = pd.Series(prices, index=pd.DatetimeIndex(times))
+    assert np.isclose(result.column(0).to_numpy(), s.rolling("2s").mean().values).all()
+    assert np.isclose(
+        result.column(1).to_numpy(zero_copy_only=False),
+        s.rolling("2s").std().values,
+        equal_nan=True,
+    ).all()
+    assert result.column(2).to_pylist() == [1, 2, 2, 3, 1, 2]
+
+
+def test_rsi():
+    df = pd.read_parquet(FILENAME)
+
+    f = Factor("(RSI 14 :price_ask_l1_open)")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+
+    def wilder(s):
+        # Seeded by the mean of the first 14 moves
+        seed = pd.Series([s.iloc[1:15].mean()], index=[s.index[14]])
+        return pd.concat([seed, s.iloc[15:]]).ewm(alpha=1 / 14, adjust=False).mean()
+
+    d = df.price_ask_l1_open.diff()
+    gain, loss = wilder(d.clip(lower=0)), wilder(-d.clip(upper=0))
+    expected = (100 * gain / (gain + loss)).fillna(0).reindex(df.index)
+    assert f.ready_offset() == 14
+    values = result.to_pandas().values.ravel()
+    assert np.isnan(values[: f.ready_offset()]).all()
+    assert ((values[f.ready_offset() :] >= 0) & (values[f.ready_offset() :] <= 100)).all()
+    assert np.isclose(expected.values[f.ready_offset() :], values[f.ready_offset() :]).all()
+
+
+def test_stoch():
+    df = pd.read_parquet(FILENAME)
+
+    fs = [
+        Factor("(StochK 5 3 :price_ask_l1_high :price_ask_l1_low :price_ask_l1_close)"),
+        Factor("(StochD 5 3 3 :price_ask_l1_high :price_ask_l1_low :price_ask_l1_close)"),
+    ]
+    result = asyncio.run(replay([FILENAME], fs, pbar=False))
+
+    highest = df.price_ask_l1_high.rolling(5).max()
+    lowest = df.price_ask_l1_low.rolling(5).min()
+    fast_k = (100 * (df.price_ask_l1_close - lowest) / (highest - lowest)).where(highest > lowest, 0)
+    k = fast_k.where(highest.notna()).rolling(3).mean()
+    d = k.rolling(3).mean()
+
+    assert [f.ready_offset() for f in fs] == [6, 8]
+    for f, expected in zip(fs, [k, d]):
+        assert np.isclose(
+            expected.values[f.ready_offset() :],
+            result.to_pandas()[str(f)].values[f.ready_offset() :],
+        ).all()
+
+
+def test_cci():
+    df = pd.read_parquet(FILENAME)
+
+    f = Factor("(CCI 20 :price_ask_l1_high :price_ask_l1_low :price_ask_l1_close)")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+
+    tp = (df.price_ask_l1_high + df.price_ask_l1_low + df.price_ask_l1_close) / 3
+    mean = tp.rolling(20).mean()
+    mad = tp.rolling(20).apply(lambda w: np.abs(w - w.mean()).mean(), raw=True)
+    expected = ((tp - mean) / (0.015 * mad)).where(mad > 1e-12, 0)
+
+    assert f.ready_offset() == 19
+    assert np.isclose(
+        expected.values[f.ready_offset() :],
+        result.to_pandas().values.ravel()[f.ready_offset() :],
+        rtol=1e-6,
+    ).all()
+
+
+def test_aroon():
+    df = pd.read_parquet(FILENAME)
+
+    fs = [
+        Factor("(AroonUp 14 :price_ask_l1_high :price_ask_l1_low)"),
+        Factor("(AroonDown 14 :price_ask_l1_high :price_ask_l1_low)"),
+        Factor("(AroonOsc 14 :price_ask_l1_high :price_ask_l1_low)"),
+    ]
+    result = asyncio.run(replay([FILENAME], fs, pbar=False))
+
+    # The bars since the extremum of the 15 bars, the latest one of the ties
+    up = 100 * (14 - df.price_ask_l1_high.rolling(15).apply(lambda w: w[::-1].argmax(), raw=True)) / 14
+    down = 100 * (14 - df.price_ask_l1_low.rolling(15).apply(lambda w: w[::-1].argmin(), raw=True)) / 14
+
+    for f, expected in zip(fs, [up, down, up - down]):
+        assert f.ready_offset() == 14
+        assert np.isclose(
+            expected.values[f.ready_offset() :],
+            result.to_pandas()[str(f)].values[f.ready_offset() :],
+        ).all()
+
+
+def test_ultosc():
+    df = pd.read_parquet(FILENAME)
+
+    f = Factor("(UltOsc :price_ask_l1_high :price_ask_l1_low :price_ask_l1_close)")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+
+    prev_close = df.price_ask_l1_close.shift(1)
+    low = np.minimum(df.price_ask_l1_low, prev_close)
+    pressure = df.price_ask_l1_close - low
+    tr = np.maximum(df.price_ask_l1_high, prev_close) - low
+
+    def avg(n):
+        return (pressure.rolling(n).sum() / tr.rolling(n).sum()).fillna(0)
+
+    expected = 100 * (4 * avg(7) + 2 * avg(14) + avg(28)) / 7
+    assert str(f) == "(UltOsc 7 14 28 :price_ask_l1_high :price_ask_l1_low :price_ask_l1_close)"
+    assert f.ready_offset() == 28
+    assert np.isclose(
+        expected.values[f.ready_offset() :],
+        result.to_pandas().values.ravel()[f.ready_offset() :],
+    ).all()
+
+
+def test_ichimoku():
+    df = pd.read_parquet(FILENAME)
+
+    fs = [
+        Factor("(Tenkan 9 :price_ask_l1_high :price_ask_l1_low)"),
+        Factor("(Kijun 26 :price_ask_l1_high :price_ask_l1_low)"),
+        Factor("(SenkouA 9 26 26 :price_ask_l1_high :price_ask_l1_low)"),
+        Factor("(SenkouB 52 26 :price_ask_l1_high :price_ask_l1_low)"),
+    ]
+    result = asyncio.run(replay([FILENAME], fs, pbar=False))
+
+    def midpoint(n):
+        return (df.price_ask_l1_high.rolling(n).max() + df.price_ask_l1_low.rolling(n).min()) / 2
+
+    tenkan, kijun = midpoint(9), midpoint(26)
+    expected = [tenkan, kijun, ((tenkan + kijun) / 2).shift(26), midpoint(52).shift(26)]
+
+    for f, e, ready_offset in zip(fs, expected, [8, 25, 51, 77]):
+        assert f.ready_offset() == ready_offset
+        assert np.isclose(
+            e.values[f.ready_offset() :],
+            result.to_pandas()[str(f)].values[f.ready_offset() :],
+        ).all()
+
+
+def test_mfi():
+    df = pd.read_parquet(FILENAME)
+
+    f = Factor("(MFI 14 :price_ask_l1_high :price_ask_l1_low :price_ask_l1_close :volume_ask_l1_high)")
+    result = asyncio.run(replay([FILENAME], [f], pbar=False))
+
+    tp = (df.price_ask_l1_high + df.price_ask_l1_low + df.price_ask_l1_close) / 3
+    flow = tp * df.volume_ask_l1_high
+    positive = flow.where(tp.diff() > 0, 0).rolling(14).sum()
+    negative = flow.where(tp.diff() < 0, 0).rolling(14).sum()
+    expected = (100 * positive / (positive + negative)).where(positive + negative > 0, 0)
+
+    assert f.ready_offset() == 14
+    assert np.isclose(
+        expected.values[f.ready_offset() :],
+        result.to_pandas().values.ravel()[f.ready_offset() :],
+    ).all()
+
+
+def test_atr():
+    df = pd.read_parquet(FILENAME)
+
+    fs = [
+        Factor("(TrueRange :price_ask_l1_high :price_ask_l1_low :price_ask_l1_close)"),
+        Factor("(ATR 14 :price_ask_l1_high :price_ask_l1_low :price_ask_l1_close)"),
+    ]
+    result = asyncio.run(replay([FILENAME], fs, pbar=False))
+
+    prev_close = df.price_ask_l1_close.shift(1)
+    tr = np.maximum(df.price_ask_l1_high, prev_close) - np.minimum(df.price_ask_l1_low, prev_close)
+    # Wilder's smoothing, seeded by the mean of the first 14 true ranges
+    seed = pd.Series([tr.iloc[1:15].mean()], index=[tr.index[14]])
+    atr = pd.concat([seed, tr.iloc[15:]]).ewm(alpha=1 / 14, adjust=False).mean().reindex(df.index)
+
+    assert [f.ready_offset() for f in fs] == [1, 14]
+    for f, expected in zip(fs, [tr, atr]):
+        assert np.isclose(
+            expected.values[f.ready_offset() :],
+            result.to_pandas()[str(f)].values[f.ready_offset() :],
+        ).all()
+
+
+def test_quantile_approx():
+    df = pd.read_parquet(FILENAME)
+
+    fs = [
+        Factor("(QuantileApprox 0.5 100 :price_ask_l1_open)"),
+        Factor("(RankApprox 100 :price_ask_l1_open)"),
+    ]
+    result = asyncio.run(replay([FILENAME], fs, pbar=False)).to_pandas()
+
+    s = df.price_ask_l1_open
+    n = np.arange(1, len(s) + 1)
+    # the error is within a few percent of the number of values seen
+    lo = s.expanding().quantile(0.45, interpolation="lower").values
+    hi = s.expanding().quantile(0.55, interpolation="higher").values
+    approx = result.iloc[:, 0].values
+    assert ((approx >= lo) & (approx <= hi))[100:].all()
+
+    for i in range(0, len(s), 97):
+        rank = (s.values[: i + 1] < s.values[i]).sum()
+        assert abs(result.iloc[i, 1] - rank) <= 0.05 * n[i]